@@ -0,0 +1,580 @@
+//! Self-contained archives of dotfiles directories for machines without access to the store.
+//!
+//! A bundle is a zstd-compressed tar archive. Its first member is `manifest.toml`, a
+//! [`BundleManifest`] describing every bundled dotfiles entry, followed by the contents of each
+//! dotfiles directory below `dotfiles/<name>/`, leaving out `.git` directories. The manifest
+//! records the SHA-256 hash of every bundled file, which is checked while the bundle is extracted.
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::config::SymlinkDir;
+use crate::config::{Config, DotfilePath, Dotfiles, DotfilesDir};
+use crate::config::{LinkMode, LinkStyle, LinkType};
+use crate::errors::{Error, Result};
+use crate::paths::{self, Traversal};
+use crate::remote::{self, Manifest};
+use crate::version::VERSION;
+
+const MANIFEST_NAME: &str = "manifest.toml";
+const DOTFILES_PREFIX: &str = "dotfiles";
+
+/// BundleManifest describes the contents of a bundle.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct BundleManifest {
+    /// Version of ghmd that created the bundle.
+    pub ghmd_version: String,
+
+    /// The bundled dotfiles entries.
+    pub entries: Vec<BundleEntry>,
+}
+
+/// BundleEntry is a dotfiles entry in a [`BundleManifest`] with paths that don't depend on the
+/// machine the bundle was created on.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct BundleEntry {
+    /// Name of the directory holding the dotfiles inside the bundle, unique within the bundle.
+    pub name: String,
+
+    /// Symlink directory of the entry, starting with `~` if it is inside the home directory.
+    pub symlink_directory: String,
+
+    /// Style of the symlinks of the entry.
+    pub link_style: LinkStyle,
+
+    /// Kind of links of the entry.
+    pub link_type: LinkType,
+
+    /// Whether the entry is linked using symlinks or hardlinks.
+    #[serde(default)]
+    pub link_mode: LinkMode,
+
+    /// Whether symlinks point at canonicalized dotfile paths.
+    pub canonical_targets: bool,
+
+    /// Tracked dotfiles of the entry.
+    pub paths: BTreeSet<DotfilePath>,
+
+    /// Tracked dotfiles that were copied rather than linked.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub copied_paths: BTreeSet<DotfilePath>,
+
+    /// Paths tracked dotfiles are stored at if they differ from the dotfile path, see
+    /// [`Dotfiles::stored_as`].
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub stored_as: BTreeMap<DotfilePath, PathBuf>,
+
+    /// Hashes of every bundled file of the dotfiles directory, see [`Manifest`].
+    pub files: Manifest,
+}
+
+impl Config {
+    /// Write a bundle of every configured dotfiles directory to `output`, returning its
+    /// manifest. File contents are streamed into the archive rather than read into memory.
+    pub fn bundle(&self, output: &Path) -> Result<BundleManifest> {
+        let home = self
+            .xdg_dirs()
+            .and_then(|dirs| dirs.home_dir().ok())
+            .map(Path::to_path_buf);
+        let mut manifest = BundleManifest {
+            ghmd_version: VERSION.to_string(),
+            entries: Vec::new(),
+        };
+        for dotfiles in &self.dotfiles {
+            let base = dotfiles
+                .dotfile_directory
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "dotfiles".to_string());
+            let mut name = base.clone();
+            let mut suffix = 1;
+            while manifest.entries.iter().any(|entry| entry.name == name) {
+                suffix += 1;
+                name = format!("{0}_{1}", base, suffix);
+            }
+            // the dotfiles directory itself may be a symlink, so only its entries are hashed
+            // without following symlinks
+            let mut files = Manifest::new();
+            for member in fs::read_dir(&*dotfiles.dotfile_directory)? {
+                let member = member?;
+                if Traversal::new().visits(&member.path()) {
+                    let relative = Path::new(&member.file_name()).to_path_buf();
+                    remote::hash_tree(&member.path(), &relative, &mut files)?;
+                }
+            }
+            manifest.entries.push(BundleEntry {
+                name,
+                symlink_directory: portable_dir(dotfiles.symlink_dir(), home.as_deref()),
+                link_style: dotfiles.link_style,
+                link_type: dotfiles.link_type,
+                link_mode: dotfiles.link_mode,
+                canonical_targets: dotfiles.canonical_targets,
+                paths: dotfiles.paths.clone(),
+                copied_paths: dotfiles.copied_paths.clone(),
+                stored_as: dotfiles.stored_as.clone(),
+                files,
+            });
+        }
+
+        let encoder = zstd::Encoder::new(File::create(output)?, 0)?;
+        let mut builder = tar::Builder::new(encoder);
+        builder.follow_symlinks(false);
+        let toml = toml::to_string_pretty(&manifest).unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(toml.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, MANIFEST_NAME, toml.as_bytes())?;
+        for (dotfiles, entry) in self.dotfiles.iter().zip(&manifest.entries) {
+            log::debug!("bundling {0}", dotfiles.dotfile_directory.display());
+            for path in entry.files.keys() {
+                let name = Path::new(DOTFILES_PREFIX).join(&entry.name).join(path);
+                builder
+                    .append_path_with_name(dotfiles.dotfile_directory.join(path), name)?;
+            }
+        }
+        let _ = builder.into_inner()?.finish()?;
+        Ok(manifest)
+    }
+
+    /// Extract the bundle at `bundle` and track its dotfiles entries, returning the dotfiles
+    /// directories they were extracted to. A bundle with a single entry is extracted to `dest`,
+    /// otherwise each entry is extracted to `dest/<name>`. `dest` must not exist yet; a relative
+    /// `dest` is taken relative to the current directory.
+    ///
+    /// The bundle is extracted next to `dest` first and only moved into place once every file
+    /// matches its hash in the manifest, so a corrupt bundle leaves nothing behind. Symlink
+    /// directories starting with `~` are taken relative to the home directory and must exist.
+    pub fn unbundle(&mut self, bundle: &Path, dest: &Path) -> Result<Vec<DotfilesDir>> {
+        let dest = &paths::normalize(&env::current_dir()?.join(dest));
+        if dest.symlink_metadata().is_ok() {
+            return Err(Error::DotfilePathAlreadyExists(dest.to_path_buf()));
+        }
+        let mut staging = dest.as_os_str().to_owned();
+        staging.push(".partial");
+        let staging = PathBuf::from(staging);
+        if staging.symlink_metadata().is_ok() {
+            return Err(Error::DotfilePathAlreadyExists(staging));
+        }
+
+        let manifest = match extract(bundle, &staging) {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                let _ = fs::remove_dir_all(&staging);
+                return Err(e);
+            },
+        };
+        let dirs: Vec<PathBuf> = match manifest.entries.len() {
+            1 => {
+                fs::rename(staging.join(&manifest.entries[0].name), dest)?;
+                fs::remove_dir(&staging)?;
+                vec![dest.to_path_buf()]
+            },
+            _ => {
+                fs::rename(&staging, dest)?;
+                manifest
+                    .entries
+                    .iter()
+                    .map(|entry| dest.join(&entry.name))
+                    .collect()
+            },
+        };
+
+        let home = self
+            .xdg_dirs()
+            .and_then(|dirs| dirs.home_dir().ok())
+            .map(Path::to_path_buf);
+        let mut registered = Vec::new();
+        for (entry, dir) in manifest.entries.into_iter().zip(dirs) {
+            let dotfiles_dir: DotfilesDir = dir.try_into()?;
+            let symlink_dir: SymlinkDir =
+                expand_dir(&entry.symlink_directory, home.as_deref())?.try_into()?;
+            let mut dotfiles = Dotfiles::new(&symlink_dir, &dotfiles_dir)?;
+            dotfiles.link_style = entry.link_style;
+            dotfiles.link_type = entry.link_type;
+            dotfiles.set_link_mode(entry.link_mode);
+            dotfiles.canonical_targets = entry.canonical_targets;
+            dotfiles.paths = entry.paths;
+            dotfiles.copied_paths = entry.copied_paths;
+            dotfiles.stored_as = entry.stored_as;
+            self.insert_dotfiles(dotfiles)?;
+            registered.push(dotfiles_dir);
+        }
+        Ok(registered)
+    }
+}
+
+/// Extract the bundle at `bundle` into `staging`, one directory per entry, and verify the hashes
+/// of the extracted files against the manifest.
+fn extract(bundle: &Path, staging: &Path) -> Result<BundleManifest> {
+    let mut archive = tar::Archive::new(zstd::Decoder::new(File::open(bundle)?)?);
+    let mut members = archive.entries()?;
+
+    let manifest: BundleManifest = match members.next() {
+        Some(member) => {
+            let mut member = member?;
+            if *member.path()? != *Path::new(MANIFEST_NAME) {
+                return Err(Error::BundleInvalid(format!("missing {0}", MANIFEST_NAME)));
+            }
+            let mut contents = String::new();
+            let _ = member.read_to_string(&mut contents)?;
+            toml::from_str(&contents)
+                .map_err(|e| Error::BundleInvalid(format!("invalid manifest: {0}", e)))?
+        },
+        None => return Err(Error::BundleInvalid("empty archive".to_string())),
+    };
+    let mut names = BTreeSet::new();
+    for entry in &manifest.entries {
+        if !is_plain_relative(Path::new(&entry.name)) || !names.insert(&entry.name) {
+            return Err(Error::BundleInvalid(format!(
+                "invalid entry name '{0}'",
+                entry.name
+            )));
+        }
+        fs::create_dir_all(staging.join(&entry.name))?;
+    }
+
+    // symlinks are only created once every regular file is written, so no member can be
+    // written through a symlink of the same archive
+    let mut extracted: BTreeMap<&str, Manifest> = BTreeMap::new();
+    let mut symlinks = Vec::new();
+    for member in members {
+        let mut member = member?;
+        let path = member.path()?.into_owned();
+        // directories are created along with the files inside them
+        if member.header().entry_type() == tar::EntryType::Directory {
+            continue;
+        }
+        let relative = path.strip_prefix(DOTFILES_PREFIX).ok();
+        let mut components = relative.map(Path::components).into_iter().flatten();
+        let name = match components.next() {
+            Some(Component::Normal(name)) => name.to_string_lossy().into_owned(),
+            _ => {
+                return Err(Error::BundleInvalid(format!(
+                    "unexpected {0}",
+                    path.display()
+                )))
+            },
+        };
+        let entry = manifest
+            .entries
+            .iter()
+            .find(|entry| entry.name == name)
+            .ok_or_else(|| Error::BundleInvalid(format!("unknown entry {0}", name)))?;
+        let file: PathBuf = components.collect();
+        if !is_plain_relative(&file) {
+            return Err(Error::BundleInvalid(format!(
+                "unsafe path {0}",
+                path.display()
+            )));
+        }
+
+        let root = staging.join(&entry.name);
+        let target = root.join(&file);
+        let hash = match member.header().entry_type() {
+            tar::EntryType::Regular => {
+                if has_symlink_ancestor(&root, &file) {
+                    return Err(Error::BundleInvalid(format!(
+                        "unsafe path {0}",
+                        path.display()
+                    )));
+                }
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                let mut writer = HashingWriter {
+                    inner: File::create(&target)?,
+                    hasher: Sha256::new(),
+                };
+                let _ = io::copy(&mut member, &mut writer)?;
+                set_mode(&target, member.header().mode()?)?;
+                format!("{0:x}", writer.hasher.finalize())
+            },
+            tar::EntryType::Symlink => {
+                let link = member.link_name()?.ok_or_else(|| {
+                    Error::BundleInvalid(format!(
+                        "symlink {0} has no target",
+                        path.display()
+                    ))
+                })?;
+                let hash = remote::hash(link.as_os_str().as_encoded_bytes());
+                symlinks.push((root, file.clone(), link.into_owned(), path));
+                hash
+            },
+            kind => {
+                log::warn!("skipping {0} of type {1:?} in bundle", path.display(), kind);
+                continue;
+            },
+        };
+        let _ = extracted.entry(&entry.name).or_default().insert(file, hash);
+    }
+
+    for (root, file, link, path) in symlinks {
+        let target = root.join(&file);
+        if has_symlink_ancestor(&root, &file) || target.symlink_metadata().is_ok() {
+            return Err(Error::BundleInvalid(format!(
+                "unsafe path {0}",
+                path.display()
+            )));
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        paths::create_symlink(&link, &target)?;
+    }
+
+    for entry in &manifest.entries {
+        let files = extracted.remove(entry.name.as_str()).unwrap_or_default();
+        let mismatch = entry
+            .files
+            .iter()
+            .find(|(path, hash)| files.get(*path) != Some(*hash))
+            .map(|(path, _)| path)
+            .or_else(|| files.keys().find(|path| !entry.files.contains_key(*path)));
+        if let Some(path) = mismatch {
+            return Err(Error::BundleChecksumMismatch(
+                Path::new(&entry.name).join(path),
+            ));
+        }
+    }
+    Ok(manifest)
+}
+
+/// HashingWriter hashes everything written to it on the way to `inner`.
+struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Whether `path` is a non-empty relative path without `..`, `.` or root components.
+fn is_plain_relative(path: &Path) -> bool {
+    path.components().next().is_some()
+        && path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+}
+
+/// Whether any directory between `root` and `root/file` is a symlink, so that writing `file`
+/// could leave `root`.
+fn has_symlink_ancestor(root: &Path, file: &Path) -> bool {
+    file.ancestors()
+        .skip(1)
+        .filter(|ancestor| !ancestor.as_os_str().is_empty())
+        .any(|ancestor| {
+            root.join(ancestor)
+                .symlink_metadata()
+                .map(|metadata| metadata.file_type().is_symlink())
+                .unwrap_or(false)
+        })
+}
+
+/// Spell `dir` relative to `home` using `~` if it is inside it.
+fn portable_dir(dir: &Path, home: Option<&Path>) -> String {
+    paths::tilde_path(dir, home)
+}
+
+/// Resolve a directory spelled by [`portable_dir`] using `home`.
+fn expand_dir(dir: &str, home: Option<&Path>) -> Result<PathBuf> {
+    let relative = match dir.strip_prefix('~') {
+        Some(relative) => relative.trim_start_matches(['/', '\\']),
+        None => return Ok(PathBuf::from(dir)),
+    };
+    let home = home.ok_or(Error::MissingHomeDirectory)?;
+    Ok(match relative.is_empty() {
+        true => home.to_path_buf(),
+        false => home.join(relative),
+    })
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o777))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, files: Manifest) -> BundleEntry {
+        BundleEntry {
+            name: name.to_string(),
+            symlink_directory: "/nonexistent".to_string(),
+            link_style: LinkStyle::default(),
+            link_type: LinkType::default(),
+            link_mode: LinkMode::default(),
+            canonical_targets: false,
+            paths: BTreeSet::new(),
+            copied_paths: BTreeSet::new(),
+            stored_as: BTreeMap::new(),
+            files,
+        }
+    }
+
+    /// Write a bundle with `manifest` followed by the members added by `members`.
+    fn write_bundle(
+        path: &Path,
+        manifest: &BundleManifest,
+        members: impl FnOnce(&mut tar::Builder<zstd::Encoder<'static, File>>),
+    ) {
+        let encoder = zstd::Encoder::new(File::create(path).unwrap(), 0).unwrap();
+        let mut builder = tar::Builder::new(encoder);
+        let toml = toml::to_string_pretty(manifest).unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(toml.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, MANIFEST_NAME, toml.as_bytes())
+            .unwrap();
+        members(&mut builder);
+        let _ = builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    fn symlink_header(link: &Path) -> tar::Header {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        header.set_link_name(link).unwrap();
+        header.set_mode(0o777);
+        header.set_cksum();
+        header
+    }
+
+    fn file_header(len: usize, mode: u32) -> tar::Header {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(len as u64);
+        header.set_mode(mode);
+        header.set_cksum();
+        header
+    }
+
+    #[test]
+    fn round_trip() {
+        let tmp = tempfile::tempdir().unwrap();
+        let store = tmp.path().join("dots");
+        fs::create_dir_all(store.join("nvim")).unwrap();
+        fs::write(store.join(".vimrc"), "set nocompatible\n").unwrap();
+        fs::write(store.join("nvim/init.lua"), "-- init\n").unwrap();
+        paths::create_symlink(&PathBuf::from(".vimrc"), &store.join(".exrc")).unwrap();
+        fs::create_dir_all(store.join(".git")).unwrap();
+        fs::write(store.join(".git/HEAD"), "ref: refs/heads/main\n").unwrap();
+        let home = tmp.path().join("home");
+        fs::create_dir_all(&home).unwrap();
+
+        let mut config = Config::in_memory(tmp.path().join("backup"));
+        let dotfiles = Dotfiles::new(
+            &home.clone().try_into().unwrap(),
+            &store.clone().try_into().unwrap(),
+        )
+        .unwrap();
+        config.insert_dotfiles(dotfiles).unwrap();
+        let bundle = tmp.path().join("dots.tar.zst");
+        let manifest = config.bundle(&bundle).unwrap();
+        assert_eq!(manifest.entries.len(), 1);
+        assert!(!manifest.entries[0]
+            .files
+            .contains_key(Path::new(".git/HEAD")));
+
+        let mut restored = Config::in_memory(tmp.path().join("backup"));
+        let dest = tmp.path().join("restored");
+        let dirs = restored.unbundle(&bundle, &dest).unwrap();
+        assert_eq!(dirs.len(), 1);
+        assert_eq!(
+            fs::read_to_string(dest.join(".vimrc")).unwrap(),
+            "set nocompatible\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dest.join("nvim/init.lua")).unwrap(),
+            "-- init\n"
+        );
+        assert_eq!(
+            fs::read_link(dest.join(".exrc")).unwrap(),
+            PathBuf::from(".vimrc")
+        );
+        assert!(!dest.join(".git").exists());
+        assert!(!tmp.path().join("restored.partial").exists());
+    }
+
+    #[test]
+    fn member_below_symlink_member_is_refused() {
+        let tmp = tempfile::tempdir().unwrap();
+        let outside = tmp.path().join("outside");
+        fs::create_dir_all(&outside).unwrap();
+        let bundle = tmp.path().join("evil.tar.zst");
+        let manifest = BundleManifest {
+            ghmd_version: VERSION.to_string(),
+            entries: vec![entry("x", Manifest::new())],
+        };
+        write_bundle(&bundle, &manifest, |builder| {
+            let mut header = symlink_header(&outside);
+            builder
+                .append_data(&mut header, "dotfiles/x/evil", io::empty())
+                .unwrap();
+            let mut header = file_header(5, 0o644);
+            builder
+                .append_data(&mut header, "dotfiles/x/evil/f", &b"owned"[..])
+                .unwrap();
+        });
+
+        let mut config = Config::in_memory(tmp.path().join("backup"));
+        let dest = tmp.path().join("dest");
+        assert!(config.unbundle(&bundle, &dest).is_err());
+        assert!(!outside.join("f").exists());
+        assert!(!dest.exists());
+        assert!(!tmp.path().join("dest.partial").exists());
+    }
+
+    #[test]
+    fn special_mode_bits_are_dropped() {
+        let tmp = tempfile::tempdir().unwrap();
+        let bundle = tmp.path().join("mode.tar.zst");
+        let mut files = Manifest::new();
+        let _ = files.insert(PathBuf::from("run"), remote::hash(b"#!/bin/sh\n"));
+        let manifest = BundleManifest {
+            ghmd_version: VERSION.to_string(),
+            entries: vec![entry("x", files)],
+        };
+        write_bundle(&bundle, &manifest, |builder| {
+            let mut header = file_header(10, 0o6755);
+            builder
+                .append_data(&mut header, "dotfiles/x/run", &b"#!/bin/sh\n"[..])
+                .unwrap();
+        });
+
+        let staging = tmp.path().join("staging");
+        let _ = extract(&bundle, &staging).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(staging.join("x/run"))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o7777, 0o755);
+        }
+    }
+}
@@ -1,27 +1,131 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::env;
 use std::fs;
 use std::fs::File;
 use std::io::prelude::*;
+use std::io::{self, IsTerminal};
 use std::ops::Deref;
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use chrono::Local;
-use dirs::{config_dir, data_dir};
+use glob::Pattern;
+use regex::Regex;
 use serde_derive::{Deserialize, Serialize};
 
 use crate::errors::Error;
 use crate::errors::Result;
+use crate::machine::{self, MachineState};
+use crate::merge::{self, FileState, Tracked};
+use crate::observer::{self, Action, ActionOutcome, Resolution};
+use crate::options::{DeployOptions, RestoreOptions, StowOptions};
 use crate::paths;
+use crate::remote;
+use crate::selector::Selector;
+use crate::status::{DotfileStatus, SymlinkState};
+use crate::toml_merge;
+use crate::version;
+use crate::xdg::{Dirs, XdgDir};
 
 /// Handles and saves configuration variables between application calls.
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct Config {
+    /// Version of ghmd that last wrote the config file.
+    #[serde(default)]
+    ghmd_version: Option<String>,
+
+    /// Version of the config file schema the config file was last written with, see
+    /// [`CONFIG_SCHEMA_VERSION`](crate::CONFIG_SCHEMA_VERSION).
+    #[serde(default)]
+    schema_version: Option<u32>,
+
+    /// Unix timestamp of the last time all dotfiles were deployed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_deploy: Option<u64>,
+
+    /// Whether commands that use a dotfiles directory warn once per machine if it isn't under
+    /// version control, see [`Config::warn_unversioned`].
+    #[serde(
+        default = "default_vcs_warning",
+        skip_serializing_if = "is_default_vcs_warning"
+    )]
+    vcs_warning: bool,
+
+    /// Names of files or directories besides `.git` and `.hg` that mark a dotfiles directory
+    /// containing one of them, or inside a directory containing one, as version-controlled.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    vcs_markers: Vec<String>,
+
+    /// Version of ghmd that wrote the config file as it was found when loading it, if any.
+    #[serde(skip)]
+    loaded_version: Option<String>,
+
     /// Dotfiles configuration. Each `Dotfiles` corresponds to a potentially different top-level
     /// store of dotfiles.
-    pub dotfiles: Vec<Dotfiles>,
+    pub(crate) dotfiles: Vec<Dotfiles>,
 
     #[serde(skip)]
     backup_directory: PathBuf,
+
+    /// Ephemeral configs are never written to disk.
+    #[serde(skip)]
+    ephemeral: bool,
+
+    /// Patterns of dotfiles that bulk deploys skip on this machine, see [`MachineState`].
+    #[serde(skip)]
+    skipped: Vec<Pattern>,
+
+    /// What is known about the config file this config was loaded from, to notice changes made
+    /// to it by others before writing it.
+    #[serde(skip)]
+    file_state: Tracked,
+
+    /// Whether changes made to the config file by others are merged when writing it, rather
+    /// than refusing to write it.
+    #[serde(skip)]
+    merge_external_edits: bool,
+
+    /// Whether the config file and the changes of deploys, stows and restores are synced to
+    /// disk, see [`Config::set_durable`].
+    #[serde(skip)]
+    durable: bool,
+
+    /// Number of snapshots kept, if set with [`Config::set_snapshot_keep`].
+    #[serde(skip)]
+    pub(crate) snapshot_keep: Option<usize>,
+
+    /// XDG base directories of this run, see [`Config::set_xdg_dirs`].
+    #[serde(skip)]
+    xdg_dirs: Option<Dirs>,
+
+    /// Name of the machine dotfiles are deployed on, see [`Config::set_hostname`].
+    #[serde(skip)]
+    hostname: Option<String>,
+}
+
+/// Profile of the dotfiles entries deployed unless others are selected, see
+/// [`Dotfiles::profiles`].
+const DEFAULT_PROFILE: &str = "default";
+
+fn default_vcs_warning() -> bool {
+    true
+}
+
+fn is_default_vcs_warning(vcs_warning: &bool) -> bool {
+    *vcs_warning == default_vcs_warning()
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn is_default_enabled(enabled: &bool) -> bool {
+    *enabled == default_enabled()
 }
 
 /// Represents a top-level container of dotfiles each containing a subset of dotfiles to be synced
@@ -32,19 +136,445 @@ pub struct Config {
 /// When stowing files, each relative path must actually exist in the `symlink_directory` and when
 /// deploying or restoring files, each relative path must actually exist in the
 /// `dotfile_directory`.
+///
+/// The fields are read through the accessors of the same name. Only settings that can't make the
+/// config inconsistent can be changed directly; dotfiles are tracked through the methods of
+/// [`Config`], e.g. [`Config::add_path`].
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct Dotfiles {
     /// Path relative to which dotfiles paths are truncated when determining the appropriate
     /// symlink path in `symlink_directory`.
-    pub dotfile_directory: DotfilesDir,
+    pub(crate) dotfile_directory: DotfilesDir,
 
     /// Path where dotfiles should "land" when deloyed or from which they should be moved and
     /// symlinked when stowed. If not set in config file, the default is $HOME.
-    pub symlink_directory: SymlinkDir,
+    pub(crate) symlink_directory: SymlinkDir,
 
     /// Relative path of actual dotfiles. A dotfile is a regular file or directory stored outside
     /// of `symlink_directory` that user wants symlinked to `symlink_directory`.
-    pub paths: BTreeSet<DotfilePath>,
+    pub(crate) paths: BTreeSet<DotfilePath>,
+
+    /// Whether symlinks created for this set of dotfiles point at their dotfile using an absolute
+    /// or a relative path. If not set in config file, the default is `absolute`.
+    #[serde(default)]
+    pub(crate) link_style: LinkStyle,
+
+    /// Whether directory dotfiles are linked using symlinks or, on Windows, junctions. File
+    /// dotfiles can only be linked using symlinks. If not set in config file, the default is
+    /// `symlink`.
+    #[serde(default)]
+    pub(crate) link_type: LinkType,
+
+    /// Whether dotfiles are linked using symlinks in the configured `link_style`, symlinks with
+    /// relative targets or hardlinks. If not set in config file, the default is `symlink`.
+    #[serde(default)]
+    pub(crate) link_mode: LinkMode,
+
+    /// Whether symlinks point at the canonicalized path of each dotfile rather than at
+    /// `dotfile_directory` joined with the dotfile path.
+    ///
+    /// Literal targets preserve the path as configured, so links stay meaningful if
+    /// `dotfile_directory` is itself a symlink that is later pointed elsewhere, e.g. when the
+    /// store is moved and the old location is symlinked to the new one. Canonical targets don't
+    /// embed `..` components or symlinked prefixes, so links keep resolving even if such
+    /// intermediate symlinks are removed, at the cost of breaking when the real store location
+    /// moves. If not set in config file, the default is `false`.
+    #[serde(default)]
+    pub(crate) canonical_targets: bool,
+
+    /// Subset of `paths` that were copied into `dotfile_directory` rather than moved there. Their
+    /// original location is left alone, so no symlinks are created for them.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub(crate) copied_paths: BTreeSet<DotfilePath>,
+
+    /// Other names a dotfile of `paths` is deployed under in addition to its own, next to its own
+    /// symlink, e.g. `.nvimrc` for `.vimrc`. See [`Config::deploy_as`].
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) symlink_names: BTreeMap<DotfilePath, BTreeSet<String>>,
+
+    /// Paths relative to `dotfile_directory` that dotfiles of `paths` are stored at if they
+    /// differ from the dotfile path, e.g. `dot_bashrc` for `.bashrc` in a chezmoi source
+    /// directory, see [`Config::import_from_chezmoi`].
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) stored_as: BTreeMap<DotfilePath, PathBuf>,
+
+    /// Shell command whose output, with surrounding whitespace trimmed, is used instead of
+    /// `symlink_directory` once it has been run, see [`Config::resolve_symlink_dirs`]. This
+    /// allows the symlink directory to vary, e.g. by hostname, without rewriting the config file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) symlink_dir_command: Option<String>,
+
+    /// Other spellings of `symlink_directory`, e.g. a bind mount or a symlink pointing at it.
+    /// Absolute paths inside an alias are treated like the same paths inside the symlink
+    /// directory when stowing or looking up dotfiles.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) symlink_dir_aliases: Vec<PathBuf>,
+
+    /// Operating systems, as named by Rust's `std::env::consts::OS` (e.g. `linux` or `macos`),
+    /// or hostnames this set of dotfiles applies to. If empty it applies everywhere. Only
+    /// [`Config::clone_for_host`] takes this into account.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) platforms: Vec<String>,
+
+    /// Profiles this set of dotfiles belongs to. If not empty it only applies to hosts using one
+    /// of them; [`Config::clone_for_host`] assumes the `default` profile.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) profiles: Vec<String>,
+
+    /// Glob patterns matched against the relative paths of tracked dotfiles that bulk
+    /// operations such as deploying or checking all dotfiles leave out, see
+    /// [`Dotfiles::apply_ignore`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) ignore_patterns: Vec<String>,
+
+    /// Whether this set of dotfiles takes part in operations on all dotfiles, such as deploying
+    /// or checking them, and in picking a dotfiles directory automatically when stowing.
+    /// Disabled entries are only used when their dotfiles directory is named explicitly. If not
+    /// set in config file, the default is `true`.
+    #[serde(
+        default = "default_enabled",
+        skip_serializing_if = "is_default_enabled"
+    )]
+    pub(crate) enabled: bool,
+
+    /// Whether each `${NAME}` in the symlink paths of these dotfiles stands for the value of the
+    /// environment variable `NAME`, e.g. `.config/app/${HOSTNAME}.conf`. Set by deploying with
+    /// [`DeployOptions::expand_dest`], so that every later command looks for the symlinks where
+    /// the deploy put them. If not set in config file, the default is `false`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) expand_dest: bool,
+
+    /// `ignore_patterns` compiled on first use.
+    #[serde(skip)]
+    compiled_ignore: CompiledPatterns,
+
+    /// Symlink directory produced by `symlink_dir_command` for this run, if any.
+    #[serde(skip)]
+    resolved_symlink_directory: Option<SymlinkDir>,
+
+    /// `dotfile_directory` and the symlink directory in effect, resolved on first use.
+    #[serde(skip)]
+    resolved_dirs: ResolvedDirs,
+
+    /// XDG base directories that dotfile paths starting with a token such as `${CONFIG}` are
+    /// deployed to in this run, see [`XdgDir`] and [`Config::set_xdg_dirs`].
+    #[serde(skip)]
+    xdg_dirs: Option<Dirs>,
+}
+
+/// CompiledPatterns caches compiled glob patterns. It never makes dotfiles entries differ.
+#[derive(Debug, Default, Clone)]
+struct CompiledPatterns(OnceLock<Vec<Pattern>>);
+
+impl PartialEq for CompiledPatterns {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+/// ResolvedDirs caches the directories of a dotfiles entry as resolved by
+/// [`paths::resolve_dir`]. It never makes dotfiles entries differ, and copies start out empty
+/// since their directories are usually changed next.
+#[derive(Debug, Default)]
+struct ResolvedDirs(OnceLock<(PathBuf, PathBuf)>);
+
+impl Clone for ResolvedDirs {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl PartialEq for ResolvedDirs {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+/// ResolvedPath is a tracked dotfile along with the absolute paths it resolves to.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ResolvedPath<'a> {
+    /// Index of the owning entry in [`Config::dotfiles`].
+    pub entry_index: usize,
+
+    /// Path of the dotfile relative to both the store and the symlink directory.
+    pub relative: &'a DotfilePath,
+
+    /// Absolute path of the dotfile in the store.
+    pub store: PathBuf,
+
+    /// Absolute path of the symlink pointing at the dotfile.
+    pub target: PathBuf,
+}
+
+/// DeployOutcome describes what deploying a single dotfile did.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum DeployOutcome {
+    /// A symlink pointing at the dotfile was created.
+    Created {
+        /// Directories created to hold the symlink, outermost first. They can be removed again
+        /// if they are left empty when the symlink is removed.
+        created_dirs: Vec<PathBuf>,
+    },
+
+    /// The expected symlink was already in place.
+    Unchanged,
+
+    /// The dotfile is stored as a symlink whose target doesn't exist, so nothing was deployed.
+    Skipped,
+}
+
+impl DeployOutcome {
+    fn action_outcome(&self) -> ActionOutcome<'static> {
+        match self {
+            Self::Created { .. } => ActionOutcome::Done,
+            Self::Unchanged => ActionOutcome::Unchanged,
+            Self::Skipped => ActionOutcome::Skipped,
+        }
+    }
+
+    /// Outcome of deploying a dotfile under several names, given the outcomes for two of them.
+    fn and(self, other: DeployOutcome) -> DeployOutcome {
+        use DeployOutcome::{Created, Skipped, Unchanged};
+        match (self, other) {
+            (Created { mut created_dirs }, Created { created_dirs: more }) => {
+                created_dirs.extend(more);
+                Created { created_dirs }
+            },
+            (created @ Created { .. }, _) | (_, created @ Created { .. }) => created,
+            (Skipped, _) | (_, Skipped) => Skipped,
+            (Unchanged, Unchanged) => Unchanged,
+        }
+    }
+}
+
+/// DeploySummary counts the outcomes of deploying several dotfiles.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct DeploySummary {
+    /// Number of symlinks that were created.
+    pub created: usize,
+
+    /// Number of symlinks that were already in place.
+    pub unchanged: usize,
+
+    /// Directories created to hold the created symlinks, each outermost first.
+    pub created_dirs: Vec<PathBuf>,
+
+    /// Number of dotfiles skipped, e.g. because they are stored as broken symlinks or are on the
+    /// skip list of this machine.
+    pub skipped: usize,
+}
+
+impl DeploySummary {
+    fn add(&mut self, outcome: DeployOutcome) {
+        match outcome {
+            DeployOutcome::Created { created_dirs } => {
+                self.created += 1;
+                self.created_dirs.extend(created_dirs);
+            },
+            DeployOutcome::Unchanged => self.unchanged += 1,
+            DeployOutcome::Skipped => self.skipped += 1,
+        }
+    }
+}
+
+/// ConvertSummary reports what converting deployed symlinks to another [`LinkStyle`] did.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct ConvertSummary {
+    /// Number of symlinks that were recreated in the requested style.
+    pub converted: usize,
+
+    /// Number of symlinks that already used the requested style.
+    pub unchanged: usize,
+
+    /// Symlinks at tracked locations that don't point at their dotfile and were left alone.
+    pub skipped: Vec<PathBuf>,
+}
+
+impl ConvertSummary {
+    fn merge(&mut self, other: ConvertSummary) {
+        self.converted += other.converted;
+        self.unchanged += other.unchanged;
+        self.skipped.extend(other.skipped);
+    }
+}
+
+/// RelinkSummary reports what pointing deployed symlinks at a dotfiles directory that was moved
+/// did, see [`Config::repair_moved_store`].
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct RelinkSummary {
+    /// Number of symlinks pointing into the old location that were recreated.
+    pub relinked: usize,
+
+    /// Number of symlinks that already pointed at their dotfile.
+    pub unchanged: usize,
+
+    /// Symlinks at tracked locations that point somewhere other than the old location, or
+    /// whose dotfile is missing from the new location, and were left alone.
+    pub skipped: Vec<PathBuf>,
+}
+
+impl RelinkSummary {
+    fn merge(&mut self, other: RelinkSummary) {
+        self.relinked += other.relinked;
+        self.unchanged += other.unchanged;
+        self.skipped.extend(other.skipped);
+    }
+}
+
+/// RenameReport reports what updating a configured directory path left unresolved, see
+/// [`Config::rename_dir`].
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct RenameReport {
+    /// Number of dotfiles entries whose directory was updated.
+    pub renamed: usize,
+
+    /// Symlink paths of the tracked dotfiles of the updated entries that aren't deployed
+    /// correctly with the new directory, along with their state.
+    pub unresolved: Vec<(PathBuf, SymlinkState)>,
+}
+
+/// DirKind selects which of the two directories of dotfiles entries is meant.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DirKind {
+    /// The directory the dotfiles are stored in.
+    Dotfiles,
+
+    /// The directory the symlinks are deployed to.
+    Symlink,
+}
+
+impl FromStr for DirKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "dotfiles" => Ok(Self::Dotfiles),
+            "symlink" => Ok(Self::Symlink),
+            _ => Err(Error::BadInput(
+                "directory kind must be 'dotfiles' or 'symlink'",
+            )),
+        }
+    }
+}
+
+/// ErrorPolicy determines how operations on several dotfiles react to a failing dotfile.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum ErrorPolicy {
+    /// Stop at the first failure and return it.
+    #[default]
+    FailFast,
+
+    /// Record the failure and carry on with the remaining dotfiles.
+    Continue,
+}
+
+/// StowAction is what stowing a path that passed validation involves.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) enum StowAction {
+    /// The path already is a symlink to the stored dotfile, which only needs to be tracked.
+    Adopt,
+
+    /// The file at the path is moved into the store and replaced with a symlink.
+    Move,
+}
+
+/// StowOutcome reports which paths were stowed by [`Config::stow_paths`] and why the others
+/// failed.
+#[derive(Debug, Default)]
+pub struct StowOutcome {
+    /// Paths that were stowed.
+    pub stowed: Vec<DotfilePath>,
+
+    /// Paths that failed to stow along with the reason.
+    pub failed: Vec<(PathBuf, Error)>,
+}
+
+/// LinkStyle determines the form of the target path written into symlinks created by ghmd.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkStyle {
+    /// Symlinks point at the absolute path of the dotfile.
+    #[default]
+    Absolute,
+
+    /// Symlinks point at the dotfile using a path relative to the directory containing the
+    /// symlink.
+    Relative,
+}
+
+impl FromStr for LinkStyle {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "absolute" => Ok(Self::Absolute),
+            "relative" => Ok(Self::Relative),
+            _ => Err(Error::BadInput(
+                "link style must be 'absolute' or 'relative'",
+            )),
+        }
+    }
+}
+
+/// LinkType determines the kind of filesystem link ghmd creates for dotfiles.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkType {
+    /// Dotfiles are linked using symlinks.
+    #[default]
+    Symlink,
+
+    /// Directory dotfiles are linked using junctions, which don't require elevated privileges
+    /// on Windows. Junctions always point at the absolute path of the dotfile regardless of the
+    /// [`LinkStyle`].
+    Junction,
+}
+
+impl FromStr for LinkType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "symlink" => Ok(Self::Symlink),
+            "junction" => Ok(Self::Junction),
+            _ => Err(Error::BadInput("link type must be 'symlink' or 'junction'")),
+        }
+    }
+}
+
+/// LinkMode determines whether ghmd links dotfiles using symlinks or hardlinks, e.g. for
+/// filesystems that don't support symlinks.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum LinkMode {
+    /// Dotfiles are linked using symlinks in the configured [`LinkStyle`].
+    #[default]
+    Symlink,
+
+    /// Dotfiles are linked using hardlinks. Only files can be hardlinked, and only if the
+    /// symlink directory is on the same filesystem as the dotfiles directory.
+    Hardlink,
+
+    /// Dotfiles are linked using symlinks with relative targets regardless of the configured
+    /// [`LinkStyle`]. This is the same as [`LinkMode::Symlink`] with [`LinkStyle::Relative`],
+    /// which is what [`Dotfiles::set_link_mode`] and loading the config turn it into for
+    /// dotfiles entries, so only [`DeployOptions::link_mode`] keeps it.
+    RelativeSymlink,
+}
+
+impl FromStr for LinkMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "symlink" => Ok(Self::Symlink),
+            "hardlink" => Ok(Self::Hardlink),
+            "relative-symlink" => Ok(Self::RelativeSymlink),
+            _ => Err(Error::BadInput(
+                "link mode must be 'symlink', 'hardlink' or 'relative-symlink'",
+            )),
+        }
+    }
 }
 
 /// DotfilesDir is directory path that must always exist where dotfiles are stored. The type doesn't
@@ -54,7 +584,7 @@ pub struct Dotfiles {
 /// Using the type system in this way helps other programmers make confident use of the code -- if
 /// we just passed around `PathBuf`s there would always be some question as to whether the path has
 /// been validated appropriately.
-#[derive(Serialize, Deserialize, Ord, PartialOrd, Eq, Debug, PartialEq, Clone)]
+#[derive(Serialize, Deserialize, Ord, PartialOrd, Eq, Debug, PartialEq, Hash, Clone)]
 pub struct DotfilesDir(PathBuf);
 
 impl TryFrom<PathBuf> for DotfilesDir {
@@ -64,7 +594,8 @@ impl TryFrom<PathBuf> for DotfilesDir {
         // verify path exists
         let _ = pb.symlink_metadata()?;
 
-        Ok(Self(pb))
+        // keep "/root/" from comparing or serializing differently from "/root"
+        Ok(Self(paths::trim_trailing_separators(&pb)))
     }
 }
 
@@ -83,7 +614,7 @@ impl Deref for DotfilesDir {
 /// Using the type system in this way helps other programmers make confident use of the code -- if
 /// we just passed around `PathBuf`s there would always be some question as to whether the path has
 /// been validated appropriately.
-#[derive(Serialize, Deserialize, Ord, PartialOrd, Eq, Debug, PartialEq, Clone)]
+#[derive(Serialize, Deserialize, Ord, PartialOrd, Eq, Debug, PartialEq, Hash, Clone)]
 pub struct SymlinkDir(PathBuf);
 
 impl TryFrom<PathBuf> for SymlinkDir {
@@ -93,7 +624,8 @@ impl TryFrom<PathBuf> for SymlinkDir {
         // verify path exists
         let _ = pb.symlink_metadata()?;
 
-        Ok(Self(pb))
+        // keep "/root/" from comparing or serializing differently from "/root"
+        Ok(Self(paths::trim_trailing_separators(&pb)))
     }
 }
 
@@ -108,6 +640,7 @@ impl Deref for SymlinkDir {
 /// DotfilePath is a type that is always relative. Meant to ensure that where a path is meant to be
 /// a dotfile path it has already been validated as or transformed into a relative path.
 #[derive(Serialize, Deserialize, Ord, PartialOrd, Eq, Debug, PartialEq, Clone)]
+#[serde(transparent)]
 pub struct DotfilePath(PathBuf);
 
 impl TryFrom<PathBuf> for DotfilePath {
@@ -164,8 +697,8 @@ impl TryFrom<(&SymlinkDir, &DotfilesDir, &PathBuf)> for DotfilePath {
             result = symlink_path.strip_prefix(&**symlink_dir)?.to_path_buf();
         } else {
             //  if not, try joining with symlink_dir and dotfile_dir
-            symlink_path = symlink_dir.join(&path);
-            dotfile_path = dotfile_dir.join(&path);
+            symlink_path = symlink_dir.join(path);
+            dotfile_path = dotfile_dir.join(path);
         }
 
         // check if dotfile path already exists
@@ -199,366 +732,5532 @@ impl Deref for DotfilePath {
     }
 }
 
-impl Dotfiles {
-    pub(crate) fn is_dotfile(&self, path: &PathBuf) -> bool {
-        match DotfilePath::try_from((self.dotfile_directory.clone(), path.clone())) {
-            Ok(p) => self.paths.contains(&p),
-            Err(_) => false,
-        }
+impl DotfilePath {
+    /// Iterate over the segments of the path.
+    pub fn iter_components(&self) -> impl Iterator<Item = Component<'_>> {
+        self.0.components()
     }
 
-    pub(crate) fn restore_dotfile(&mut self, path: &DotfilePath) -> Result<Option<()>> {
-        if !self.is_dotfile(path) {
-            return Err(Error::NoMatchingDotfileConfigured(path.to_path_buf()));
-        }
+    /// Number of segments of the path, e.g. 1 for `.vimrc` and 3 for `.config/nvim/init.lua`.
+    pub fn depth(&self) -> usize {
+        self.iter_components()
+            .filter(|component| {
+                !matches!(
+                    component,
+                    Component::RootDir | Component::Prefix(_) | Component::CurDir
+                )
+            })
+            .count()
+    }
+}
 
-        let dotfile_path = self.dotfile_directory.join(path.to_path_buf());
-        let symlink_path = self.symlink_directory.join(path.to_path_buf());
+impl Dotfiles {
+    /// Create an empty set of dotfiles after verifying that both directories exist and are
+    /// directories.
+    pub fn new(symlink_dir: &SymlinkDir, dotfile_dir: &DotfilesDir) -> Result<Self> {
+        if !dotfile_dir.exists() {
+            return Err(Error::BadInput("path does not exist"));
+        } else if !dotfile_dir.is_dir() {
+            return Err(Error::BadInput("path must be a directory"));
+        };
 
-        // if one of these doesn't exist then this Dotfiles doesn't handle the given path.
-        if !dotfile_path.exists() {
-            return Err(Error::PathDoesNotExist(dotfile_path.to_path_buf()));
-        }
+        if !symlink_dir.exists() {
+            return Err(Error::BadInput("path does not exist"));
+        } else if !symlink_dir.is_dir() {
+            return Err(Error::BadInput("path must be a directory"));
+        };
 
-        if !symlink_path.exists() {
-            return Err(Error::PathDoesNotExist(symlink_path.to_path_buf()));
-        }
+        Ok(Self {
+            dotfile_directory: dotfile_dir.clone(),
+            symlink_directory: symlink_dir.clone(),
+            paths: BTreeSet::new(),
+            link_style: LinkStyle::default(),
+            link_type: LinkType::default(),
+            link_mode: LinkMode::default(),
+            canonical_targets: false,
+            copied_paths: BTreeSet::new(),
+            symlink_names: BTreeMap::new(),
+            stored_as: BTreeMap::new(),
+            symlink_dir_command: None,
+            symlink_dir_aliases: Vec::new(),
+            platforms: Vec::new(),
+            profiles: Vec::new(),
+            ignore_patterns: Vec::new(),
+            enabled: true,
+            expand_dest: false,
+            compiled_ignore: CompiledPatterns::default(),
+            resolved_symlink_directory: None,
+            resolved_dirs: ResolvedDirs::default(),
+            xdg_dirs: None,
+        })
+    }
 
-        let symlink_metadata = symlink_path.symlink_metadata()?;
-        if !symlink_metadata.is_symlink() {
-            return Err(Error::SymlinkPathIsNotASymlink(symlink_path.to_path_buf()));
-        }
+    /// The symlink directory in effect for this run: the output of `symlink_dir_command` if it
+    /// has been resolved, otherwise the configured `symlink_directory`.
+    pub fn symlink_dir(&self) -> &SymlinkDir {
+        self.resolved_symlink_directory
+            .as_ref()
+            .unwrap_or(&self.symlink_directory)
+    }
 
-        if symlink_path.canonicalize()? != *dotfile_path {
-            return Err(Error::SymlinkPathDoesNotMatchDotfilePath(
-                symlink_path.to_path_buf(),
-                dotfile_path.to_path_buf(),
-            ));
-        }
+    /// Directory the dotfiles of this entry are stored in.
+    pub fn dotfile_directory(&self) -> &DotfilesDir {
+        &self.dotfile_directory
+    }
 
-        fs::remove_file(&symlink_path)?;
-        paths::move_file(&dotfile_path, &symlink_path)?;
-        let _ = self.paths.remove(path);
-        Ok(Some(()))
+    /// Symlink directory as configured, see [`Dotfiles::symlink_dir`] for the one in effect.
+    pub fn symlink_directory(&self) -> &SymlinkDir {
+        &self.symlink_directory
     }
 
-    // Deploy a dotfile from the dotfile store to the user's home directory.
-    pub(crate) fn deploy(&self, path: &DotfilePath) -> Result<()> {
-        let dotfile_path = self
-            .dotfile_directory
-            .exists()
-            .then(|| self.dotfile_directory.clone())
-            .ok_or(Error::PathDoesNotExist(
-                self.dotfile_directory.to_path_buf(),
-            ))?
-            .join(&**path);
+    /// Relative paths of the tracked dotfiles.
+    pub fn paths(&self) -> &BTreeSet<DotfilePath> {
+        &self.paths
+    }
 
-        // if the given dotfile_path doesn't exist at this point then it's definitely not legit
-        if !dotfile_path.exists() {
-            return Err(Error::DotfileNotFound(dotfile_path));
-        }
+    /// Whether symlinks point at their dotfile using an absolute or a relative path.
+    pub fn link_style(&self) -> LinkStyle {
+        self.link_style
+    }
 
-        // don't try deploying a dotfile that we don't "own"
-        if !self.is_dotfile(&dotfile_path) {
-            return Err(Error::NoMatchingDotfileConfigured(dotfile_path));
-        }
+    /// Set whether symlinks created from now on point at their dotfile using an absolute or a
+    /// relative path.
+    pub fn set_link_style(&mut self, link_style: LinkStyle) {
+        self.link_style = link_style;
+    }
 
-        let symlink_path = self.symlink_directory.join(&**path);
-        if symlink_path.exists() {
-            let md = symlink_path.symlink_metadata()?;
-            if !md.is_symlink() {
-                // will need to backup higher in the stack
-                return Err(Error::SymlinkPathIsNotASymlink(symlink_path.to_path_buf()));
-            }
-            // read_link will return an error if:
-            // * it is not a symbolic link
-            // * it doesn't exist
-            if fs::read_link(&symlink_path)? == dotfile_path {
-                return Ok(());
-            }
-            // we reach this point if the path is a symlink but it doesn't point to the expected
-            // dotfile. in that case, return an error
-            return Err(Error::SymlinkPathAlreadyExists(symlink_path.clone()));
+    /// Whether directory dotfiles are linked using symlinks or junctions.
+    pub fn link_type(&self) -> LinkType {
+        self.link_type
+    }
+
+    /// Set whether directory dotfiles are linked using symlinks or junctions. Fails with
+    /// [`Error::JunctionsUnsupported`] for junctions on platforms other than Windows.
+    pub fn set_link_type(&mut self, link_type: LinkType) -> Result<()> {
+        if link_type == LinkType::Junction && !cfg!(windows) {
+            return Err(Error::JunctionsUnsupported);
         }
+        self.link_type = link_type;
+        Ok(())
+    }
 
-        let symlink_path_dir =
-            symlink_path
-                .parent()
-                .ok_or(Error::InvalidSymlinkDestinationDirectory(
-                    symlink_path.clone(),
-                ))?;
+    /// Whether dotfiles are linked using symlinks or hardlinks.
+    pub fn link_mode(&self) -> LinkMode {
+        self.link_mode
+    }
 
-        if !symlink_path_dir.exists() {
-            log::debug!("creating symlink path dir {0}", symlink_path_dir.display());
-            fs::create_dir_all(symlink_path_dir)?;
+    /// Set whether dotfiles are linked using symlinks or hardlinks from now on.
+    /// [`LinkMode::RelativeSymlink`] sets [`LinkMode::Symlink`] and [`LinkStyle::Relative`].
+    pub fn set_link_mode(&mut self, link_mode: LinkMode) {
+        match link_mode {
+            LinkMode::RelativeSymlink => {
+                self.link_mode = LinkMode::Symlink;
+                self.link_style = LinkStyle::Relative;
+            },
+            link_mode => self.link_mode = link_mode,
         }
+    }
 
-        paths::create_symlink(&dotfile_path, &symlink_path)?;
+    /// Whether symlinks point at the canonicalized paths of the dotfiles.
+    pub fn canonical_targets(&self) -> bool {
+        self.canonical_targets
+    }
 
-        return Ok(());
+    /// Set whether symlinks created from now on point at the canonicalized paths of the
+    /// dotfiles.
+    pub fn set_canonical_targets(&mut self, canonical_targets: bool) {
+        self.canonical_targets = canonical_targets;
     }
 
-    fn stow_path(&mut self, stow_path: &DotfilePath) -> Result<()> {
-        log::debug!("");
-        log::debug!("stow_path: {:?}", stow_path);
-        let symlink_path = self.symlink_directory.join(&**stow_path);
-        log::debug!("");
-        log::debug!("symlink_directory: {:?}", self.symlink_directory);
-        log::debug!("symlink_path: {:?}", symlink_path);
-        log::debug!(
-            "canonicalized symlink_path: {:?}",
-            symlink_path.canonicalize()?
-        );
+    /// Relative paths of the tracked dotfiles that were copied rather than moved into the
+    /// dotfiles directory.
+    pub fn copied_paths(&self) -> &BTreeSet<DotfilePath> {
+        &self.copied_paths
+    }
 
-        let dotfile_path = self.dotfile_directory.join(&**stow_path);
-        log::debug!("");
-        log::debug!("dotfile_directory: {:?}", self.dotfile_directory);
-        log::debug!("dotfile_path: {:?}", dotfile_path);
+    /// Other names the dotfiles are deployed under in addition to their own.
+    pub fn symlink_names(&self) -> &BTreeMap<DotfilePath, BTreeSet<String>> {
+        &self.symlink_names
+    }
 
-        if dotfile_path.try_exists()? {
-            if symlink_path.canonicalize()? == dotfile_path {
-                log::debug!("");
-                log::debug!("path already stowed: {:?}", stow_path);
-                return Ok(());
-            }
+    /// Paths relative to the dotfiles directory that dotfiles are stored at if they differ from
+    /// the dotfile path.
+    pub fn stored_as(&self) -> &BTreeMap<DotfilePath, PathBuf> {
+        &self.stored_as
+    }
 
-            return Err(Error::DotfilePathAlreadyExists(stow_path.to_path_buf()));
-        }
+    /// Shell command producing the symlink directory, if any.
+    pub fn symlink_dir_command(&self) -> Option<&str> {
+        self.symlink_dir_command.as_deref()
+    }
 
-        let _ = symlink_path.try_exists()?;
-        log::debug!("creating symlink0");
-        paths::move_file(&symlink_path, &dotfile_path)?;
-        log::debug!("creating symlink5");
-        paths::create_symlink(&dotfile_path, &symlink_path)?;
+    /// Other spellings of the symlink directory.
+    pub fn symlink_dir_aliases(&self) -> &[PathBuf] {
+        &self.symlink_dir_aliases
+    }
 
-        log::debug!("stowed path: {:?}", stow_path);
+    /// Operating systems or hostnames this entry applies to, everywhere if empty.
+    pub fn platforms(&self) -> &[String] {
+        &self.platforms
+    }
 
-        let _ = self.paths.insert(stow_path.clone());
-        Ok(())
+    /// Profiles this entry belongs to, all of them if empty.
+    pub fn profiles(&self) -> &[String] {
+        &self.profiles
     }
-}
 
-impl Config {
-    /// Load a config from disk and return it to caller.
-    pub fn load() -> Result<Self> {
-        let backup_directory = Self::data_dir()?.join(Local::now().to_rfc3339());
-        log::debug!("setting backup directory to {0}", backup_directory.display());
-        if let Some(config_path) = Self::get_config_file() {
-            let mut file = File::open(config_path)?;
-            let mut contents = String::new();
-            let _ = file.read_to_string(&mut contents)?;
-            let mut c: Self = toml::from_str(&contents)?;
-            c.backup_directory = backup_directory;
-            Ok(c)
-        } else {
-            Ok(Self {
-                dotfiles: Vec::new(),
-                backup_directory,
-            })
+    /// Whether this set of dotfiles applies to the host `hostname` running this build's
+    /// operating system, see [`Dotfiles::platforms`].
+    pub fn applies_to_host(&self, hostname: &str) -> bool {
+        self.platforms.is_empty()
+            || self
+                .platforms
+                .iter()
+                .any(|platform| platform == env::consts::OS || platform == hostname)
+    }
+
+    /// Whether this set of dotfiles applies to hosts using `profile`, see
+    /// [`Dotfiles::profiles`].
+    pub fn in_profile(&self, profile: &str) -> bool {
+        self.profiles.is_empty() || self.profiles.iter().any(|p| p == profile)
+    }
+
+    /// Glob patterns of the dotfiles that bulk operations leave out.
+    pub fn ignore_patterns(&self) -> &[String] {
+        &self.ignore_patterns
+    }
+
+    /// Whether this set of dotfiles takes part in operations on all dotfiles.
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Set whether this set of dotfiles takes part in operations on all dotfiles.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Iterate over the tracked dotfiles that don't match any of the `ignore_patterns`. The
+    /// patterns are compiled once and fail with [`Error::InvalidPattern`] if one isn't valid.
+    pub fn apply_ignore(&self) -> Result<impl Iterator<Item = &DotfilePath>> {
+        let patterns = self.compiled_ignore()?;
+        Ok(self
+            .paths
+            .iter()
+            .filter(move |path| !patterns.iter().any(|p| p.matches_path(path))))
+    }
+
+    /// Whether the tracked dotfile `path` matches one of the `ignore_patterns`, see
+    /// [`Dotfiles::apply_ignore`].
+    pub fn is_ignored(&self, path: &DotfilePath) -> Result<bool> {
+        let patterns = self.compiled_ignore()?;
+        Ok(patterns.iter().any(|p| p.matches_path(path)))
+    }
+
+    // glob patterns like those of the skip list rather than a globset, and a std OnceLock rather
+    // than once_cell, so that no dependency is added for either
+    fn compiled_ignore(&self) -> Result<&[Pattern]> {
+        if let Some(patterns) = self.compiled_ignore.0.get() {
+            return Ok(patterns);
         }
+        let patterns = self
+            .ignore_patterns
+            .iter()
+            .map(|pattern| Pattern::new(pattern))
+            .collect::<std::result::Result<_, _>>()?;
+        Ok(self.compiled_ignore.0.get_or_init(|| patterns))
     }
 
-    /// Deploy specified dotfiles.
-    pub fn deploy_paths(&self, paths: Vec<PathBuf>) -> Result<()> {
-        'paths: for path in paths.iter() {
-            log::info!("deploying {:?}", path);
-            for dotfiles in &self.dotfiles {
-                let dotfile_path = match DotfilePath::try_from((
-                    dotfiles.dotfile_directory.clone(),
-                    path.to_path_buf(),
-                )) {
-                    Err(_) => continue,
-                    Ok(p) => p,
-                };
-                match dotfiles.deploy(&dotfile_path) {
-                    Err(Error::DotfileNotFound(_)) => continue,
-                    Err(e) => return Err(e),
-                    Ok(_) => continue 'paths,
-                };
-            }
-            // if we reach this point then the current `path` hasn't been found. that's a whoopin'
-            //
-            // TODO: should we fail on the first dotfile that doesn't match? or should this be
-            // all-or-nothing where if any specified dotfile doesn't match an existing dotfile then
-            // we cowardly refuse to do anything
-            //
-            // i'm leaning toward all-or nothing, but that would ideally be a separate check from
-            // the deployment step
-            return Err(Error::NoMatchingDotfileConfigured(path.clone()));
+    /// Copy of this entry without its dotfiles and any resolved symlink directory, i.e. only what
+    /// is configured for the entry itself.
+    pub(crate) fn settings(&self) -> Self {
+        Self {
+            paths: BTreeSet::new(),
+            copied_paths: BTreeSet::new(),
+            stored_as: BTreeMap::new(),
+            resolved_symlink_directory: None,
+            ..self.clone()
         }
-        Ok(())
     }
 
-    /// Deploy all dotfiles.
-    pub fn deploy_all(&self) -> Result<()> {
-        for dotfiles in &self.dotfiles {
-            log::info!(
-                "deploying paths from {0} to {1}",
-                dotfiles.dotfile_directory.display(),
-                dotfiles.symlink_directory.display()
-            );
-            for path in dotfiles.paths.iter() {
-                log::info!("deploying path {0}", path.display());
-                match dotfiles.deploy(path) {
-                    Err(Error::SymlinkPathIsNotASymlink(p)) => {
-                        self.backup(&p)?;
-                        log::debug!("retrying deploy of path {0}", p.display());
-                        dotfiles.deploy(path)?;
-                    },
-                    _ => (),
-                }
-            }
+    /// Absolute path of the given dotfile in `dotfile_directory`, see [`Dotfiles::stored_as`].
+    pub fn store_path(&self, path: &DotfilePath) -> PathBuf {
+        match self.stored_as.get(path) {
+            Some(stored) => self.dotfile_directory.join(stored),
+            None => self.dotfile_directory.join(&**path),
+        }
+    }
+
+    /// Absolute path where the symlink for the given dotfile is placed. Paths starting with the
+    /// token of an XDG base directory are placed in that directory instead of the symlink
+    /// directory, see [`XdgDir`].
+    ///
+    /// If `expand_dest` is set, each `${NAME}` in the rest of the path is replaced with the value
+    /// of the environment variable `NAME`. Unset variables are left as they are, which is where
+    /// [`DeployOptions::expand_dest`] fails instead.
+    pub fn symlink_path(&self, path: &DotfilePath) -> PathBuf {
+        let symlink_path = self.unexpanded_symlink_path(path);
+        match self.expand_dest {
+            true => paths::expand_env(&symlink_path).unwrap_or(symlink_path),
+            false => symlink_path,
+        }
+    }
+
+    /// Like [`Dotfiles::symlink_path`], but ignoring `expand_dest`.
+    fn unexpanded_symlink_path(&self, path: &DotfilePath) -> PathBuf {
+        XdgDir::split(path)
+            .and_then(|_| self.with_xdg_dirs(|dirs| dirs.expand(path)))
+            .unwrap_or_else(|| self.symlink_dir().join(&**path))
+    }
+
+    /// Whether the symlink path of one of these dotfiles contains a `${NAME}` that deploying
+    /// with [`DeployOptions::expand_dest`] replaces.
+    fn has_env_in_symlink_paths(&self) -> bool {
+        self.paths.iter().any(|path| {
+            let symlink_path = self.unexpanded_symlink_path(path);
+            symlink_path.to_string_lossy().contains("${")
+        })
+    }
+
+    /// Spell `path` for messages, relative to the home directory if it is inside it.
+    fn display_path(&self, path: &Path) -> String {
+        let home = self.xdg_dirs.as_ref().and_then(|dirs| dirs.home_dir().ok());
+        paths::tilde_path(path, home)
+    }
+
+    /// Call `f` with the XDG base directories of this run, if they were resolved.
+    fn with_xdg_dirs<T>(&self, f: impl FnOnce(&Dirs) -> Option<T>) -> Option<T> {
+        self.xdg_dirs.as_ref().and_then(f)
+    }
+
+    /// Absolute paths of the symlink of the given dotfile followed by those of
+    /// [`Dotfiles::alias_paths`].
+    pub fn all_symlink_paths(&self, path: &DotfilePath) -> Vec<PathBuf> {
+        let mut symlink_paths = vec![self.symlink_path(path)];
+        symlink_paths.extend(self.alias_paths(path));
+        symlink_paths
+    }
+
+    /// Absolute paths of the symlinks for the other names the given dotfile is deployed under,
+    /// see `symlink_names`.
+    pub fn alias_paths(&self, path: &DotfilePath) -> Vec<PathBuf> {
+        let symlink_path = self.symlink_path(path);
+        self.symlink_names
+            .get(path)
+            .into_iter()
+            .flatten()
+            .map(|name| symlink_path.with_file_name(name))
+            .collect()
+    }
+
+    /// Iterate over the tracked dotfiles of this entry along with their resolved store and symlink
+    /// paths. The `entry_index` of each item is 0; use [`Config::iter_entries`] to obtain indices
+    /// within a config.
+    pub fn iter_paths(&self) -> impl Iterator<Item = ResolvedPath<'_>> {
+        self.resolved_paths(0)
+    }
+
+    fn resolved_paths(
+        &self,
+        entry_index: usize,
+    ) -> impl Iterator<Item = ResolvedPath<'_>> {
+        self.paths
+            .iter()
+            .map(move |path| self.resolve(entry_index, path))
+    }
+
+    fn resolve<'a>(&'a self, index: usize, path: &'a DotfilePath) -> ResolvedPath<'a> {
+        ResolvedPath {
+            entry_index: index,
+            relative: path,
+            store: self.store_path(path),
+            target: self.symlink_path(path),
+        }
+    }
+
+    /// Run `symlink_dir_command`, if set, and use its output as the symlink directory for this
+    /// run. The output must name an existing directory.
+    pub(crate) fn resolve_symlink_dir_command(&mut self) -> Result<()> {
+        let command = match &self.symlink_dir_command {
+            Some(command) => command,
+            None => return Ok(()),
+        };
+        let failed =
+            |reason: String| Error::SymlinkDirCommandFailed(command.clone(), reason);
+
+        #[cfg(not(target_os = "windows"))]
+        let output = Command::new("sh").arg("-c").arg(command).output();
+        #[cfg(target_os = "windows")]
+        let output = Command::new("cmd").arg("/C").arg(command).output();
+
+        let output = output.map_err(|e| failed(e.to_string()))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let reason = match stderr.trim() {
+                "" => output.status.to_string(),
+                stderr => format!("{0}: {1}", output.status, stderr),
+            };
+            return Err(failed(reason));
+        }
+
+        let dir = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+        if !dir.is_absolute() || !dir.is_dir() {
+            return Err(failed(format!(
+                "'{0}' is not an existing absolute directory",
+                dir.display()
+            )));
         }
+        log::debug!(
+            "using symlink directory {0} from '{1}'",
+            dir.display(),
+            command
+        );
+        self.resolved_symlink_directory = Some(SymlinkDir(dir));
+        self.resolved_dirs = ResolvedDirs::default();
         Ok(())
     }
 
-    fn stow_path(
-        &mut self,
-        symlink_dir: &SymlinkDir,
-        dotfile_dir: &DotfilesDir,
-        stow_path: &DotfilePath,
-    ) -> Result<()> {
-        for dotfiles in &mut self.dotfiles {
-            if dotfiles.dotfile_directory == *dotfile_dir
-                && dotfiles.symlink_directory == *symlink_dir
+    /// Whether this set of dotfiles is stored in `dotfiles_dir`, however either directory is
+    /// spelled.
+    pub fn is_stored_in(&self, dotfiles_dir: &DotfilesDir) -> bool {
+        self.dotfile_directory == *dotfiles_dir
+            || self.resolved_dirs().0 == paths::resolve_dir(dotfiles_dir)
+    }
+
+    /// Whether this set of dotfiles is deployed to `symlink_dir`, however either directory is
+    /// spelled.
+    pub fn is_deployed_to(&self, symlink_dir: &SymlinkDir) -> bool {
+        self.symlink_dir() == symlink_dir
+            || self.resolved_dirs().1 == paths::resolve_dir(symlink_dir)
+    }
+
+    /// `dotfile_directory` and the symlink directory in effect as resolved by
+    /// [`paths::resolve_dir`]. They are only resolved once, so that looking up entries doesn't
+    /// hit the filesystem for each entry.
+    fn resolved_dirs(&self) -> &(PathBuf, PathBuf) {
+        self.resolved_dirs.0.get_or_init(|| {
+            (
+                paths::resolve_dir(&self.dotfile_directory),
+                paths::resolve_dir(self.symlink_dir()),
+            )
+        })
+    }
+
+    /// Rewrite an absolute `path` inside one of `symlink_dir_aliases` to the same location inside
+    /// the symlink directory. Any other path is returned unchanged.
+    pub fn unalias(&self, path: &Path) -> PathBuf {
+        self.symlink_dir_aliases
+            .iter()
+            .find_map(|alias| path.strip_prefix(alias).ok())
+            .map(|relative| self.symlink_dir().join(relative))
+            .unwrap_or_else(|| path.to_path_buf())
+    }
+
+    /// Interpret an absolute `path` as a location inside `symlink_directory`, or one of its
+    /// aliases, and return it relative to that directory.
+    pub fn strip_symlink_dir(&self, path: &Path) -> Option<DotfilePath> {
+        let path = self.unalias(path);
+        // dotfiles tracked relative to an XDG base directory take precedence
+        let tokenized = self
+            .with_xdg_dirs(|dirs| dirs.tokenize(&path))
+            .map(DotfilePath);
+        if let Some(tokenized) = tokenized {
+            if self
+                .paths
+                .iter()
+                .any(|tracked| tokenized.starts_with(&**tracked))
             {
-                return dotfiles.stow_path(stow_path);
+                return Some(tokenized);
             }
         }
-        // if we reach this point then we need to create a new dotfiles entry in this config and
-        // stow using that
+        let relative = path.strip_prefix(&**self.symlink_dir()).ok()?;
+        Some(DotfilePath(relative.to_path_buf()))
+    }
 
-        self.add_dotfiles(&symlink_dir, &dotfile_dir)?;
-        self.dotfiles
-            .last_mut()
-            .ok_or(Error::UnexpectedError(
-                "could not retrieve new dotfiles dir",
-            ))?
-            .stow_path(stow_path)?;
-        Ok(())
+    /// Interpret an absolute `path` as a location inside `dotfile_directory` and return it relative
+    /// to that directory.
+    pub fn strip_dotfile_dir(&self, path: &Path) -> Option<DotfilePath> {
+        let relative = path.strip_prefix(&*self.dotfile_directory).ok()?;
+        let stored = self
+            .stored_as
+            .iter()
+            .find(|(_, stored)| *stored == relative)
+            .map(|(path, _)| path.clone());
+        Some(stored.unwrap_or_else(|| DotfilePath(relative.to_path_buf())))
     }
 
-    /// Stow paths in given dotfile dir.
-    pub fn stow_paths(
-        &mut self,
-        symlink_dir: SymlinkDir,
-        dotfile_dir: DotfilesDir,
-        stow_paths: Vec<DotfilePath>,
-    ) -> Result<()> {
-        for path in stow_paths.iter() {
-            log::info!("stowing path: {:?}", path);
-            self.stow_path(&symlink_dir, &dotfile_dir, path)?;
+    /// Return the path that a symlink for the given dotfile should point at. This is either the
+    /// literal `dotfile_directory.join(path)` or, if `canonical_targets` is set, its canonical
+    /// form. If the dotfile can't be canonicalized the literal path is returned.
+    pub fn target_for(&self, path: &DotfilePath) -> PathBuf {
+        let dotfile_path = self.store_path(path);
+        if self.canonical_targets {
+            return dotfile_path.canonicalize().unwrap_or(dotfile_path);
         }
-        Ok(())
+        dotfile_path
     }
 
-    /// Restores the named dotfile if it can be found in one of the configured dotfile directories.
-    pub fn restore_dotfile(&mut self, path: DotfilePath) -> Result<()> {
-        for dotfiles in &mut self.dotfiles {
+    /// Whether the symlink at `symlink_path` points at the given dotfile, either through its
+    /// literal or its canonical target path.
+    pub(crate) fn links_to(
+        &self,
+        symlink_path: &Path,
+        path: &DotfilePath,
+    ) -> Result<bool> {
+        let link_target = paths::resolve_link(symlink_path)?;
+        Ok(link_target == self.store_path(path) || link_target == self.target_for(path))
+    }
+
+    /// Whether `symlink_path` is a symlink pointing at the given dotfile or a hardlink of it, see
+    /// [`LinkMode`].
+    pub(crate) fn is_linked_at(
+        &self,
+        symlink_path: &Path,
+        path: &DotfilePath,
+    ) -> Result<bool> {
+        match symlink_path.symlink_metadata() {
+            Ok(md) if md.is_symlink() => self.links_to(symlink_path, path),
+            Ok(_) => Ok(paths::same_inode(symlink_path, &self.store_path(path))?),
+            Err(_) => Ok(false),
+        }
+    }
+
+    /// Whether the given dotfile is tracked by this set of dotfiles.
+    pub fn has_path(&self, path: &DotfilePath) -> bool {
+        self.paths.contains(path)
+    }
+
+    /// Dotfiles located in a subdirectory of `dotfile_directory` rather than directly inside it.
+    pub fn subdirectory_paths(&self) -> impl Iterator<Item = &DotfilePath> {
+        self.paths.iter().filter(|path| path.depth() > 1)
+    }
+
+    /// Pairs of a tracked dotfile and the tracked directory containing it. Deploying the
+    /// directory already puts such a dotfile in place, so its own symlink would end up inside
+    /// the dotfiles directory.
+    pub fn nested_paths(&self) -> Vec<(&DotfilePath, &DotfilePath)> {
+        let mut nested = Vec::new();
+        for path in self.subdirectory_paths() {
+            let outer = self
+                .paths
+                .iter()
+                .find(|dir| dir.depth() < path.depth() && path.starts_with(&***dir));
+            if let Some(outer) = outer {
+                nested.push((path, outer));
+            }
+        }
+        nested
+    }
+
+    /// Whether the given dotfile was copied into the store rather than moved there.
+    pub fn is_copied(&self, path: &DotfilePath) -> bool {
+        self.copied_paths.contains(path)
+    }
+
+    /// Stop tracking the given dotfile, returning whether it was tracked.
+    fn untrack(&mut self, path: &DotfilePath) -> bool {
+        let _ = self.copied_paths.remove(path);
+        let _ = self.symlink_names.remove(path);
+        let _ = self.stored_as.remove(path);
+        self.paths.remove(path)
+    }
+
+    /// Move the given dotfile back to `symlink_directory`, replacing its symlink.
+    ///
+    /// If the symlink has been replaced by a regular file, e.g. by an editor that saves files by
+    /// renaming a new file over the old one, that file is moved into the backup directory and
+    /// replaced by the stored dotfile unless it was modified more recently than the stored
+    /// dotfile. In that case `force` is required to avoid losing recent edits.
+    ///
+    /// Copied dotfiles never replaced their original, so they are only no longer tracked and the
+    /// copy in the store is left in place.
+    ///
+    /// With `dry_run` every check is made but nothing is moved and the dotfile remains tracked.
+    /// With `to` the dotfile is moved there instead, see [`Dotfiles::restore_to`].
+    pub(crate) fn restore_dotfile(
+        &mut self,
+        path: &DotfilePath,
+        options: &RestoreOptions,
+    ) -> Result<Option<()>> {
+        if !self.has_path(path) {
+            return Err(Error::NoMatchingDotfileConfigured(path.to_path_buf()));
+        }
+        if let Some(dir) = options.to {
+            return self.restore_to(path, dir, options).map(Some);
+        }
+
+        if self.is_copied(path) {
+            if options.dry_run {
+                log::info!("would stop tracking copied dotfile {0}", path.display());
+                return Ok(Some(()));
+            }
+            log::info!("no longer tracking copied dotfile {0}", path.display());
+            let _ = self.untrack(path);
+            return Ok(Some(()));
+        }
+
+        let dotfile_path = self.store_path(path);
+        let symlink_path = self.symlink_path(path);
+
+        // if one of these doesn't exist then this Dotfiles doesn't handle the given path.
+        if !dotfile_path.exists() {
+            return Err(Error::PathDoesNotExist(dotfile_path.to_path_buf()));
+        }
+
+        if !symlink_path.exists() {
+            return Err(Error::PathDoesNotExist(symlink_path.to_path_buf()));
+        }
+
+        let symlink_metadata = symlink_path.symlink_metadata()?;
+        if !symlink_metadata.is_symlink() {
+            if !symlink_metadata.is_file() || !dotfile_path.is_file() {
+                return Err(Error::SymlinkPathIsNotASymlink(symlink_path.to_path_buf()));
+            }
+            let stored = dotfile_path.metadata()?.modified()?;
+            let newer = symlink_metadata.modified()? > stored;
+            // without a backup directory the file would be lost, so only force replaces it
+            if !options.force && (newer || options.backup_directory.is_none()) {
+                return Err(Error::RestoreTargetNewer(symlink_path.to_path_buf()));
+            }
+            if options.dry_run {
+                log::info!(
+                    "would back up {0} and replace it with the stored version of the dotfile",
+                    symlink_path.display()
+                );
+                return Ok(Some(()));
+            }
             log::info!(
-                "attempting to restore {:?} from {:?}",
-                *path,
-                *dotfiles.dotfile_directory
+                "backing up {0} and replacing it with the stored version of the dotfile",
+                symlink_path.display()
             );
-            match dotfiles.restore_dotfile(&path) {
-                Ok(Some(_)) => return Ok(()),
-                Ok(None) => continue,
-                Err(e) => match e {
-                    Error::NoMatchingDotfileConfigured(_)
-                    | Error::PathDoesNotExist(_)
-                    | Error::SymlinkPathIsNotASymlink(_)
-                    | Error::SymlinkPathDoesNotMatchDotfilePath(_, _) => {
-                        log::info!("restore failed: {:?}", e,);
-                    },
-                    _ => return Err(e),
-                },
+            self.remove_aliases(path)?;
+            match options.backup_directory {
+                Some(backup_directory) => backup_to(backup_directory, &symlink_path)?,
+                None => fs::remove_file(&symlink_path)?,
             }
+            paths::move_file(&dotfile_path, &symlink_path)?;
+            sync_dirs(options.durable, &[&dotfile_path, &symlink_path])?;
+            let _ = self.untrack(path);
+            self.clean_store(&dotfile_path, options)?;
+            return Ok(Some(()));
         }
-        Err(Error::DotfileNotFound(path.to_path_buf()))
+
+        if symlink_path.canonicalize()? != *dotfile_path {
+            return Err(Error::SymlinkPathDoesNotMatchDotfilePath(
+                symlink_path.to_path_buf(),
+                dotfile_path.to_path_buf(),
+            ));
+        }
+
+        if options.dry_run {
+            log::info!("would restore {0}", symlink_path.display());
+            return Ok(Some(()));
+        }
+        self.remove_aliases(path)?;
+        fs::remove_file(&symlink_path)?;
+        paths::move_file(&dotfile_path, &symlink_path)?;
+        sync_dirs(options.durable, &[&dotfile_path, &symlink_path])?;
+        let _ = self.untrack(path);
+        self.clean_store(&dotfile_path, options)?;
+        Ok(Some(()))
     }
 
-    /// Adds new dotfiles to dotfile_dir
-    pub fn add_dotfiles(
+    /// Move the given dotfile from the store to its path relative to `dir`, creating missing
+    /// directories, remove its symlinks and stop tracking it. Copied dotfiles are moved as well.
+    ///
+    /// Nothing is moved if something already exists at the destination, or if something other
+    /// than a link to the dotfile replaced its symlink.
+    fn restore_to(
         &mut self,
-        symlink_dir: &SymlinkDir,
-        dotfile_dir: &DotfilesDir,
+        path: &DotfilePath,
+        dir: &Path,
+        options: &RestoreOptions,
     ) -> Result<()> {
-        if !dotfile_dir.exists() {
-            return Err(Error::BadInput("path does not exist"));
-        } else if !dotfile_dir.is_dir() {
-            return Err(Error::BadInput("path must be a directory"));
-        };
+        let dotfile_path = self.store_path(path);
+        let symlink_path = self.symlink_path(path);
+        let destination = dir.join(&**path);
+        if dotfile_path.symlink_metadata().is_err() {
+            return Err(Error::PathDoesNotExist(dotfile_path));
+        }
+        if destination.symlink_metadata().is_ok() {
+            return Err(Error::RestoreDestinationTaken(destination));
+        }
 
-        if !symlink_dir.exists() {
-            return Err(Error::BadInput("path does not exist"));
-        } else if !symlink_dir.is_dir() {
-            return Err(Error::BadInput("path must be a directory"));
+        let linked = match symlink_path.symlink_metadata() {
+            _ if self.is_copied(path) => false,
+            Ok(md) if md.is_symlink() && self.links_to(&symlink_path, path)? => true,
+            Ok(_) if paths::same_inode(&symlink_path, &dotfile_path)? => true,
+            Ok(_) => {
+                return Err(Error::SymlinkPathDoesNotMatchDotfilePath(
+                    symlink_path,
+                    dotfile_path,
+                ))
+            },
+            Err(_) => false,
         };
 
-        self.dotfiles.push(Dotfiles {
-            dotfile_directory: dotfile_dir.clone(),
-            symlink_directory: symlink_dir.clone(),
-            paths: BTreeSet::new(),
-        });
+        if options.dry_run {
+            log::info!(
+                "would move {0} to {1}",
+                path.display(),
+                destination.display()
+            );
+            return Ok(());
+        }
+        self.remove_aliases(path)?;
+        if linked {
+            fs::remove_file(&symlink_path)?;
+        }
+        let _ = paths::create_parent_dirs(&destination)?;
+        paths::move_file(&dotfile_path, &destination)?;
+        sync_dirs(
+            options.durable,
+            &[&dotfile_path, &destination, &symlink_path],
+        )?;
+        log::info!("moved {0} to {1}", path.display(), destination.display());
+        let _ = self.untrack(path);
+        self.clean_store(&dotfile_path, options)
+    }
 
-        self.write_toml_config()?;
+    /// Remove the directories of the store left empty by moving the dotfile at `dotfile_path`
+    /// out of it, if `options` ask for it.
+    fn clean_store(&self, dotfile_path: &Path, options: &RestoreOptions) -> Result<()> {
+        if !options.clean_store {
+            return Ok(());
+        }
+        for dir in paths::remove_empty_parents(dotfile_path, &self.dotfile_directory)? {
+            log::info!("removed empty store directory {0}", dir.display());
+        }
         Ok(())
     }
 
-    /// Search `$HOME` and `$XDG_CONFIG_HOME` for config file path.
-    fn get_config_file() -> Option<PathBuf> {
-        let config_path = Self::config_file_path().ok()?;
+    /// Remove the symlinks deployed for the other names of the given dotfile, leaving alone
+    /// whatever else took their place.
+    fn remove_aliases(&self, path: &DotfilePath) -> Result<()> {
+        for alias in self.alias_paths(path) {
+            if !alias.is_symlink() || !self.links_to(&alias, path)? {
+                let alias = alias.display();
+                log::warn!("leaving {0} alone, it doesn't link to its dotfile", alias);
+                continue;
+            }
+            log::info!("removing symlink {0}", alias.display());
+            fs::remove_file(&alias)?;
+        }
+        Ok(())
+    }
 
-        if config_path.exists() {
-            return Some(config_path);
-        };
-        None
+    /// Restore every dotfile in this set of dotfiles, returning the number of restored dotfiles.
+    /// Dotfiles whose symlink is already gone are considered restored and are only dropped from
+    /// `paths`. Dotfiles matching the `ignore_patterns` are left alone and stay tracked.
+    pub(crate) fn restore_all(&mut self, options: &RestoreOptions) -> Result<usize> {
+        let mut count = 0;
+        let paths: Vec<DotfilePath> = self.apply_ignore()?.cloned().collect();
+        for path in &paths {
+            let symlink_path = self.symlink_path(path);
+            if symlink_path.symlink_metadata().is_err() && options.to.is_none() {
+                log::info!("{0} is already restored", symlink_path.display());
+                self.remove_aliases(path)?;
+                let _ = self.untrack(path);
+                continue;
+            }
+            log::debug!("restoring {0}", symlink_path.display());
+            let _ = self.restore_dotfile(path, options)?;
+            count += 1;
+        }
+        Ok(count)
     }
 
-    fn config_file_path() -> Result<PathBuf> {
-        Ok(config_dir()
-            .ok_or(Error::CannotDetermineConfigDir)?
-            .join("ghmd")
-            .join("config.toml"))
+    // Deploy a dotfile from the dotfile store to the user's home directory.
+    pub(crate) fn deploy(
+        &self,
+        path: &DotfilePath,
+        options: &DeployOptions,
+    ) -> Result<DeployOutcome> {
+        self.deploy_to(path, &self.deploy_path(path, options)?, options)
     }
 
-    fn data_dir() -> Result<PathBuf> {
-        Ok(data_dir()
-            .ok_or(Error::CannotDetermineDataDir)?
-            .join("ghmd"))
+    /// Where deploying the given dotfile with `options` puts its symlink, see
+    /// [`DeployOptions::expand_dest`].
+    pub(crate) fn deploy_path(
+        &self,
+        path: &DotfilePath,
+        options: &DeployOptions,
+    ) -> Result<PathBuf> {
+        match options.expand_dest || self.expand_dest {
+            true => paths::expand_env(&self.unexpanded_symlink_path(path)),
+            false => Ok(self.symlink_path(path)),
+        }
     }
 
-    /// Save configuration variables to `$XDG_CONFIG_HOME/ghmd/config.toml`.
-    pub fn write_toml_config(&self) -> Result<()> {
-        let config_file_path = Self::config_file_path()?;
-        fs::create_dir_all(
-            config_file_path
-                .parent()
-                .ok_or(Error::CannotDetermineConfigDir)?,
-        )?;
-        let toml = toml::to_string_pretty(&self).unwrap();
-        let mut file = File::create(config_file_path)?;
+    /// Deploy a dotfile like [`Dotfiles::deploy`], but create its symlink at `symlink_path`.
+    pub(crate) fn deploy_to(
+        &self,
+        path: &DotfilePath,
+        symlink_path: &Path,
+        options: &DeployOptions,
+    ) -> Result<DeployOutcome> {
+        if !self.dotfile_directory.exists() {
+            return Err(Error::PathDoesNotExist(
+                self.dotfile_directory.to_path_buf(),
+            ));
+        }
+        let dotfile_path = self.store_path(path);
+
+        // if the given dotfile_path doesn't exist at this point then it's definitely not
+        // legit, and if it's a symlink to nothing then linking to it would be pointless
+        if !dotfile_path.exists() {
+            if dotfile_path.symlink_metadata().is_ok() {
+                return Err(Error::StoreEntryBroken(dotfile_path));
+            }
+            return Err(Error::DotfileNotFound(dotfile_path));
+        }
 
-        file.write_all(&toml.into_bytes())?;
-        file.sync_data()?;
+        // don't try deploying a dotfile that we don't "own"
+        if !self.has_path(path) {
+            return Err(Error::NoMatchingDotfileConfigured(dotfile_path));
+        }
 
-        Ok(())
+        let symlink_path = symlink_path.to_path_buf();
+        if self.is_copied(path) {
+            log::debug!(
+                "{0} is copy-tracked, leaving it alone",
+                symlink_path.display()
+            );
+            return Ok(DeployOutcome::Unchanged);
+        }
+        if symlink_path.parent().is_none() {
+            return Err(Error::InvalidSymlinkDestinationDirectory(symlink_path));
+        }
+        if options.dry_run {
+            if symlink_path.symlink_metadata().is_ok() {
+                return self.deploy_over(path, &symlink_path, &dotfile_path, options);
+            }
+            log::info!(
+                "would create {0} -> {1}",
+                symlink_path.display(),
+                dotfile_path.display()
+            );
+            let created_dirs = paths::missing_parent_dirs(&symlink_path);
+            return Ok(DeployOutcome::Created { created_dirs });
+        }
+        let created_dirs = paths::create_parent_dirs(&symlink_path)?;
+
+        // creating the link right away and only examining what's in the way if that fails
+        // leaves no window for another process to create something at the symlink path
+        // between checking for it and creating the link
+        let mode = options.link_mode.unwrap_or(self.link_mode);
+        match self.link_as(mode, &self.target_for(path), &symlink_path) {
+            Err(Error::StdIOError(e)) if e.kind() == io::ErrorKind::AlreadyExists => {
+                self.deploy_over(path, &symlink_path, &dotfile_path, options)
+            },
+            Err(e) => Err(e),
+            Ok(()) => {
+                log::info!(
+                    "created {0} -> {1}",
+                    self.display_path(&symlink_path),
+                    self.display_path(&dotfile_path)
+                );
+                sync_dirs(options.durable, &[&symlink_path])?;
+                Ok(DeployOutcome::Created { created_dirs })
+            },
+        }
     }
 
-    fn backup(&self, path: &PathBuf) -> Result<()> {
-        let backup_path = &self.backup_directory.join(
-            path.file_name()
-                .ok_or(Error::UnexpectedError("couldn't get filename from path"))?,
-        );
-        log::debug!("backing up {0} to {1}", path.display(), backup_path.display());
-        fs::create_dir_all(&self.backup_directory)?;
-        paths::move_file(path, &backup_path)?;
-        Ok(())
+    /// Deploy `path` given that something already exists at its `symlink_path`: the expected
+    /// symlink or a hardlink of the dotfile is left alone, with `options.force` other symlinks
+    /// are replaced and anything else is an error.
+    fn deploy_over(
+        &self,
+        path: &DotfilePath,
+        symlink_path: &PathBuf,
+        dotfile_path: &Path,
+        options: &DeployOptions,
+    ) -> Result<DeployOutcome> {
+        let md = symlink_path.symlink_metadata()?;
+        if !md.is_symlink() && !paths::same_file_type(symlink_path, dotfile_path)? {
+            return Err(Error::StorePathTypeMismatch(symlink_path.clone()));
+        }
+        if !md.is_symlink() && paths::same_inode(symlink_path, dotfile_path)? {
+            log::debug!("unchanged hardlink {0}", symlink_path.display());
+            return Ok(DeployOutcome::Unchanged);
+        }
+        if !md.is_symlink() {
+            // will need to backup higher in the stack
+            return Err(Error::SymlinkPathIsNotASymlink(symlink_path.clone()));
+        }
+        if self.links_to(symlink_path, path)? {
+            log::debug!("unchanged {0}", symlink_path.display());
+            return Ok(DeployOutcome::Unchanged);
+        }
+        // we reach this point if the path is a symlink but it doesn't point to the expected
+        // dotfile. in that case, return an error unless it may be replaced
+        if !options.force {
+            return Err(Error::SymlinkPathAlreadyExists(symlink_path.clone()));
+        }
+        if options.dry_run {
+            log::info!("would replace symlink {0}", symlink_path.display());
+            return Ok(DeployOutcome::Created {
+                created_dirs: Vec::new(),
+            });
+        }
+        log::info!("replacing symlink {0}", symlink_path.display());
+        fs::remove_file(symlink_path)?;
+        let mode = options.link_mode.unwrap_or(self.link_mode);
+        self.link_as(mode, &self.target_for(path), symlink_path)?;
+        sync_dirs(options.durable, &[symlink_path])?;
+        Ok(DeployOutcome::Created {
+            created_dirs: Vec::new(),
+        })
     }
-}
+
+    /// Return the dotfiles not left out by [`Dotfiles::apply_ignore`] that were modified after
+    /// `since`. Dotfiles missing from the store are left out with a warning, since there is
+    /// nothing to deploy for them.
+    pub(crate) fn changed_since(&self, since: SystemTime) -> Result<Vec<&DotfilePath>> {
+        let mut changed = Vec::new();
+        for path in self.apply_ignore()? {
+            let store_path = self.store_path(path);
+            match paths::modified(&store_path) {
+                Ok(modified) if modified > since => changed.push(path),
+                Ok(_) => (),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    log::warn!("skipping {0}, it is missing", store_path.display());
+                },
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Deploy only the dotfiles that were modified after `since`, returning the deployed paths.
+    /// Unlike checking the state of every symlink this only requires the metadata of the stored
+    /// dotfiles.
+    pub fn deploy_changed(&self, since: SystemTime) -> Result<Vec<&DotfilePath>> {
+        let changed = self.changed_since(since)?;
+        for path in &changed {
+            log::debug!("deploying changed path {0}", path.display());
+            let _ = self.deploy(path, &DeployOptions::new())?;
+        }
+        Ok(changed)
+    }
+
+    /// Recreate the deployed symlinks of this set of dotfiles so that they point at their dotfiles
+    /// using `style`, and use `style` for symlinks created from now on. Each symlink keeps
+    /// pointing at the same path and is replaced atomically. Symlinks pointing somewhere other
+    /// than their dotfile are left alone and reported in the returned summary.
+    pub fn convert_links(&mut self, style: LinkStyle) -> Result<ConvertSummary> {
+        let mut summary = ConvertSummary::default();
+        if self.link_type == LinkType::Junction {
+            log::warn!(
+                "skipping {0}, junctions always use absolute targets",
+                self.dotfile_directory.display()
+            );
+            return Ok(summary);
+        }
+
+        for path in self.paths.iter().filter(|path| !self.is_copied(path)) {
+            let symlink_path = self.symlink_path(path);
+            match symlink_path.symlink_metadata() {
+                Ok(metadata) if metadata.is_symlink() => {},
+                _ => continue,
+            }
+            if !self.links_to(&symlink_path, path)? {
+                summary.skipped.push(symlink_path);
+                continue;
+            }
+            let absolute = fs::read_link(&symlink_path)?.is_absolute();
+            if absolute == (style == LinkStyle::Absolute) {
+                summary.unchanged += 1;
+                continue;
+            }
+
+            let mut target = paths::resolve_link(&symlink_path)?;
+            if style == LinkStyle::Relative {
+                let symlink_dir = symlink_path.parent().unwrap_or(Path::new("/"));
+                target = paths::relative_path(symlink_dir, &target);
+            }
+            paths::replace_symlink(&target, &symlink_path)?;
+            log::info!(
+                "converted {0} -> {1}",
+                symlink_path.display(),
+                target.display()
+            );
+            summary.converted += 1;
+        }
+        self.link_style = style;
+        Ok(summary)
+    }
+
+    /// Recreate the deployed symlinks that point into `old_dir`, where the dotfiles directory
+    /// was located before it was moved to its current location, so that they point at their
+    /// dotfiles again. Symlinks pointing anywhere else are left alone.
+    pub fn relink_moved(&self, old_dir: &Path) -> Result<RelinkSummary> {
+        let mut summary = RelinkSummary::default();
+        for path in self.paths.iter().filter(|path| !self.is_copied(path)) {
+            let stored = self.store_path(path).symlink_metadata().is_ok();
+            for symlink_path in self.all_symlink_paths(path) {
+                match symlink_path.symlink_metadata() {
+                    Ok(metadata) if metadata.is_symlink() => {},
+                    _ => continue,
+                }
+                if self.links_to(&symlink_path, path)? {
+                    summary.unchanged += 1;
+                    continue;
+                }
+                if !stored || !paths::resolve_link(&symlink_path)?.starts_with(old_dir) {
+                    summary.skipped.push(symlink_path);
+                    continue;
+                }
+
+                self.relink(path, &symlink_path)?;
+                log::info!("relinked {0}", symlink_path.display());
+                summary.relinked += 1;
+            }
+        }
+        Ok(summary)
+    }
+
+    /// Point the symlink at `symlink_path` at the given dotfile, replacing it atomically so that
+    /// there is always something at `symlink_path`. Junctions and hardlinks can't be swapped in
+    /// place, so for those the symlink is removed first.
+    fn relink(&self, path: &DotfilePath, symlink_path: &PathBuf) -> Result<()> {
+        let target = self.target_for(path);
+        if self.link_type == LinkType::Junction || self.link_mode == LinkMode::Hardlink {
+            fs::remove_file(symlink_path)?;
+            return self.link(&target, symlink_path);
+        }
+        let relative = self.link_mode == LinkMode::RelativeSymlink
+            || self.link_style == LinkStyle::Relative;
+        let target = if relative {
+            let symlink_dir = symlink_path.parent().unwrap_or(Path::new("/"));
+            paths::relative_path(symlink_dir, &target)
+        } else {
+            target
+        };
+        paths::replace_symlink(&target, symlink_path)?;
+        Ok(())
+    }
+
+    /// Create a symlink at `symlink_path` pointing to `dotfile_path` according to the configured
+    /// `link_style`, a junction if `link_type` asks for one, or a hardlink if `link_mode` does.
+    fn link(&self, dotfile_path: &PathBuf, symlink_path: &PathBuf) -> Result<()> {
+        self.link_as(self.link_mode, dotfile_path, symlink_path)
+    }
+
+    /// Whether stowing with `options` replaces files with hardlinks rather than symlinks.
+    fn stows_hardlinks(&self, options: &StowOptions) -> bool {
+        options.link_style.is_none() && self.link_mode == LinkMode::Hardlink
+    }
+
+    /// Like [`Dotfiles::link`], but linking in the given mode.
+    fn link_as(
+        &self,
+        mode: LinkMode,
+        dotfile_path: &PathBuf,
+        symlink_path: &PathBuf,
+    ) -> Result<()> {
+        match mode {
+            LinkMode::Symlink => {
+                self.link_styled(self.link_style, dotfile_path, symlink_path)
+            },
+            LinkMode::RelativeSymlink => {
+                self.link_styled(LinkStyle::Relative, dotfile_path, symlink_path)
+            },
+            LinkMode::Hardlink if dotfile_path.is_dir() => {
+                Err(Error::HardlinkToDirectory(dotfile_path.clone()))
+            },
+            LinkMode::Hardlink => Ok(paths::hardlink_file(dotfile_path, symlink_path)?),
+        }
+    }
+
+    /// Like [`Dotfiles::link`], but creating symlinks in the given style.
+    fn link_styled(
+        &self,
+        style: LinkStyle,
+        dotfile_path: &PathBuf,
+        symlink_path: &PathBuf,
+    ) -> Result<()> {
+        if self.link_type == LinkType::Junction {
+            return paths::create_junction(dotfile_path, symlink_path);
+        }
+        match style {
+            LinkStyle::Absolute => paths::create_symlink(dotfile_path, symlink_path)?,
+            LinkStyle::Relative => {
+                paths::create_symlink_relative(dotfile_path, symlink_path)?
+            },
+        }
+        Ok(())
+    }
+
+    /// Validate stowing `stow_path` without touching the filesystem and determine what it
+    /// involves.
+    pub(crate) fn check_stow(&self, stow_path: &DotfilePath) -> Result<StowAction> {
+        log::debug!("");
+        log::debug!("stow_path: {:?}", stow_path);
+        let symlink_path = self.symlink_path(stow_path);
+        log::debug!("");
+        log::debug!("symlink_directory: {:?}", self.symlink_dir());
+        log::debug!("symlink_path: {:?}", symlink_path);
+        log::debug!(
+            "canonicalized symlink_path: {:?}",
+            symlink_path.canonicalize()?
+        );
+
+        let dotfile_path = self.store_path(stow_path);
+        log::debug!("");
+        log::debug!("dotfile_directory: {:?}", self.dotfile_directory);
+        log::debug!("dotfile_path: {:?}", dotfile_path);
+
+        if dotfile_path.try_exists()? {
+            // a symlink already pointing at the stored dotfile is left over from an earlier stow
+            // whose config entry was lost, so only the path needs to be tracked again
+            let is_symlink = symlink_path.symlink_metadata()?.is_symlink();
+            if is_symlink
+                && (self.links_to(&symlink_path, stow_path)?
+                    || symlink_path.canonicalize()? == dotfile_path.canonicalize()?)
+            {
+                return Ok(StowAction::Adopt);
+            }
+
+            if !paths::same_file_type(&symlink_path, &dotfile_path)? {
+                return Err(Error::StorePathTypeMismatch(dotfile_path));
+            }
+
+            return Err(Error::DotfilePathAlreadyExists(stow_path.to_path_buf()));
+        }
+
+        let _ = symlink_path.try_exists()?;
+        Ok(StowAction::Move)
+    }
+
+    /// Move the file at `stow_path` in the symlink directory into the store and replace it with a
+    /// symlink, returning whether it was adopted or moved. With `dry_run` every check is made but
+    /// nothing is moved or tracked.
+    fn stow_path(
+        &mut self,
+        stow_path: &DotfilePath,
+        options: &StowOptions,
+    ) -> Result<StowAction> {
+        let symlink_path = self.symlink_path(stow_path);
+        let dotfile_path = self.store_path(stow_path);
+        if self.stows_hardlinks(options) {
+            if symlink_path.is_dir() {
+                return Err(Error::HardlinkToDirectory(symlink_path));
+            }
+            self.check_hardlinkable(stow_path)?;
+        }
+        match self.check_stow(stow_path)? {
+            StowAction::Adopt if options.dry_run => {
+                log::info!("would adopt existing link {0}", symlink_path.display());
+                return Ok(StowAction::Adopt);
+            },
+            StowAction::Adopt => {
+                log::info!("adopted existing link {0}", symlink_path.display());
+                let _ = self.paths.insert(stow_path.clone());
+                return Ok(StowAction::Adopt);
+            },
+            StowAction::Move if options.dry_run => {
+                log::info!(
+                    "would move {0} to {1} and link it back",
+                    symlink_path.display(),
+                    dotfile_path.display()
+                );
+                return Ok(StowAction::Move);
+            },
+            StowAction::Move => {},
+        }
+        log::debug!("creating symlink0");
+        // paths below a token never have their directories in the store yet
+        let _ = paths::create_parent_dirs(&dotfile_path)?;
+        paths::move_file(&symlink_path, &dotfile_path)?;
+        log::debug!("creating symlink5");
+        self.link_stowed(stow_path, &symlink_path, options)?;
+        Ok(StowAction::Move)
+    }
+
+    /// Move the file at `source`, which may be located anywhere, into the store as `path` and put
+    /// a symlink to it at the location of `path` in the symlink directory. With `dry_run` every
+    /// check is made but nothing is moved or tracked.
+    fn stow_from(
+        &mut self,
+        source: &Path,
+        path: &DotfilePath,
+        options: &StowOptions,
+    ) -> Result<()> {
+        if source.symlink_metadata().is_err() {
+            return Err(Error::PathDoesNotExist(source.to_path_buf()));
+        }
+        if self.stows_hardlinks(options) {
+            if source.is_dir() {
+                return Err(Error::HardlinkToDirectory(source.to_path_buf()));
+            }
+            self.check_hardlinkable(path)?;
+        }
+        let symlink_path = self.symlink_path(path);
+        let dotfile_path = self.store_path(path);
+        if dotfile_path.symlink_metadata().is_ok() {
+            return Err(Error::DotfilePathAlreadyExists(path.to_path_buf()));
+        }
+        if symlink_path.symlink_metadata().is_ok() {
+            return Err(Error::SymlinkPathAlreadyExists(symlink_path));
+        }
+        if options.dry_run {
+            log::info!(
+                "would move {0} to {1} and link it at {2}",
+                source.display(),
+                dotfile_path.display(),
+                symlink_path.display()
+            );
+            return Ok(());
+        }
+
+        let _ = paths::create_parent_dirs(&dotfile_path)?;
+        let _ = paths::create_parent_dirs(&symlink_path)?;
+        paths::move_file(&source.to_path_buf(), &dotfile_path)?;
+        self.link_stowed(path, source, options)
+    }
+
+    /// Refuse to hardlink the dotfile `path` when the store and the symlink directory are on
+    /// different filesystems, before anything is moved.
+    fn check_hardlinkable(&self, path: &DotfilePath) -> Result<()> {
+        let (dotfile_path, symlink_path) =
+            (self.store_path(path), self.symlink_path(path));
+        if !paths::same_device(&dotfile_path, &symlink_path)? {
+            return Err(Error::HardlinkAcrossDevices(dotfile_path, symlink_path));
+        }
+        Ok(())
+    }
+
+    /// Link the dotfile that was just moved into the store as `stow_path` from `source` and track
+    /// it.
+    fn link_stowed(
+        &mut self,
+        stow_path: &DotfilePath,
+        source: &Path,
+        options: &StowOptions,
+    ) -> Result<()> {
+        let symlink_path = self.symlink_path(stow_path);
+        let target = self.target_for(stow_path);
+        let linked = match options.link_style {
+            Some(style) => self.link_styled(style, &target, &symlink_path),
+            None => self.link(&target, &symlink_path),
+        };
+        match linked {
+            // something was created at the symlink path after the file was moved away from
+            // it, which is fine if it's the link we wanted. otherwise keep the moved file
+            // tracked so it can be deployed once the conflict is resolved
+            Err(Error::StdIOError(e)) if e.kind() == io::ErrorKind::AlreadyExists => {
+                let _ = self.paths.insert(stow_path.clone());
+                if !symlink_path.symlink_metadata()?.is_symlink()
+                    || !self.links_to(&symlink_path, stow_path)?
+                {
+                    return Err(Error::SymlinkPathAlreadyExists(symlink_path));
+                }
+            },
+            // a hardlink can't be deployed later either, so put the file back where it came from
+            Err(e) if self.stows_hardlinks(options) => {
+                let _ = self.untrack(stow_path);
+                let dotfile_path = self.store_path(stow_path);
+                if let Err(move_err) =
+                    paths::move_file(&dotfile_path, &source.to_path_buf())
+                {
+                    log::error!(
+                        "failed to move {0} back to {1}: {2}",
+                        dotfile_path.display(),
+                        source.display(),
+                        move_err
+                    );
+                }
+                return Err(e);
+            },
+            result => result?,
+        }
+
+        log::debug!("stowed path: {:?}", stow_path);
+        sync_dirs(
+            options.durable,
+            &[&self.store_path(stow_path), &symlink_path],
+        )?;
+
+        let _ = self.paths.insert(stow_path.clone());
+        Ok(())
+    }
+
+    /// Copy the file at `path` in the symlink directory into the store and track it without
+    /// replacing the original with a symlink.
+    fn copy_path(&mut self, path: &DotfilePath) -> Result<()> {
+        let source = self.symlink_path(path);
+        let dotfile_path = self.store_path(path);
+        if dotfile_path.symlink_metadata().is_ok() {
+            return Err(Error::DotfilePathAlreadyExists(path.to_path_buf()));
+        }
+        let _ = paths::create_parent_dirs(&dotfile_path)?;
+        log::debug!(
+            "copying {0} to {1}",
+            source.display(),
+            dotfile_path.display()
+        );
+        if source.is_dir() {
+            let mut opts = fs_extra::dir::CopyOptions::new();
+            opts.copy_inside = true;
+            let _ = fs_extra::dir::copy(&source, &dotfile_path, &opts)?;
+        } else {
+            let _ = fs::copy(&source, &dotfile_path)?;
+        }
+        let _ = self.paths.insert(path.clone());
+        let _ = self.copied_paths.insert(path.clone());
+        Ok(())
+    }
+
+    /// Rename the tracked dotfile `old` to `new` within `dotfile_directory`, replacing the symlink
+    /// for `old`, and those for its other names, with symlinks for `new`. Anything at the old
+    /// locations that doesn't link to the dotfile is left alone.
+    ///
+    /// Nothing is changed if `new` leaves the directories, is tracked already or something is in
+    /// the way of one of its symlinks. If moving fails partway, the dotfile and its symlinks are
+    /// moved back.
+    pub fn move_path(&mut self, old: &DotfilePath, new: DotfilePath) -> Result<()> {
+        if !self.paths.contains(old) {
+            return Err(Error::DotfileNotFound(old.to_path_buf()));
+        }
+        let escapes = new.components().any(|component| {
+            !matches!(component, Component::Normal(_) | Component::CurDir)
+        });
+        let new = DotfilePath(paths::normalize(&new));
+        if escapes || new.as_os_str().is_empty() {
+            return Err(Error::BadInput(
+                "the new path must be a relative path inside the dotfiles directory",
+            ));
+        }
+        if self.paths.contains(&new) {
+            return Err(Error::DotfilePathAlreadyExists(new.to_path_buf()));
+        }
+
+        let old_dotfile_path = self.store_path(old);
+        if old_dotfile_path.symlink_metadata().is_err() {
+            return Err(Error::PathDoesNotExist(old_dotfile_path));
+        }
+        let new_dotfile_path = self.store_path(&new);
+        if new_dotfile_path.symlink_metadata().is_ok() {
+            return Err(Error::DotfilePathAlreadyExists(new.to_path_buf()));
+        }
+
+        if self.copied_paths.contains(old) {
+            // copied dotfiles have no symlink to move
+            self.relocate(old, &new, &[], &[])?;
+            let _ = self.copied_paths.remove(old);
+            let _ = self.copied_paths.insert(new.clone());
+            let _ = self.paths.remove(old);
+            let _ = self.paths.insert(new);
+            return Ok(());
+        }
+
+        let mut old_links = Vec::new();
+        for symlink_path in self.all_symlink_paths(old) {
+            if self.is_linked_at(&symlink_path, old)? {
+                old_links.push(symlink_path);
+            } else if symlink_path.symlink_metadata().is_ok() {
+                let symlink_path = symlink_path.display();
+                log::warn!(
+                    "leaving {0} alone, it doesn't link to its dotfile",
+                    symlink_path
+                );
+            }
+        }
+        // the other names follow the dotfile into its new directory
+        let new_symlink_path = self.symlink_path(&new);
+        let mut new_links = vec![new_symlink_path.clone()];
+        new_links.extend(
+            self.symlink_names
+                .get(old)
+                .into_iter()
+                .flatten()
+                .map(|name| new_symlink_path.with_file_name(name)),
+        );
+        for symlink_path in &new_links {
+            if symlink_path.symlink_metadata().is_ok()
+                && !old_links.contains(symlink_path)
+            {
+                return Err(Error::SymlinkPathAlreadyExists(symlink_path.clone()));
+            }
+        }
+
+        self.relocate(old, &new, &old_links, &new_links)?;
+        if let Some(names) = self.symlink_names.remove(old) {
+            let _ = self.symlink_names.insert(new.clone(), names);
+        }
+        let _ = self.paths.remove(old);
+        let _ = self.paths.insert(new);
+        Ok(())
+    }
+
+    /// Move the stored dotfile `old` to `new` and replace the links at `old_links` with links to
+    /// `new` at `new_links`. If any step fails, the steps taken so far are undone.
+    fn relocate(
+        &self,
+        old: &DotfilePath,
+        new: &DotfilePath,
+        old_links: &[PathBuf],
+        new_links: &[PathBuf],
+    ) -> Result<()> {
+        let old_dotfile_path = self.store_path(old);
+        let new_dotfile_path = self.store_path(new);
+        let mut created_dirs = paths::create_parent_dirs(&new_dotfile_path)?;
+        if let Err(e) = paths::move_file(&old_dotfile_path, &new_dotfile_path) {
+            for dir in created_dirs.iter().rev() {
+                let _ = fs::remove_dir(dir);
+            }
+            return Err(e);
+        }
+
+        let mut removed = Vec::new();
+        let mut linked = Vec::new();
+        let mut moved = || -> Result<()> {
+            for symlink_path in old_links {
+                log::debug!("removing link {0}", symlink_path.display());
+                fs::remove_file(symlink_path)?;
+                removed.push(symlink_path);
+            }
+            let target = self.target_for(new);
+            for symlink_path in new_links {
+                created_dirs.extend(paths::create_parent_dirs(symlink_path)?);
+                self.link(&target, symlink_path)?;
+                linked.push(symlink_path);
+            }
+            Ok(())
+        };
+        let Err(e) = moved() else {
+            return Ok(());
+        };
+
+        log::warn!("moving {0} failed, moving it back", old.display());
+        let undo = || -> Result<()> {
+            for symlink_path in &linked {
+                fs::remove_file(symlink_path)?;
+            }
+            paths::move_file(&new_dotfile_path, &old_dotfile_path)?;
+            let target = self.target_for(old);
+            for symlink_path in &removed {
+                self.link(&target, symlink_path)?;
+            }
+            Ok(())
+        };
+        if let Err(undo_err) = undo() {
+            log::error!("failed to move {0} back: {1}", old.display(), undo_err);
+        }
+        // innermost first, and only those left empty
+        for dir in created_dirs.iter().rev() {
+            let _ = fs::remove_dir(dir);
+        }
+        Err(e)
+    }
+
+    /// Stow each of the given paths according to `options`, returning the number of stowed
+    /// paths. If any path fails to stow, the paths newly stowed by this call are restored before
+    /// the error is returned: adopted links are only untracked, moved files are moved back.
+    pub(crate) fn stow_multiple(
+        &mut self,
+        stow_paths: &[DotfilePath],
+        options: &StowOptions,
+    ) -> Result<usize> {
+        let mut stowed: Vec<(&DotfilePath, StowAction)> = Vec::new();
+        let force = RestoreOptions::new().force(true);
+        for path in stow_paths {
+            log::debug!("stowing path: {:?}", path);
+            let already_tracked = self.paths.contains(path);
+            let result = observer::observe(
+                options.observer,
+                Action::Stow(path),
+                || self.stow_path(path, options),
+                |_| ActionOutcome::Done,
+            );
+            let action = match result {
+                Ok(action) => action,
+                Err(e) => {
+                    self.roll_back_stows(&stowed, &force);
+                    return Err(e);
+                },
+            };
+            if !already_tracked {
+                stowed.push((path, action));
+            }
+        }
+        Ok(stow_paths.len())
+    }
+
+    /// Undo the stows recorded by [`Dotfiles::stow_multiple`], most recent first. An adopted link
+    /// still points into the store, so it is only untracked; a moved file is moved back.
+    fn roll_back_stows(
+        &mut self,
+        stowed: &[(&DotfilePath, StowAction)],
+        force: &RestoreOptions,
+    ) {
+        for (stowed_path, action) in stowed.iter().rev() {
+            log::info!("rolling back stow of {0}", stowed_path.display());
+            if *action == StowAction::Adopt {
+                let _ = self.untrack(stowed_path);
+                continue;
+            }
+            if let Err(rollback_err) = self.restore_dotfile(stowed_path, force) {
+                log::error!(
+                    "failed to roll back stow of {0}: {1}",
+                    stowed_path.display(),
+                    rollback_err
+                );
+            }
+        }
+    }
+}
+
+impl Config {
+    /// Load a config from disk and return it to caller.
+    pub fn load() -> Result<Self> {
+        let dirs = Dirs::from_env()?;
+        let backup_directory = dirs.data_dir().join(Local::now().to_rfc3339());
+        log::debug!(
+            "setting backup directory to {0}",
+            backup_directory.display()
+        );
+        if let Some(config_path) = Self::get_config_file() {
+            let mut file = File::open(&config_path)?;
+            let mut contents = String::new();
+            let _ = file.read_to_string(&mut contents)?;
+            let mut c: Self = match toml::from_str(&contents) {
+                Ok(c) => c,
+                Err(e) => Self::load_backup(&config_path, e)?,
+            };
+            let compatible = c
+                .ghmd_version
+                .as_ref()
+                .map_or(Ok(()), |written| {
+                    version::check_compatible(&config_path, written)
+                })
+                .and_then(|()| {
+                    c.schema_version.map_or(Ok(()), |written| {
+                        version::check_schema(&config_path, written)
+                    })
+                });
+            if let Err(e) = compatible {
+                // don't clobber a config file we don't understand
+                c.ephemeral = true;
+                return Err(e);
+            }
+            c.loaded_version = c.ghmd_version.replace(version::VERSION.to_string());
+            c.schema_version = Some(version::CONFIG_SCHEMA_VERSION);
+            let hash = remote::hash(contents.as_bytes());
+            c.file_state = Tracked::new(FileState::new(Some(hash), &c.dotfiles));
+            for dotfiles in &mut c.dotfiles {
+                dotfiles.set_link_mode(dotfiles.link_mode);
+            }
+            c.backup_directory = backup_directory;
+            c.skipped = MachineState::load_from(&dirs.state_file())?.skip_patterns()?;
+            c.set_xdg_dirs(dirs);
+            c.hostname = machine::hostname();
+            Ok(c)
+        } else {
+            let mut c = Self::empty(backup_directory);
+            c.file_state.set(FileState::new(None, &[]));
+            c.set_xdg_dirs(dirs);
+            c.hostname = machine::hostname();
+            Ok(c)
+        }
+    }
+
+    /// Parse a config handed over by another ghmd process, e.g. one running as root for
+    /// [`Config::deploy_for_user`]. It is never written to disk, and backups and the skip list
+    /// are those of the user ghmd runs as.
+    pub(crate) fn from_parent(contents: &str) -> Result<Self> {
+        let dirs = Dirs::from_env()?;
+        let mut c: Self = toml::from_str(contents)?;
+        c.ephemeral = true;
+        c.backup_directory = dirs.data_dir().join(Local::now().to_rfc3339());
+        c.skipped = MachineState::load_from(&dirs.state_file())?.skip_patterns()?;
+        c.set_xdg_dirs(dirs);
+        c.hostname = machine::hostname();
+        Ok(c)
+    }
+
+    /// Load a config from disk like [`Config::load`], resolve its symlink directories, see
+    /// [`Config::resolve_symlink_dirs`], and check that it is consistent, see
+    /// [`Config::check_consistency`].
+    pub fn load_and_validate() -> Result<Self> {
+        let mut config = Self::load()?;
+        config.resolve_symlink_dirs()?;
+        config.check_consistency()?;
+        Ok(config)
+    }
+
+    /// Check that the config is consistent, see [`Config::verify_integrity`] and
+    /// [`Config::validate`]. The first inconsistency found is returned as
+    /// [`Error::InvalidConfig`] naming the config file.
+    pub fn check_consistency(&self) -> Result<()> {
+        if let Err(e) = self.verify_integrity().and_then(|()| self.validate()) {
+            return Err(Error::InvalidConfig(Self::file_path()?, Box::new(e)));
+        }
+        Ok(())
+    }
+
+    /// Run the `symlink_dir_command` of every enabled dotfiles entry that has one, see
+    /// [`Dotfiles::symlink_dir`]. Loading a config doesn't run them, so that commands which
+    /// never look at the symlink directories don't pay for them; until they are resolved the
+    /// configured `symlink_directory` is used.
+    pub fn resolve_symlink_dirs(&mut self) -> Result<()> {
+        for dotfiles in self.dotfiles.iter_mut().filter(|dotfiles| dotfiles.enabled) {
+            if dotfiles.resolved_symlink_directory.is_none() {
+                dotfiles.resolve_symlink_dir_command()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Parse the backup of the config file at `config_path` after the config file itself failed
+    /// to parse with `error`. The config file is replaced with the backup the next time it is
+    /// written. If the backup can't be used either, `error` is returned.
+    fn load_backup(config_path: &Path, error: toml::de::Error) -> Result<Self> {
+        let backup_path = Self::backup_file_path(config_path);
+        let backup = match fs::read_to_string(&backup_path) {
+            Ok(contents) => toml::from_str::<Self>(&contents).ok(),
+            Err(_) => None,
+        };
+        let c = match backup {
+            Some(c) => c,
+            None => return Err(error.into()),
+        };
+        log::warn!("{0} is corrupt: {1}", config_path.display(), error);
+        log::warn!(
+            "using the previous config from {0}; {1} will be overwritten with it",
+            backup_path.display(),
+            config_path.display()
+        );
+        Ok(c)
+    }
+
+    /// Determine which dotfiles entries could own `path` and what the corresponding dotfile path
+    /// would be for each of them.
+    ///
+    /// Relative paths are taken as-is for every entry. Absolute paths may point either into a
+    /// `symlink_directory` or into a `dotfile_directory`; if a path is located under both kinds of
+    /// directory, the symlink-side interpretations are ordered first.
+    pub fn resolve_candidates(&self, path: &Path) -> Vec<(&Dotfiles, DotfilePath)> {
+        if path.is_relative() {
+            return self
+                .dotfiles
+                .iter()
+                .map(|dotfiles| (dotfiles, DotfilePath(path.to_path_buf())))
+                .collect();
+        }
+
+        let symlink_side: Vec<(&Dotfiles, DotfilePath)> = self
+            .dotfiles
+            .iter()
+            .filter_map(|dotfiles| Some((dotfiles, dotfiles.strip_symlink_dir(path)?)))
+            .collect();
+        let store_side: Vec<(&Dotfiles, DotfilePath)> = self
+            .dotfiles
+            .iter()
+            .filter_map(|dotfiles| Some((dotfiles, dotfiles.strip_dotfile_dir(path)?)))
+            .collect();
+
+        if !symlink_side.is_empty() && !store_side.is_empty() {
+            log::info!(
+                "{0} is located in both a symlink directory and a dotfiles directory, \
+                 preferring to treat it as a symlink path",
+                path.display()
+            );
+        }
+
+        symlink_side.into_iter().chain(store_side).collect()
+    }
+
+    /// Resolve `path` to the relative dotfile path it refers to, accepting relative paths as well
+    /// as absolute paths inside either a symlink directory or a dotfiles directory.
+    pub fn resolve_dotfile_path(&self, path: &Path) -> Result<DotfilePath> {
+        self.resolve_candidates(path)
+            .into_iter()
+            .find(|(dotfiles, dotfile_path)| dotfiles.paths.contains(dotfile_path))
+            .map(|(_, dotfile_path)| dotfile_path)
+            .ok_or_else(|| Error::NoMatchingDotfileConfigured(path.to_path_buf()))
+    }
+
+    /// Deploy specified dotfiles.
+    pub fn deploy_paths(&self, paths: Vec<PathBuf>) -> Result<DeploySummary> {
+        self.deploy_paths_with(paths, &DeployOptions::new())
+    }
+
+    /// Deploy specified dotfiles according to `options`. Skipped conflicts are counted in neither
+    /// field of the returned summary.
+    pub fn deploy_paths_with(
+        &self,
+        paths: Vec<PathBuf>,
+        options: &DeployOptions,
+    ) -> Result<DeploySummary> {
+        let mut summary = DeploySummary::default();
+        'paths: for path in paths.iter() {
+            log::debug!("deploying {:?}", path);
+            for (dotfiles, dotfile_path) in self.resolve_candidates(path) {
+                match self.deploy_dotfile(dotfiles, &dotfile_path, options) {
+                    Err(Error::DotfileNotFound(_)) => continue,
+                    Err(Error::NoMatchingDotfileConfigured(_)) => continue,
+                    Err(e) if options.skip_conflicts && e.is_conflict() => {
+                        log::warn!("skipping {0}: {1}", path.display(), e);
+                        continue 'paths;
+                    },
+                    Err(e) => return Err(e),
+                    Ok(outcome) => {
+                        if self.is_skipped(&dotfile_path) {
+                            log::warn!(
+                                "deployed {0} even though it is skipped on this machine",
+                                dotfile_path.display()
+                            );
+                        }
+                        summary.add(outcome);
+                        continue 'paths;
+                    },
+                };
+            }
+            // if we reach this point then the current `path` hasn't been found. that's a whoopin'
+            //
+            // TODO: should we fail on the first dotfile that doesn't match? or should this be
+            // all-or-nothing where if any specified dotfile doesn't match an existing dotfile then
+            // we cowardly refuse to do anything
+            //
+            // i'm leaning toward all-or nothing, but that would ideally be a separate check from
+            // the deployment step
+            return Err(Error::NoMatchingDotfileConfigured(path.clone()));
+        }
+        Ok(summary)
+    }
+
+    /// Deploy the tracked dotfile `path` under the other file name `name`, next to where its own
+    /// symlink goes, and record `name` so that deploying, verifying and restoring the dotfile
+    /// take care of that symlink as well. A dotfile can be given any number of other names.
+    /// `path` is resolved like with [`Config::deploy_paths`].
+    pub fn deploy_as(
+        &mut self,
+        path: &Path,
+        name: &str,
+        options: &DeployOptions,
+    ) -> Result<DeployOutcome> {
+        let mut components = Path::new(name).components();
+        let first = components.next();
+        if !matches!(first, Some(Component::Normal(_))) || components.next().is_some() {
+            return Err(Error::InvalidSymlinkName(name.to_string()));
+        }
+        let (index, dotfile_path) = self
+            .resolve_candidates(path)
+            .into_iter()
+            .find(|(dotfiles, dotfile_path)| dotfiles.has_path(dotfile_path))
+            .and_then(|(dotfiles, dotfile_path)| {
+                let (dotfile_dir, symlink_dir) =
+                    (&dotfiles.dotfile_directory, &dotfiles.symlink_directory);
+                let index = self.dotfiles_index(symlink_dir, dotfile_dir)?;
+                Some((index, dotfile_path))
+            })
+            .ok_or_else(|| Error::NoMatchingDotfileConfigured(path.to_path_buf()))?;
+
+        let dotfiles = &self.dotfiles[index];
+        let alias = dotfiles.symlink_path(&dotfile_path).with_file_name(name);
+        if alias == dotfiles.symlink_path(&dotfile_path) {
+            return Err(Error::InvalidSymlinkName(name.to_string()));
+        }
+        let outcome = observer::observe(
+            options.observer,
+            Action::Deploy(&dotfile_path),
+            || self.deploy_resolving_to(dotfiles, &dotfile_path, &alias, options),
+            DeployOutcome::action_outcome,
+        )?;
+        if !options.dry_run {
+            let _ = self.dotfiles[index]
+                .symlink_names
+                .entry(dotfile_path)
+                .or_default()
+                .insert(name.to_string());
+            self.write_toml_config()?;
+        }
+        Ok(outcome)
+    }
+
+    /// Deploy the dotfiles selected by `selector`.
+    pub fn deploy_selected(&mut self, selector: &Selector) -> Result<DeploySummary> {
+        let options = DeployOptions::new().backup(true).skip_broken(true);
+        self.deploy_selected_with(selector, &options)
+    }
+
+    /// Deploy the dotfiles selected by `selector` according to `options`, see
+    /// [`Config::deploy_selected`]. Dotfiles matching the `ignore_patterns` of their entry are
+    /// left out, see [`Dotfiles::apply_ignore`]. A deploy of every dotfile [`Config::deploy_all`]
+    /// would deploy is recorded like one of those, see [`Config::deploy_incremental`].
+    pub fn deploy_selected_with(
+        &mut self,
+        selector: &Selector,
+        options: &DeployOptions,
+    ) -> Result<DeploySummary> {
+        let mut selected = Vec::new();
+        for resolved in selector.evaluate(self)? {
+            let dotfiles = &self.dotfiles[resolved.entry_index];
+            if dotfiles.is_ignored(resolved.relative)? {
+                log::debug!("ignoring {0}", resolved.relative.display());
+            } else if !selector.picks_entries() && !self.applies_here(dotfiles) {
+                log::debug!("{0} doesn't apply here", resolved.relative.display());
+            } else {
+                selected.push(resolved);
+            }
+        }
+        let complete = self.iter_included_entries()?.all(|included| {
+            selected.iter().any(|resolved| {
+                resolved.entry_index == included.entry_index
+                    && resolved.relative == included.relative
+            })
+        });
+        let mut summary = DeploySummary::default();
+        for resolved in selected {
+            log::debug!("deploying {0}", resolved.relative.display());
+            if self.is_skipped(resolved.relative) {
+                log::warn!(
+                    "deploying {0} even though it is skipped on this machine",
+                    resolved.relative.display()
+                );
+            }
+            let dotfiles = &self.dotfiles[resolved.entry_index];
+            summary.add(self.deploy_dotfile(dotfiles, resolved.relative, options)?);
+        }
+        if complete && options.modified.is_empty() && !options.dry_run {
+            self.record_deploy();
+        }
+        Ok(summary)
+    }
+
+    /// Deploy all dotfiles of the entries deployed on this machine, see [`Config::deploys_here`],
+    /// backing up files in the way and skipping broken dotfiles and those on the skip list of
+    /// this machine. Anything else that keeps a dotfile from being deployed,
+    /// such as a directory in the way of a file ([`Error::StorePathTypeMismatch`]), fails the
+    /// deploy at that dotfile; dotfiles after it are left as they are.
+    pub fn deploy_all(&mut self) -> Result<DeploySummary> {
+        let options = DeployOptions::new()
+            .backup(true)
+            .skip_broken(true)
+            .skip_listed(true);
+        self.deploy_all_with(&options)
+    }
+
+    /// Deploy all dotfiles according to `options`, stopping at the first failure other than a
+    /// skipped conflict.
+    pub fn deploy_all_with(&mut self, options: &DeployOptions) -> Result<DeploySummary> {
+        let mut summary = DeploySummary::default();
+        for resolved in self.included_entries_modified(options)? {
+            log::debug!(
+                "deploying {0} to {1}",
+                resolved.store.display(),
+                resolved.target.display()
+            );
+            let dotfiles = &self.dotfiles[resolved.entry_index];
+            match self.deploy_dotfile(dotfiles, resolved.relative, options) {
+                Err(e) if options.skip_conflicts && e.is_conflict() => {
+                    log::warn!("skipping {0}: {1}", resolved.target.display(), e);
+                },
+                outcome => summary.add(outcome?),
+            }
+        }
+        if !options.dry_run {
+            self.record_deploy();
+        }
+        Ok(summary)
+    }
+
+    /// Deploy all dotfiles using up to `jobs` threads. Each dotfile is deployed independently, so
+    /// unlike [`Config::deploy_all`] a failure doesn't stop the remaining dotfiles from being
+    /// deployed; all failures are reported once every dotfile has been attempted. With a single
+    /// job this is the same as [`Config::deploy_all`]. With `skip_conflicts` dotfiles whose
+    /// symlink path is occupied by something ghmd can't back up (see [`Error::is_conflict`]) are
+    /// skipped with a warning instead of being reported as failures.
+    pub fn deploy_all_parallel(
+        &mut self,
+        jobs: usize,
+        skip_conflicts: bool,
+    ) -> Result<DeploySummary> {
+        if jobs <= 1 && !skip_conflicts {
+            return self.deploy_all();
+        }
+
+        let mut summary = DeploySummary::default();
+        let mut errors = Vec::new();
+        for (_, result) in self.deploy_all_results(jobs, skip_conflicts)? {
+            match result {
+                Ok(outcome) => summary.add(outcome),
+                Err(e) => errors.push(e),
+            }
+        }
+        match errors.len() {
+            0 => Ok(summary),
+            1 => Err(errors.remove(0)),
+            _ => Err(Error::Multiple(errors)),
+        }
+    }
+
+    /// Deploy all dotfiles like [`Config::deploy_all_parallel`], but return how deploying each
+    /// dotfile ended instead of a summary, in the order of [`Config::iter_entries`]. With a
+    /// single job and without `skip_conflicts` deploying stops at the first failure, which is
+    /// then the last result. Dotfiles skipped because of a conflict are reported as
+    /// [`DeployOutcome::Skipped`].
+    pub fn deploy_all_results(
+        &mut self,
+        jobs: usize,
+        skip_conflicts: bool,
+    ) -> Result<Vec<(DotfilePath, Result<DeployOutcome>)>> {
+        let options = DeployOptions::new().skip_conflicts(skip_conflicts);
+        self.deploy_all_results_with(jobs, &options)
+    }
+
+    /// Deploy all dotfiles like [`Config::deploy_all_results`], taking whether conflicts are
+    /// skipped and the link mode from `options`. Files in the way are always backed up and
+    /// broken dotfiles and those on the skip list of this machine always skipped.
+    pub fn deploy_all_results_with(
+        &mut self,
+        jobs: usize,
+        options: &DeployOptions,
+    ) -> Result<Vec<(DotfilePath, Result<DeployOutcome>)>> {
+        let skip_conflicts = options.skip_conflicts;
+        let fail_fast = jobs <= 1 && !skip_conflicts;
+        let mut results = {
+            let config = &*self;
+            let resolved = config.included_entries_modified(options)?;
+            let next = AtomicUsize::new(0);
+            let failed = AtomicBool::new(false);
+            let options = options.backup(true).skip_broken(true).skip_listed(true);
+            let results = Mutex::new(Vec::with_capacity(resolved.len()));
+            let worker = || loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let resolved = match resolved.get(index) {
+                    Some(_) if fail_fast && failed.load(Ordering::Relaxed) => break,
+                    Some(resolved) => resolved,
+                    None => break,
+                };
+                log::debug!("deploying path {0}", resolved.target.display());
+                let dotfiles = &config.dotfiles[resolved.entry_index];
+                let path = resolved.relative;
+                let result = match config.deploy_dotfile(dotfiles, path, &options) {
+                    Err(e) if skip_conflicts && e.is_conflict() => {
+                        log::warn!("skipping {0}: {1}", resolved.target.display(), e);
+                        Ok(DeployOutcome::Skipped)
+                    },
+                    Err(e) => {
+                        let target = resolved.target.display();
+                        log::debug!("failed to deploy {0}: {1}", target, e);
+                        failed.store(true, Ordering::Relaxed);
+                        Err(e)
+                    },
+                    outcome => outcome,
+                };
+                results
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .push((index, path.clone(), result));
+            };
+            thread::scope(|scope| {
+                for _ in 0..jobs.max(1) {
+                    let _ = scope.spawn(worker);
+                }
+            });
+            results.into_inner().unwrap_or_else(PoisonError::into_inner)
+        };
+
+        if results.iter().all(|(_, _, result)| result.is_ok()) {
+            self.record_deploy();
+        }
+        results.sort_by_key(|(index, _, _)| *index);
+        Ok(results
+            .into_iter()
+            .map(|(_, path, result)| (path, result))
+            .collect())
+    }
+
+    /// Summarize the `results` of deploying dotfiles, e.g. from [`Config::deploy_all_results`],
+    /// as a line counting the deployed, failed and already correct dotfiles followed by a line
+    /// for each failed dotfile with its error. The counts and failures are colored if stdout is
+    /// a terminal.
+    pub fn format_deploy_report(
+        results: &[(DotfilePath, Result<DeployOutcome>)],
+    ) -> String {
+        const GREEN: &str = "32";
+        const RED: &str = "31";
+        let color = io::stdout().is_terminal();
+        let paint = |code: &str, text: String| match color {
+            true => format!("\x1b[{0}m{1}\x1b[0m", code, text),
+            false => text,
+        };
+        use DeployOutcome::{Created, Skipped, Unchanged};
+        let count = |f: fn(&Result<DeployOutcome>) -> bool| {
+            results.iter().filter(|(_, result)| f(result)).count()
+        };
+        let deployed = count(|result| matches!(result, Ok(Created { .. })));
+        let unchanged = count(|result| matches!(result, Ok(Unchanged)));
+        let skipped = count(|result| matches!(result, Ok(Skipped)));
+        let failed = count(Result::is_err);
+
+        let mut report = format!(
+            "{0}, {1}, \u{2192} {2} already correct",
+            paint(GREEN, format!("\u{2713} {0} deployed", deployed)),
+            paint(RED, format!("\u{2717} {0} failed", failed)),
+            unchanged
+        );
+        if skipped > 0 {
+            report.push_str(&format!(", {0} skipped", skipped));
+        }
+        report.push('\n');
+        for (path, result) in results {
+            if let Err(e) = result {
+                let line = format!("  \u{2717} {0}: {1}", path.display(), e);
+                report.push_str(&paint(RED, line));
+                report.push('\n');
+            }
+        }
+        report
+    }
+
+    /// Remove the symlinks of all dotfiles without restoring the dotfiles, returning the number
+    /// of removed symlinks. Only symlinks pointing at their dotfile and hardlinks of it are
+    /// removed; neither the
+    /// dotfiles directories nor the config are modified, so [`Config::deploy_all`] recreates
+    /// them.
+    pub fn unlink_all(&self) -> Result<usize> {
+        let mut count = 0;
+        for resolved in self.iter_entries() {
+            let dotfiles = &self.dotfiles[resolved.entry_index];
+            for symlink_path in dotfiles.all_symlink_paths(resolved.relative) {
+                if !dotfiles.is_linked_at(&symlink_path, resolved.relative)? {
+                    continue;
+                }
+                log::info!("removing link {0}", symlink_path.display());
+                fs::remove_file(&symlink_path)?;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Create the missing parent directories of the symlinks of all dotfiles, returning the
+    /// number of directories created. Symlinks whose parent directory was deleted after they were
+    /// deployed can then be redeployed.
+    pub fn touch_symlinks(&self) -> Result<usize> {
+        let mut created = 0;
+        for resolved in self.iter_entries() {
+            created += paths::create_parent_dirs(&resolved.target)?.len();
+        }
+        Ok(created)
+    }
+
+    /// Deploy only the dotfiles that were modified after `since`, backing up files in the way
+    /// and skipping broken dotfiles and those on the skip list of this machine, and return the
+    /// number of deployed dotfiles.
+    pub fn deploy_changed(&self, since: SystemTime) -> Result<usize> {
+        let options = DeployOptions::new()
+            .backup(true)
+            .skip_broken(true)
+            .skip_listed(true);
+        self.deploy_changed_with(since, &options)
+    }
+
+    /// Deploy only the dotfiles that were modified after `since` according to `options`,
+    /// returning the number of deployed dotfiles. Dotfiles missing from the store are skipped
+    /// with a warning. Every changed dotfile is attempted even if others fail; the first
+    /// failure is returned afterwards.
+    pub fn deploy_changed_with(
+        &self,
+        since: SystemTime,
+        options: &DeployOptions,
+    ) -> Result<usize> {
+        let mut count = 0;
+        let mut first_error = None;
+        for dotfiles in self.dotfiles.iter().filter(|d| self.deploys_here(d)) {
+            for path in dotfiles.changed_since(since)? {
+                log::debug!("deploying changed path {0}", path.display());
+                match self.deploy_dotfile(dotfiles, path, options) {
+                    Ok(DeployOutcome::Skipped) => (),
+                    Ok(_) => count += 1,
+                    Err(e) => {
+                        log::error!("failed to deploy {0}: {1}", path.display(), e);
+                        let _ = first_error.get_or_insert(e);
+                    },
+                }
+            }
+        }
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(count),
+        }
+    }
+
+    /// Deploy the dotfiles that were modified since the last time all dotfiles were deployed and
+    /// record the time of this deploy. If dotfiles have never been deployed, all of them are.
+    pub fn deploy_incremental(&mut self) -> Result<usize> {
+        let options = DeployOptions::new()
+            .backup(true)
+            .skip_broken(true)
+            .skip_listed(true);
+        self.deploy_incremental_with(&options)
+    }
+
+    /// Like [`Config::deploy_incremental`], deploying according to `options`. The time of this
+    /// deploy is only recorded if every changed dotfile was deployed, so that failed ones are
+    /// tried again next time.
+    pub fn deploy_incremental_with(&mut self, options: &DeployOptions) -> Result<usize> {
+        let since = self
+            .last_deploy
+            .map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+            .unwrap_or(UNIX_EPOCH);
+        let count = self.deploy_changed_with(since, options)?;
+        if !options.dry_run {
+            self.record_deploy();
+        }
+        Ok(count)
+    }
+
+    fn record_deploy(&mut self) {
+        self.last_deploy = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|d| d.as_secs());
+    }
+
+    /// Deploy `path`, with `options.backup` moving whatever non-symlink is in the way into the
+    /// backup directory first. Other conflicts are resolved by `options.observer`, if any.
+    pub(crate) fn deploy_dotfile(
+        &self,
+        dotfiles: &Dotfiles,
+        path: &DotfilePath,
+        options: &DeployOptions,
+    ) -> Result<DeployOutcome> {
+        let options = &DeployOptions {
+            durable: options.durable || self.durable,
+            ..*options
+        };
+        observer::observe(
+            options.observer,
+            Action::Deploy(path),
+            || self.deploy_resolving(dotfiles, path, options),
+            DeployOutcome::action_outcome,
+        )
+    }
+
+    fn deploy_resolving(
+        &self,
+        dotfiles: &Dotfiles,
+        path: &DotfilePath,
+        options: &DeployOptions,
+    ) -> Result<DeployOutcome> {
+        if options.skip_listed && self.is_skipped(path) {
+            log::info!("skipping {0} on this machine", path.display());
+            return Ok(DeployOutcome::Skipped);
+        }
+        let own = dotfiles.deploy_path(path, options)?;
+        let mut outcome = self.deploy_resolving_to(dotfiles, path, &own, options)?;
+        for alias in dotfiles.alias_paths(path) {
+            let alias = match options.expand_dest {
+                true => paths::expand_env(&alias)?,
+                false => alias,
+            };
+            let deployed = self.deploy_resolving_to(dotfiles, path, &alias, options)?;
+            outcome = outcome.and(deployed);
+        }
+        Ok(outcome)
+    }
+
+    fn deploy_resolving_to(
+        &self,
+        dotfiles: &Dotfiles,
+        path: &DotfilePath,
+        symlink_path: &Path,
+        options: &DeployOptions,
+    ) -> Result<DeployOutcome> {
+        match dotfiles.deploy_to(path, symlink_path, options) {
+            Err(Error::StoreEntryBroken(p)) if options.skip_broken => {
+                log::warn!("skipping {0}, it is a broken symlink", p.display());
+                Ok(DeployOutcome::Skipped)
+            },
+            Err(Error::SymlinkPathIsNotASymlink(p)) if options.backup => {
+                self.backup_and_deploy(dotfiles, path, &p, options)
+            },
+            Err(e) => {
+                let observer = match options.observer {
+                    Some(observer) => observer,
+                    None => return Err(e),
+                };
+                let conflict = match e.conflict() {
+                    Some(conflict) => conflict,
+                    None => return Err(e),
+                };
+                match observer.on_conflict(&conflict) {
+                    Resolution::Fail => Err(e),
+                    Resolution::Skip => {
+                        log::debug!("skipping {0}", conflict.path.display());
+                        Ok(DeployOutcome::Skipped)
+                    },
+                    Resolution::Backup => {
+                        self.backup_and_deploy(dotfiles, path, &conflict.path, options)
+                    },
+                }
+            },
+            result => result,
+        }
+    }
+
+    /// Move `occupant`, whatever is in the way of the symlink of `path`, into the backup
+    /// directory and deploy `path`.
+    fn backup_and_deploy(
+        &self,
+        dotfiles: &Dotfiles,
+        path: &DotfilePath,
+        occupant: &Path,
+        options: &DeployOptions,
+    ) -> Result<DeployOutcome> {
+        if options.dry_run {
+            log::info!("would back up {0} and replace it", occupant.display());
+            let created_dirs = Vec::new();
+            return Ok(DeployOutcome::Created { created_dirs });
+        }
+        backup_to(&self.backup_directory, occupant)?;
+        log::debug!("retrying deploy of path {0}", occupant.display());
+        dotfiles.deploy_to(path, occupant, options)
+    }
+
+    fn stow_path(
+        &mut self,
+        symlink_dir: &SymlinkDir,
+        dotfile_dir: &DotfilesDir,
+        stow_path: &DotfilePath,
+        options: &StowOptions,
+    ) -> Result<()> {
+        let options = &options.durable(options.durable || self.durable);
+        self.check_owner(symlink_dir, dotfile_dir, stow_path)?;
+        // a dry run mustn't create a dotfiles entry, so check against a throwaway one instead
+        if options.dry_run && self.dotfiles_index(symlink_dir, dotfile_dir).is_none() {
+            return self
+                .new_dotfiles(symlink_dir, dotfile_dir)?
+                .stow_path(stow_path, options)
+                .map(|_| ());
+        }
+
+        let _ = self.add_dotfiles_if_absent(symlink_dir, dotfile_dir)?;
+        self.get_dotfiles_for_mut(symlink_dir, dotfile_dir)
+            .ok_or(Error::UnexpectedError(
+                "could not retrieve new dotfiles dir",
+            ))?
+            .stow_path(stow_path, options)
+            .map(|_| ())
+    }
+
+    /// Stow paths in given dotfile dir. With [`ErrorPolicy::FailFast`] the first failure is
+    /// returned as an error, with [`ErrorPolicy::Continue`] failures are collected in the returned
+    /// outcome and the remaining paths are still stowed. Either way the config is written with
+    /// every successfully stowed path.
+    pub fn stow_paths(
+        &mut self,
+        symlink_dir: SymlinkDir,
+        dotfile_dir: DotfilesDir,
+        stow_paths: Vec<DotfilePath>,
+        policy: ErrorPolicy,
+    ) -> Result<StowOutcome> {
+        let options = StowOptions::new().policy(policy);
+        self.stow_paths_with(symlink_dir, dotfile_dir, stow_paths, &options)
+    }
+
+    /// Stow paths in given dotfile dir according to `options`, see [`Config::stow_paths`]. In a
+    /// dry run the paths that would be stowed are reported as stowed and the config is left
+    /// untouched.
+    pub fn stow_paths_with(
+        &mut self,
+        symlink_dir: SymlinkDir,
+        dotfile_dir: DotfilesDir,
+        stow_paths: Vec<DotfilePath>,
+        options: &StowOptions,
+    ) -> Result<StowOutcome> {
+        let mut outcome = StowOutcome::default();
+        let sizes: Vec<u64> = match options.observer {
+            Some(_) => stow_paths
+                .iter()
+                .map(|path| paths::disk_size(&symlink_dir.join(&**path)))
+                .collect(),
+            None => Vec::new(),
+        };
+        let total = sizes.iter().sum();
+        let mut moved = 0;
+        for (index, path) in stow_paths.into_iter().enumerate() {
+            log::debug!("stowing path: {:?}", path);
+            let result = observer::observe(
+                options.observer,
+                Action::Stow(&path),
+                || self.stow_path(&symlink_dir, &dotfile_dir, &path, options),
+                |_| ActionOutcome::Done,
+            );
+            if let Some(observer) = options.observer {
+                moved += sizes[index];
+                observer.on_progress(moved, total);
+            }
+            match result {
+                Ok(()) => outcome.stowed.push(path),
+                Err(e) if options.policy == ErrorPolicy::Continue => {
+                    log::debug!("failed to stow {0}: {1}", path.display(), e);
+                    outcome.failed.push((path.to_path_buf(), e));
+                },
+                Err(e) => {
+                    if !options.dry_run {
+                        self.write_toml_config()?;
+                    }
+                    return Err(e);
+                },
+            }
+        }
+        if !options.dry_run {
+            self.write_toml_config()?;
+        }
+        Ok(outcome)
+    }
+
+    /// Stow the file at `path` into the dotfiles directory whose symlink directory contains it,
+    /// returning that dotfiles directory and the stowed dotfile path. Relative paths are taken
+    /// relative to the current directory. If the symlink directories of several entries contain
+    /// the path the innermost one is used. Disabled entries are never used and no new dotfiles
+    /// entry is ever created.
+    pub fn stow_auto_path(&mut self, path: &Path) -> Result<(DotfilesDir, DotfilePath)> {
+        let path = match path.is_relative() {
+            true => env::current_dir()?.join(path),
+            false => path.to_path_buf(),
+        };
+        if self
+            .dotfiles
+            .iter()
+            .any(|dotfiles| dotfiles.dotfile_directory.starts_with(&path))
+        {
+            return Err(Error::BadInput("refusing to stow a dotfiles directory"));
+        }
+
+        let mut owners: Vec<(usize, DotfilePath)> = self
+            .dotfiles
+            .iter()
+            .enumerate()
+            .filter(|(_, dotfiles)| dotfiles.enabled)
+            .filter_map(|(index, dotfiles)| {
+                let relative = dotfiles.strip_symlink_dir(&path)?;
+                (relative.depth() > 0).then_some((index, relative))
+            })
+            .collect();
+        let innermost = owners.iter().map(|(_, relative)| relative.depth()).min();
+        owners.retain(|(_, relative)| Some(relative.depth()) == innermost);
+        let (index, relative) = match owners.len() {
+            0 => return Err(Error::NoStoreForPath(path)),
+            1 => owners.remove(0),
+            _ => return Err(Error::AmbiguousStoreForPath(path)),
+        };
+
+        let symlink_dir = self.dotfiles[index].symlink_dir().clone();
+        let dotfile_dir = self.dotfiles[index].dotfile_directory.clone();
+        self.check_owner(&symlink_dir, &dotfile_dir, &relative)?;
+        log::info!(
+            "stowing {0} into {1}",
+            path.display(),
+            dotfile_dir.display()
+        );
+        let _ = self.dotfiles[index].stow_path(&relative, &StowOptions::new())?;
+        self.write_toml_config()?;
+        Ok((dotfile_dir, relative))
+    }
+
+    /// Copy `paths` from `symlink_dir` into `dotfile_dir` and track them without replacing them
+    /// with symlinks, e.g. for files that must not be modified. Returns the number of copied
+    /// paths; the config is written even if copying fails part way.
+    pub fn copy_paths(
+        &mut self,
+        symlink_dir: SymlinkDir,
+        dotfile_dir: DotfilesDir,
+        paths: Vec<DotfilePath>,
+    ) -> Result<usize> {
+        for path in &paths {
+            self.check_owner(&symlink_dir, &dotfile_dir, path)?;
+        }
+        let dotfiles = self.get_or_create_dotfiles(&symlink_dir, &dotfile_dir)?;
+        let mut count = 0;
+        for path in &paths {
+            log::info!("copying path: {:?}", path);
+            if let Err(e) = dotfiles.copy_path(path) {
+                self.write_toml_config()?;
+                return Err(e);
+            }
+            count += 1;
+        }
+        self.write_toml_config()?;
+        Ok(count)
+    }
+
+    /// Restores the named dotfile if it can be found in one of the configured dotfile directories.
+    /// `force` allows replacing a file at the original location that is newer than the stored
+    /// dotfile.
+    pub fn restore_dotfile(&mut self, path: DotfilePath, force: bool) -> Result<()> {
+        self.restore_with(path, &RestoreOptions::new().force(force))
+    }
+
+    /// Restores the named dotfile tracked by the dotfiles entry stored in `dotfile_dir`, see
+    /// [`Config::get_dotfiles_mut`], according to `options`. Fails with
+    /// [`Error::DotfilesDirNotConfigured`] if there is no such entry.
+    pub fn restore_from_with(
+        &mut self,
+        dotfile_dir: &DotfilesDir,
+        path: DotfilePath,
+        options: &RestoreOptions,
+    ) -> Result<()> {
+        let backup_directory = self.backup_directory.clone();
+        let options = &options
+            .backup_directory(&backup_directory)
+            .durable(options.durable || self.durable);
+        observer::observe(
+            options.observer,
+            Action::Restore(&path),
+            || {
+                self.get_dotfiles_mut(dotfile_dir)
+                    .ok_or_else(|| {
+                        Error::DotfilesDirNotConfigured(dotfile_dir.to_path_buf())
+                    })?
+                    .restore_dotfile(&path, options)
+                    .map(|_| ())
+            },
+            |_| ActionOutcome::Done,
+        )
+    }
+
+    /// Restores the named dotfile according to `options`, see [`Config::restore_dotfile`].
+    pub fn restore_with(
+        &mut self,
+        path: DotfilePath,
+        options: &RestoreOptions,
+    ) -> Result<()> {
+        observer::observe(
+            options.observer,
+            Action::Restore(&path),
+            || self.restore_from_any(&path, options),
+            |_| ActionOutcome::Done,
+        )
+    }
+
+    fn restore_from_any(
+        &mut self,
+        path: &DotfilePath,
+        options: &RestoreOptions,
+    ) -> Result<()> {
+        let options = &options
+            .backup_directory(&self.backup_directory)
+            .durable(options.durable || self.durable);
+        for dotfiles in &mut self.dotfiles {
+            log::info!(
+                "attempting to restore {:?} from {:?}",
+                *path,
+                *dotfiles.dotfile_directory
+            );
+            match dotfiles.restore_dotfile(path, options) {
+                Ok(Some(_)) => return Ok(()),
+                Ok(None) => continue,
+                Err(e) => match e {
+                    Error::NoMatchingDotfileConfigured(_)
+                    | Error::PathDoesNotExist(_)
+                    | Error::SymlinkPathIsNotASymlink(_)
+                    | Error::SymlinkPathDoesNotMatchDotfilePath(_, _) => {
+                        log::info!("restore failed: {:?}", e,);
+                    },
+                    _ => return Err(e),
+                },
+            }
+        }
+        Err(Error::DotfileNotFound(path.to_path_buf()))
+    }
+
+    /// Restore the dotfiles selected by `selector`, returning the number of restored dotfiles.
+    /// `force` allows replacing files at the original locations that are newer than the stored
+    /// dotfiles.
+    pub fn restore_selected(
+        &mut self,
+        selector: &Selector,
+        force: bool,
+    ) -> Result<usize> {
+        self.restore_selected_with(selector, &RestoreOptions::new().force(force))
+    }
+
+    /// Restore the dotfiles selected by `selector` according to `options`, see
+    /// [`Config::restore_selected`].
+    pub fn restore_selected_with(
+        &mut self,
+        selector: &Selector,
+        options: &RestoreOptions,
+    ) -> Result<usize> {
+        let selected = Self::owned_selection(selector.evaluate(self)?);
+        if let Some(dir) = options.to {
+            Self::check_restore_to(dir, selected.iter().map(|(_, path)| path))?;
+        }
+        let options = &options
+            .backup_directory(&self.backup_directory)
+            .durable(options.durable || self.durable);
+        for (index, path) in &selected {
+            let _ = self.dotfiles[*index].restore_dotfile(path, options)?;
+        }
+        self.write_toml_config()?;
+        Ok(selected.len())
+    }
+
+    /// Check that the dotfiles `paths` can all be restored into `dir`, see
+    /// [`RestoreOptions::to`]: fails with [`Error::RestoreDestinationTaken`] if something
+    /// already exists where one of them goes, or if two of them go to the same place.
+    pub fn check_restore_to<'p>(
+        dir: &Path,
+        paths: impl IntoIterator<Item = &'p DotfilePath>,
+    ) -> Result<()> {
+        let mut destinations = BTreeSet::new();
+        for path in paths {
+            let destination = dir.join(&**path);
+            if destination.symlink_metadata().is_ok() || !destinations.insert(path) {
+                return Err(Error::RestoreDestinationTaken(destination));
+            }
+        }
+        Ok(())
+    }
+
+    fn owned_selection(selected: Vec<ResolvedPath>) -> Vec<(usize, DotfilePath)> {
+        selected
+            .into_iter()
+            .map(|resolved| (resolved.entry_index, resolved.relative.clone()))
+            .collect()
+    }
+
+    /// Iterate over every tracked dotfile of every entry along with its resolved store and
+    /// symlink paths.
+    pub fn iter_entries(&self) -> impl Iterator<Item = ResolvedPath<'_>> {
+        self.dotfiles
+            .iter()
+            .enumerate()
+            .flat_map(|(index, dotfiles)| dotfiles.resolved_paths(index))
+    }
+
+    /// Iterate over the tracked dotfiles of every dotfiles entry deployed on this machine, see
+    /// [`Config::deploys_here`], like [`Config::iter_entries`], leaving out the ones matching the
+    /// `ignore_patterns` of their entry, see [`Dotfiles::apply_ignore`].
+    pub fn iter_included_entries(
+        &self,
+    ) -> Result<impl Iterator<Item = ResolvedPath<'_>>> {
+        let mut entries = Vec::new();
+        for (index, dotfiles) in self.dotfiles.iter().enumerate() {
+            if !self.deploys_here(dotfiles) {
+                continue;
+            }
+            let included = dotfiles.apply_ignore()?;
+            entries.extend(included.map(|path| dotfiles.resolve(index, path)));
+        }
+        Ok(entries.into_iter())
+    }
+
+    /// The dotfiles of [`Config::iter_included_entries`] whose stored dotfile was modified within
+    /// the range of `options`, see [`DeployOptions::modified`].
+    fn included_entries_modified(
+        &self,
+        options: &DeployOptions,
+    ) -> Result<Vec<ResolvedPath<'_>>> {
+        let included = self.iter_included_entries()?.collect();
+        let modified = options.modified;
+        match modified.is_empty() {
+            true => Ok(included),
+            false => self.filter_by_mtime(included, |time| modified.matches(time)),
+        }
+    }
+
+    /// Keep the dotfiles among `paths` for whose stored dotfile `predicate` holds when given its
+    /// modification time. The time of a stored symlink whose target doesn't exist is that of
+    /// the symlink.
+    pub fn filter_by_mtime<'a>(
+        &self,
+        paths: Vec<ResolvedPath<'a>>,
+        predicate: impl Fn(SystemTime) -> bool,
+    ) -> Result<Vec<ResolvedPath<'a>>> {
+        let mut kept = Vec::with_capacity(paths.len());
+        for resolved in paths {
+            if predicate(paths::modified(&resolved.store)?) {
+                kept.push(resolved);
+            }
+        }
+        Ok(kept)
+    }
+
+    /// Recreate the deployed symlinks of every dotfiles entry using `style` and record `style` as
+    /// the link style of every entry. See [`Dotfiles::convert_links`].
+    pub fn convert_links(&mut self, style: LinkStyle) -> Result<ConvertSummary> {
+        let mut summary = ConvertSummary::default();
+        let mut result = Ok(());
+        for dotfiles in &mut self.dotfiles {
+            match dotfiles.convert_links(style) {
+                Ok(converted) => summary.merge(converted),
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                },
+            }
+        }
+        self.write_toml_config()?;
+        result.map(|_| summary)
+    }
+
+    /// Point the config and the deployed symlinks at `new` after the dotfiles directory `old`
+    /// was moved there by other means, e.g. with `mv`. Every dotfiles entry whose dotfiles
+    /// directory is `old` or is located inside it is updated and its symlinks are recreated, see
+    /// [`Dotfiles::relink_moved`]. Relative paths are taken relative to the current directory.
+    pub fn repair_moved_store(
+        &mut self,
+        old: &Path,
+        new: &DotfilesDir,
+    ) -> Result<RelinkSummary> {
+        let cwd = env::current_dir()?;
+        let old = paths::normalize(&cwd.join(old));
+        let new = paths::normalize(&cwd.join(&**new));
+        let moved: Vec<usize> = (0..self.dotfiles.len())
+            .filter(|index| self.dotfiles[*index].dotfile_directory.starts_with(&old))
+            .collect();
+        if moved.is_empty() {
+            return Err(Error::DotfilesDirNotConfigured(old));
+        }
+
+        let mut dirs = Vec::with_capacity(moved.len());
+        for index in &moved {
+            let dotfiles = &self.dotfiles[*index];
+            let inner = dotfiles
+                .dotfile_directory
+                .strip_prefix(&old)
+                .unwrap_or(Path::new(""));
+            let dir: DotfilesDir = new.join(inner).try_into()?;
+            match self.dotfiles_index(dotfiles.symlink_dir(), &dir) {
+                Some(other) if other != *index => {
+                    return Err(Error::DotfilesDirAlreadyConfigured(dir.to_path_buf()));
+                },
+                _ => dirs.push(dir),
+            }
+        }
+
+        let mut summary = RelinkSummary::default();
+        let mut result = Ok(());
+        for (index, dir) in moved.into_iter().zip(dirs) {
+            let dotfiles = &mut self.dotfiles[index];
+            let old_dir = std::mem::replace(&mut dotfiles.dotfile_directory, dir);
+            log::info!(
+                "moved {0} to {1}",
+                old_dir.display(),
+                dotfiles.dotfile_directory.display()
+            );
+            match dotfiles.relink_moved(&old_dir) {
+                Ok(relinked) => summary.merge(relinked),
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                },
+            }
+        }
+        self.write_toml_config()?;
+        result.map(|_| summary)
+    }
+
+    /// Change the `kind` directory of the dotfiles entry configured with `old` to `new` in the
+    /// config only, e.g. after restoring a backup that stored the dotfiles elsewhere. `new` must
+    /// be an existing directory, `old` need not exist. Every tracked dotfile of the updated entry
+    /// is then checked against the new layout and the ones that aren't deployed correctly are
+    /// reported; the filesystem isn't touched. Relative paths are taken relative to the current
+    /// directory.
+    ///
+    /// Fails with [`Error::DirConfiguredMoreThanOnce`] if several entries are configured with
+    /// `old`, unless `all_matching` is set to update all of them.
+    pub fn rename_dir(
+        &mut self,
+        kind: DirKind,
+        old: &Path,
+        new: &Path,
+        all_matching: bool,
+    ) -> Result<RenameReport> {
+        let cwd = env::current_dir()?;
+        let old = paths::normalize(&cwd.join(old));
+        let new = paths::normalize(&cwd.join(new));
+        if !new.is_dir() {
+            return Err(Error::PathDoesNotExist(new));
+        }
+        let resolved = paths::resolve_dir(&old);
+        let configured = |dotfiles: &Dotfiles| match kind {
+            DirKind::Dotfiles => dotfiles.resolved_dirs().0 == resolved,
+            DirKind::Symlink => {
+                paths::resolve_dir(&dotfiles.symlink_directory) == resolved
+            },
+        };
+        let matching: Vec<usize> = (0..self.dotfiles.len())
+            .filter(|index| configured(&self.dotfiles[*index]))
+            .collect();
+        match (kind, matching.len()) {
+            (DirKind::Dotfiles, 0) => return Err(Error::DotfilesDirNotConfigured(old)),
+            (DirKind::Symlink, 0) => return Err(Error::SymlinkDirNotConfigured(old)),
+            (_, 1) => (),
+            (_, count) if !all_matching => {
+                return Err(Error::DirConfiguredMoreThanOnce(old, count))
+            },
+            _ => (),
+        }
+
+        let mut renamed = Vec::with_capacity(matching.len());
+        for index in &matching {
+            let mut dotfiles = self.dotfiles[*index].clone();
+            match kind {
+                DirKind::Dotfiles => {
+                    dotfiles.dotfile_directory = new.clone().try_into()?
+                },
+                DirKind::Symlink => {
+                    dotfiles.symlink_directory = new.clone().try_into()?;
+                    dotfiles.resolved_symlink_directory = None;
+                },
+            }
+            let taken = self.dotfiles.iter().enumerate().any(|(other, entry)| {
+                !matching.contains(&other)
+                    && entry.is_stored_in(&dotfiles.dotfile_directory)
+                    && entry.is_deployed_to(&dotfiles.symlink_directory)
+            });
+            if taken {
+                return Err(Error::DotfilesDirAlreadyConfigured(
+                    dotfiles.dotfile_directory.to_path_buf(),
+                ));
+            }
+            renamed.push(dotfiles);
+        }
+
+        let mut report = RenameReport::default();
+        for (index, dotfiles) in matching.into_iter().zip(renamed) {
+            log::info!("updated {0} to {1}", old.display(), new.display());
+            self.dotfiles[index] = dotfiles;
+            report.renamed += 1;
+            let dotfiles = &self.dotfiles[index];
+            for path in &dotfiles.paths {
+                let state = self.verify_symlink(dotfiles, path)?;
+                if !state.is_ok() {
+                    report.unresolved.push((dotfiles.symlink_path(path), state));
+                }
+            }
+        }
+        self.write_toml_config()?;
+        Ok(report)
+    }
+
+    /// Restore every dotfile deployed to `dir` and remove the dotfiles entries targeting `dir` from
+    /// the config, returning the number of restored dotfiles. Dotfiles whose symlink is already
+    /// gone are skipped.
+    pub fn restore_by_symlink_dir(&mut self, dir: &SymlinkDir) -> Result<usize> {
+        self.restore_by_symlink_dir_with(dir, &RestoreOptions::new())
+    }
+
+    /// Restore every dotfile deployed to `dir` according to `options`, see
+    /// [`Config::restore_by_symlink_dir`].
+    pub fn restore_by_symlink_dir_with(
+        &mut self,
+        dir: &SymlinkDir,
+        options: &RestoreOptions,
+    ) -> Result<usize> {
+        if !self
+            .dotfiles
+            .iter()
+            .any(|dotfiles| dotfiles.is_deployed_to(dir))
+        {
+            log::info!("no dotfiles are deployed to {0}", dir.display());
+            return Ok(0);
+        }
+
+        if let Some(to) = options.to {
+            let mut paths = Vec::new();
+            for dotfiles in self.dotfiles.iter().filter(|d| d.is_deployed_to(dir)) {
+                paths.extend(dotfiles.apply_ignore()?.cloned());
+            }
+            Self::check_restore_to(to, &paths)?;
+        }
+
+        let options = &options
+            .backup_directory(&self.backup_directory)
+            .durable(options.durable || self.durable);
+        let mut count = 0;
+        let mut result = Ok(());
+        for dotfiles in &mut self.dotfiles {
+            if !dotfiles.is_deployed_to(dir) {
+                continue;
+            }
+            match dotfiles.restore_all(options) {
+                Ok(restored) => count += restored,
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                },
+            }
+        }
+        // keep entries that still track dotfiles after a failure so that nothing is lost
+        self.dotfiles.retain(|dotfiles| {
+            !dotfiles.is_deployed_to(dir) || !dotfiles.paths.is_empty()
+        });
+        self.write_toml_config()?;
+        result.map(|_| count)
+    }
+
+    /// Set whether every dotfiles entry stored in `dotfiles_dir` takes part in operations on all
+    /// dotfiles, see [`Dotfiles::enabled`]. Returns the number of entries that changed.
+    pub fn set_enabled(
+        &mut self,
+        dotfiles_dir: &DotfilesDir,
+        enabled: bool,
+    ) -> Result<usize> {
+        let mut found = false;
+        let mut changed = 0;
+        for dotfiles in &mut self.dotfiles {
+            if dotfiles.is_stored_in(dotfiles_dir) {
+                found = true;
+                if dotfiles.enabled != enabled {
+                    dotfiles.enabled = enabled;
+                    changed += 1;
+                }
+            }
+        }
+        if !found {
+            return Err(Error::DotfilesDirNotConfigured(dotfiles_dir.to_path_buf()));
+        }
+        if changed > 0 {
+            self.write_toml_config()?;
+        }
+        Ok(changed)
+    }
+
+    /// Number of dotfiles entries that are disabled, see [`Dotfiles::enabled`].
+    pub fn disabled_count(&self) -> usize {
+        self.dotfiles
+            .iter()
+            .filter(|dotfiles| !dotfiles.enabled)
+            .count()
+    }
+
+    /// Stop tracking `path` in every dotfiles entry stored in `dotfiles_dir` without touching any
+    /// files.
+    pub fn forget(
+        &mut self,
+        dotfiles_dir: &DotfilesDir,
+        path: &DotfilePath,
+    ) -> Result<()> {
+        let mut found = false;
+        for dotfiles in &mut self.dotfiles {
+            if dotfiles.is_stored_in(dotfiles_dir) {
+                found |= dotfiles.untrack(path);
+            }
+        }
+        if !found {
+            return Err(Error::NoMatchingDotfileConfigured(path.to_path_buf()));
+        }
+        self.write_toml_config()
+    }
+
+    /// Stop tracking the dotfiles selected by `selector` without touching any files, returning the
+    /// number of forgotten dotfiles.
+    pub fn forget_selected(&mut self, selector: &Selector) -> Result<usize> {
+        let selected = Self::owned_selection(selector.evaluate(self)?);
+        for (index, path) in &selected {
+            let _ = self.dotfiles[*index].untrack(path);
+        }
+        self.write_toml_config()?;
+        Ok(selected.len())
+    }
+
+    /// Stop tracking every dotfile stored in `dotfiles_dir` without touching any files, returning
+    /// the number of forgotten dotfiles. With `remove_store` the dotfiles entries for
+    /// `dotfiles_dir` are removed from the config altogether rather than left empty.
+    pub fn forget_all(
+        &mut self,
+        dotfiles_dir: &DotfilesDir,
+        remove_store: bool,
+    ) -> Result<usize> {
+        let mut count = 0;
+        let mut found = false;
+        for dotfiles in &mut self.dotfiles {
+            if dotfiles.is_stored_in(dotfiles_dir) {
+                found = true;
+                count += dotfiles.paths.len();
+                dotfiles.paths.clear();
+                dotfiles.copied_paths.clear();
+            }
+        }
+        if !found {
+            return Err(Error::DotfilesDirNotConfigured(dotfiles_dir.to_path_buf()));
+        }
+        if remove_store {
+            self.dotfiles
+                .retain(|dotfiles| !dotfiles.is_stored_in(dotfiles_dir));
+        }
+        self.write_toml_config()?;
+        Ok(count)
+    }
+
+    /// Remove the first dotfiles entry for `dotfiles_dir` from the config and return it, writing
+    /// the config afterwards.
+    pub fn take_dotfiles(&mut self, dotfiles_dir: &DotfilesDir) -> Option<Dotfiles> {
+        let index = self
+            .dotfiles
+            .iter()
+            .position(|dotfiles| dotfiles.is_stored_in(dotfiles_dir))?;
+        let dotfiles = self.dotfiles.remove(index);
+        if let Err(e) = self.write_toml_config() {
+            log::error!("failed to write config: {0}", e);
+        }
+        Some(dotfiles)
+    }
+
+    /// Add a dotfiles entry, e.g. one taken from another config using
+    /// [`Config::take_dotfiles`], and write the config. Fails if an entry for the same dotfiles
+    /// and symlink directories already exists.
+    pub fn insert_dotfiles(&mut self, mut dotfiles: Dotfiles) -> Result<()> {
+        let symlink_dir = dotfiles.symlink_dir();
+        if self
+            .dotfiles_index(symlink_dir, &dotfiles.dotfile_directory)
+            .is_some()
+        {
+            return Err(Error::DotfilesDirAlreadyConfigured(
+                dotfiles.dotfile_directory.to_path_buf(),
+            ));
+        }
+        dotfiles.xdg_dirs = self.xdg_dirs.clone();
+        self.dotfiles.push(dotfiles);
+        self.write_toml_config()
+    }
+
+    /// Track the dotfile `path`, which must already be in `dotfile_dir`, in the dotfiles entry
+    /// for the given directories without touching the filesystem, and write the config. Returns
+    /// whether the dotfile wasn't tracked yet. `path` must stay inside the directories, the
+    /// entry must be configured and the dotfile must exist.
+    pub fn add_path(
+        &mut self,
+        symlink_dir: &SymlinkDir,
+        dotfile_dir: &DotfilesDir,
+        path: DotfilePath,
+    ) -> Result<bool> {
+        let escapes = path
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_)));
+        if escapes || path.as_os_str().is_empty() {
+            return Err(Error::BadInput(
+                "dotfile path must be a relative path inside the dotfiles directory",
+            ));
+        }
+        let index = self
+            .dotfiles_index(symlink_dir, dotfile_dir)
+            .ok_or_else(|| Error::DotfilesDirNotConfigured(dotfile_dir.to_path_buf()))?;
+        let dotfiles = &mut self.dotfiles[index];
+        let store_path = dotfiles.store_path(&path);
+        if store_path.symlink_metadata().is_err() {
+            return Err(Error::DotfileNotFound(store_path));
+        }
+        if !dotfiles.paths.insert(path) {
+            return Ok(false);
+        }
+        self.write_toml_config()?;
+        Ok(true)
+    }
+
+    /// Record `alias` as another spelling of the symlink directory of every dotfiles entry for
+    /// `dir` and write the config. The alias must be absolute; adding an alias twice has no
+    /// effect.
+    pub fn add_symlink_dir_alias(
+        &mut self,
+        dir: &DotfilesDir,
+        alias: PathBuf,
+    ) -> Result<()> {
+        if !alias.is_absolute() {
+            return Err(Error::BadInput("symlink directory alias must be absolute"));
+        }
+        let mut found = false;
+        for dotfiles in &mut self.dotfiles {
+            if dotfiles.is_stored_in(dir) {
+                found = true;
+                if !dotfiles.symlink_dir_aliases.contains(&alias) {
+                    dotfiles.symlink_dir_aliases.push(alias.clone());
+                }
+            }
+        }
+        if !found {
+            return Err(Error::DotfilesDirNotConfigured(dir.to_path_buf()));
+        }
+        self.write_toml_config()
+    }
+
+    /// Rewrite an absolute `path` inside an alias of `symlink_dir`, as recorded by any dotfiles
+    /// entry deployed to it, to the same location inside `symlink_dir`. Any other path is
+    /// returned unchanged.
+    pub fn unalias(&self, symlink_dir: &SymlinkDir, path: &Path) -> PathBuf {
+        self.dotfiles
+            .iter()
+            .filter(|dotfiles| dotfiles.is_deployed_to(symlink_dir))
+            .flat_map(|dotfiles| &dotfiles.symlink_dir_aliases)
+            .find_map(|alias| path.strip_prefix(alias).ok())
+            .map(|relative| symlink_dir.join(relative))
+            .unwrap_or_else(|| path.to_path_buf())
+    }
+
+    /// Move every dotfiles entry for `dotfiles_dir` into a new config file at `path`, returning
+    /// the number of moved entries. The new config file is written before the entries are
+    /// removed from this config, and an existing file at `path` is never overwritten.
+    pub fn split(&mut self, dotfiles_dir: &DotfilesDir, path: &Path) -> Result<usize> {
+        if path.exists() {
+            return Err(Error::ConfigFileAlreadyExists(path.to_path_buf()));
+        }
+
+        let mut split = Self::empty(self.backup_directory.clone());
+        split.ephemeral = true;
+        for dotfiles in &self.dotfiles {
+            if dotfiles.is_stored_in(dotfiles_dir) {
+                split.insert_dotfiles(dotfiles.clone())?;
+            }
+        }
+        if split.dotfiles.is_empty() {
+            return Err(Error::DotfilesDirNotConfigured(dotfiles_dir.to_path_buf()));
+        }
+        split.write_toml_config_to(path)?;
+
+        let mut count = 0;
+        while self.take_dotfiles(dotfiles_dir).is_some() {
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Stop tracking the dotfiles described by `stale`, returning the number of paths removed from
+    /// the config. With `remove_links`, symlinks for those dotfiles that no longer resolve are
+    /// deleted as well.
+    pub fn prune_paths(
+        &mut self,
+        stale: &[DotfileStatus],
+        remove_links: bool,
+    ) -> Result<usize> {
+        let positions: Vec<Option<usize>> = {
+            let index = self.get_dotfiles_index();
+            stale
+                .iter()
+                .map(|status| {
+                    (status.dotfiles_dir.as_path(), status.symlink_dir.as_path())
+                })
+                .map(|key| index.get(&key).copied())
+                .collect()
+        };
+        let mut count = 0;
+        for (status, position) in stale.iter().zip(positions) {
+            let index = match position {
+                Some(index) => index,
+                None => continue,
+            };
+            if !self.dotfiles[index].untrack(&status.path) {
+                continue;
+            }
+            count += 1;
+
+            let symlink_path = self.dotfiles[index].symlink_path(&status.path);
+            let is_symlink = symlink_path
+                .symlink_metadata()
+                .map(|md| md.is_symlink())
+                .unwrap_or(false);
+            if remove_links && is_symlink && !symlink_path.exists() {
+                log::info!("removing dangling symlink {0}", symlink_path.display());
+                fs::remove_file(&symlink_path)?;
+            }
+        }
+        self.write_toml_config()?;
+        Ok(count)
+    }
+
+    /// Group every configured dotfile by the symlink directory it is deployed to. Within each
+    /// group dotfiles are ordered by path.
+    pub fn group_by_symlink_dir(
+        &self,
+    ) -> BTreeMap<&SymlinkDir, Vec<(&Dotfiles, &DotfilePath)>> {
+        let mut groups = BTreeMap::<&SymlinkDir, Vec<(&Dotfiles, &DotfilePath)>>::new();
+        for dotfiles in &self.dotfiles {
+            groups
+                .entry(dotfiles.symlink_dir())
+                .or_default()
+                .extend(dotfiles.paths.iter().map(|path| (dotfiles, path)));
+        }
+        for group in groups.values_mut() {
+            group.sort_by_key(|(_, path)| *path);
+        }
+        groups
+    }
+
+    /// Select every configured dotfile whose relative path matches at least one of the given glob
+    /// patterns. If no patterns are given, every configured dotfile is selected.
+    pub fn select_paths(
+        &self,
+        patterns: &[Pattern],
+    ) -> Vec<(&Dotfiles, ResolvedPath<'_>)> {
+        self.iter_entries()
+            .filter(|resolved| {
+                patterns.is_empty()
+                    || patterns.iter().any(|p| p.matches_path(resolved.relative))
+            })
+            .map(|resolved| (&self.dotfiles[resolved.entry_index], resolved))
+            .collect()
+    }
+
+    /// Find every configured dotfile whose relative path contains `query`, ignoring case. Results
+    /// are sorted by dotfile directory and then by path.
+    pub fn search(&self, query: &str) -> Vec<(&Dotfiles, &DotfilePath)> {
+        let query = query.to_lowercase();
+        self.search_by(|path| path.to_string_lossy().to_lowercase().contains(&query))
+    }
+
+    /// Find every configured dotfile whose relative path matches the regular expression
+    /// `pattern`. Results are sorted by dotfile directory and then by path.
+    pub fn search_regex(&self, pattern: &str) -> Result<Vec<(&Dotfiles, &DotfilePath)>> {
+        let re = Regex::new(pattern)?;
+        Ok(self.search_by(|path| re.is_match(&path.to_string_lossy())))
+    }
+
+    fn search_by<F>(&self, matches: F) -> Vec<(&Dotfiles, &DotfilePath)>
+    where
+        F: Fn(&DotfilePath) -> bool,
+    {
+        let mut results: Vec<(&Dotfiles, &DotfilePath)> = self
+            .dotfiles
+            .iter()
+            .flat_map(|dotfiles| dotfiles.paths.iter().map(move |path| (dotfiles, path)))
+            .filter(|(_, path)| matches(path))
+            .collect();
+        results.sort_by(|(a, a_path), (b, b_path)| {
+            a.dotfile_directory
+                .cmp(&b.dotfile_directory)
+                .then_with(|| a_path.cmp(b_path))
+        });
+        results
+    }
+
+    /// Adds new dotfiles to dotfile_dir. Fails with [`Error::DotfilesDirAlreadyConfigured`] if
+    /// an entry for the same directories already exists.
+    pub fn add_dotfiles(
+        &mut self,
+        symlink_dir: &SymlinkDir,
+        dotfile_dir: &DotfilesDir,
+    ) -> Result<()> {
+        match self.add_dotfiles_if_absent(symlink_dir, dotfile_dir)? {
+            true => Ok(()),
+            false => Err(Error::DotfilesDirAlreadyConfigured(
+                dotfile_dir.to_path_buf(),
+            )),
+        }
+    }
+
+    /// Add a dotfiles entry for the given directories and write the config unless an entry for
+    /// them already exists. Returns whether an entry was added.
+    pub fn add_dotfiles_if_absent(
+        &mut self,
+        symlink_dir: &SymlinkDir,
+        dotfile_dir: &DotfilesDir,
+    ) -> Result<bool> {
+        if self.dotfiles_index(symlink_dir, dotfile_dir).is_some() {
+            return Ok(false);
+        }
+        self.dotfiles
+            .push(self.new_dotfiles(symlink_dir, dotfile_dir)?);
+        self.write_toml_config()?;
+        Ok(true)
+    }
+
+    /// Create a dotfiles entry for the given directories and stow `files` into it, writing the
+    /// config once at the end. If the entry already exists it is reused. Returns the number of
+    /// stowed files.
+    ///
+    /// If stowing any of the files fails, files stowed earlier in the same call are restored and a
+    /// newly created entry is removed again before the error is returned.
+    pub fn add_dotfiles_with_stow(
+        &mut self,
+        symlink_dir: SymlinkDir,
+        dotfile_dir: DotfilesDir,
+        files: Vec<DotfilePath>,
+    ) -> Result<usize> {
+        self.add_dotfiles_with_stow_with(
+            symlink_dir,
+            dotfile_dir,
+            files,
+            &StowOptions::new(),
+        )
+    }
+
+    /// Create a dotfiles entry for the given directories and stow `files` into it according to
+    /// `options`, see [`Config::add_dotfiles_with_stow`]. The error policy of `options` is
+    /// ignored, the first failure always undoes the call.
+    pub fn add_dotfiles_with_stow_with(
+        &mut self,
+        symlink_dir: SymlinkDir,
+        dotfile_dir: DotfilesDir,
+        files: Vec<DotfilePath>,
+        options: &StowOptions,
+    ) -> Result<usize> {
+        for file in &files {
+            self.check_owner(&symlink_dir, &dotfile_dir, file)?;
+        }
+        let created = self.dotfiles_index(&symlink_dir, &dotfile_dir).is_none();
+        let dotfiles = self.get_or_create_dotfiles(&symlink_dir, &dotfile_dir)?;
+        match dotfiles.stow_multiple(&files, options) {
+            Ok(count) => {
+                self.write_toml_config()?;
+                Ok(count)
+            },
+            Err(e) => {
+                if created {
+                    let _ = self.dotfiles.pop();
+                }
+                Err(e)
+            },
+        }
+    }
+
+    /// Stow the file at `source`, which doesn't have to be inside `symlink_dir`, as the dotfile
+    /// `dest`: it is moved to `dest` in `dotfile_dir` and a symlink to it is put at `dest` in
+    /// `symlink_dir`, creating missing parent directories. `dest` must be a relative path that
+    /// stays inside both directories. The config is written unless `options.dry_run` is set.
+    pub fn stow_as(
+        &mut self,
+        symlink_dir: &SymlinkDir,
+        dotfile_dir: &DotfilesDir,
+        source: &Path,
+        dest: &Path,
+        options: &StowOptions,
+    ) -> Result<DotfilePath> {
+        observer::observe(
+            options.observer,
+            Action::Stow(dest),
+            || self.stow_as_unobserved(symlink_dir, dotfile_dir, source, dest, options),
+            |_| ActionOutcome::Done,
+        )
+    }
+
+    fn stow_as_unobserved(
+        &mut self,
+        symlink_dir: &SymlinkDir,
+        dotfile_dir: &DotfilesDir,
+        source: &Path,
+        dest: &Path,
+        options: &StowOptions,
+    ) -> Result<DotfilePath> {
+        let options = &options.durable(options.durable || self.durable);
+        let escapes = dest.components().any(|component| {
+            !matches!(component, Component::Normal(_) | Component::CurDir)
+        });
+        let dest = paths::normalize(dest);
+        if escapes || dest.as_os_str().is_empty() {
+            return Err(Error::BadInput(
+                "destination must be a relative path inside the symlink directory",
+            ));
+        }
+        let dest = DotfilePath(dest);
+        self.check_owner(symlink_dir, dotfile_dir, &dest)?;
+
+        // only the directories matter for the checks, so a throwaway entry will do
+        if options.dry_run {
+            self.new_dotfiles(symlink_dir, dotfile_dir)?
+                .stow_from(source, &dest, options)?;
+            return Ok(dest);
+        }
+
+        let created = !self.has_dotfiles(symlink_dir, dotfile_dir);
+        let dotfiles = self.get_or_create_dotfiles(symlink_dir, dotfile_dir)?;
+        if let Err(e) = dotfiles.stow_from(source, &dest, options) {
+            if created {
+                let _ = self.remove_dotfiles_if_unused(symlink_dir, dotfile_dir);
+            }
+            return Err(e);
+        }
+        self.write_toml_config()?;
+        Ok(dest)
+    }
+
+    /// Find a dotfiles entry other than the one for `symlink_dir` and `dotfile_dir` that already
+    /// tracks a dotfile deployed to the same location as `path` would be, returning the index of
+    /// the entry and its path for the dotfile.
+    ///
+    /// Entries whose symlink directories overlap, i.e. where one is located inside the other, are
+    /// considered as well.
+    pub fn owner_of(
+        &self,
+        symlink_dir: &SymlinkDir,
+        dotfile_dir: &DotfilesDir,
+        path: &DotfilePath,
+    ) -> Option<(usize, DotfilePath)> {
+        let symlink_path = symlink_dir.join(&**path);
+        self.dotfiles
+            .iter()
+            .enumerate()
+            .filter(|(_, dotfiles)| {
+                let same_entry = dotfiles.is_stored_in(dotfile_dir);
+                !(same_entry && dotfiles.is_deployed_to(symlink_dir))
+            })
+            .find_map(|(index, dotfiles)| {
+                let owned = dotfiles.strip_symlink_dir(&symlink_path)?;
+                dotfiles.paths.contains(&owned).then_some((index, owned))
+            })
+    }
+
+    /// Fail if a dotfiles entry other than the one for `symlink_dir` and `dotfile_dir` already
+    /// tracks the dotfile deployed to the same location as `path`, see [`Config::owner_of`].
+    pub fn check_owner(
+        &self,
+        symlink_dir: &SymlinkDir,
+        dotfile_dir: &DotfilesDir,
+        path: &DotfilePath,
+    ) -> Result<()> {
+        match self.owner_of(symlink_dir, dotfile_dir, path) {
+            Some((index, _)) => Err(Error::DotfileTrackedElsewhere(
+                symlink_dir.join(&**path),
+                self.dotfiles[index].dotfile_directory.to_path_buf(),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Take over a dotfile tracked by another dotfiles entry, see [`Config::owner_of`]. The stored
+    /// file is moved from the other entry's dotfiles directory into `dotfile_dir`, both entries
+    /// are updated and an existing symlink is pointed at the new location.
+    pub fn steal_path(
+        &mut self,
+        symlink_dir: &SymlinkDir,
+        dotfile_dir: &DotfilesDir,
+        path: &DotfilePath,
+    ) -> Result<()> {
+        let (owner, owned) =
+            self.owner_of(symlink_dir, dotfile_dir, path)
+                .ok_or_else(|| {
+                    Error::NoMatchingDotfileConfigured(symlink_dir.join(&**path))
+                })?;
+
+        let old_store_path = self.dotfiles[owner].store_path(&owned);
+        let new_store_path = dotfile_dir.join(&**path);
+        if new_store_path.symlink_metadata().is_ok() {
+            return Err(Error::DotfilePathAlreadyExists(path.to_path_buf()));
+        }
+        if let Some(parent) = new_store_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        log::info!(
+            "moving {0} to {1}",
+            old_store_path.display(),
+            new_store_path.display()
+        );
+        paths::move_file(&old_store_path, &new_store_path)?;
+        let names = self.dotfiles[owner].symlink_names.remove(&owned);
+        let _ = self.dotfiles[owner].untrack(&owned);
+
+        let dotfiles = self.get_or_create_dotfiles(symlink_dir, dotfile_dir)?;
+        let _ = dotfiles.paths.insert(path.clone());
+        if let Some(names) = names {
+            let _ = dotfiles.symlink_names.insert(path.clone(), names);
+        }
+        for symlink_path in dotfiles.all_symlink_paths(path) {
+            if let Ok(md) = symlink_path.symlink_metadata() {
+                if md.is_symlink() {
+                    fs::remove_file(&symlink_path)?;
+                    dotfiles.link(&dotfiles.target_for(path), &symlink_path)?;
+                }
+            }
+        }
+        self.write_toml_config()
+    }
+
+    /// Return the first dotfiles entry stored in `dotfile_dir`. A dotfiles directory deployed to
+    /// several symlink directories has one entry per symlink directory.
+    pub fn get_dotfiles(&self, dotfile_dir: &DotfilesDir) -> Option<&Dotfiles> {
+        let index = self.position_of(dotfile_dir, None)?;
+        Some(&self.dotfiles[index])
+    }
+
+    /// Mutable version of [`Config::get_dotfiles`]. Changes are not written to the config file.
+    pub fn get_dotfiles_mut(
+        &mut self,
+        dotfile_dir: &DotfilesDir,
+    ) -> Option<&mut Dotfiles> {
+        let index = self.position_of(dotfile_dir, None)?;
+        Some(&mut self.dotfiles[index])
+    }
+
+    /// Like [`Config::get_dotfiles_mut`], but for the entry stored in `dotfile_dir` that is
+    /// deployed to `symlink_dir` in particular.
+    pub fn get_dotfiles_for_mut(
+        &mut self,
+        symlink_dir: &SymlinkDir,
+        dotfile_dir: &DotfilesDir,
+    ) -> Option<&mut Dotfiles> {
+        let index = self.dotfiles_index(symlink_dir, dotfile_dir)?;
+        Some(&mut self.dotfiles[index])
+    }
+
+    /// The dotfiles entries of this config.
+    pub fn dotfiles(&self) -> &[Dotfiles] {
+        &self.dotfiles
+    }
+
+    /// Whether there is a dotfiles entry for the given directories.
+    pub fn has_dotfiles(
+        &self,
+        symlink_dir: &SymlinkDir,
+        dotfile_dir: &DotfilesDir,
+    ) -> bool {
+        self.dotfiles_index(symlink_dir, dotfile_dir).is_some()
+    }
+
+    /// Remove the dotfiles entry for the given directories if it tracks no dotfiles, e.g. one
+    /// created with [`Config::get_or_create_dotfiles`] for a stow that failed. Returns whether
+    /// it was removed. The config is not written.
+    pub fn remove_dotfiles_if_unused(
+        &mut self,
+        symlink_dir: &SymlinkDir,
+        dotfile_dir: &DotfilesDir,
+    ) -> bool {
+        match self.dotfiles_index(symlink_dir, dotfile_dir) {
+            Some(index) if self.dotfiles[index].paths.is_empty() => {
+                let _ = self.dotfiles.remove(index);
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Return the dotfiles entry for the given directories, creating it if it doesn't exist yet.
+    /// The config is not written.
+    pub fn get_or_create_dotfiles(
+        &mut self,
+        symlink_dir: &SymlinkDir,
+        dotfile_dir: &DotfilesDir,
+    ) -> Result<&mut Dotfiles> {
+        let index = match self.dotfiles_index(symlink_dir, dotfile_dir) {
+            Some(index) => index,
+            None => {
+                self.dotfiles
+                    .push(self.new_dotfiles(symlink_dir, dotfile_dir)?);
+                self.dotfiles.len() - 1
+            },
+        };
+        Ok(&mut self.dotfiles[index])
+    }
+
+    /// Pairs of indices of dotfiles entries in [`Config::dotfiles`] that refer to the same
+    /// dotfiles and symlink directories spelled differently, e.g. once with and once without
+    /// resolving a symlink. Such entries should be merged.
+    pub fn duplicate_entries(&self) -> Vec<(usize, usize)> {
+        let mut duplicates = Vec::new();
+        for (i, a) in self.dotfiles.iter().enumerate() {
+            for (j, b) in self.dotfiles.iter().enumerate().skip(i + 1) {
+                if a.resolved_dirs() == b.resolved_dirs() {
+                    duplicates.push((i, j));
+                }
+            }
+        }
+        duplicates
+    }
+
+    /// Check that the entries of this config agree with each other without looking at the
+    /// filesystem beyond the configured directories themselves: no two entries may share their
+    /// dotfiles and symlink directories, see [`Config::duplicate_entries`], every dotfile path
+    /// must be relative, and copied dotfiles and dotfiles with other names must be tracked.
+    pub fn verify_integrity(&self) -> Result<()> {
+        if let Some((_, b)) = self.duplicate_entries().first() {
+            let dir = self.dotfiles[*b].dotfile_directory.to_path_buf();
+            return Err(Error::DotfilesDirAlreadyConfigured(dir));
+        }
+        for dotfiles in &self.dotfiles {
+            if let Some(path) = dotfiles.paths.iter().find(|path| !path.is_relative()) {
+                return Err(Error::DotfilePathMustBeRelative(path.to_path_buf()));
+            }
+            let untracked = dotfiles
+                .copied_paths
+                .iter()
+                .chain(dotfiles.symlink_names.keys())
+                .chain(dotfiles.stored_as.keys())
+                .find(|path| !dotfiles.paths.contains(*path));
+            if let Some(path) = untracked {
+                let store_path = dotfiles.store_path(path);
+                return Err(Error::NoMatchingDotfileConfigured(store_path));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the dotfiles and symlink directory of every enabled entry exist and are
+    /// directories. Disabled entries are left out, so a directory that only exists on some
+    /// machines can be configured in an entry disabled elsewhere, see [`Dotfiles::enabled`].
+    /// The symlink directory of an entry whose `symlink_dir_command` hasn't been run is left out
+    /// as well, since the configured one isn't used.
+    pub fn validate(&self) -> Result<()> {
+        for dotfiles in self.dotfiles.iter().filter(|dotfiles| dotfiles.enabled) {
+            let mut dirs = vec![&*dotfiles.dotfile_directory];
+            let unresolved = dotfiles.symlink_dir_command.is_some()
+                && dotfiles.resolved_symlink_directory.is_none();
+            if !unresolved {
+                dirs.push(&**dotfiles.symlink_dir());
+            }
+            for dir in dirs {
+                if !dir.exists() {
+                    return Err(Error::PathDoesNotExist(dir.to_path_buf()));
+                }
+                if !dir.is_dir() {
+                    return Err(Error::NotADirectory(dir.to_path_buf()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Build an index from the dotfiles and symlink directory of each entry to the position of
+    /// the entry in [`Config::dotfiles`], for looking up many entries without scanning the list
+    /// each time. Each entry is indexed both by its directories as configured and as resolved
+    /// by [`paths::resolve_dir`], the same way [`Config::get_dotfiles`] matches them, so keys
+    /// spelled differently have to be resolved first. The index borrows the config, so it can't
+    /// become stale; it has to be rebuilt after entries are added or removed. If several entries
+    /// share the same directories, the first one is indexed.
+    pub fn get_dotfiles_index(&self) -> HashMap<(&Path, &Path), usize> {
+        let mut index = HashMap::with_capacity(2 * self.dotfiles.len());
+        for (i, dotfiles) in self.dotfiles.iter().enumerate() {
+            let (store, target) = dotfiles.resolved_dirs();
+            let configured = (
+                dotfiles.dotfile_directory.as_path(),
+                dotfiles.symlink_dir().as_path(),
+            );
+            for key in [configured, (store.as_path(), target.as_path())] {
+                let _ = index.entry(key).or_insert(i);
+            }
+        }
+        index
+    }
+
+    pub(crate) fn dotfiles_index(
+        &self,
+        symlink_dir: &SymlinkDir,
+        dotfile_dir: &DotfilesDir,
+    ) -> Option<usize> {
+        self.position_of(dotfile_dir, Some(symlink_dir))
+    }
+
+    /// Position of the first entry stored in `dotfile_dir` and, if given, deployed to
+    /// `symlink_dir`, however the directories are spelled. Each directory is resolved once
+    /// rather than for every entry.
+    fn position_of(
+        &self,
+        dotfile_dir: &DotfilesDir,
+        symlink_dir: Option<&SymlinkDir>,
+    ) -> Option<usize> {
+        let store = paths::resolve_dir(dotfile_dir);
+        let target = symlink_dir.map(|dir| paths::resolve_dir(dir));
+        self.dotfiles.iter().position(|dotfiles| {
+            let resolved = dotfiles.resolved_dirs();
+            resolved.0 == store
+                && target.as_ref().is_none_or(|target| resolved.1 == *target)
+        })
+    }
+
+    /// Search `$HOME` and `$XDG_CONFIG_HOME` for config file path.
+    fn get_config_file() -> Option<PathBuf> {
+        let config_path = Self::config_file_path().ok()?;
+
+        if config_path.exists() {
+            return Some(config_path);
+        };
+        None
+    }
+
+    fn config_file_path() -> Result<PathBuf> {
+        Ok(Dirs::from_env()?.config_file())
+    }
+
+    /// Version of ghmd that wrote the config file before it was loaded, if it has been written
+    /// before and recorded its version.
+    pub fn loaded_version(&self) -> Option<&str> {
+        self.loaded_version.as_deref()
+    }
+
+    /// Whether dotfiles directories that aren't under version control are warned about, see
+    /// [`Config::warn_unversioned`].
+    pub fn vcs_warning(&self) -> bool {
+        self.vcs_warning
+    }
+
+    /// Names of files or directories besides `.git` and `.hg` that mark a directory as being
+    /// under version control, see [`is_version_controlled`](crate::is_version_controlled).
+    pub fn vcs_markers(&self) -> &[String] {
+        &self.vcs_markers
+    }
+
+    fn empty(backup_directory: PathBuf) -> Self {
+        Self {
+            ghmd_version: Some(version::VERSION.to_string()),
+            schema_version: Some(version::CONFIG_SCHEMA_VERSION),
+            last_deploy: None,
+            vcs_warning: true,
+            vcs_markers: Vec::new(),
+            loaded_version: None,
+            dotfiles: Vec::new(),
+            backup_directory,
+            ephemeral: false,
+            skipped: Vec::new(),
+            file_state: Tracked::default(),
+            merge_external_edits: false,
+            durable: false,
+            snapshot_keep: None,
+            xdg_dirs: None,
+            hostname: None,
+        }
+    }
+
+    /// Deploy the dotfiles entries that apply to the machine named `hostname` rather than to
+    /// the one ghmd runs on, see [`Config::deploys_here`]. [`Config::load`] sets the name of
+    /// the machine ghmd runs on, see [`hostname`](crate::hostname).
+    pub fn set_hostname(&mut self, hostname: &str) {
+        self.hostname = Some(hostname.to_string());
+    }
+
+    /// Whether `dotfiles` applies to this machine, i.e. to its hostname and operating system,
+    /// and is in the `default` profile, see [`Dotfiles::platforms`] and [`Dotfiles::profiles`].
+    pub fn applies_here(&self, dotfiles: &Dotfiles) -> bool {
+        let hostname = self.hostname.as_deref().unwrap_or_default();
+        dotfiles.applies_to_host(hostname) && dotfiles.in_profile(DEFAULT_PROFILE)
+    }
+
+    /// Whether deploys that aren't given a selection deploy the dotfiles of `dotfiles`, i.e. the
+    /// entry is enabled and applies to this machine, see [`Config::applies_here`].
+    pub fn deploys_here(&self, dotfiles: &Dotfiles) -> bool {
+        dotfiles.enabled && self.applies_here(dotfiles)
+    }
+
+    /// Resolve dotfile paths starting with the token of an XDG base directory against `dirs`
+    /// from now on, see [`XdgDir`]. [`Config::load`] sets the directories of the environment,
+    /// which are resolved once per run.
+    pub fn set_xdg_dirs(&mut self, dirs: Dirs) {
+        for dotfiles in &mut self.dotfiles {
+            dotfiles.xdg_dirs = Some(dirs.clone());
+        }
+        self.xdg_dirs = Some(dirs);
+    }
+
+    /// XDG base directories of this run, see [`Config::set_xdg_dirs`].
+    pub fn xdg_dirs(&self) -> Option<&Dirs> {
+        self.xdg_dirs.as_ref()
+    }
+
+    /// A new dotfiles entry for the given directories resolving XDG tokens like the existing
+    /// ones, see [`Config::set_xdg_dirs`].
+    pub(crate) fn new_dotfiles(
+        &self,
+        symlink_dir: &SymlinkDir,
+        dotfile_dir: &DotfilesDir,
+    ) -> Result<Dotfiles> {
+        let mut dotfiles = Dotfiles::new(symlink_dir, dotfile_dir)?;
+        dotfiles.xdg_dirs = self.xdg_dirs.clone();
+        Ok(dotfiles)
+    }
+
+    /// An empty config that is never written to disk, for tests.
+    #[cfg(test)]
+    pub(crate) fn in_memory(backup_directory: PathBuf) -> Self {
+        let mut config = Self::empty(backup_directory);
+        config.ephemeral = true;
+        config
+    }
+
+    /// Whether the given dotfile is on the skip list of this machine, see [`MachineState`].
+    pub fn is_skipped(&self, path: &DotfilePath) -> bool {
+        self.skipped
+            .iter()
+            .any(|pattern| pattern.matches_path(path))
+    }
+
+    /// Path of the config file.
+    pub fn file_path() -> Result<PathBuf> {
+        Self::config_file_path()
+    }
+
+    /// Return a copy of this config in which every dotfiles entry uses `dir` as its symlink
+    /// directory. The copy is never written to disk, so it can be used to deploy or inspect
+    /// dotfiles against an alternate location without affecting the saved config. Dotfiles
+    /// tracked below an XDG token go into the default location of its base directory inside
+    /// `dir`, e.g. `dir/.config` for `${CONFIG}`, see [`XdgDir`].
+    pub fn with_target_dir(&self, dir: &SymlinkDir) -> Config {
+        let mut config = self.clone();
+        config.ephemeral = true;
+        for dotfiles in &mut config.dotfiles {
+            dotfiles.symlink_directory = dir.clone();
+            dotfiles.resolved_symlink_directory = None;
+            dotfiles.xdg_dirs = None;
+        }
+        config.xdg_dirs = None;
+        let dirs = Dirs::from_env_with(|key| match key {
+            "HOME" => Some(dir.as_os_str().to_owned()),
+            _ => None,
+        });
+        if let Ok(dirs) = dirs {
+            config.set_xdg_dirs(dirs);
+        }
+        config
+    }
+
+    /// Return a copy of this config for deploying into `user_home`, the home directory of
+    /// another user, the dotfiles deployed into `home`, the home directory of the invoking user.
+    /// Entries whose symlink directory is inside `home` use the same directory inside
+    /// `user_home`, other entries are left out. The skip list is the one of the state file in
+    /// `user_home`, see [`MachineState`]. Dotfiles tracked below an XDG token go into the
+    /// default location of its base directory inside `user_home`. Like
+    /// [`Config::with_target_dir`] the copy is never written to disk.
+    pub fn with_home_dir(&self, home: &Path, user_home: &Path) -> Result<Config> {
+        let mut config = self.clone();
+        config.ephemeral = true;
+        config
+            .dotfiles
+            .retain(|dotfiles| dotfiles.symlink_dir().starts_with(home));
+        for dotfiles in &mut config.dotfiles {
+            let dir = dotfiles
+                .symlink_dir()
+                .strip_prefix(home)
+                .unwrap_or(Path::new(""));
+            dotfiles.symlink_directory = SymlinkDir(user_home.join(dir));
+            dotfiles.resolved_symlink_directory = None;
+        }
+        let dirs = Dirs::from_env_with(|key| match key {
+            "HOME" => Some(user_home.as_os_str().to_owned()),
+            _ => None,
+        })?;
+        config.skipped = MachineState::load_from(&dirs.state_file())?.skip_patterns()?;
+        config.set_xdg_dirs(dirs);
+        Ok(config)
+    }
+
+    /// Return a copy of this config with only the dotfiles entries that apply to the host
+    /// `hostname` running this build's operating system with the `default` profile, see
+    /// [`Dotfiles::platforms`] and [`Dotfiles::profiles`]. Like [`Config::with_target_dir`] the
+    /// copy is never written to disk, and nothing is read from the filesystem.
+    pub fn clone_for_host(&self, hostname: &str) -> Config {
+        let mut config = self.clone();
+        config.ephemeral = true;
+        config.dotfiles.retain(|dotfiles| {
+            dotfiles.applies_to_host(hostname) && dotfiles.in_profile(DEFAULT_PROFILE)
+        });
+        config.set_hostname(hostname);
+        config
+    }
+
+    /// Merge changes made to the config file by others since it was loaded when writing this
+    /// config, instead of failing with [`Error::ConfigChangedOnDisk`]. Changes to the same
+    /// dotfiles entry that contradict each other still fail with
+    /// [`Error::ConfigMergeConflict`].
+    pub fn set_merge_external_edits(&mut self, merge: bool) {
+        self.merge_external_edits = merge;
+    }
+
+    /// Never write this config to disk, not even when it is saved or dropped, because it was
+    /// only loaded to inspect the dotfiles. Upgrading the config file or replacing a corrupt one
+    /// with its backup is left to the next command that changes the config.
+    pub fn set_read_only(&mut self) {
+        self.ephemeral = true;
+    }
+
+    /// Set `expand_dest` on every enabled dotfiles entry with a `${NAME}` in the symlink path of
+    /// one of its dotfiles, so that every later command expands them like a deploy with
+    /// [`DeployOptions::expand_dest`] does. Returns the number of entries changed.
+    pub fn record_expand_dest(&mut self) -> usize {
+        let mut changed = 0;
+        for dotfiles in self
+            .dotfiles
+            .iter_mut()
+            .filter(|d| d.enabled && !d.expand_dest)
+        {
+            if dotfiles.has_env_in_symlink_paths() {
+                dotfiles.expand_dest = true;
+                changed += 1;
+            }
+        }
+        changed
+    }
+
+    /// Sync the config file and the directories containing the symlinks and files created by
+    /// deploys, stows and restores of this config to disk, so that the changes survive a crash or
+    /// power loss right after they were made. This costs a disk flush per written file and
+    /// created symlink, which makes deploying many dotfiles noticeably slower. The
+    /// `durable` field of the options of an operation requests the same for that operation
+    /// only.
+    pub fn set_durable(&mut self, durable: bool) {
+        self.durable = durable;
+    }
+
+    /// Save configuration variables to `$XDG_CONFIG_HOME/ghmd/config.toml`.
+    ///
+    /// If this config was loaded from the config file and the file has been changed by
+    /// something else since, it is only written if the changes can be merged and merging was
+    /// enabled with [`Config::set_merge_external_edits`].
+    pub fn write_toml_config(&self) -> Result<()> {
+        if self.ephemeral {
+            return Ok(());
+        }
+        let config_file_path = Self::config_file_path()?;
+        let state = match self.file_state.get() {
+            Some(state) => state,
+            None => {
+                self.write_toml_config_to(&config_file_path)?;
+                self.snapshot_or_warn();
+                return Ok(());
+            },
+        };
+
+        let (dotfiles, external) = match self.merge_with_file(&config_file_path, &state) {
+            Ok(merged) => merged,
+            Err(e) => {
+                // dropping the config must not try again
+                self.file_state.set(FileState {
+                    refused: true,
+                    ..state
+                });
+                return Err(e);
+            },
+        };
+
+        let mut merged = self.clone();
+        merged.ephemeral = true;
+        merged.dotfiles = dotfiles;
+        let written = merged.write_toml(&config_file_path)?;
+        let hash = remote::hash(written.as_bytes());
+        if state.hash.as_ref() != Some(&hash) {
+            merged.snapshot_or_warn();
+        }
+        self.file_state.set(FileState {
+            hash: Some(hash),
+            written: std::mem::take(&mut merged.dotfiles),
+            external,
+            ..state
+        });
+        Ok(())
+    }
+
+    /// Take a snapshot of the config just written, see [`Config::snapshot`]. The config file
+    /// itself is fine if that fails, so it's only worth a warning.
+    fn snapshot_or_warn(&self) {
+        if let Err(e) = self.snapshot() {
+            log::warn!("failed to take a snapshot of the config: {0}", e);
+        }
+    }
+
+    /// Dotfiles entries to write to the config file at `config_file_path`, last read or written
+    /// in `state`, along with the entries of the file including the changes others made to it
+    /// since it was loaded.
+    fn merge_with_file(
+        &self,
+        config_file_path: &Path,
+        state: &FileState,
+    ) -> Result<(Vec<Dotfiles>, Vec<Dotfiles>)> {
+        let existing = fs::read_to_string(config_file_path).ok();
+        let conflict = |e| Error::ConfigMergeConflict(config_file_path.to_path_buf(), e);
+        let hash = existing.as_deref().map(|c| remote::hash(c.as_bytes()));
+        let unchanged = hash == state.hash;
+        let external = if unchanged {
+            state.external.clone()
+        } else if self.merge_external_edits {
+            let theirs = existing
+                .as_deref()
+                .and_then(|contents| toml::from_str::<Self>(contents).ok())
+                .map(|mut c| {
+                    c.ephemeral = true;
+                    std::mem::take(&mut c.dotfiles)
+                })
+                .ok_or_else(|| {
+                    conflict("it is missing or can't be parsed".to_string())
+                })?;
+            log::info!("merging changes made to {0}", config_file_path.display());
+            merge::merge_entries(&state.written, &state.external, &theirs)
+                .map_err(conflict)?
+        } else {
+            return Err(Error::ConfigChangedOnDisk(config_file_path.to_path_buf()));
+        };
+        let dotfiles = if external == state.base {
+            self.dotfiles.clone()
+        } else {
+            merge::merge_entries(&state.base, &self.dotfiles, &external)
+                .map_err(conflict)?
+        };
+        Ok((dotfiles, external))
+    }
+
+    /// Save configuration variables like [`Config::write_toml_config`] and consume the config,
+    /// so that a failure to save is reported to the caller instead of being logged when the
+    /// config is dropped.
+    pub fn save(mut self) -> Result<()> {
+        let result = self.write_toml_config();
+        self.ephemeral = true;
+        result
+    }
+
+    /// Save configuration variables to `config_file_path`, regardless of whether this config is
+    /// ephemeral.
+    pub fn write_toml_config_to(&self, config_file_path: &Path) -> Result<()> {
+        let _ = self.write_toml(config_file_path)?;
+        Ok(())
+    }
+
+    /// Write this config to `config_file_path`, returning what was written.
+    fn write_toml(&self, config_file_path: &Path) -> Result<String> {
+        Dirs::create(
+            config_file_path
+                .parent()
+                .ok_or(Error::CannotDetermineConfigDir)?,
+        )?;
+        let mut toml = toml::to_string_pretty(&self).unwrap();
+        if let Ok(existing) = fs::read_to_string(config_file_path) {
+            // the parsed config must not be written back when it is dropped
+            let understood = toml::from_str::<Self>(&existing).ok().map(|mut parsed| {
+                parsed.set_read_only();
+                toml::to_string_pretty(&parsed).unwrap()
+            });
+            // keep comments, formatting and unknown keys the user added to the existing file
+            if let Some(merged) =
+                toml_merge::merge_documents(&existing, understood.as_deref(), &toml)
+            {
+                toml = merged;
+            }
+            // keep the previous config around in case this write doesn't complete, but never
+            // replace a good backup with a corrupt config
+            if existing != toml && understood.is_some() {
+                let backup_path = Self::backup_file_path(config_file_path);
+                paths::write_atomic(&backup_path, &existing, self.durable)?;
+            }
+        }
+        paths::write_atomic(config_file_path, &toml, self.durable)?;
+        Ok(toml)
+    }
+
+    /// Path of the copy of the previous contents of the config file at `config_file_path`.
+    pub fn backup_file_path(config_file_path: &Path) -> PathBuf {
+        let mut path = config_file_path.as_os_str().to_owned();
+        path.push(".bak");
+        PathBuf::from(path)
+    }
+}
+
+/// Move `path` into `backup_directory`, at its absolute path below it, e.g. `/home/me/.vimrc` to
+/// `<backup_directory>/home/me/.vimrc`, so that files of the same name in different directories
+/// don't overwrite each other, even when they are backed up by parallel deploys.
+fn backup_to(backup_directory: &Path, path: &Path) -> Result<()> {
+    let relative: PathBuf = paths::normalize(path)
+        .components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .collect();
+    if relative.as_os_str().is_empty() {
+        return Err(Error::UnexpectedError("couldn't get filename from path"));
+    }
+    let backup_path = backup_directory.join(relative);
+    log::debug!(
+        "backing up {0} to {1}",
+        path.display(),
+        backup_path.display()
+    );
+    Dirs::create(backup_path.parent().unwrap_or(backup_directory))?;
+    paths::move_file(&path.to_path_buf(), &backup_path)?;
+    Ok(())
+}
+
+/// Sync the directories containing `changed` to disk if `durable` is set, see
+/// [`paths::sync_parent_dir`].
+fn sync_dirs(durable: bool, changed: &[&Path]) -> Result<()> {
+    if durable {
+        for path in changed {
+            paths::sync_parent_dir(path)?;
+        }
+    }
+    Ok(())
+}
 
 impl Drop for Config {
     fn drop(&mut self) {
-        self.write_toml_config().unwrap();
+        if self.file_state.get().is_some_and(|state| state.refused) {
+            return;
+        }
+        if let Err(e) = self.write_toml_config() {
+            log::error!("failed to save config: {0}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::FileTimes;
+
+    use crate::conflict::Conflict;
+    use crate::observer::Observer;
+    use crate::selector::{parse_age, MtimeFilter};
+
+    /// A config with a single entry storing `paths` in `<tmp>/dots` and deploying them to
+    /// `<tmp>/home`. Each stored file contains its own path.
+    fn setup(paths: &[&str]) -> (tempfile::TempDir, Config) {
+        let tmp = tempfile::tempdir().unwrap();
+        let store = tmp.path().join("dots");
+        let home = tmp.path().join("home");
+        fs::create_dir_all(&store).unwrap();
+        fs::create_dir_all(&home).unwrap();
+        let mut dotfiles = Dotfiles::new(
+            &home.try_into().unwrap(),
+            &store.clone().try_into().unwrap(),
+        )
+        .unwrap();
+        for path in paths {
+            let stored = store.join(path);
+            fs::create_dir_all(stored.parent().unwrap()).unwrap();
+            fs::write(&stored, path).unwrap();
+            let _ = dotfiles.paths.insert(DotfilePath(PathBuf::from(path)));
+        }
+        let mut config = Config::in_memory(tmp.path().join("backup"));
+        config.dotfiles.push(dotfiles);
+        (tmp, config)
+    }
+
+    #[test]
+    fn deploying_reports_only_the_directories_it_created() {
+        let (tmp, config) = setup(&["a/b/c/deep", "a/b/other", "top"]);
+        let home = tmp.path().join("home");
+        fs::create_dir(home.join("a")).unwrap();
+        let symlinks = ["a/b/c/deep", "a/b/other", "top"].map(|path| home.join(path));
+
+        let dry_run = DeployOptions::new().dry_run(true);
+        let summary = config
+            .deploy_paths_with(symlinks[..1].to_vec(), &dry_run)
+            .unwrap();
+        assert_eq!(summary.created_dirs, [home.join("a/b"), home.join("a/b/c")]);
+        assert!(!home.join("a/b").exists());
+
+        let summary = config.deploy_paths(symlinks.to_vec()).unwrap();
+        assert_eq!(summary.created, 3);
+        // `a` already existed and `a/b` is only created once
+        assert_eq!(summary.created_dirs, [home.join("a/b"), home.join("a/b/c")]);
+        assert!(symlinks.iter().all(|symlink| symlink.is_symlink()));
+
+        let summary = config.deploy_paths(symlinks.to_vec()).unwrap();
+        assert_eq!(summary.unchanged, 3);
+        assert!(summary.created_dirs.is_empty());
+    }
+
+    #[test]
+    fn links_are_converted_between_absolute_and_relative_targets() {
+        let (tmp, mut config) = setup(&[".vimrc", "sub/.inputrc", "foreign"]);
+        let (home, dots) = (tmp.path().join("home"), tmp.path().join("dots"));
+        let tracked = [".vimrc", "sub/.inputrc"];
+        let _ = config
+            .deploy_paths(tracked.iter().map(|path| home.join(path)).collect())
+            .unwrap();
+        // a symlink at a tracked location pointing elsewhere is left alone
+        paths::create_symlink(&tmp.path().join("elsewhere"), &home.join("foreign"))
+            .unwrap();
+        let targets = || tracked.map(|path| fs::read_link(home.join(path)).unwrap());
+        assert!(targets().iter().all(|target| target.is_absolute()));
+
+        let summary = config.convert_links(LinkStyle::Relative).unwrap();
+        assert_eq!(summary.converted, 2);
+        assert_eq!(summary.skipped, [home.join("foreign")]);
+        assert_eq!(
+            targets(),
+            [
+                PathBuf::from("../dots/.vimrc"),
+                PathBuf::from("../../dots/sub/.inputrc")
+            ]
+        );
+        for path in tracked {
+            assert_eq!(fs::read_to_string(home.join(path)).unwrap(), path);
+        }
+        assert_eq!(config.dotfiles[0].link_style(), LinkStyle::Relative);
+
+        let summary = config.convert_links(LinkStyle::Relative).unwrap();
+        assert_eq!((summary.converted, summary.unchanged), (0, 2));
+
+        let summary = config.convert_links(LinkStyle::Absolute).unwrap();
+        assert_eq!(summary.converted, 2);
+        assert_eq!(targets(), tracked.map(|path| dots.join(path)));
+        assert_eq!(config.dotfiles[0].link_style(), LinkStyle::Absolute);
+        assert_eq!(
+            fs::read_link(home.join("foreign")).unwrap(),
+            tmp.path().join("elsewhere")
+        );
+    }
+
+    #[test]
+    fn stow_options_apply_through_the_library() {
+        let (tmp, mut config) = setup(&[]);
+        let (home, dots) = (tmp.path().join("home"), tmp.path().join("dots"));
+        fs::write(home.join("a"), "a").unwrap();
+        let (symlink_dir, dotfile_dir) = (
+            config.dotfiles[0].symlink_dir().clone(),
+            config.dotfiles[0].dotfile_directory.clone(),
+        );
+        let stow = |config: &mut Config, options: &StowOptions| {
+            config
+                .stow_paths_with(
+                    symlink_dir.clone(),
+                    dotfile_dir.clone(),
+                    vec![dotfile_path("a")],
+                    options,
+                )
+                .unwrap()
+        };
+
+        let outcome = stow(&mut config, &StowOptions::new().dry_run(true));
+        assert_eq!(outcome.stowed, [dotfile_path("a")]);
+        assert!(home.join("a").is_file() && !home.join("a").is_symlink());
+        assert!(!dots.join("a").exists());
+        assert!(!config.dotfiles[0].has_path(&dotfile_path("a")));
+
+        let options = StowOptions::new()
+            .link_style(LinkStyle::Relative)
+            .durable(true);
+        let _ = stow(&mut config, &options);
+        assert_eq!(
+            fs::read_link(home.join("a")).unwrap(),
+            Path::new("../dots/a")
+        );
+        assert_eq!(fs::read_to_string(dots.join("a")).unwrap(), "a");
+        assert!(config.dotfiles[0].has_path(&dotfile_path("a")));
+    }
+
+    #[test]
+    fn deploy_options_apply_through_the_library() {
+        let (tmp, mut config) = setup(&["conflict", "foreign", "listed", "hard"]);
+        let home = tmp.path().join("home");
+        let deploy = |config: &Config, path: &str, options: &DeployOptions| {
+            config.deploy_paths_with(vec![home.join(path)], options)
+        };
+
+        // something in the way is skipped, or backed up and replaced
+        fs::write(home.join("conflict"), "mine").unwrap();
+        let conflict = deploy(&config, "conflict", &DeployOptions::new());
+        assert!(matches!(conflict, Err(Error::SymlinkPathIsNotASymlink(_))));
+        let skipped = DeployOptions::new().skip_conflicts(true);
+        let summary = deploy(&config, "conflict", &skipped).unwrap();
+        assert_eq!((summary.created, summary.skipped), (0, 0));
+        let summary =
+            deploy(&config, "conflict", &DeployOptions::new().backup(true)).unwrap();
+        assert_eq!(summary.created, 1);
+        assert!(home.join("conflict").is_symlink());
+        let backup = tmp.path().join("backup").join(
+            home.join("conflict")
+                .components()
+                .filter(|component| matches!(component, Component::Normal(_)))
+                .collect::<PathBuf>(),
+        );
+        assert_eq!(fs::read_to_string(backup).unwrap(), "mine");
+
+        // other symlinks are only replaced with force
+        paths::create_symlink(&tmp.path().join("elsewhere"), &home.join("foreign"))
+            .unwrap();
+        let foreign = deploy(&config, "foreign", &DeployOptions::new());
+        assert!(matches!(foreign, Err(Error::SymlinkPathAlreadyExists(_))));
+        let summary =
+            deploy(&config, "foreign", &DeployOptions::new().force(true)).unwrap();
+        assert_eq!(summary.created, 1);
+        assert!(config.dotfiles[0]
+            .links_to(&home.join("foreign"), &dotfile_path("foreign"))
+            .unwrap());
+
+        // dotfiles on the skip list of the machine are only left out when asked to
+        config.skipped = vec![Pattern::new("listed").unwrap()];
+        let summary =
+            deploy(&config, "listed", &DeployOptions::new().skip_listed(true)).unwrap();
+        assert_eq!(summary.skipped, 1);
+        assert!(!home.join("listed").exists());
+        let summary = deploy(&config, "listed", &DeployOptions::new()).unwrap();
+        assert_eq!(summary.created, 1);
+
+        let hardlink = DeployOptions::new().link_mode(LinkMode::Hardlink);
+        let _ = deploy(&config, "hard", &hardlink).unwrap();
+        assert!(!home.join("hard").is_symlink());
+        assert!(
+            paths::same_inode(&home.join("hard"), &tmp.path().join("dots/hard")).unwrap()
+        );
+
+        // nothing is modified after now, so nothing is deployed
+        fs::remove_file(home.join("listed")).unwrap();
+        let modified = MtimeFilter {
+            newer_than: Some(SystemTime::now() + Duration::from_secs(60)),
+            ..MtimeFilter::default()
+        };
+        let summary = config
+            .deploy_all_with(&DeployOptions::new().modified(modified))
+            .unwrap();
+        assert_eq!(summary, DeploySummary::default());
+        assert!(!home.join("listed").exists());
+    }
+
+    #[test]
+    fn restore_options_apply_through_the_library() {
+        let (tmp, mut config) = setup(&["a", "b", "c"]);
+        let (home, dots) = (tmp.path().join("home"), tmp.path().join("dots"));
+        let _ = config
+            .deploy_paths(["a", "b", "c"].map(|path| home.join(path)).to_vec())
+            .unwrap();
+
+        let dry_run = RestoreOptions::new().dry_run(true);
+        config.restore_with(dotfile_path("a"), &dry_run).unwrap();
+        assert!(home.join("a").is_symlink() && dots.join("a").is_file());
+        assert!(config.dotfiles[0].has_path(&dotfile_path("a")));
+        config
+            .restore_with(dotfile_path("a"), &RestoreOptions::new())
+            .unwrap();
+        assert!(home.join("a").is_file() && !home.join("a").is_symlink());
+        assert!(!dots.join("a").exists());
+        assert!(!config.dotfiles[0].has_path(&dotfile_path("a")));
+
+        // a file newer than the stored dotfile is only replaced with force
+        fs::remove_file(home.join("b")).unwrap();
+        fs::write(home.join("b"), "newer").unwrap();
+        set_mtime(&home.join("b"), SystemTime::now() + Duration::from_secs(60));
+        let newer = config.restore_with(dotfile_path("b"), &RestoreOptions::new());
+        assert!(matches!(newer, Err(Error::RestoreTargetNewer(_))));
+        let force = RestoreOptions::new().force(true);
+        config.restore_with(dotfile_path("b"), &force).unwrap();
+        assert_eq!(fs::read_to_string(home.join("b")).unwrap(), "b");
+
+        let elsewhere = tmp.path().join("elsewhere");
+        let to = RestoreOptions::new().to(&elsewhere);
+        config.restore_with(dotfile_path("c"), &to).unwrap();
+        assert_eq!(fs::read_to_string(elsewhere.join("c")).unwrap(), "c");
+        assert!(!home.join("c").exists() && !dots.join("c").exists());
+        assert!(config.dotfiles[0].paths.is_empty());
+    }
+
+    #[test]
+    fn dotfiles_stored_as_broken_symlinks_are_not_deployed() {
+        let (tmp, mut config) = setup(&["ok"]);
+        let (home, dots) = (tmp.path().join("home"), tmp.path().join("dots"));
+        paths::create_symlink(&dots.join("missing"), &dots.join("broken")).unwrap();
+        let _ = config.dotfiles[0].paths.insert(dotfile_path("broken"));
+
+        let broken = config.deploy_paths(vec![home.join("broken")]);
+        assert!(
+            matches!(broken, Err(Error::StoreEntryBroken(p)) if p == dots.join("broken"))
+        );
+        assert!(home.join("broken").symlink_metadata().is_err());
+
+        let skip_broken = DeployOptions::new().skip_broken(true);
+        let summary = config
+            .deploy_paths_with(vec![home.join("broken")], &skip_broken)
+            .unwrap();
+        assert_eq!((summary.created, summary.skipped), (0, 1));
+
+        let summary = config.deploy_all().unwrap();
+        assert_eq!((summary.created, summary.skipped), (1, 1));
+        assert!(home.join("ok").is_symlink());
+        assert!(home.join("broken").symlink_metadata().is_err());
+    }
+
+    #[test]
+    fn existing_symlink_paths_are_examined_after_linking_fails() {
+        let (tmp, config) = setup(&["linked", "foreign", "file"]);
+        let (home, dots) = (tmp.path().join("home"), tmp.path().join("dots"));
+        let dotfiles = &config.dotfiles[0];
+        let options = DeployOptions::new();
+        // as if another process created these between checking and linking
+        paths::create_symlink(&dots.join("linked"), &home.join("linked")).unwrap();
+        paths::create_symlink(&dots.join("file"), &home.join("foreign")).unwrap();
+        fs::write(home.join("file"), "mine").unwrap();
+
+        let linked = dotfiles.deploy(&dotfile_path("linked"), &options);
+        assert_eq!(linked.unwrap(), DeployOutcome::Unchanged);
+        let foreign = dotfiles.deploy(&dotfile_path("foreign"), &options);
+        assert!(matches!(foreign, Err(Error::SymlinkPathAlreadyExists(_))));
+        let file = dotfiles.deploy(&dotfile_path("file"), &options);
+        assert!(matches!(file, Err(Error::SymlinkPathIsNotASymlink(_))));
+        assert_eq!(fs::read_to_string(home.join("file")).unwrap(), "mine");
+    }
+
+    #[test]
+    fn stowed_files_are_tracked_when_their_symlink_path_is_taken() {
+        let (tmp, mut config) = setup(&[]);
+        let (home, dots) = (tmp.path().join("home"), tmp.path().join("dots"));
+        let dotfiles = &mut config.dotfiles[0];
+        let options = StowOptions::new();
+        // the files were moved into the store before something took their place
+        for name in ["linked", "taken"] {
+            fs::write(dots.join(name), name).unwrap();
+        }
+        paths::create_symlink(&dots.join("linked"), &home.join("linked")).unwrap();
+        fs::write(home.join("taken"), "mine").unwrap();
+
+        dotfiles
+            .link_stowed(&dotfile_path("linked"), &home.join("linked"), &options)
+            .unwrap();
+        assert!(dotfiles.has_path(&dotfile_path("linked")));
+        let taken =
+            dotfiles.link_stowed(&dotfile_path("taken"), &home.join("taken"), &options);
+        assert!(matches!(taken, Err(Error::SymlinkPathAlreadyExists(_))));
+        assert!(dotfiles.has_path(&dotfile_path("taken")));
+        assert_eq!(fs::read_to_string(home.join("taken")).unwrap(), "mine");
+        assert_eq!(fs::read_to_string(dots.join("taken")).unwrap(), "taken");
+    }
+
+    #[test]
+    fn redeploying_hardlinks_changes_nothing() {
+        let (tmp, config) = setup(&["hard"]);
+        let (symlink, stored) =
+            (tmp.path().join("home/hard"), tmp.path().join("dots/hard"));
+        let hardlink = DeployOptions::new().link_mode(LinkMode::Hardlink);
+
+        let summary = config
+            .deploy_paths_with(vec![symlink.clone()], &hardlink)
+            .unwrap();
+        assert_eq!((summary.created, summary.unchanged), (1, 0));
+        assert!(paths::same_inode(&symlink, &stored).unwrap());
+
+        let summary = config
+            .deploy_paths_with(vec![symlink.clone()], &hardlink)
+            .unwrap();
+        assert_eq!((summary.created, summary.unchanged), (0, 1));
+        // deploying symlinks leaves the hardlink alone as well
+        let summary = config.deploy_paths(vec![symlink.clone()]).unwrap();
+        assert_eq!((summary.created, summary.unchanged), (0, 1));
+        assert!(!symlink.is_symlink());
+        assert!(paths::same_inode(&symlink, &stored).unwrap());
+    }
+
+    #[test]
+    fn restoring_only_removes_emptied_store_directories_with_clean_store() {
+        let tracked = ["a/b/kept", "c/d/cleaned", "c/other"];
+        let (tmp, mut config) = setup(&tracked);
+        let (home, dots) = (tmp.path().join("home"), tmp.path().join("dots"));
+        let _ = config
+            .deploy_paths(tracked.iter().map(|path| home.join(path)).collect())
+            .unwrap();
+
+        config
+            .restore_with(dotfile_path("a/b/kept"), &RestoreOptions::new())
+            .unwrap();
+        assert!(dots.join("a/b").is_dir());
+
+        let clean_store = RestoreOptions::new().clean_store(true);
+        config
+            .restore_with(dotfile_path("c/d/cleaned"), &clean_store)
+            .unwrap();
+        assert!(!dots.join("c/d").exists());
+        // `c` still holds a dotfile
+        assert!(dots.join("c/other").is_file());
+        config
+            .restore_with(dotfile_path("c/other"), &clean_store)
+            .unwrap();
+        assert!(!dots.join("c").exists());
+        assert!(dots.is_dir());
+        assert_eq!(
+            fs::read_to_string(home.join("c/d/cleaned")).unwrap(),
+            "c/d/cleaned"
+        );
+    }
+
+    #[test]
+    fn dotfiles_are_filtered_by_the_mtime_of_their_stored_files() {
+        let tracked = ["old", "new", "sub/old", "sub/new"];
+        let (tmp, mut config) = setup(&tracked);
+        let (home, dots) = (tmp.path().join("home"), tmp.path().join("dots"));
+        let now = SystemTime::now();
+        for path in tracked {
+            let age = if path.ends_with("old") { 10 } else { 1 };
+            set_mtime(&dots.join(path), now - Duration::from_secs(age * 24 * 3600));
+        }
+        let week = now - parse_age("7d").unwrap();
+        let relative = |resolved: Vec<ResolvedPath>| -> Vec<String> {
+            resolved
+                .iter()
+                .map(|resolved| resolved.relative.display().to_string())
+                .collect()
+        };
+
+        let all = config.iter_entries().collect();
+        let newer = config.filter_by_mtime(all, |time| time > week).unwrap();
+        assert_eq!(relative(newer), ["new", "sub/new"]);
+
+        // combined with glob selection
+        let selector = Selector {
+            paths: vec![Pattern::new("sub/*").unwrap()],
+            modified: MtimeFilter {
+                older_than: Some(week),
+                ..MtimeFilter::default()
+            },
+            ..Selector::default()
+        };
+        assert_eq!(relative(selector.select(&config).unwrap()), ["sub/old"]);
+
+        let modified = MtimeFilter {
+            newer_than: Some(week),
+            ..MtimeFilter::default()
+        };
+        let summary = config
+            .deploy_all_with(&DeployOptions::new().modified(modified))
+            .unwrap();
+        assert_eq!(summary.created, 2);
+        assert!(home.join("new").is_symlink() && home.join("sub/new").is_symlink());
+        assert!(!home.join("old").exists() && !home.join("sub/old").exists());
+    }
+
+    #[test]
+    fn failed_stows_roll_back_adopted_links_without_moving_them() {
+        let (tmp, mut config) = setup(&[]);
+        let (home, dots) = (tmp.path().join("home"), tmp.path().join("dots"));
+        fs::write(home.join(".c"), ".c").unwrap();
+        // `.a` was stowed before but the config lost track of it, so it's adopted
+        fs::write(dots.join(".a"), ".a").unwrap();
+        paths::create_symlink(&dots.join(".a"), &home.join(".a")).unwrap();
+        // already stored, so stowing `.b` conflicts
+        fs::write(dots.join(".b"), "stored").unwrap();
+        fs::write(home.join(".b"), ".b").unwrap();
+
+        let paths = [".c", ".a", ".b"].map(dotfile_path);
+        assert!(matches!(
+            config.dotfiles[0].stow_multiple(&paths, &StowOptions::new()),
+            Err(Error::DotfilePathAlreadyExists(_))
+        ));
+        assert!(config.dotfiles[0].paths.is_empty());
+        // the adopted link and the only copy of its file stay where they were
+        assert_eq!(fs::read_link(home.join(".a")).unwrap(), dots.join(".a"));
+        assert_eq!(fs::read_to_string(dots.join(".a")).unwrap(), ".a");
+        // the moved file is moved back
+        assert!(!home.join(".c").is_symlink() && !dots.join(".c").exists());
+        assert_eq!(fs::read_to_string(home.join(".c")).unwrap(), ".c");
+        assert_eq!(fs::read_to_string(home.join(".b")).unwrap(), ".b");
+        assert_eq!(fs::read_to_string(dots.join(".b")).unwrap(), "stored");
+    }
+
+    #[test]
+    fn failed_hardlinks_move_the_stowed_file_back() {
+        let (tmp, mut config) = setup(&[]);
+        let (home, dots) = (tmp.path().join("home"), tmp.path().join("dots"));
+        config.dotfiles[0].set_link_mode(LinkMode::Hardlink);
+        // as if `source` had just been moved into the store, with a file where the link's
+        // parent directory should be
+        let source = tmp.path().join("x");
+        fs::create_dir(dots.join("sub")).unwrap();
+        fs::write(dots.join("sub/x"), "x").unwrap();
+        fs::write(home.join("sub"), "sub").unwrap();
+
+        let path = dotfile_path("sub/x");
+        assert!(config.dotfiles[0]
+            .link_stowed(&path, &source, &StowOptions::new())
+            .is_err());
+        assert_eq!(fs::read_to_string(&source).unwrap(), "x");
+        assert!(!dots.join("sub/x").exists());
+        assert!(!config.dotfiles[0].has_path(&path));
+    }
+
+    #[test]
+    fn hardlinks_across_filesystems_are_refused_before_moving() {
+        let shm = Path::new("/dev/shm");
+        let tmp = tempfile::tempdir().unwrap();
+        if !shm.is_dir() || paths::same_device(shm, tmp.path()).unwrap() {
+            return;
+        }
+        let store = tempfile::tempdir_in(shm).unwrap();
+        let home = tmp.path().join("home");
+        fs::create_dir(&home).unwrap();
+        fs::write(home.join(".h"), ".h").unwrap();
+        let mut dotfiles = Dotfiles::new(
+            &home.clone().try_into().unwrap(),
+            &store.path().to_path_buf().try_into().unwrap(),
+        )
+        .unwrap();
+        dotfiles.set_link_mode(LinkMode::Hardlink);
+
+        let result = dotfiles.stow_multiple(&[dotfile_path(".h")], &StowOptions::new());
+        assert!(matches!(
+            result,
+            Err(Error::HardlinkAcrossDevices(dotfile, symlink))
+                if dotfile == store.path().join(".h") && symlink == home.join(".h")
+        ));
+        assert_eq!(fs::read_to_string(home.join(".h")).unwrap(), ".h");
+        assert!(!store.path().join(".h").exists());
+        assert!(dotfiles.paths.is_empty());
+    }
+
+    #[test]
+    fn resolved_paths_round_trip_through_the_conversions() {
+        let tracked = [
+            ".vimrc",
+            ".config/nvim/init.lua",
+            "a b/c",
+            ".local/share/x.d/y",
+        ];
+        let (tmp, mut config) = setup(&tracked);
+        let other_store = tmp.path().join("other");
+        fs::create_dir_all(&other_store).unwrap();
+        fs::write(other_store.join(".bashrc"), "").unwrap();
+        let home: SymlinkDir = tmp.path().join("home").try_into().unwrap();
+        let mut other = Dotfiles::new(&home, &other_store.try_into().unwrap()).unwrap();
+        let _ = other.paths.insert(DotfilePath(PathBuf::from(".bashrc")));
+        config.dotfiles.push(other);
+        let _ = config.deploy_all().unwrap();
+
+        let resolved: Vec<ResolvedPath> = config.iter_entries().collect();
+        assert_eq!(resolved.len(), tracked.len() + 1);
+        for item in &resolved {
+            let dotfiles = &config.dotfiles[item.entry_index];
+            let (store_dir, symlink_dir) =
+                (&dotfiles.dotfile_directory, dotfiles.symlink_dir());
+            assert_eq!(item.store, dotfiles.store_path(item.relative));
+            assert_eq!(item.target, dotfiles.symlink_path(item.relative));
+
+            let from_store: DotfilePath =
+                (store_dir.clone(), item.store.clone()).try_into().unwrap();
+            assert_eq!(&from_store, item.relative);
+            let from_target: DotfilePath =
+                (symlink_dir, store_dir, &item.target).try_into().unwrap();
+            assert_eq!(&from_target, item.relative);
+            let relative: DotfilePath = item.relative.to_path_buf().try_into().unwrap();
+            assert_eq!(&relative, item.relative);
+
+            assert_eq!(
+                dotfiles.strip_dotfile_dir(&item.store).as_ref(),
+                Some(item.relative)
+            );
+            assert_eq!(
+                dotfiles.strip_symlink_dir(&item.target).as_ref(),
+                Some(item.relative)
+            );
+        }
+        let per_entry: Vec<_> = config.dotfiles[1].iter_paths().collect();
+        assert_eq!(per_entry.len(), 1);
+        assert_eq!(per_entry[0].entry_index, 0);
+        assert_eq!(per_entry[0].target, resolved.last().unwrap().target);
+    }
+
+    #[test]
+    fn symlinks_point_at_literal_or_canonical_targets() {
+        let (tmp, mut config) = setup(&[".vimrc"]);
+        let link = tmp.path().join("link");
+        std::os::unix::fs::symlink(tmp.path().join("dots"), &link).unwrap();
+        config.dotfiles[0].dotfile_directory = DotfilesDir(link.clone());
+        let vimrc = DotfilePath(PathBuf::from(".vimrc"));
+        let symlink = tmp.path().join("home/.vimrc");
+        let canonical = tmp.path().join("dots/.vimrc").canonicalize().unwrap();
+
+        assert_eq!(config.dotfiles[0].target_for(&vimrc), link.join(".vimrc"));
+        let _ = config.deploy_all().unwrap();
+        assert_eq!(fs::read_link(&symlink).unwrap(), link.join(".vimrc"));
+
+        fs::remove_file(&symlink).unwrap();
+        config.dotfiles[0].set_canonical_targets(true);
+        assert_eq!(config.dotfiles[0].target_for(&vimrc), canonical);
+        let _ = config.deploy_all().unwrap();
+        assert_eq!(fs::read_link(&symlink).unwrap(), canonical);
+        assert!(config.dotfiles[0].links_to(&symlink, &vimrc).unwrap());
+
+        // the link keeps resolving after the symlinked prefix is gone
+        fs::remove_file(&link).unwrap();
+        assert_eq!(fs::read_to_string(&symlink).unwrap(), ".vimrc");
+    }
+
+    #[test]
+    fn dotfiles_are_found_by_relative_store_and_symlink_paths() {
+        let (tmp, mut config) = setup(&[".vimrc"]);
+        let vimrc = DotfilePath(PathBuf::from(".vimrc"));
+        let symlink = tmp.path().join("home/.vimrc");
+        let spellings = [
+            PathBuf::from(".vimrc"),
+            tmp.path().join("dots/.vimrc"),
+            symlink.clone(),
+        ];
+        for path in spellings {
+            assert_eq!(config.resolve_dotfile_path(&path).unwrap(), vimrc);
+            let _ = fs::remove_file(&symlink);
+            let summary = config.deploy_paths(vec![path]).unwrap();
+            assert_eq!(summary.created, 1);
+            assert!(symlink.is_symlink());
+        }
+        assert!(matches!(
+            config.resolve_dotfile_path(&tmp.path().join("elsewhere/.vimrc")),
+            Err(Error::NoMatchingDotfileConfigured(_))
+        ));
+
+        let path = config.resolve_dotfile_path(&symlink).unwrap();
+        config.restore_with(path, &RestoreOptions::new()).unwrap();
+        assert!(symlink.is_file() && !symlink.is_symlink());
+    }
+
+    #[test]
+    fn symlink_side_paths_are_preferred() {
+        let (tmp, mut config) = setup(&[]);
+        let nested = tmp.path().join("home/dots");
+        fs::create_dir_all(&nested).unwrap();
+        let home: SymlinkDir = tmp.path().join("home").try_into().unwrap();
+        let mut dotfiles =
+            Dotfiles::new(&home, &nested.clone().try_into().unwrap()).unwrap();
+        let _ = dotfiles.paths.insert(DotfilePath(PathBuf::from("x")));
+        config.dotfiles.push(dotfiles);
+
+        let candidates = config.resolve_candidates(&nested.join("x"));
+        let relative: Vec<_> = candidates
+            .iter()
+            .map(|(_, path)| path.display().to_string())
+            .collect();
+        assert_eq!(relative, ["dots/x", "dots/x", "x"]);
+    }
+
+    #[test]
+    fn validated_mutators_reject_bad_input() {
+        let (tmp, mut config) = setup(&["a"]);
+        fs::write(tmp.path().join("dots/b"), "b").unwrap();
+        let store: DotfilesDir = tmp.path().join("dots").try_into().unwrap();
+        let home: SymlinkDir = tmp.path().join("home").try_into().unwrap();
+        let path = |path: &str| DotfilePath(PathBuf::from(path));
+
+        for bad in ["../escape", "/etc/passwd", "", "./b", "x/../b"] {
+            assert!(
+                matches!(
+                    config.add_path(&home, &store, path(bad)),
+                    Err(Error::BadInput(_))
+                ),
+                "{bad}"
+            );
+        }
+        assert!(matches!(
+            config.add_path(&home, &store, path("missing")),
+            Err(Error::DotfileNotFound(_))
+        ));
+        let elsewhere: DotfilesDir = tmp.path().join("home").try_into().unwrap();
+        assert!(matches!(
+            config.add_path(&home, &elsewhere, path("b")),
+            Err(Error::DotfilesDirNotConfigured(_))
+        ));
+        assert!(config.add_path(&home, &store, path("b")).unwrap());
+        assert!(!config.add_path(&home, &store, path("b")).unwrap());
+        assert_eq!(config.dotfiles[0].paths.len(), 2);
+
+        assert!(matches!(
+            config.add_symlink_dir_alias(&store, PathBuf::from("relative")),
+            Err(Error::BadInput(_))
+        ));
+        assert!(config.dotfiles[0].symlink_dir_aliases.is_empty());
+
+        let dotfiles = &mut config.dotfiles[0];
+        if cfg!(windows) {
+            dotfiles.set_link_type(LinkType::Junction).unwrap();
+        } else {
+            assert!(matches!(
+                dotfiles.set_link_type(LinkType::Junction),
+                Err(Error::JunctionsUnsupported)
+            ));
+            assert_eq!(dotfiles.link_type(), LinkType::Symlink);
+        }
+        dotfiles.set_link_type(LinkType::Symlink).unwrap();
+    }
+
+    #[test]
+    fn dotfiles_inside_tracked_directories_are_nested() {
+        let (_tmp, config) =
+            setup(&[".vimrc", ".config/nvim/init.lua", ".local/bin/ghmd"]);
+        let mut dotfiles = config.dotfiles[0].clone();
+        assert_eq!(dotfiles.subdirectory_paths().count(), 2);
+        assert!(dotfiles.nested_paths().is_empty());
+
+        let _ = dotfiles.paths.insert(DotfilePath(PathBuf::from(".config")));
+        let nested: Vec<_> = dotfiles
+            .nested_paths()
+            .into_iter()
+            .map(|(path, dir)| (path.display().to_string(), dir.display().to_string()))
+            .collect();
+        assert_eq!(
+            nested,
+            [(".config/nvim/init.lua".to_string(), ".config".to_string())]
+        );
+    }
+
+    #[test]
+    fn entries_are_looked_up_by_their_resolved_directories() {
+        let (tmp, config) = setup(&["a"]);
+        let spelled = tmp.path().join("home/../dots");
+        let store: DotfilesDir = spelled.clone().try_into().unwrap();
+        let home: SymlinkDir = tmp.path().join("home/.").try_into().unwrap();
+        assert!(config.get_dotfiles(&store).is_some());
+        assert_eq!(config.dotfiles_index(&home, &store), Some(0));
+
+        let index = config.get_dotfiles_index();
+        let configured = (tmp.path().join("dots"), tmp.path().join("home"));
+        assert_eq!(
+            index.get(&(configured.0.as_path(), configured.1.as_path())),
+            Some(&0)
+        );
+        let resolved = (paths::resolve_dir(&spelled), paths::resolve_dir(&home));
+        assert_eq!(
+            index.get(&(resolved.0.as_path(), resolved.1.as_path())),
+            Some(&0)
+        );
+    }
+
+    #[test]
+    fn store_patterns_only_recurse_with_double_star() {
+        let (tmp, config) = setup(&["config/a.toml", "config/nested/b.toml", "top.toml"]);
+        let store: DotfilesDir = tmp.path().join("dots").try_into().unwrap();
+        let select = |pattern: &str| {
+            let selector = Selector {
+                dirs: vec![store.clone()],
+                paths: vec![Pattern::new(pattern).unwrap()],
+                literal_separator: true,
+                ..Selector::default()
+            };
+            let mut selected: Vec<String> = selector
+                .evaluate(&config)
+                .map(|selected| {
+                    selected
+                        .iter()
+                        .map(|resolved| resolved.relative.display().to_string())
+                        .collect()
+                })
+                .unwrap_or_default();
+            selected.sort();
+            selected
+        };
+
+        assert_eq!(select("config/*.toml"), ["config/a.toml"]);
+        assert_eq!(
+            select("config/**/*.toml"),
+            ["config/a.toml", "config/nested/b.toml"]
+        );
+        assert_eq!(
+            select("**/*.toml"),
+            ["config/a.toml", "config/nested/b.toml", "top.toml"]
+        );
+        assert_eq!(select("*.toml"), ["top.toml"]);
+        assert!(select("*/b.toml").is_empty());
+    }
+
+    #[test]
+    fn selector_filters_by_group_and_host() {
+        let (_tmp, mut config) = setup(&["a", "b"]);
+        config.dotfiles[0].profiles = vec!["work".to_string()];
+        config.dotfiles[0].platforms = vec!["laptop".to_string()];
+        let select = |selector: Selector| selector.select(&config).unwrap().len();
+
+        let work = Selector {
+            groups: vec!["work".to_string()],
+            ..Selector::default()
+        };
+        assert_eq!(select(work.clone()), 2);
+        let laptop = Selector {
+            hosts: vec!["laptop".to_string()],
+            ..work
+        };
+        assert_eq!(select(laptop.clone()), 2);
+        let desktop = Selector {
+            hosts: vec!["desktop".to_string()],
+            ..laptop
+        };
+        assert_eq!(select(desktop.clone()), 0);
+        assert!(matches!(
+            desktop.evaluate(&config),
+            Err(Error::NothingSelected)
+        ));
+
+        let home = Selector {
+            groups: vec!["home".to_string()],
+            ..Selector::default()
+        };
+        assert!(matches!(
+            home.evaluate(&config),
+            Err(Error::GroupNotConfigured(g)) if g == "home"
+        ));
+    }
+
+    #[test]
+    fn select_allows_patterns_matching_nothing() {
+        let (_tmp, config) = setup(&["a"]);
+        let selector = Selector {
+            paths: vec![Pattern::new("nomatch").unwrap()],
+            ..Selector::default()
+        };
+        assert!(selector.select(&config).unwrap().is_empty());
+        assert!(matches!(
+            selector.evaluate(&config),
+            Err(Error::PatternMatchesNothing(p)) if p == "nomatch"
+        ));
+    }
+
+    #[test]
+    fn deploy_selected_backs_up_and_records_complete_deploys() {
+        let (tmp, mut config) = setup(&["a", "b"]);
+        fs::write(tmp.path().join("home/a"), "in the way").unwrap();
+        let only_a = Selector {
+            paths: vec![Pattern::new("a").unwrap()],
+            ..Selector::default()
+        };
+        let _ = config.deploy_selected(&only_a).unwrap();
+        assert!(tmp.path().join("home/a").is_symlink());
+        let backup = tmp
+            .path()
+            .join("backup")
+            .join(tmp.path().strip_prefix("/").unwrap());
+        assert_eq!(
+            fs::read_to_string(backup.join("home/a")).unwrap(),
+            "in the way"
+        );
+        assert!(config.last_deploy.is_none());
+
+        let all = Selector {
+            paths: vec![Pattern::new("*").unwrap()],
+            ..Selector::default()
+        };
+        let _ = config.deploy_selected(&all).unwrap();
+        assert!(config.last_deploy.is_some());
+    }
+
+    #[test]
+    fn stow_auto_path_stows_into_the_containing_entry() {
+        let (tmp, mut config) = setup(&[]);
+        let file = tmp.path().join("home/.newconfig");
+        fs::write(&file, "new").unwrap();
+        let (dir, relative) = config.stow_auto_path(&file).unwrap();
+        assert_eq!(dir.as_path(), tmp.path().join("dots"));
+        assert_eq!(relative, dotfile_path(".newconfig"));
+        assert!(file.is_symlink());
+        assert_eq!(
+            fs::read_to_string(tmp.path().join("dots/.newconfig")).unwrap(),
+            "new"
+        );
+        assert!(config.dotfiles[0].paths.contains(&relative));
+    }
+
+    #[test]
+    fn stow_auto_path_fails_outside_every_symlink_directory() {
+        let (tmp, mut config) = setup(&[]);
+        let file = tmp.path().join("elsewhere");
+        fs::write(&file, "new").unwrap();
+        assert!(matches!(
+            config.stow_auto_path(&file),
+            Err(Error::NoStoreForPath(p)) if p == file
+        ));
+        assert!(!file.is_symlink());
+        assert_eq!(config.dotfiles.len(), 1);
+        assert!(config.dotfiles[0].paths.is_empty());
+    }
+
+    #[test]
+    fn deploy_watched_deploys_each_changed_dotfile_once() {
+        let (tmp, config) = setup(&["a", "dir/b", "dir/c"]);
+        let dots = tmp.path().join("dots");
+        fs::write(dots.join("untracked"), "").unwrap();
+        let changed = [
+            dots.join("a"),
+            dots.join("dir/b"),
+            dots.join("dir/b"),
+            dots.join("untracked"),
+            dots.join("removed"),
+        ];
+        assert_eq!(
+            config.deploy_watched(changed.iter().map(PathBuf::as_path)),
+            2
+        );
+        assert!(tmp.path().join("home/a").is_symlink());
+        assert!(tmp.path().join("home/dir/b").is_symlink());
+        assert!(!tmp.path().join("home/dir/c").exists());
+        assert!(config
+            .deploy_single_by_path(&dots.join("removed"))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn ignored_dotfiles_are_never_deployed() {
+        let (tmp, mut config) = setup(&["a", "a.bak"]);
+        config.dotfiles[0].ignore_patterns = vec!["*.bak".to_string()];
+        let home = tmp.path().join("home");
+        let ignored = DotfilePath(PathBuf::from("a.bak"));
+        let pending = config.pending_operations().unwrap();
+        assert!(pending.iter().all(|(_, path)| **path != ignored));
+        assert_eq!(pending.len(), 1);
+
+        let store = tmp.path().join("dots");
+        let changed = [store.join("a.bak")];
+        assert_eq!(
+            config.deploy_watched(changed.iter().map(PathBuf::as_path)),
+            0
+        );
+        assert_eq!(config.deploy_changed(UNIX_EPOCH).unwrap(), 1);
+        assert!(home.join("a").is_symlink());
+        fs::remove_file(home.join("a")).unwrap();
+
+        let everything = Selector {
+            paths: vec![Pattern::new("*").unwrap()],
+            ..Selector::default()
+        };
+        let summary = config.deploy_selected(&everything).unwrap();
+        assert_eq!(summary.created, 1);
+        assert!(home.join("a").is_symlink());
+        assert!(!home.join("a.bak").exists());
+    }
+
+    #[test]
+    fn deploys_leave_out_entries_for_other_hosts_and_profiles() {
+        let (tmp, mut config) = setup(&["a"]);
+        let home = tmp.path().join("home");
+        for (name, platforms, profiles) in [
+            ("laptop", &["laptop"][..], &[][..]),
+            ("work", &[][..], &["work"][..]),
+            ("desktop", &["desktop"][..], &["default"][..]),
+        ] {
+            let store = tmp.path().join(name);
+            fs::create_dir(&store).unwrap();
+            fs::write(store.join(name), name).unwrap();
+            let mut dotfiles = config
+                .new_dotfiles(
+                    &home.clone().try_into().unwrap(),
+                    &store.try_into().unwrap(),
+                )
+                .unwrap();
+            let _ = dotfiles.paths.insert(DotfilePath(PathBuf::from(name)));
+            dotfiles.platforms = platforms.iter().map(|p| p.to_string()).collect();
+            dotfiles.profiles = profiles.iter().map(|p| p.to_string()).collect();
+            config.dotfiles.push(dotfiles);
+        }
+        config.set_hostname("laptop");
+
+        let pending: Vec<_> = config
+            .pending_operations()
+            .unwrap()
+            .into_iter()
+            .map(|(_, path)| path.to_path_buf())
+            .collect();
+        assert_eq!(pending, [PathBuf::from("a"), PathBuf::from("laptop")]);
+        let _ = config.deploy_all().unwrap();
+        assert!(home.join("a").is_symlink());
+        assert!(home.join("laptop").is_symlink());
+        assert!(!home.join("work").exists());
+        assert!(!home.join("desktop").exists());
+
+        let work = Selector {
+            groups: vec!["work".to_string()],
+            ..Selector::default()
+        };
+        let _ = config.deploy_selected(&work).unwrap();
+        assert!(home.join("work").is_symlink());
+        let everything = Selector {
+            paths: vec![Pattern::new("*").unwrap()],
+            ..Selector::default()
+        };
+        let _ = config.deploy_selected(&everything).unwrap();
+        assert!(!home.join("desktop").exists());
+    }
+
+    #[test]
+    fn restore_from_with_restores_from_the_named_dotfiles_directory() {
+        let (tmp, mut config) = setup(&["a"]);
+        let (other, other_home) = (tmp.path().join("other"), tmp.path().join("home2"));
+        fs::create_dir(&other).unwrap();
+        fs::create_dir(&other_home).unwrap();
+        fs::write(other.join("a"), "other").unwrap();
+        let other: DotfilesDir = other.try_into().unwrap();
+        let other_home: SymlinkDir = other_home.try_into().unwrap();
+        let _ = config
+            .get_or_create_dotfiles(&other_home, &other)
+            .unwrap()
+            .paths
+            .insert(DotfilePath(PathBuf::from("a")));
+        let _ = config.deploy_all().unwrap();
+
+        let a = DotfilePath(PathBuf::from("a"));
+        config
+            .restore_from_with(&other, a.clone(), &RestoreOptions::new())
+            .unwrap();
+        assert_eq!(fs::read_to_string(other_home.join("a")).unwrap(), "other");
+        assert!(!other_home.join("a").is_symlink());
+        assert!(tmp.path().join("home/a").is_symlink());
+        assert!(!config.get_dotfiles(&other).unwrap().has_path(&a));
+
+        let missing: DotfilesDir = tmp.path().join("home").try_into().unwrap();
+        assert!(matches!(
+            config.restore_from_with(&missing, a, &RestoreOptions::new()),
+            Err(Error::DotfilesDirNotConfigured(_))
+        ));
+    }
+
+    /// XDG base directories with `HOME` set to `<tmp>/home` and the given other variables.
+    fn xdg_dirs(tmp: &tempfile::TempDir, vars: &[(&str, &str)]) -> Dirs {
+        let home = tmp.path().join("home");
+        Dirs::from_env_with(|key| match key {
+            "HOME" => Some(home.clone().into_os_string()),
+            _ => vars
+                .iter()
+                .find(|(name, _)| *name == key)
+                .map(|(_, dir)| tmp.path().join(dir).into_os_string()),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn xdg_tokens_resolve_against_the_dirs_of_the_run() {
+        let tracked = "${CONFIG}/nvim/init.lua";
+        for (vars, expected) in [
+            (&[][..], "home/.config/nvim/init.lua"),
+            (&[("XDG_CONFIG_HOME", "cfg")][..], "cfg/nvim/init.lua"),
+        ] {
+            let (tmp, mut config) = setup(&[tracked]);
+            config.set_xdg_dirs(xdg_dirs(&tmp, vars));
+            let symlink = tmp.path().join(expected);
+            let _ = config.deploy_all().unwrap();
+
+            assert_eq!(
+                fs::read_link(&symlink).unwrap(),
+                tmp.path().join("dots").join(tracked)
+            );
+            let status = config.dotfiles[0].status_all().unwrap();
+            assert_eq!(status[0].symlink_path(), symlink);
+            assert_eq!(status[0].state, SymlinkState::Deployed);
+            assert_eq!(
+                config.dotfiles[0].strip_symlink_dir(&symlink),
+                Some(DotfilePath(PathBuf::from(tracked)))
+            );
+        }
+    }
+
+    #[test]
+    fn xdg_tokens_follow_the_target_directory() {
+        let (tmp, mut config) = setup(&["${CONFIG}/nvim/init.lua"]);
+        config.set_xdg_dirs(xdg_dirs(&tmp, &[("XDG_CONFIG_HOME", "cfg")]));
+        fs::create_dir(tmp.path().join("other")).unwrap();
+        let target: SymlinkDir = tmp.path().join("other").try_into().unwrap();
+        let retargeted = config.with_target_dir(&target);
+
+        let status = retargeted.dotfiles[0].status_all().unwrap();
+        assert_eq!(
+            status[0].symlink_path(),
+            tmp.path().join("other/.config/nvim/init.lua")
+        );
+        let new = retargeted
+            .new_dotfiles(&target, &config.dotfiles[0].dotfile_directory)
+            .unwrap();
+        assert_eq!(new.symlink_path(&status[0].path), status[0].symlink_path());
+    }
+
+    /// An observer recording every event it receives, resolving conflicts as scripted.
+    #[derive(Debug, Default)]
+    struct Script {
+        resolutions: Mutex<Vec<Resolution>>,
+        events: Mutex<Vec<String>>,
+    }
+
+    impl Script {
+        fn resolving(resolutions: &[Resolution]) -> Self {
+            Self {
+                resolutions: Mutex::new(resolutions.to_vec()),
+                ..Self::default()
+            }
+        }
+
+        fn push(&self, event: String) {
+            self.events.lock().unwrap().push(event);
+        }
+
+        fn events(&self) -> Vec<String> {
+            self.events.lock().unwrap().clone()
+        }
+    }
+
+    impl Observer for Script {
+        fn on_action_start(&self, action: &Action<'_>) {
+            self.push(format!("start {0}", action));
+        }
+
+        fn on_action_done(&self, action: &Action<'_>, outcome: &ActionOutcome<'_>) {
+            let outcome = match outcome {
+                ActionOutcome::Failed(e) => format!("failed: {0}", e),
+                outcome => format!("{0:?}", outcome),
+            };
+            self.push(format!("{0}: {1}", action, outcome));
+        }
+
+        fn on_conflict(&self, conflict: &Conflict) -> Resolution {
+            let name = conflict.path.file_name().unwrap().to_string_lossy();
+            self.push(format!("conflict {0}", name));
+            self.resolutions.lock().unwrap().remove(0)
+        }
+
+        fn on_progress(&self, bytes: u64, total: u64) {
+            self.push(format!("progress {0}/{1}", bytes, total));
+        }
+    }
+
+    #[test]
+    fn scripted_observer_resolves_deploy_conflicts() {
+        let (tmp, mut config) = setup(&["a", "b", "c"]);
+        let home = tmp.path().join("home");
+        for name in ["a", "b", "c"] {
+            std::os::unix::fs::symlink("/elsewhere", home.join(name)).unwrap();
+        }
+        let script =
+            Script::resolving(&[Resolution::Skip, Resolution::Backup, Resolution::Fail]);
+        let options = DeployOptions::new().observer(&script);
+        assert!(config.deploy_all_with(&options).is_err());
+
+        let events = script.events();
+        assert_eq!(
+            events[..6],
+            [
+                "start deploy a",
+                "conflict a",
+                "deploy a: Skipped",
+                "start deploy b",
+                "conflict b",
+                "deploy b: Done",
+            ]
+        );
+        assert_eq!(events[6..8], ["start deploy c", "conflict c"]);
+        assert!(events[8].starts_with("deploy c: failed: "));
+        assert_eq!(
+            fs::read_link(home.join("a")).unwrap(),
+            Path::new("/elsewhere")
+        );
+        assert_eq!(
+            fs::read_link(home.join("b")).unwrap(),
+            tmp.path().join("dots/b")
+        );
+        assert_eq!(
+            fs::read_link(home.join("c")).unwrap(),
+            Path::new("/elsewhere")
+        );
+    }
+
+    #[test]
+    fn scripted_observer_sees_stow_progress() {
+        let (tmp, mut config) = setup(&[]);
+        let home = tmp.path().join("home");
+        fs::write(home.join("x"), "abc").unwrap();
+        fs::write(home.join("y"), "abcde").unwrap();
+        let script = Script::default();
+        let options = StowOptions::new().observer(&script);
+        let (symlink_dir, dotfile_dir) = (
+            config.dotfiles[0].symlink_dir().clone(),
+            config.dotfiles[0].dotfile_directory.clone(),
+        );
+        let paths = vec![dotfile_path("x"), dotfile_path("y")];
+        let _ = config
+            .stow_paths_with(symlink_dir, dotfile_dir, paths, &options)
+            .unwrap();
+        assert_eq!(
+            script.events(),
+            [
+                "start stow x",
+                "stow x: Done",
+                "progress 3/8",
+                "start stow y",
+                "stow y: Done",
+                "progress 8/8",
+            ]
+        );
+    }
+
+    #[test]
+    fn stowing_continues_past_failures_with_the_continue_policy() {
+        let (tmp, mut config) = setup(&[]);
+        let (home, dots) = (tmp.path().join("home"), tmp.path().join("dots"));
+        for name in ["a", "b", "c"] {
+            fs::write(home.join(name), name).unwrap();
+        }
+        // already stored, so stowing `b` conflicts
+        fs::write(dots.join("b"), "stored").unwrap();
+        let (symlink_dir, dotfile_dir) = (
+            config.dotfiles[0].symlink_dir().clone(),
+            config.dotfiles[0].dotfile_directory.clone(),
+        );
+        let paths = ["a", "b", "missing", "c"].map(dotfile_path).to_vec();
+
+        let options = StowOptions::new().policy(ErrorPolicy::Continue);
+        let outcome = config
+            .stow_paths_with(symlink_dir.clone(), dotfile_dir.clone(), paths, &options)
+            .unwrap();
+        assert_eq!(outcome.stowed, [dotfile_path("a"), dotfile_path("c")]);
+        let failed: Vec<&Path> = outcome.failed.iter().map(|(path, _)| &**path).collect();
+        assert_eq!(failed, [Path::new("b"), Path::new("missing")]);
+        assert!(matches!(
+            outcome.failed[0].1,
+            Error::DotfilePathAlreadyExists(_)
+        ));
+        assert!(home.join("a").is_symlink() && home.join("c").is_symlink());
+        assert!(!home.join("b").is_symlink());
+        assert_eq!(fs::read_to_string(dots.join("b")).unwrap(), "stored");
+        let tracked: Vec<_> = config.dotfiles[0].paths.iter().cloned().collect();
+        assert_eq!(tracked, [dotfile_path("a"), dotfile_path("c")]);
+
+        // failing fast still keeps what was stowed before the failure
+        fs::write(home.join("d"), "d").unwrap();
+        let paths = ["d", "b", "e"].map(dotfile_path).to_vec();
+        assert!(config
+            .stow_paths_with(symlink_dir, dotfile_dir, paths, &StowOptions::new())
+            .is_err());
+        assert!(config.dotfiles[0].has_path(&dotfile_path("d")));
+        assert!(!config.dotfiles[0].has_path(&dotfile_path("e")));
+    }
+
+    #[test]
+    fn deploy_all_fails_at_a_directory_in_the_way_of_a_file() {
+        let (tmp, mut config) = setup(&["a"]);
+        let in_the_way = tmp.path().join("home/a");
+        fs::create_dir(&in_the_way).unwrap();
+        assert!(matches!(
+            config.deploy_all(),
+            Err(Error::StorePathTypeMismatch(p)) if p == in_the_way
+        ));
+        assert!(in_the_way.is_dir());
+        assert!(!tmp.path().join("backup").exists());
+    }
+
+    #[test]
+    fn stow_as_moves_the_file_to_its_destination() {
+        let (tmp, mut config) = setup(&[]);
+        let (home, store) = (tmp.path().join("home"), tmp.path().join("dots"));
+        let source = tmp.path().join("vimrc");
+        fs::write(&source, "set nu").unwrap();
+        let symlink_dir = home.clone().try_into().unwrap();
+        let dotfile_dir = store.clone().try_into().unwrap();
+
+        let dest = config
+            .stow_as(
+                &symlink_dir,
+                &dotfile_dir,
+                &source,
+                Path::new("vim/vimrc"),
+                &StowOptions::new(),
+            )
+            .unwrap();
+        assert_eq!(dest, dotfile_path("vim/vimrc"));
+        assert_eq!(
+            fs::read_to_string(store.join("vim/vimrc")).unwrap(),
+            "set nu"
+        );
+        assert!(home.join("vim/vimrc").is_symlink());
+        assert!(!source.exists());
+        assert!(config.dotfiles[0].paths.contains(&dest));
+    }
+
+    #[test]
+    fn failed_stow_as_leaves_no_new_entry() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (home, store) = (tmp.path().join("home"), tmp.path().join("dots"));
+        fs::create_dir_all(&home).unwrap();
+        fs::create_dir_all(&store).unwrap();
+        let symlink_dir = home.try_into().unwrap();
+        let dotfile_dir = store.try_into().unwrap();
+        let mut config = Config::in_memory(tmp.path().join("backup"));
+
+        let result = config.stow_as(
+            &symlink_dir,
+            &dotfile_dir,
+            &tmp.path().join("missing"),
+            Path::new(".vimrc"),
+            &StowOptions::new(),
+        );
+        assert!(result.is_err());
+        assert!(!config.has_dotfiles(&symlink_dir, &dotfile_dir));
+
+        let result = config.stow_as(
+            &symlink_dir,
+            &dotfile_dir,
+            &tmp.path().join("missing"),
+            Path::new("../escape"),
+            &StowOptions::new(),
+        );
+        assert!(matches!(result, Err(Error::BadInput(_))));
+        assert!(config.dotfiles.is_empty());
+    }
+
+    #[test]
+    fn backups_are_keyed_by_path() {
+        let tmp = tempfile::tempdir().unwrap();
+        let backup = tmp.path().join("backup");
+        for dir in ["a", "b"] {
+            let path = tmp.path().join(dir).join(".vimrc");
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, dir).unwrap();
+            backup_to(&backup, &path).unwrap();
+            assert!(!path.exists());
+        }
+        let backed_up = backup.join(tmp.path().strip_prefix("/").unwrap());
+        for dir in ["a", "b"] {
+            let path = backed_up.join(dir).join(".vimrc");
+            assert_eq!(fs::read_to_string(path).unwrap(), dir);
+        }
+    }
+
+    #[test]
+    fn skipped_dotfiles_still_report_what_is_in_the_way() {
+        let (tmp, mut config) = setup(&[".vimrc", ".bashrc", ".zshrc"]);
+        config.skipped = vec![Pattern::new(".*rc").unwrap()];
+        let home = tmp.path().join("home");
+        fs::write(home.join(".bashrc"), "").unwrap();
+        std::os::unix::fs::symlink(tmp.path(), home.join(".zshrc")).unwrap();
+
+        let dotfiles = &config.dotfiles[0];
+        let state = |path| {
+            config
+                .verify_symlink(dotfiles, &dotfile_path(path))
+                .unwrap()
+        };
+        assert_eq!(state(".vimrc"), SymlinkState::Skipped);
+        assert!(state(".vimrc").is_ok());
+        assert_eq!(state(".bashrc"), SymlinkState::NotASymlink);
+        assert_eq!(state(".zshrc"), SymlinkState::WrongTarget);
+        assert!(!state(".zshrc").is_ok());
+    }
+
+    #[test]
+    fn deploy_changed_skips_missing_dotfiles() {
+        let (tmp, config) = setup(&[".vimrc", ".bashrc"]);
+        fs::remove_file(tmp.path().join("dots/.bashrc")).unwrap();
+        assert_eq!(config.deploy_changed(UNIX_EPOCH).unwrap(), 1);
+        assert!(tmp.path().join("home/.vimrc").is_symlink());
+    }
+
+    #[test]
+    fn deploy_changed_attempts_every_dotfile() {
+        let (tmp, config) = setup(&[".bashrc", ".vimrc", ".zshrc"]);
+        let home = tmp.path().join("home");
+        std::os::unix::fs::symlink(tmp.path(), home.join(".vimrc")).unwrap();
+
+        assert!(matches!(
+            config.deploy_changed(UNIX_EPOCH),
+            Err(Error::SymlinkPathAlreadyExists(p)) if p == home.join(".vimrc")
+        ));
+        assert!(home.join(".bashrc").is_symlink());
+        assert!(home.join(".zshrc").is_symlink());
+    }
+
+    fn dotfile_path(path: &str) -> DotfilePath {
+        DotfilePath(PathBuf::from(path))
+    }
+
+    fn set_mtime(path: &Path, time: SystemTime) {
+        let file = File::options().write(true).open(path).unwrap();
+        file.set_times(FileTimes::new().set_modified(time)).unwrap();
+    }
+
+    #[test]
+    fn restore_backs_up_older_file_that_replaced_symlink() {
+        let (tmp, mut config) = setup(&[".vimrc"]);
+        let symlink = tmp.path().join("home/.vimrc");
+        fs::write(&symlink, "local edits").unwrap();
+        set_mtime(&symlink, UNIX_EPOCH + Duration::from_secs(1));
+
+        config
+            .restore_dotfile(dotfile_path(".vimrc"), false)
+            .unwrap();
+
+        assert_eq!(fs::read_to_string(&symlink).unwrap(), ".vimrc");
+        let backup = tmp
+            .path()
+            .join("backup")
+            .join(symlink.strip_prefix("/").unwrap());
+        assert_eq!(fs::read_to_string(backup).unwrap(), "local edits");
+        assert!(config.dotfiles[0].paths.is_empty());
+    }
+
+    #[test]
+    fn restore_refuses_newer_file_that_replaced_symlink() {
+        let (tmp, mut config) = setup(&[".vimrc"]);
+        let symlink = tmp.path().join("home/.vimrc");
+        set_mtime(
+            &tmp.path().join("dots/.vimrc"),
+            UNIX_EPOCH + Duration::from_secs(1),
+        );
+        fs::write(&symlink, "local edits").unwrap();
+
+        let result = config.restore_dotfile(dotfile_path(".vimrc"), false);
+        assert!(matches!(result, Err(Error::RestoreTargetNewer(_))));
+        assert_eq!(fs::read_to_string(&symlink).unwrap(), "local edits");
+        assert!(tmp.path().join("dots/.vimrc").exists());
+    }
+
+    #[test]
+    fn validate_skips_disabled_entries() {
+        let (tmp, mut config) = setup(&[]);
+        let mut missing = config.dotfiles[0].clone();
+        missing.dotfile_directory = DotfilesDir(tmp.path().join("missing"));
+        config.dotfiles.push(missing);
+        assert!(matches!(config.validate(), Err(Error::PathDoesNotExist(_))));
+
+        config.dotfiles[1].enabled = false;
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn durable_deploy_and_restore() {
+        let (tmp, mut config) = setup(&[".vimrc"]);
+        config.set_durable(true);
+        let symlink = tmp.path().join("home/.vimrc");
+
+        let summary = config.deploy_paths(vec![symlink.clone()]).unwrap();
+        assert_eq!(summary.created, 1);
+        assert!(symlink.symlink_metadata().unwrap().is_symlink());
+
+        config
+            .restore_dotfile(dotfile_path(".vimrc"), false)
+            .unwrap();
+        assert!(symlink.symlink_metadata().unwrap().is_file());
+    }
+
+    #[test]
+    fn config_file_is_replaced_atomically() {
+        let (tmp, mut config) = setup(&[".vimrc"]);
+        let config_file = tmp.path().join("config/config.toml");
+        config.write_toml_config_to(&config_file).unwrap();
+
+        config.dotfiles[0].enabled = false;
+        config.write_toml_config_to(&config_file).unwrap();
+
+        let mut written: Config =
+            toml::from_str(&fs::read_to_string(&config_file).unwrap()).unwrap();
+        written.ephemeral = true;
+        assert!(!written.dotfiles[0].enabled);
+        let names: Vec<_> = fs::read_dir(tmp.path().join("config"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names.len(), 2, "{names:?}");
+        assert!(!tmp.path().join("config/config.toml.tmp").exists());
+    }
+
+    /// `setup` with `.vimrc` deployed under its own name as well as `.nvimrc` and `.exrc`.
+    fn setup_with_two_names() -> (tempfile::TempDir, Config) {
+        let (tmp, mut config) = setup(&[".vimrc"]);
+        let symlink = tmp.path().join("home/.vimrc");
+        let _ = config.deploy_paths(vec![symlink.clone()]).unwrap();
+        for name in [".nvimrc", ".exrc"] {
+            let _ = config
+                .deploy_as(&symlink, name, &DeployOptions::new())
+                .unwrap();
+        }
+        (tmp, config)
+    }
+
+    #[test]
+    fn unlink_all_removes_every_name() {
+        let (tmp, config) = setup_with_two_names();
+        assert_eq!(config.unlink_all().unwrap(), 3);
+        for name in [".vimrc", ".nvimrc", ".exrc"] {
+            assert!(!tmp.path().join("home").join(name).is_symlink(), "{name}");
+        }
+    }
+
+    #[test]
+    fn relink_moved_relinks_every_name() {
+        let (tmp, mut config) = setup_with_two_names();
+        let moved = tmp.path().join("moved");
+        fs::rename(tmp.path().join("dots"), &moved).unwrap();
+        config.dotfiles[0].dotfile_directory = DotfilesDir(moved.clone());
+
+        let summary = config.dotfiles[0]
+            .relink_moved(&tmp.path().join("dots"))
+            .unwrap();
+        assert_eq!(summary.relinked, 3);
+        for name in [".vimrc", ".nvimrc", ".exrc"] {
+            let symlink = tmp.path().join("home").join(name);
+            assert_eq!(fs::read_link(&symlink).unwrap(), moved.join(".vimrc"));
+        }
+    }
+
+    #[test]
+    fn move_path_moves_every_name() {
+        let (tmp, mut config) = setup_with_two_names();
+        let new = dotfile_path("vim/.vimrc");
+        config.dotfiles[0]
+            .move_path(&dotfile_path(".vimrc"), new.clone())
+            .unwrap();
+
+        let home = tmp.path().join("home");
+        for name in [".vimrc", ".nvimrc", ".exrc"] {
+            assert!(!home.join(name).exists(), "{name}");
+            let symlink = home.join("vim").join(name);
+            assert_eq!(fs::read_to_string(&symlink).unwrap(), ".vimrc", "{name}");
+        }
+        assert_eq!(config.dotfiles[0].alias_paths(&new).len(), 2);
+    }
+
+    #[test]
+    fn move_path_leaves_foreign_symlinks_alone() {
+        let (tmp, mut config) = setup(&[".vimrc"]);
+        let home = tmp.path().join("home");
+        let elsewhere = tmp.path().join("elsewhere");
+        fs::write(&elsewhere, "").unwrap();
+        std::os::unix::fs::symlink(&elsewhere, home.join(".vimrc")).unwrap();
+
+        let new = dotfile_path("vim/.vimrc");
+        config.dotfiles[0]
+            .move_path(&dotfile_path(".vimrc"), new.clone())
+            .unwrap();
+        assert_eq!(fs::read_link(home.join(".vimrc")).unwrap(), elsewhere);
+        assert!(home.join("vim/.vimrc").is_symlink());
+        assert!(config.dotfiles[0].paths.contains(&new));
+    }
+
+    #[test]
+    fn move_path_checks_the_new_path_first() {
+        let (tmp, mut config) = setup(&[".vimrc", ".bashrc"]);
+        let home = tmp.path().join("home");
+        let _ = config.deploy_paths(vec![home.join(".vimrc")]).unwrap();
+        fs::write(home.join(".vimrc.old"), "").unwrap();
+
+        let dotfiles = &mut config.dotfiles[0];
+        let old = dotfile_path(".vimrc");
+        for new in ["../.vimrc", "/.vimrc", ""] {
+            assert!(matches!(
+                dotfiles.move_path(&old, dotfile_path(new)),
+                Err(Error::BadInput(_))
+            ));
+        }
+        assert!(matches!(
+            dotfiles.move_path(&old, dotfile_path(".bashrc")),
+            Err(Error::DotfilePathAlreadyExists(_))
+        ));
+        assert!(matches!(
+            dotfiles.move_path(&old, dotfile_path(".vimrc.old")),
+            Err(Error::SymlinkPathAlreadyExists(p)) if p == home.join(".vimrc.old")
+        ));
+        assert!(dotfiles.paths.contains(&old));
+        assert!(tmp.path().join("dots/.vimrc").exists());
+        assert!(!tmp.path().join("dots/.vimrc.old").exists());
+        assert!(home.join(".vimrc").is_symlink());
+    }
+
+    #[test]
+    fn move_path_moves_back_when_linking_fails() {
+        let (tmp, mut config) = setup(&[".vimrc"]);
+        let home = tmp.path().join("home");
+        let _ = config.deploy_paths(vec![home.join(".vimrc")]).unwrap();
+        // a file where the new symlink needs a directory
+        fs::write(home.join("vim"), "").unwrap();
+
+        let old = dotfile_path(".vimrc");
+        assert!(config.dotfiles[0]
+            .move_path(&old, dotfile_path("vim/.vimrc"))
+            .is_err());
+        assert!(config.dotfiles[0].paths.contains(&old));
+        assert!(tmp.path().join("dots/.vimrc").exists());
+        assert!(!tmp.path().join("dots/vim").exists());
+        assert_eq!(fs::read_to_string(home.join(".vimrc")).unwrap(), ".vimrc");
+        assert!(home.join(".vimrc").is_symlink());
+    }
+
+    #[test]
+    fn unlink_all_removes_hardlinks() {
+        let (tmp, mut config) = setup(&[".vimrc", ".bashrc"]);
+        config.dotfiles[0].set_link_mode(LinkMode::Hardlink);
+        let home = tmp.path().join("home");
+        let summary = config
+            .deploy_paths(vec![home.join(".vimrc"), home.join(".bashrc")])
+            .unwrap();
+        assert_eq!(summary.created, 2);
+        // a copy isn't a link of the dotfile
+        fs::remove_file(home.join(".bashrc")).unwrap();
+        fs::write(home.join(".bashrc"), ".bashrc").unwrap();
+
+        assert_eq!(config.unlink_all().unwrap(), 1);
+        assert!(!home.join(".vimrc").exists());
+        assert!(home.join(".bashrc").exists());
+        assert!(tmp.path().join("dots/.vimrc").exists());
+    }
+
+    #[test]
+    fn relative_symlink_mode_is_the_relative_link_style() {
+        let (_tmp, mut config) = setup(&[]);
+        let dotfiles = &mut config.dotfiles[0];
+        dotfiles.set_link_mode(LinkMode::RelativeSymlink);
+        assert_eq!(dotfiles.link_mode(), LinkMode::Symlink);
+        assert_eq!(dotfiles.link_style(), LinkStyle::Relative);
     }
 }
@@ -0,0 +1,245 @@
+//! Details about whatever occupies the location a dotfile is deployed to.
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use chrono::{DateTime, Local};
+use serde_derive::Serialize;
+
+use crate::paths;
+use crate::users::User;
+use crate::xdg::Dirs;
+
+/// Directories GNU stow is commonly run from. Relative entries are relative to the home
+/// directory. Any directory containing a `.stow` marker file is recognized as well.
+const STOW_DIRS: &[&str] = &["/usr/local/stow", "/opt/stow", "stow", ".stow"];
+
+/// ForeignManager is another dotfile manager that an occupying symlink appears to belong to.
+#[derive(Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ForeignManager {
+    /// The symlink points into chezmoi's source directory.
+    Chezmoi,
+
+    /// The symlink points into a GNU stow directory.
+    GnuStow,
+}
+
+impl ForeignManager {
+    /// Suggestion for handing the dotfile over to ghmd.
+    pub fn hint(&self) -> &'static str {
+        match self {
+            Self::Chezmoi => {
+                "`chezmoi forget <path>` stops chezmoi from managing it; remove the symlink \
+                 and deploy again to replace it"
+            },
+            Self::GnuStow => "`stow -D <package>` removes GNU stow's symlinks",
+        }
+    }
+}
+
+impl fmt::Display for ForeignManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Chezmoi => "chezmoi",
+            Self::GnuStow => "GNU stow",
+        };
+        f.pad(s)
+    }
+}
+
+/// Conflict describes what occupies the location a dotfile is deployed to, to help tell who put
+/// it there.
+#[derive(Serialize, Debug, PartialEq, Eq, Clone)]
+pub struct Conflict {
+    /// Location the dotfile is deployed to.
+    pub path: PathBuf,
+
+    /// Target of the symlink at `path`, or `None` if something other than a symlink is there.
+    pub link_target: Option<PathBuf>,
+
+    /// Numeric id of the user owning whatever is at `path`, where the platform has one.
+    pub uid: Option<u32>,
+
+    /// Name of the user owning whatever is at `path`, if it can be looked up.
+    pub owner: Option<String>,
+
+    /// Unix timestamp of the last modification of whatever is at `path`.
+    pub modified: Option<u64>,
+
+    /// Dotfile manager the symlink target appears to belong to.
+    pub manager: Option<ForeignManager>,
+}
+
+impl Conflict {
+    /// Inspect whatever exists at `path` without following symlinks, returning `None` if nothing
+    /// does.
+    pub fn inspect(path: &Path) -> Option<Self> {
+        let metadata = path.symlink_metadata().ok()?;
+        let link_target = match metadata.is_symlink() {
+            true => fs::read_link(path).ok(),
+            false => None,
+        };
+        let dirs = Dirs::from_env().ok();
+        let manager = match metadata.is_symlink() {
+            true => paths::resolve_link(path)
+                .ok()
+                .and_then(|t| foreign_manager(&t, dirs.as_ref())),
+            false => None,
+        };
+        let uid = owner_uid(&metadata);
+        Some(Self {
+            path: path.to_path_buf(),
+            link_target,
+            uid,
+            owner: uid.and_then(User::by_uid).map(|user| user.name),
+            modified: metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs()),
+            manager,
+        })
+    }
+
+    /// Describe the conflict, one line per fact.
+    pub fn describe(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        match &self.link_target {
+            Some(target) => lines.push(format!("symlink to {0}", target.display())),
+            None => lines.push("not a symlink".to_string()),
+        }
+        match (&self.owner, self.uid) {
+            (Some(owner), Some(uid)) => {
+                lines.push(format!("owned by {0} (uid {1})", owner, uid))
+            },
+            (None, Some(uid)) => lines.push(format!("owned by uid {0}", uid)),
+            _ => {},
+        }
+        if let Some(modified) = self.modified {
+            let modified = UNIX_EPOCH + Duration::from_secs(modified);
+            let time: DateTime<Local> = modified.into();
+            lines.push(format!("modified {0}", time.format("%Y-%m-%d %H:%M:%S")));
+        }
+        if let Some(manager) = self.manager {
+            lines.push(format!("appears to be managed by {0}", manager));
+            lines.push(manager.hint().to_string());
+        }
+        lines
+    }
+}
+
+/// Guess which dotfile manager, if any, put a symlink pointing at `target` in place, looking
+/// for their directories in `dirs`.
+fn foreign_manager(target: &Path, dirs: Option<&Dirs>) -> Option<ForeignManager> {
+    if let Some(share) = dirs.and_then(|dirs| dirs.data_dir().parent()) {
+        if target.starts_with(share.join("chezmoi")) {
+            return Some(ForeignManager::Chezmoi);
+        }
+    }
+
+    let home = dirs.and_then(|dirs| dirs.home_dir().ok());
+    let known = STOW_DIRS
+        .iter()
+        .filter_map(|dir| match Path::new(dir).is_absolute() {
+            true => Some(PathBuf::from(dir)),
+            false => home.map(|home| home.join(dir)),
+        });
+    for dir in known {
+        if target.starts_with(&dir) {
+            return Some(ForeignManager::GnuStow);
+        }
+    }
+    if target
+        .ancestors()
+        .skip(1)
+        .any(|dir| dir.join(".stow").is_file())
+    {
+        return Some(ForeignManager::GnuStow);
+    }
+    None
+}
+
+#[cfg(unix)]
+fn owner_uid(metadata: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.uid())
+}
+
+#[cfg(not(unix))]
+fn owner_uid(_metadata: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::ffi::OsString;
+
+    fn dirs_with_home(home: &Path) -> Dirs {
+        Dirs::from_env_with(|key| (key == "HOME").then(|| OsString::from(home))).unwrap()
+    }
+
+    #[test]
+    fn links_into_other_managers_are_recognized() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dirs = dirs_with_home(tmp.path());
+        let chezmoi = tmp.path().join(".local/share/chezmoi/dot_bashrc");
+        assert_eq!(
+            foreign_manager(&chezmoi, Some(&dirs)),
+            Some(ForeignManager::Chezmoi)
+        );
+        let stow = tmp.path().join("stow/bash/.bashrc");
+        assert_eq!(
+            foreign_manager(&stow, Some(&dirs)),
+            Some(ForeignManager::GnuStow)
+        );
+
+        let marked = tmp.path().join("packages");
+        fs::create_dir_all(marked.join("bash")).unwrap();
+        fs::write(marked.join(".stow"), "").unwrap();
+        assert_eq!(
+            foreign_manager(&marked.join("bash/.bashrc"), None),
+            Some(ForeignManager::GnuStow)
+        );
+        assert_eq!(
+            foreign_manager(&tmp.path().join("dots/.bashrc"), Some(&dirs)),
+            None
+        );
+    }
+
+    #[test]
+    fn conflicting_symlinks_are_described() {
+        let tmp = tempfile::tempdir().unwrap();
+        let stow = tmp.path().join("packages");
+        fs::create_dir_all(stow.join("bash")).unwrap();
+        fs::write(stow.join(".stow"), "").unwrap();
+        fs::write(stow.join("bash/.bashrc"), "").unwrap();
+        let link = tmp.path().join(".bashrc");
+        std::os::unix::fs::symlink(stow.join("bash/.bashrc"), &link).unwrap();
+
+        let conflict = Conflict::inspect(&link).unwrap();
+        assert_eq!(conflict.link_target, Some(stow.join("bash/.bashrc")));
+        assert_eq!(conflict.manager, Some(ForeignManager::GnuStow));
+        assert!(conflict.uid.is_some());
+        assert!(conflict.modified.is_some());
+        let lines = conflict.describe();
+        assert!(lines[0].starts_with("symlink to "));
+        assert!(lines.contains(&"appears to be managed by GNU stow".to_string()));
+        assert!(lines.contains(&ForeignManager::GnuStow.hint().to_string()));
+    }
+
+    #[test]
+    fn plain_files_are_described_without_a_manager() {
+        let tmp = tempfile::tempdir().unwrap();
+        let file = tmp.path().join(".bashrc");
+        fs::write(&file, "").unwrap();
+        let conflict = Conflict::inspect(&file).unwrap();
+        assert_eq!(conflict.link_target, None);
+        assert_eq!(conflict.manager, None);
+        assert_eq!(conflict.describe()[0], "not a symlink");
+        assert!(Conflict::inspect(&tmp.path().join("missing")).is_none());
+    }
+}
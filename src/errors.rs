@@ -2,14 +2,15 @@ use std::io;
 use std::path;
 
 use thiserror::Error;
-use toml;
-use fs_extra;
+
+use crate::conflict::Conflict;
 
 /// The Result type for ghmd.
 pub type Result<T> = std::result::Result<T, Error>;
 
 /// The Error type for ghmd.
 #[derive(Error, Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
     /// Config toml is malformed.
     #[error("could not parse toml")]
@@ -23,64 +24,554 @@ pub enum Error {
     #[error("could not strip prefix")]
     StripPrefixError(#[from] path::StripPrefixError),
 
+    /// Wrapper around `regex::Error`.
+    #[error("invalid regular expression")]
+    InvalidRegex(#[from] regex::Error),
+
+    /// Wrapper around `glob::PatternError`.
+    #[error("invalid glob pattern")]
+    InvalidPattern(#[from] glob::PatternError),
+
+    /// Wrapper around `notify::Error`.
+    #[error("could not watch dotfiles directories")]
+    WatchError(#[from] notify::Error),
+
     /// Indicates bad input detected.
     #[error("bad input detected: {0}")]
     BadInput(&'static str),
 
+    /// Wrapper around `fs_extra::error::Error`.
     #[error("fs_extra error")]
     FSExtraError(#[from] fs_extra::error::Error),
 
+    /// The device of a path could not be determined.
     #[error("unable to retrieve path device info for {0}")]
     UnableToRetrievePathDeviceInfo(path::PathBuf),
 
+    /// Neither the environment nor the platform define a configuration directory.
     #[error("cannot determine configuration directory on this platform")]
     CannotDetermineConfigDir,
 
+    /// Neither the environment nor the platform define a data directory.
     #[error("cannot determine data directory on this platform")]
     CannotDetermineDataDir,
 
+    /// Neither the environment nor the platform define a state directory.
+    #[error("cannot determine state directory on this platform")]
+    CannotDetermineStateDir,
+
+    /// Neither the environment nor the platform define a cache directory.
+    #[error("cannot determine cache directory on this platform")]
+    CannotDetermineCacheDir,
+
+    /// The config file doesn't exist.
     #[error("config not found")]
     ConfigNotFound,
 
+    /// The home directory of the user could not be determined.
     #[error("missing HOME directory!")]
     MissingHomeDirectory,
 
+    /// A path is not located in the expected symlink directory.
     #[error("path doesn't match symlink dir")]
     DoesntMatchSymlinkDir,
 
+    /// A path that is required to exist doesn't.
     #[error("expected path '{0}' to exist, but it doesn't")]
     PathDoesNotExist(path::PathBuf),
 
+    /// Something other than the expected symlink occupies a symlink path.
     #[error("'{0}' already exists and doesn't point to the expected dotfile")]
     SymlinkPathAlreadyExists(path::PathBuf),
 
+    /// A symlink path is occupied by something other than a symlink.
     #[error("'{0}' is not a symlink")]
     SymlinkPathIsNotASymlink(path::PathBuf),
 
+    /// A symlink doesn't point at the dotfile it is expected to point at.
     #[error("symlink path {0} does not match dotfile path {1}")]
     SymlinkPathDoesNotMatchDotfilePath(path::PathBuf, path::PathBuf),
 
+    /// A dotfile is missing from its dotfiles directory.
     #[error("could not find specified dotfile: {0}")]
     DotfileNotFound(path::PathBuf),
 
+    /// A path is not located in the directory it is expected to be located in.
     #[error("path '{0}' does not start with prefix '{1}'")]
     PathDoesNotStartWithPrefix(path::PathBuf, path::PathBuf),
 
+    /// The directory a symlink should be placed in could not be determined.
     #[error("cannot determine symlink destination directory for {0}")]
     CannotDetermineSymlinkDestinationDirectory(path::PathBuf),
 
+    /// The directory a symlink should be placed in is not usable.
     #[error("invalid symlink destination directory: {0}")]
     InvalidSymlinkDestinationDirectory(path::PathBuf),
 
+    /// A path doesn't correspond to any tracked dotfile.
     #[error("no configured dotfile found that matches {0}")]
     NoMatchingDotfileConfigured(path::PathBuf),
 
+    /// An internal invariant was violated.
     #[error("unexpected error: {0}")]
     UnexpectedError(&'static str),
 
+    /// A dotfile path is absolute.
     #[error("dotfile path must be relative: {0}")]
     DotfilePathMustBeRelative(path::PathBuf),
 
+    /// A file already exists at the location a dotfile should be stored at.
     #[error("dotfile path already exists: {0}")]
     DotfilePathAlreadyExists(path::PathBuf),
+
+    /// A file and a directory were found where both should be the same type of file.
+    #[error("'{0}' already exists as a different file type (file vs directory)")]
+    StorePathTypeMismatch(path::PathBuf),
+
+    /// A file that would be replaced on restore was modified after the stored dotfile.
+    #[error("'{0}' is newer than the stored dotfile, use --force to replace it anyway")]
+    RestoreTargetNewer(path::PathBuf),
+
+    /// A file was written by a newer, incompatible version of ghmd.
+    #[error("{0} written by ghmd {1}, you are running {2}")]
+    WrittenByNewerVersion(path::PathBuf, String, String),
+
+    /// The config file was written with a config schema newer than the running ghmd supports.
+    #[error("{0} uses config schema {1}, this version of ghmd only supports up to {2}")]
+    UnsupportedSchemaVersion(path::PathBuf, u32, u32),
+
+    /// The command computing a symlink directory failed or produced an unusable directory.
+    #[error("symlink_dir_command '{0}' failed: {1}")]
+    SymlinkDirCommandFailed(String, String),
+
+    /// No dotfiles entry uses the given dotfiles directory.
+    #[error("no dotfiles are configured for dotfiles directory {0}")]
+    DotfilesDirNotConfigured(path::PathBuf),
+
+    /// A name to deploy a dotfile under isn't a plain file name other than its own.
+    #[error("'{0}' is not a valid name for the symlink of a dotfile")]
+    InvalidSymlinkName(String),
+
+    /// No dotfiles entry uses the given symlink directory.
+    #[error("no dotfiles are configured for symlink directory {0}")]
+    SymlinkDirNotConfigured(path::PathBuf),
+
+    /// A directory to change is configured for several dotfiles entries.
+    #[error("{0} is configured for {1} dotfiles entries")]
+    DirConfiguredMoreThanOnce(path::PathBuf, usize),
+
+    /// A dotfiles entry for the same dotfiles and symlink directories already exists.
+    #[error(
+        "dotfiles directory {0} is already configured for the same symlink directory"
+    )]
+    DotfilesDirAlreadyConfigured(path::PathBuf),
+
+    /// A configured directory is not a directory.
+    #[error("'{0}' is not a directory")]
+    NotADirectory(path::PathBuf),
+
+    /// The config file was loaded, but its entries are inconsistent.
+    #[error("config file {0} is inconsistent: {1}")]
+    InvalidConfig(path::PathBuf, Box<Error>),
+
+    /// A config file would be overwritten.
+    #[error("config file {0} already exists")]
+    ConfigFileAlreadyExists(path::PathBuf),
+
+    /// The config file was changed by something else since it was loaded and merging such
+    /// changes wasn't enabled.
+    #[error("config file {0} was changed by something else since ghmd loaded it")]
+    ConfigChangedOnDisk(path::PathBuf),
+
+    /// Changes made to the config file by something else since it was loaded conflict with the
+    /// changes to be written.
+    #[error("could not merge the changes to config file {0}: {1}")]
+    ConfigMergeConflict(path::PathBuf, String),
+
+    /// The location a dotfile would be deployed to belongs to a dotfile of another dotfiles
+    /// directory.
+    #[error(
+        "'{0}' is already tracked in dotfiles directory {1}, use --steal to take it over"
+    )]
+    DotfileTrackedElsewhere(path::PathBuf, path::PathBuf),
+
+    /// Running `systemctl` failed.
+    #[error("systemctl {0} failed: {1}")]
+    SystemctlFailed(String, String),
+
+    /// Running `git` failed.
+    #[error("git {0} failed: {1}")]
+    GitFailed(String, String),
+
+    /// Committing stowed files to the git repository of a dotfiles directory failed. The files
+    /// are stowed regardless.
+    #[error("could not commit stowed files in {0}: {1}")]
+    GitCommitFailed(path::PathBuf, String),
+
+    /// A reference manifest could not be downloaded or parsed.
+    #[error("could not fetch manifest from {0}: {1}")]
+    ManifestFetchFailed(String, String),
+
+    /// A bundle is not a valid ghmd bundle.
+    #[error("invalid bundle: {0}")]
+    BundleInvalid(String),
+
+    /// A file extracted from a bundle doesn't match the hash recorded in its manifest.
+    #[error("'{0}' in the bundle doesn't match its manifest")]
+    BundleChecksumMismatch(path::PathBuf),
+
+    /// Deploying for other users was requested by a user other than root.
+    #[error("only root can deploy dotfiles for other users")]
+    NotRoot,
+
+    /// A user doesn't exist on this machine.
+    #[error("no such user: {0}")]
+    UnknownUser(String),
+
+    /// The process deploying dotfiles as another user failed.
+    #[error("deploying for {0} failed: {1}")]
+    UserDeployFailed(String, String),
+
+    /// Junctions were requested on a platform other than Windows.
+    #[error("junctions are only supported on Windows")]
+    JunctionsUnsupported,
+
+    /// A junction was requested for something other than a directory.
+    #[error("junctions can only point at directories, '{0}' is not a directory")]
+    JunctionTargetNotADirectory(path::PathBuf),
+
+    /// A hardlink was requested for a directory.
+    #[error("'{0}' is a directory, which can't be hardlinked")]
+    HardlinkToDirectory(path::PathBuf),
+
+    /// A hardlink was requested between directories on different filesystems.
+    #[error("can't hardlink '{1}' to '{0}', which is on another filesystem")]
+    HardlinkAcrossDevices(path::PathBuf, path::PathBuf),
+
+    /// A dotfile restored into another directory would end up where something already exists
+    /// or another restored dotfile goes.
+    #[error("'{0}' already exists or is where another restored dotfile goes")]
+    RestoreDestinationTaken(path::PathBuf),
+
+    /// A path refers to an environment variable that isn't set.
+    #[error("environment variable '{0}' used in '{1}' is not set")]
+    UnsetEnvVar(String, path::PathBuf),
+
+    /// A glob pattern selecting dotfiles doesn't match any tracked dotfile.
+    #[error("pattern '{0}' doesn't match any tracked dotfile")]
+    PatternMatchesNothing(String),
+
+    /// A group selecting dotfiles isn't among the profiles of any configured dotfiles directory.
+    #[error("no dotfiles directory is in group '{0}'")]
+    GroupNotConfigured(String),
+
+    /// The given selection criteria exclude every tracked dotfile.
+    #[error("no tracked dotfile is selected")]
+    NothingSelected,
+
+    /// A path to stow isn't inside the symlink directory of any configured dotfiles directory.
+    #[error("'{0}' is not inside a configured symlink directory")]
+    NoStoreForPath(path::PathBuf),
+
+    /// A path to stow is inside the symlink directory of several dotfiles directories.
+    #[error("'{0}' is inside the symlink directory of several dotfiles directories")]
+    AmbiguousStoreForPath(path::PathBuf),
+
+    /// A dotfile in a dotfiles directory is a symlink whose target doesn't exist.
+    #[error("stored dotfile '{0}' is a symlink whose target doesn't exist")]
+    StoreEntryBroken(path::PathBuf),
+
+    /// An age such as `7d` is malformed.
+    #[error("invalid age '{0}', expected a number followed by s, m, h, d or w, e.g. 7d")]
+    InvalidAge(String),
+
+    /// A format template is malformed.
+    #[error("invalid format template: {0}")]
+    InvalidTemplate(String),
+
+    /// A format template contains a placeholder that isn't a known field.
+    #[error(
+        "unknown field '{{{0}}}' in format template, valid fields are: {}",
+        crate::template::TEMPLATE_FIELDS.join(", ")
+    )]
+    UnknownTemplateField(String),
+
+    /// Several independent operations failed.
+    #[error("{} operations failed, the first error was: {}", .0.len(), .0[0])]
+    Multiple(Vec<Error>),
+}
+
+impl Error {
+    /// Stable identifier of the kind of error, meant to be matched on by other programs.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidToml(_) => "config_parse",
+            Self::StdIOError(_) => "io",
+            Self::StripPrefixError(_) => "strip_prefix",
+            Self::InvalidRegex(_) => "invalid_regex",
+            Self::InvalidPattern(_) => "invalid_pattern",
+            Self::WatchError(_) => "watch",
+            Self::BadInput(_) => "bad_input",
+            Self::FSExtraError(_) => "fs_extra",
+            Self::UnableToRetrievePathDeviceInfo(_) => "device_info_unavailable",
+            Self::CannotDetermineConfigDir => "config_dir_unknown",
+            Self::CannotDetermineDataDir => "data_dir_unknown",
+            Self::CannotDetermineStateDir => "state_dir_unknown",
+            Self::CannotDetermineCacheDir => "cache_dir_unknown",
+            Self::ConfigNotFound => "config_not_found",
+            Self::MissingHomeDirectory => "home_dir_missing",
+            Self::DoesntMatchSymlinkDir => "symlink_dir_mismatch",
+            Self::PathDoesNotExist(_) => "path_missing",
+            Self::SymlinkPathAlreadyExists(_) => "symlink_conflict",
+            Self::SymlinkPathIsNotASymlink(_) => "not_a_symlink",
+            Self::SymlinkPathDoesNotMatchDotfilePath(_, _) => "symlink_mismatch",
+            Self::DotfileNotFound(_) => "dotfile_missing",
+            Self::PathDoesNotStartWithPrefix(_, _) => "path_outside_directory",
+            Self::CannotDetermineSymlinkDestinationDirectory(_) => {
+                "symlink_destination_unknown"
+            },
+            Self::InvalidSymlinkDestinationDirectory(_) => "invalid_symlink_destination",
+            Self::NoMatchingDotfileConfigured(_) => "dotfile_not_configured",
+            Self::UnexpectedError(_) => "unexpected",
+            Self::DotfilePathMustBeRelative(_) => "dotfile_path_not_relative",
+            Self::DotfilePathAlreadyExists(_) => "dotfile_exists",
+            Self::StorePathTypeMismatch(_) => "file_type_mismatch",
+            Self::RestoreTargetNewer(_) => "restore_target_newer",
+            Self::WrittenByNewerVersion(_, _, _) => "config_too_new",
+            Self::UnsupportedSchemaVersion(_, _, _) => "config_schema_too_new",
+            Self::SymlinkDirCommandFailed(_, _) => "symlink_dir_command_failed",
+            Self::DotfilesDirNotConfigured(_) => "dotfiles_dir_not_configured",
+            Self::InvalidSymlinkName(_) => "invalid_symlink_name",
+            Self::SymlinkDirNotConfigured(_) => "symlink_dir_not_configured",
+            Self::DirConfiguredMoreThanOnce(_, _) => "dir_configured_more_than_once",
+            Self::DotfilesDirAlreadyConfigured(_) => "dotfiles_dir_already_configured",
+            Self::NotADirectory(_) => "not_a_directory",
+            Self::InvalidConfig(_, _) => "config_invalid",
+            Self::ConfigFileAlreadyExists(_) => "config_exists",
+            Self::ConfigChangedOnDisk(_) => "config_changed_on_disk",
+            Self::ConfigMergeConflict(_, _) => "config_merge_conflict",
+            Self::DotfileTrackedElsewhere(_, _) => "dotfile_tracked_elsewhere",
+            Self::SystemctlFailed(_, _) => "systemctl_failed",
+            Self::GitFailed(_, _) => "git_failed",
+            Self::GitCommitFailed(_, _) => "git_commit_failed",
+            Self::ManifestFetchFailed(_, _) => "manifest_fetch_failed",
+            Self::BundleInvalid(_) => "bundle_invalid",
+            Self::BundleChecksumMismatch(_) => "bundle_checksum_mismatch",
+            Self::NotRoot => "not_root",
+            Self::UnknownUser(_) => "unknown_user",
+            Self::UserDeployFailed(_, _) => "user_deploy_failed",
+            Self::JunctionsUnsupported => "junctions_unsupported",
+            Self::JunctionTargetNotADirectory(_) => "junction_target_not_a_directory",
+            Self::HardlinkToDirectory(_) => "hardlink_to_directory",
+            Self::HardlinkAcrossDevices(_, _) => "hardlink_across_devices",
+            Self::RestoreDestinationTaken(_) => "restore_destination_taken",
+            Self::UnsetEnvVar(_, _) => "env_var_unset",
+            Self::PatternMatchesNothing(_) => "pattern_matches_nothing",
+            Self::GroupNotConfigured(_) => "group_not_configured",
+            Self::NothingSelected => "nothing_selected",
+            Self::NoStoreForPath(_) => "no_store_for_path",
+            Self::AmbiguousStoreForPath(_) => "ambiguous_store_for_path",
+            Self::StoreEntryBroken(_) => "store_entry_broken",
+            Self::InvalidAge(_) => "invalid_age",
+            Self::InvalidTemplate(_) => "invalid_template",
+            Self::UnknownTemplateField(_) => "unknown_template_field",
+            Self::Multiple(_) => "multiple",
+        }
+    }
+
+    /// The path the error is about, if any.
+    pub fn path(&self) -> Option<&path::Path> {
+        match self {
+            Self::UnableToRetrievePathDeviceInfo(p)
+            | Self::PathDoesNotExist(p)
+            | Self::SymlinkPathAlreadyExists(p)
+            | Self::SymlinkPathIsNotASymlink(p)
+            | Self::SymlinkPathDoesNotMatchDotfilePath(p, _)
+            | Self::DotfileNotFound(p)
+            | Self::PathDoesNotStartWithPrefix(p, _)
+            | Self::CannotDetermineSymlinkDestinationDirectory(p)
+            | Self::InvalidSymlinkDestinationDirectory(p)
+            | Self::NoMatchingDotfileConfigured(p)
+            | Self::DotfilePathMustBeRelative(p)
+            | Self::DotfilePathAlreadyExists(p)
+            | Self::StorePathTypeMismatch(p)
+            | Self::RestoreTargetNewer(p)
+            | Self::WrittenByNewerVersion(p, _, _)
+            | Self::UnsupportedSchemaVersion(p, _, _)
+            | Self::DotfilesDirNotConfigured(p)
+            | Self::SymlinkDirNotConfigured(p)
+            | Self::DirConfiguredMoreThanOnce(p, _)
+            | Self::DotfilesDirAlreadyConfigured(p)
+            | Self::NotADirectory(p)
+            | Self::HardlinkToDirectory(p)
+            | Self::HardlinkAcrossDevices(p, _)
+            | Self::RestoreDestinationTaken(p)
+            | Self::UnsetEnvVar(_, p)
+            | Self::InvalidConfig(p, _)
+            | Self::ConfigFileAlreadyExists(p)
+            | Self::ConfigChangedOnDisk(p)
+            | Self::ConfigMergeConflict(p, _)
+            | Self::DotfileTrackedElsewhere(p, _)
+            | Self::JunctionTargetNotADirectory(p)
+            | Self::NoStoreForPath(p)
+            | Self::AmbiguousStoreForPath(p)
+            | Self::GitCommitFailed(p, _)
+            | Self::StoreEntryBroken(p) => Some(p),
+            _ => None,
+        }
+    }
+
+    /// Suggestion for resolving the error, if there is a common way to do so.
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            Self::InvalidToml(_) => Some("fix the syntax of the config file"),
+            Self::MissingHomeDirectory => {
+                Some("set HOME to an absolute path or set the XDG_*_HOME variables")
+            },
+            Self::SymlinkPathAlreadyExists(_)
+            | Self::SymlinkPathDoesNotMatchDotfilePath(_, _) => {
+                Some("move the existing file out of the way or check `ghmd status`")
+            },
+            Self::SymlinkPathIsNotASymlink(_) => {
+                Some("`ghmd deploy --all` backs up files that are in the way of symlinks")
+            },
+            Self::DotfileNotFound(_) => {
+                Some("`ghmd prune-config` stops tracking dotfiles missing from the store")
+            },
+            Self::NoMatchingDotfileConfigured(_)
+            | Self::PatternMatchesNothing(_)
+            | Self::NothingSelected => Some("`ghmd list` shows tracked dotfiles"),
+            Self::ManifestFetchFailed(_, _) => Some(
+                "download the manifest by other means and pass it with --manifest-file",
+            ),
+            Self::GroupNotConfigured(_) => {
+                Some("the `profiles` list of a `[[dotfiles]]` entry names its groups")
+            },
+            Self::StorePathTypeMismatch(_) => Some("`ghmd doctor` shows broken dotfiles"),
+            Self::RestoreTargetNewer(_) => Some("use --force to replace it anyway"),
+            Self::WrittenByNewerVersion(_, _, _)
+            | Self::UnsupportedSchemaVersion(_, _, _) => Some("upgrade ghmd"),
+            Self::DotfileTrackedElsewhere(_, _) => Some("use --steal to take it over"),
+            Self::NotRoot => Some("run the deploy with sudo"),
+            Self::HardlinkToDirectory(_) => Some("link directories using symlinks"),
+            Self::HardlinkAcrossDevices(_, _) => {
+                Some("keep the store on the same filesystem or use --link-mode symlink")
+            },
+            Self::UnsetEnvVar(_, _) => Some("export the variable before deploying"),
+            Self::JunctionsUnsupported | Self::JunctionTargetNotADirectory(_) => {
+                Some("use --link-type symlink")
+            },
+            Self::NoStoreForPath(_) | Self::AmbiguousStoreForPath(_) => {
+                Some("pass the symlink and dotfiles directories explicitly")
+            },
+            Self::ConfigChangedOnDisk(_) => {
+                Some("re-run the command, or pass --merge-config to merge the changes")
+            },
+            Self::ConfigMergeConflict(_, _) => Some("re-run the command"),
+            Self::InvalidConfig(_, _) => {
+                Some("fix the config file, or pass --no-validate to use it anyway")
+            },
+            Self::DirConfiguredMoreThanOnce(_, _) => {
+                Some("pass --all-matching to change it in every entry")
+            },
+            Self::GitCommitFailed(_, _) => {
+                Some("the files are stowed, commit them with git")
+            },
+            Self::StoreEntryBroken(_) => {
+                Some("restore the missing target or stop tracking it with `ghmd forget`")
+            },
+            _ => None,
+        }
+    }
+
+    /// Whether the error is caused by something other than the expected symlink occupying the
+    /// location a dotfile is deployed to.
+    pub fn is_conflict(&self) -> bool {
+        matches!(
+            self,
+            Self::SymlinkPathAlreadyExists(_)
+                | Self::SymlinkPathIsNotASymlink(_)
+                | Self::SymlinkPathDoesNotMatchDotfilePath(_, _)
+                | Self::StorePathTypeMismatch(_)
+                | Self::DotfileTrackedElsewhere(_, _)
+        )
+    }
+
+    /// Details about whatever occupies the symlink path of a conflict, see
+    /// [`Error::is_conflict`].
+    pub fn conflict(&self) -> Option<Conflict> {
+        match self {
+            Self::SymlinkPathAlreadyExists(p)
+            | Self::SymlinkPathIsNotASymlink(p)
+            | Self::SymlinkPathDoesNotMatchDotfilePath(p, _) => Conflict::inspect(p),
+            _ => None,
+        }
+    }
+
+    /// Describe the error as a JSON object with `code`, `message`, `path`, `hint` and, for
+    /// conflicts, `conflict` members.
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "code": self.code(),
+            "message": self.to_string(),
+            "path": self.path(),
+            "hint": self.hint(),
+            "conflict": self.conflict(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn conflict_as_json() {
+        let e = Error::SymlinkPathAlreadyExists("/nonexistent/.vimrc".into());
+        assert_eq!(
+            e.to_json(),
+            json!({
+                "code": "symlink_conflict",
+                "message": "'/nonexistent/.vimrc' already exists and doesn't point to the \
+                            expected dotfile",
+                "path": "/nonexistent/.vimrc",
+                "hint": "move the existing file out of the way or check `ghmd status`",
+                "conflict": null,
+            })
+        );
+    }
+
+    #[test]
+    fn missing_dotfile_as_json() {
+        let e = Error::DotfileNotFound("/dots/.vimrc".into());
+        assert_eq!(
+            e.to_json(),
+            json!({
+                "code": "dotfile_missing",
+                "message": e.to_string(),
+                "path": "/dots/.vimrc",
+                "hint": "`ghmd prune-config` stops tracking dotfiles missing from the store",
+                "conflict": null,
+            })
+        );
+    }
+
+    #[test]
+    fn parse_error_as_json() {
+        let e: Error = toml::from_str::<toml::Value>("dotfiles = [")
+            .unwrap_err()
+            .into();
+        assert_eq!(
+            e.to_json(),
+            json!({
+                "code": "config_parse",
+                "message": "could not parse toml",
+                "path": null,
+                "hint": "fix the syntax of the config file",
+                "conflict": null,
+            })
+        );
+    }
 }
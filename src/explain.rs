@@ -0,0 +1,127 @@
+//! Step by step tracing of how paths given on the command line are resolved to dotfiles.
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+
+impl Config {
+    /// Describe, one line per step, how `path` is resolved to a tracked dotfile: which dotfiles
+    /// entries are considered, how the path is interpreted relative to each of them and which
+    /// dotfile, if any, it finally refers to. Nothing is modified.
+    pub fn explain(&self, path: &Path) -> Vec<String> {
+        let mut steps = vec![format!("explaining {0}", path.display())];
+
+        if path.is_relative() {
+            steps.push(
+                "  path is relative and used as-is for every dotfiles entry".to_string(),
+            );
+        } else {
+            steps.push(
+                "  path is absolute, so it must be inside a symlink or dotfiles directory"
+                    .to_string(),
+            );
+            for dotfiles in &self.dotfiles {
+                steps.push(format!(
+                    "  entry {0} -> {1}",
+                    dotfiles.dotfile_directory.display(),
+                    dotfiles.symlink_dir().display()
+                ));
+                steps.push(match dotfiles.strip_symlink_dir(path) {
+                    Some(relative) => format!(
+                        "    inside the symlink directory, relative path {0}",
+                        relative.display()
+                    ),
+                    None => "    not inside the symlink directory".to_string(),
+                });
+                steps.push(match dotfiles.strip_dotfile_dir(path) {
+                    Some(relative) => format!(
+                        "    inside the dotfiles directory, relative path {0}",
+                        relative.display()
+                    ),
+                    None => "    not inside the dotfiles directory".to_string(),
+                });
+            }
+        }
+
+        let candidates = self.resolve_candidates(path);
+        if candidates.is_empty() {
+            steps.push("  no dotfiles entry could own the path".to_string());
+        }
+
+        let mut resolved = None;
+        for (dotfiles, dotfile_path) in &candidates {
+            let tracked = dotfiles.paths.contains(dotfile_path);
+            steps.push(format!(
+                "  candidate {0} in {1}: {2}",
+                dotfile_path.display(),
+                dotfiles.dotfile_directory.display(),
+                if tracked { "tracked" } else { "not tracked" }
+            ));
+            if !tracked {
+                continue;
+            }
+
+            let state = match dotfiles.verify_symlink(dotfile_path) {
+                Ok(state) => state.to_string(),
+                Err(e) => format!("unknown ({0})", e),
+            };
+            steps.push(format!(
+                "    store path:   {0}",
+                dotfiles.store_path(dotfile_path).display()
+            ));
+            steps.push(format!(
+                "    symlink path: {0}",
+                dotfiles.symlink_path(dotfile_path).display()
+            ));
+            steps.push(format!(
+                "    link target:  {0}",
+                dotfiles.target_for(dotfile_path).display()
+            ));
+            steps.push(format!("    state:        {0}", state));
+            if resolved.is_none() {
+                resolved = Some((dotfiles, dotfile_path));
+            }
+        }
+
+        steps.push(match resolved {
+            Some((dotfiles, dotfile_path)) => format!(
+                "  resolved to {0} in {1}",
+                dotfile_path.display(),
+                dotfiles.dotfile_directory.display()
+            ),
+            None => format!("  no configured dotfile matches {0}", path.display()),
+        });
+        steps
+    }
+
+    /// Directory in a dotfiles directory corresponding to the directory `path`. For a path inside
+    /// a symlink directory this is its store path if that exists, otherwise the closest existing
+    /// parent of it. Paths inside a dotfiles directory correspond to themselves. If several
+    /// entries match, the deepest existing directory wins.
+    pub fn store_dir_for(&self, path: &Path) -> Option<PathBuf> {
+        if self
+            .dotfiles
+            .iter()
+            .any(|dotfiles| dotfiles.strip_dotfile_dir(path).is_some())
+        {
+            return Some(path.to_path_buf());
+        }
+
+        let mut best: Option<PathBuf> = None;
+        for dotfiles in &self.dotfiles {
+            let relative = match dotfiles.strip_symlink_dir(path) {
+                Some(relative) => relative,
+                None => continue,
+            };
+            let mut dir: PathBuf = dotfiles.store_path(&relative).components().collect();
+            while !dir.is_dir() && *dir != **dotfiles.dotfile_directory && dir.pop() {}
+            let deeper = match &best {
+                Some(best) => dir.components().count() > best.components().count(),
+                None => true,
+            };
+            if deeper {
+                best = Some(dir);
+            }
+        }
+        best
+    }
+}
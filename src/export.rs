@@ -0,0 +1,197 @@
+//! Export of the configured dotfiles into formats understood by other tools.
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::errors::Result;
+use crate::paths;
+
+impl Config {
+    /// Generate an Ansible role under `role_dir` that deploys the configured dotfiles.
+    ///
+    /// The role consists of:
+    /// * `defaults/main.yml` defining the dotfiles and symlink directory of each dotfiles entry,
+    /// * `tasks/main.yml` including one task file per dotfiles entry,
+    /// * `tasks/dotfiles_<n>.yml` creating a symlink for each dotfile of entry `<n>`,
+    /// * `files/dotfiles_<n>/` containing symlinks to the dotfiles of entry `<n>`.
+    pub fn export_ansible(&self, role_dir: &Path) -> Result<()> {
+        let tasks_dir = role_dir.join("tasks");
+        let defaults_dir = role_dir.join("defaults");
+        let files_dir = role_dir.join("files");
+        fs::create_dir_all(&tasks_dir)?;
+        fs::create_dir_all(&defaults_dir)?;
+        fs::create_dir_all(&files_dir)?;
+
+        let mut defaults = String::from("---\n");
+        let mut main_tasks = String::from("---\n");
+
+        for (index, dotfiles) in self.dotfiles.iter().enumerate() {
+            let name = format!("dotfiles_{0}", index);
+
+            let _ = writeln!(
+                defaults,
+                "{0}_dotfiles_dir: {1}",
+                name,
+                yaml_string(&dotfiles.dotfile_directory.to_string_lossy())
+            );
+            let _ = writeln!(
+                defaults,
+                "{0}_symlink_dir: {1}",
+                name,
+                yaml_string(&dotfiles.symlink_dir().to_string_lossy())
+            );
+
+            let _ = writeln!(
+                main_tasks,
+                "- name: {0}\n  include_tasks: {1}.yml\n  vars:\n    dotfiles_dir: \"{{{{ \
+                 {1}_dotfiles_dir }}}}\"\n    symlink_dir: \"{{{{ {1}_symlink_dir }}}}\"",
+                yaml_string(&format!(
+                    "dotfiles from {0}",
+                    dotfiles.dotfile_directory.display()
+                )),
+                name
+            );
+
+            let mut tasks = String::from("---\n");
+            let entry_files_dir = files_dir.join(&name);
+            for resolved in dotfiles.iter_paths() {
+                let path = resolved.relative;
+                let relative = path.to_string_lossy();
+                if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty())
+                {
+                    let _ = writeln!(
+                        tasks,
+                        "- name: {0}\n  file:\n    path: {1}\n    state: directory",
+                        yaml_string(&format!("create parent directory of {0}", relative)),
+                        yaml_string(&format!(
+                            "{{{{ symlink_dir }}}}/{0}",
+                            parent.display()
+                        ))
+                    );
+                }
+                let _ = writeln!(
+                    tasks,
+                    "- name: {0}\n  file:\n    src: {1}\n    dest: {2}\n    state: link",
+                    yaml_string(&format!("link {0}", relative)),
+                    yaml_string(&format!("{{{{ dotfiles_dir }}}}/{0}", relative)),
+                    yaml_string(&format!("{{{{ symlink_dir }}}}/{0}", relative))
+                );
+
+                let file_link = entry_files_dir.join(&**path);
+                if let Some(parent) = file_link.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                if file_link.symlink_metadata().is_ok() {
+                    fs::remove_file(&file_link)?;
+                }
+                paths::create_symlink(&resolved.store, &file_link)?;
+            }
+            fs::write(tasks_dir.join(format!("{0}.yml", name)), tasks)?;
+        }
+
+        fs::write(defaults_dir.join("main.yml"), defaults)?;
+        fs::write(tasks_dir.join("main.yml"), main_tasks)?;
+        Ok(())
+    }
+
+    /// Generate a POSIX `Makefile` at `output` that manages the configured dotfiles by running
+    /// `ghmd`. It has the targets:
+    /// * `all` deploying every dotfiles entry through the per-entry targets,
+    /// * `install` deploying all dotfiles at once,
+    /// * `clean` removing the symlinks of all dotfiles,
+    /// * `status` showing the state of all dotfiles,
+    /// * one target per dotfiles entry deploying its dotfiles, named after the last component of
+    ///   its dotfiles directory and made unique with a numeric suffix if necessary.
+    pub fn create_makefile(&self, output: &Path) -> Result<()> {
+        let mut names: Vec<String> = Vec::new();
+        let mut entries = String::new();
+        for dotfiles in &self.dotfiles {
+            let base = dotfiles
+                .dotfile_directory
+                .file_name()
+                .map(|name| make_target(&name.to_string_lossy()))
+                .unwrap_or_else(|| "dotfiles".to_string());
+            let mut name = base.clone();
+            let mut suffix = 1;
+            while MAKEFILE_TARGETS.contains(&name.as_str()) || names.contains(&name) {
+                suffix += 1;
+                name = format!("{0}_{1}", base, suffix);
+            }
+            let _ = write!(
+                entries,
+                "\n{0}:\n\t@ghmd deploy --dir {1}\n",
+                name,
+                make_command_arg(&dotfiles.dotfile_directory.to_string_lossy())
+            );
+            names.push(name);
+        }
+
+        let mut makefile = String::from("# generated by ghmd export --makefile\n");
+        makefile.push_str(".POSIX:\n\n");
+        let _ = writeln!(
+            makefile,
+            ".PHONY: {0}\n",
+            MAKEFILE_TARGETS
+                .iter()
+                .copied()
+                .chain(names.iter().map(String::as_str))
+                .collect::<Vec<_>>()
+                .join(" ")
+        );
+        makefile.push_str("all:");
+        names
+            .iter()
+            .for_each(|name| makefile.push_str(&format!(" {0}", name)));
+        makefile.push_str("\n\ninstall:\n\t@ghmd deploy --all\n");
+        makefile.push_str("\nclean:\n\t@ghmd unlink --all\n");
+        makefile.push_str("\nstatus:\n\t@ghmd status\n");
+        makefile.push_str(&entries);
+
+        fs::write(output, makefile)?;
+        Ok(())
+    }
+}
+
+/// Targets of the generated `Makefile` that don't belong to a dotfiles entry.
+const MAKEFILE_TARGETS: &[&str] = &["all", "install", "clean", "status"];
+
+/// Turn `name` into a portable make target name, replacing characters other than ASCII letters,
+/// digits, `.`, `_` and `-` with `_`.
+fn make_target(name: &str) -> String {
+    let target: String = name
+        .chars()
+        .map(
+            |c| match c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-') {
+                true => c,
+                false => '_',
+            },
+        )
+        .collect();
+    match target.starts_with(['.', '-']) {
+        true => format!("_{0}", target),
+        false => target,
+    }
+}
+
+/// Quote `arg` for the shell running a `Makefile` recipe, escaping `$` for make itself.
+fn make_command_arg(arg: &str) -> String {
+    format!("'{0}'", arg.replace('\'', "'\\''").replace('$', "$$"))
+}
+
+/// Quote `s` as a double-quoted YAML scalar.
+fn yaml_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,194 @@
+//! Import of dotfiles managed by other tools.
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::{Config, DotfilePath, Dotfiles, DotfilesDir, SymlinkDir};
+use crate::errors::{Error, Result};
+
+/// Prefixes of chezmoi source state names that only set attributes of the target, in the order
+/// chezmoi expects them. They are dropped on import.
+const CHEZMOI_ATTRIBUTES: [&str; 5] =
+    ["exact_", "private_", "readonly_", "empty_", "executable_"];
+
+/// Prefixes of chezmoi source state names describing something other than a plain file or
+/// directory, e.g. scripts, which can't be imported.
+const CHEZMOI_UNSUPPORTED: [&str; 7] = [
+    "run_",
+    "modify_",
+    "create_",
+    "symlink_",
+    "encrypted_",
+    "remove_",
+    "external_",
+];
+
+/// Files tracked when importing a chezmoi source directory.
+#[derive(Default)]
+struct ChezmoiImport {
+    /// Paths of the imported files relative to their target directory, each with its path
+    /// relative to the source directory.
+    files: Vec<(PathBuf, PathBuf)>,
+}
+
+impl Config {
+    /// Track the files in the chezmoi source directory `chezmoi_src` in a new dotfiles entry
+    /// deployed to `target`, returning the number of tracked files. Nothing is deployed.
+    ///
+    /// Each file is tracked under the name chezmoi would give its target, e.g. `dot_bashrc` as
+    /// `.bashrc` and `private_dot_ssh/config` as `.ssh/config`, and the name it has in
+    /// `chezmoi_src` is recorded in the config, see [`Dotfiles::stored_as`]. `chezmoi_src` itself
+    /// is left untouched, so chezmoi keeps working with it. chezmoi's own files, templates and
+    /// entries that aren't plain files or directories, such as scripts or encrypted files, are
+    /// skipped with a warning.
+    pub fn import_from_chezmoi(
+        &mut self,
+        chezmoi_src: &Path,
+        target: &SymlinkDir,
+    ) -> Result<usize> {
+        let dotfiles_dir: DotfilesDir = chezmoi_src.to_path_buf().try_into()?;
+        let mut dotfiles = Dotfiles::new(target, &dotfiles_dir)?;
+        if self.dotfiles_index(target, &dotfiles_dir).is_some() {
+            return Err(Error::DotfilesDirAlreadyConfigured(
+                dotfiles_dir.to_path_buf(),
+            ));
+        }
+
+        let mut import = ChezmoiImport::default();
+        plan_chezmoi_import(&dotfiles_dir, Path::new(""), Path::new(""), &mut import)?;
+
+        let count = import.files.len();
+        for (file, source) in import.files {
+            let path = DotfilePath::try_from(file)?;
+            // two source names may translate to the same target
+            if !dotfiles.paths.insert(path.clone()) {
+                return Err(Error::DotfilePathAlreadyExists(dotfiles_dir.join(source)));
+            }
+            if *path != source {
+                let _ = dotfiles.stored_as.insert(path, source);
+            }
+        }
+        self.insert_dotfiles(dotfiles)?;
+        Ok(count)
+    }
+}
+
+/// Collect the files of the chezmoi source directory `dir`, whose path relative to the source
+/// directory is `source` and whose target path relative to the target directory is
+/// `translated`.
+fn plan_chezmoi_import(
+    dir: &Path,
+    source: &Path,
+    translated: &Path,
+    import: &mut ChezmoiImport,
+) -> Result<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let path = entry.path();
+        let name = match entry.file_name().into_string() {
+            Ok(name) => name,
+            Err(_) => {
+                log::warn!("skipping {0}, its name is not valid UTF-8", path.display());
+                continue;
+            },
+        };
+        if name.starts_with('.') {
+            log::debug!("skipping {0}", path.display());
+            continue;
+        }
+        let target_name = match chezmoi_target_name(&name) {
+            Some(target_name) => target_name,
+            None => {
+                log::warn!("skipping {0}, it can't be imported", path.display());
+                continue;
+            },
+        };
+
+        let relative = translated.join(&target_name);
+        let source = source.join(&name);
+        if entry.file_type()?.is_dir() {
+            plan_chezmoi_import(&path, &source, &relative, import)?;
+        } else {
+            import.files.push((relative, source));
+        }
+    }
+    Ok(())
+}
+
+/// Translate the chezmoi source state name `name` into the name of its target, or None if the
+/// entry can't be imported.
+fn chezmoi_target_name(name: &str) -> Option<String> {
+    let unsupported = CHEZMOI_UNSUPPORTED.iter().any(|p| name.starts_with(p));
+    if unsupported || name.ends_with(".tmpl") {
+        return None;
+    }
+
+    let mut name = name;
+    for attribute in CHEZMOI_ATTRIBUTES {
+        name = name.strip_prefix(attribute).unwrap_or(name);
+    }
+    if let Some(literal) = name.strip_prefix("literal_") {
+        return Some(literal.to_string());
+    }
+    match name.strip_prefix("dot_") {
+        Some(rest) => Some(format!(".{0}", rest)),
+        None => Some(name.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn target_names() {
+        assert_eq!(chezmoi_target_name("dot_bashrc").unwrap(), ".bashrc");
+        assert_eq!(chezmoi_target_name("private_dot_ssh").unwrap(), ".ssh");
+        assert_eq!(chezmoi_target_name("executable_dot_run").unwrap(), ".run");
+        assert_eq!(chezmoi_target_name("literal_dot_x").unwrap(), "dot_x");
+        assert_eq!(chezmoi_target_name("README").unwrap(), "README");
+        assert_eq!(chezmoi_target_name("dot_gitconfig.tmpl"), None);
+        assert_eq!(chezmoi_target_name("run_once_setup.sh"), None);
+    }
+
+    #[test]
+    fn import_leaves_source_directory_untouched() {
+        let tmp = tempfile::tempdir().unwrap();
+        let src = tmp.path().join("chezmoi");
+        let home = tmp.path().join("home");
+        fs::create_dir_all(src.join("private_dot_config/nvim")).unwrap();
+        fs::create_dir_all(&home).unwrap();
+        fs::write(src.join("dot_bashrc"), "# bash\n").unwrap();
+        fs::write(src.join("private_dot_config/nvim/init.lua"), "-- nvim\n").unwrap();
+        fs::write(src.join("dot_profile.tmpl"), "{{ .chezmoi }}\n").unwrap();
+        fs::write(src.join(".chezmoiignore"), "").unwrap();
+
+        let mut config = Config::in_memory(tmp.path().join("backup"));
+        let target: SymlinkDir = home.clone().try_into().unwrap();
+        assert_eq!(config.import_from_chezmoi(&src, &target).unwrap(), 2);
+
+        assert!(src.join("dot_bashrc").is_file());
+        assert!(src.join("private_dot_config/nvim/init.lua").is_file());
+        assert!(!src.join(".bashrc").exists());
+        let dotfiles = &config.dotfiles()[0];
+        let bashrc = DotfilePath::try_from(PathBuf::from(".bashrc")).unwrap();
+        let init = DotfilePath::try_from(PathBuf::from(".config/nvim/init.lua")).unwrap();
+        assert!(dotfiles.has_path(&bashrc));
+        assert_eq!(dotfiles.store_path(&bashrc), src.join("dot_bashrc"));
+        assert_eq!(
+            dotfiles.store_path(&init),
+            src.join("private_dot_config/nvim/init.lua")
+        );
+        assert_eq!(
+            dotfiles.strip_dotfile_dir(&src.join("dot_bashrc")),
+            Some(bashrc.clone())
+        );
+
+        let _ = config.deploy_paths(vec![PathBuf::from(".bashrc")]).unwrap();
+        assert_eq!(
+            fs::read_link(home.join(".bashrc")).unwrap(),
+            src.join("dot_bashrc")
+        );
+    }
+}
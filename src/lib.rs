@@ -14,7 +14,6 @@
     missing_debug_implementations,
     missing_docs,
     missing_copy_implementations,
-    missing_docs,
     nonstandard_style,
     trivial_casts,
     trivial_numeric_casts,
@@ -22,14 +21,65 @@
     unused_extern_crates,
     unused_import_braces,
     unused_qualifications,
-    unused_results,
-    unused_qualifications
+    unused_results
 )]
 
+#[cfg(feature = "bundle")]
+mod bundle;
 pub(crate) mod config;
+mod conflict;
 mod errors;
+mod explain;
+mod export;
+mod import;
+mod machine;
+mod merge;
+mod observer;
+mod options;
 pub mod paths;
+mod plan;
+mod remote;
+mod selector;
+mod service;
+mod shell;
+mod snapshot;
+mod status;
+mod template;
+mod toml_merge;
+mod tree;
+mod users;
+mod vcs;
+mod version;
+mod watch;
+mod xdg;
 
+#[cfg(feature = "bundle")]
+pub use crate::bundle::{BundleEntry, BundleManifest};
 pub use crate::config::Config;
-pub use crate::config::{DotfilesDir, DotfilePath, SymlinkDir};
-pub use crate::errors::Result;
+pub use crate::config::StowOutcome;
+pub use crate::config::{ConvertSummary, DeployOutcome, DeploySummary, DirKind};
+pub use crate::config::{DotfilePath, Dotfiles, DotfilesDir, SymlinkDir};
+pub use crate::config::{ErrorPolicy, RelinkSummary, RenameReport, ResolvedPath};
+pub use crate::config::{LinkMode, LinkStyle, LinkType};
+pub use crate::conflict::{Conflict, ForeignManager};
+pub use crate::errors::{Error, Result};
+pub use crate::machine::{hostname, MachineState};
+pub use crate::observer::{Action, ActionOutcome, Observer, Resolution};
+pub use crate::options::{DeployOptions, RestoreOptions, StowOptions};
+pub use crate::plan::{
+    ConflictKind, DeployReport, PlannedOperation, SkipReason, StowPlan,
+};
+pub use crate::remote::{Git, GitRunner, Manifest, ManifestFile, Reference, RemoteDiff};
+pub use crate::selector::{parse_age, MtimeFilter, Selector};
+pub use crate::service::{Service, ServiceRunner, Systemctl};
+pub use crate::shell::Shell;
+pub use crate::snapshot::{DEFAULT_SNAPSHOT_KEEP, SNAPSHOT_KEEP_VAR};
+pub use crate::status::{DotfileStatus, FsDiff, SymlinkState};
+pub use crate::template::{Template, TEMPLATE_FIELDS};
+pub use crate::tree::{DeployTree, TreeMark, TreeNode};
+pub use crate::users::{running_as_root, OperationsLog, User, USER_DEPLOY_SUBCOMMAND};
+pub use crate::vcs::{commit_paths, is_git_repo, stow_commit_message};
+pub use crate::vcs::{init_repo, is_version_controlled, VCS_MARKERS};
+pub use crate::version::{CONFIG_SCHEMA_VERSION, VERSION};
+pub use crate::watch::{debounce, WATCH_DEBOUNCE};
+pub use crate::xdg::{Dirs, XdgDir};
@@ -0,0 +1,120 @@
+//! State that belongs to the machine ghmd runs on rather than to the shared config file.
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use glob::Pattern;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::errors::Result;
+use crate::paths;
+use crate::version;
+use crate::xdg::Dirs;
+
+/// Name of the machine ghmd runs on, if it can be determined: the hostname of the kernel on
+/// Linux, else the one in `/etc/hostname`, else the `HOSTNAME` or `COMPUTERNAME` environment
+/// variable.
+pub fn hostname() -> Option<String> {
+    ["/proc/sys/kernel/hostname", "/etc/hostname"]
+        .iter()
+        .find_map(|path| fs::read_to_string(path).ok())
+        .or_else(|| env::var("HOSTNAME").ok())
+        .or_else(|| env::var("COMPUTERNAME").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+/// MachineState is kept in the state directory of the machine, so unlike the config file it isn't
+/// meant to be shared between machines.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq, Clone)]
+pub struct MachineState {
+    /// Version of ghmd that last wrote the state file.
+    #[serde(default)]
+    ghmd_version: Option<String>,
+
+    /// Glob patterns matched against the relative paths of tracked dotfiles that are never
+    /// deployed to this machine by bulk operations.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    skip: BTreeSet<String>,
+
+    /// Dotfiles directories this machine already warned about not being under version control,
+    /// so that the warning is only given once.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    vcs_warned: BTreeSet<PathBuf>,
+}
+
+impl MachineState {
+    /// Load the state file of this machine, or an empty state if there is none yet.
+    pub fn load() -> Result<Self> {
+        Self::load_from(&Self::file_path()?)
+    }
+
+    /// Load the state file at `path`, or an empty state if it doesn't exist.
+    pub fn load_from(path: &Path) -> Result<Self> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Self::default())
+            },
+            Err(e) => return Err(e.into()),
+        };
+        let state: Self = toml::from_str(&contents)?;
+        if let Some(written) = &state.ghmd_version {
+            version::check_compatible(path, written)?;
+        }
+        Ok(state)
+    }
+
+    /// Path of the state file.
+    pub fn file_path() -> Result<PathBuf> {
+        Ok(Dirs::from_env()?.state_file())
+    }
+
+    /// Write the state to the state file of this machine.
+    pub fn write(&mut self) -> Result<()> {
+        self.write_to(&Self::file_path()?)
+    }
+
+    /// Write the state to `path`, recording the running version of ghmd.
+    pub fn write_to(&mut self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            Dirs::create(parent)?;
+        }
+        self.ghmd_version = Some(version::VERSION.to_string());
+        let toml = toml::to_string_pretty(&self).unwrap();
+        paths::write_atomic(path, &toml, false)?;
+        Ok(())
+    }
+
+    /// Add `pattern` to the skip list, returning whether it wasn't on the list yet.
+    pub fn add_skip(&mut self, pattern: &str) -> Result<bool> {
+        let _ = Pattern::new(pattern)?;
+        Ok(self.skip.insert(pattern.to_string()))
+    }
+
+    /// Remove `pattern` from the skip list, returning whether it was on the list.
+    pub fn remove_skip(&mut self, pattern: &str) -> bool {
+        self.skip.remove(pattern)
+    }
+
+    /// Patterns on the skip list, in lexicographic order.
+    pub fn skip_list(&self) -> impl Iterator<Item = &str> {
+        self.skip.iter().map(String::as_str)
+    }
+
+    /// Record that this machine warned about `dir` not being under version control, returning
+    /// whether it hadn't been recorded yet.
+    pub fn record_vcs_warning(&mut self, dir: &Path) -> bool {
+        self.vcs_warned.insert(dir.to_path_buf())
+    }
+
+    /// Compile the patterns on the skip list.
+    pub fn skip_patterns(&self) -> Result<Vec<Pattern>> {
+        Ok(self
+            .skip
+            .iter()
+            .map(|pattern| Pattern::new(pattern))
+            .collect::<std::result::Result<_, _>>()?)
+    }
+}
@@ -1,16 +1,43 @@
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::mpsc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use anyhow::{anyhow, Result};
 use clap::{crate_authors, crate_description, crate_name};
-use clap::{App, AppSettings, Arg, ArgMatches};
-use glob::glob;
-use log;
-use pretty_env_logger;
+use clap::{App, AppSettings, Arg, ArgGroup, ArgMatches};
+use glob::{glob_with, MatchOptions, Pattern};
 
-use ghmd::Config;
-use ghmd::{DotfilePath, DotfilesDir, SymlinkDir};
+use ghmd::paths::Traversal;
+use ghmd::USER_DEPLOY_SUBCOMMAND;
+use ghmd::{commit_paths, init_repo, stow_commit_message};
+use ghmd::{debounce, WATCH_DEBOUNCE};
+use ghmd::{parse_age, MtimeFilter};
+use ghmd::{
+    running_as_root, Action, ActionOutcome, Observer, OperationsLog, Resolution, User,
+};
+use ghmd::{Config, Dirs, Error, ErrorPolicy, Selector, CONFIG_SCHEMA_VERSION, VERSION};
+use ghmd::{Conflict, MachineState, Service, Shell, Systemctl};
+use ghmd::{
+    DeployOptions, LinkType, RestoreOptions, StowOptions, SymlinkState, Template,
+};
+use ghmd::{
+    DirKind, DotfilePath, Dotfiles, DotfilesDir, LinkMode, LinkStyle, SymlinkDir,
+};
+use ghmd::{Git, ManifestFile, Reference, TreeMark};
+
+fn main() -> Result<ExitCode> {
+    let version = format!("{0} (config schema {1})", VERSION, CONFIG_SCHEMA_VERSION);
+
+    let link_mode_arg = Arg::with_name("link_mode")
+        .long("link-mode")
+        .takes_value(true)
+        .value_name("mode")
+        .possible_values(["symlink", "hardlink", "relative-symlink"]);
 
-fn main() -> Result<()> {
     let stow_subcommand = App::new("stow")
         .about(
             "store input files in the specified dotfiles directory, and replace the file's \
@@ -20,22 +47,194 @@ fn main() -> Result<()> {
         .arg(
             Arg::with_name("symlink_dir")
                 .help("path relative to which symlink directory")
-                .required(true)
+                .required_unless_present("auto")
                 .multiple(false),
         )
         .arg(
             Arg::with_name("dotfiles_dir")
                 .help("path of the dotfiles directory")
-                .required(true)
+                .required_unless_present("auto")
                 .multiple(false),
         )
         .arg(
             Arg::with_name("files")
                 .help("path of the file/files to be stored in the dotfiles directory")
-                .required(true)
                 .multiple(true),
+        )
+        .arg(
+            Arg::with_name("auto")
+                .help(
+                    "stow these files into the configured dotfiles directories whose \
+                     symlink directories contain them",
+                )
+                .long("auto")
+                .takes_value(true)
+                .multiple_values(true)
+                .value_name("file")
+                .conflicts_with_all(&[
+                    "symlink_dir",
+                    "dotfiles_dir",
+                    "files",
+                    "link_style",
+                    "link_type",
+                    "link_mode",
+                    "canonical_targets",
+                    "copy",
+                    "steal",
+                ]),
+        )
+        .arg(
+            Arg::with_name("link_style")
+                .help("whether symlinks point at dotfiles using absolute or relative paths")
+                .long("link-style")
+                .takes_value(true)
+                .possible_values(["absolute", "relative"]),
+        )
+        .arg(
+            Arg::with_name("link_type")
+                .help("link directories using symlinks or, on Windows, junctions")
+                .long("link-type")
+                .takes_value(true)
+                .possible_values(["symlink", "junction"]),
+        )
+        .arg(link_mode_arg.clone().help(
+            "link files using symlinks, symlinks with relative targets or hardlinks",
+        ))
+        .arg(
+            Arg::with_name("canonical_targets")
+                .help("point symlinks at the canonicalized paths of dotfiles")
+                .long("canonical-targets"),
+        )
+        .arg(
+            Arg::with_name("copy")
+                .help("copy files into the dotfiles directory, leaving the originals alone")
+                .long("copy")
+                .conflicts_with_all(&[
+                    "continue_on_error",
+                    "steal",
+                    "link_type",
+                    "link_mode",
+                ]),
+        )
+        .arg(
+            Arg::with_name("glob_case_insensitive")
+                .help("match glob patterns in file arguments ignoring case")
+                .long("glob-case-insensitive"),
+        )
+        .arg(
+            Arg::with_name("include_dotfiles")
+                .help("let wildcards in file arguments match hidden files")
+                .long("include-dotfiles"),
+        )
+        .arg(
+            Arg::with_name("include_dotdirs")
+                .help("let wildcards in file arguments match hidden directories like .git")
+                .long("include-dotdirs"),
+        )
+        .arg(
+            Arg::with_name("continue_on_error")
+                .help("stow as many files as possible and report all failures at the end")
+                .long("continue-on-error"),
+        )
+        .arg(
+            Arg::with_name("steal")
+                .help(
+                    "take over files already tracked in another dotfiles directory, moving \
+                     them into this one",
+                )
+                .long("steal"),
+        )
+        .arg(
+            Arg::with_name("xdg")
+                .help(
+                    "store files inside an XDG base directory like $XDG_CONFIG_HOME \
+                     under a token such as ${CONFIG}, so they deploy into that \
+                     directory on every machine",
+                )
+                .long("xdg")
+                .conflicts_with_all(&["auto", "steal"]),
+        )
+        .arg(
+            Arg::with_name("dry_run")
+                .help("print what would be done and ask for confirmation before doing it")
+                .long("dry-run")
+                .conflicts_with_all(&["auto", "copy", "steal"]),
+        )
+        .arg(
+            Arg::with_name("as")
+                .help(
+                    "stow a single file from anywhere, storing and linking it at this \
+                     path relative to the dotfiles and symlink directories",
+                )
+                .long("as")
+                .takes_value(true)
+                .value_name("relative_dest")
+                .conflicts_with_all(&[
+                    "auto",
+                    "copy",
+                    "steal",
+                    "xdg",
+                    "dry_run",
+                    "continue_on_error",
+                    "glob_case_insensitive",
+                    "include_dotfiles",
+                    "include_dotdirs",
+                ]),
+        )
+        .arg(
+            Arg::with_name("git_commit")
+                .help(
+                    "add the stowed files to the git repository of the dotfiles directory \
+                     and commit them, with a message describing them unless one is given",
+                )
+                .long("git-commit")
+                .takes_value(true)
+                .min_values(0)
+                .max_values(1)
+                .require_equals(true)
+                .value_name("message"),
         );
 
+    let target_dir_arg = Arg::with_name("target_dir")
+        .help(
+            "use this directory in place of every configured symlink directory without \
+             modifying the config",
+        )
+        .long("target-dir")
+        .takes_value(true);
+
+    let path_arg = Arg::with_name("path")
+        .help("select tracked dotfiles whose paths match this glob pattern")
+        .long("path")
+        .takes_value(true)
+        .multiple_occurrences(true);
+
+    let dir_arg = Arg::with_name("dir")
+        .help("select tracked dotfiles stored in this dotfiles directory")
+        .long("dir")
+        .takes_value(true)
+        .multiple_occurrences(true);
+
+    let group_arg = Arg::with_name("group")
+        .help("select tracked dotfiles of entries with this profile, see `profiles`")
+        .long("group")
+        .takes_value(true)
+        .multiple_occurrences(true);
+
+    let host_arg = Arg::with_name("host")
+        .help(
+            "select tracked dotfiles of entries that apply to this host, see `platforms`",
+        )
+        .long("host")
+        .takes_value(true)
+        .value_name("hostname")
+        .multiple_occurrences(true);
+
+    let invert_arg = Arg::with_name("invert")
+        .help("select the tracked dotfiles not matched by the other selection arguments")
+        .long("invert");
+
+    let mtime_conflicts = ["incremental", "for_user", "dry_run", "symlink_name"];
     let deploy_subcommand = App::new("deploy")
         .about(
             "for new configurations, create symlinks in directories relative to the \
@@ -46,15 +245,168 @@ fn main() -> Result<()> {
         .arg(
             Arg::with_name("dotfiles")
                 .help("stored dotfile/s to be deployed to system")
-                .required(true)
                 .multiple(true),
         )
+        .arg(
+            Arg::with_name("store_dir")
+                .help("treat dotfiles as glob patterns within this dotfiles directory")
+                .long("dotfiles-dir")
+                .takes_value(true)
+                .value_name("dotfiles_dir")
+                .requires("dotfiles")
+                .conflicts_with_all(&["path", "dir", "group", "host", "invert"]),
+        )
         .arg(
             Arg::with_name("all")
                 .help("deploy all stored dotfiles")
                 .long("all")
-                .conflicts_with("dotfiles")
-                .required(true),
+                .conflicts_with_all(&["dotfiles", "path", "dir", "group", "host", "invert"])
+                .required_unless_present_any([
+                    "dotfiles",
+                    "incremental",
+                    "path",
+                    "dir",
+                    "group",
+                    "host",
+                    "invert",
+                ]),
+        )
+        .arg(
+            Arg::with_name("incremental")
+                .help("deploy only dotfiles modified since the last deploy of all dotfiles")
+                .long("incremental")
+                .conflicts_with_all(&["dotfiles", "all"]),
+        )
+        .arg(
+            Arg::with_name("symlink_name")
+                .help("also deploy the given dotfile under this file name from now on")
+                .long("symlink-name")
+                .takes_value(true)
+                .value_name("name")
+                .requires("dotfiles")
+                .conflicts_with_all(&[
+                    "store_dir",
+                    "all",
+                    "incremental",
+                    "path",
+                    "dir",
+                    "group",
+                    "host",
+                    "invert",
+                    "dry_run",
+                    "target_dir",
+                ]),
+        )
+        .arg(
+            Arg::with_name("jobs")
+                .help("number of dotfiles deployed in parallel with --all")
+                .long("jobs")
+                .short('j')
+                .takes_value(true)
+                .value_parser(clap::value_parser!(usize))
+                .conflicts_with_all(&["dotfiles", "incremental"]),
+        )
+        .arg(
+            Arg::with_name("skip_conflicts")
+                .help("with --all, skip dotfiles with occupied symlink paths instead of failing")
+                .long("skip-conflicts")
+                .conflicts_with_all(&["dotfiles", "incremental"]),
+        )
+        .arg(path_arg.clone().conflicts_with_all(&["dotfiles", "incremental"]))
+        .arg(dir_arg.clone().conflicts_with_all(&["dotfiles", "incremental"]))
+        .arg(group_arg.clone().conflicts_with_all(&["dotfiles", "incremental"]))
+        .arg(host_arg.clone().conflicts_with_all(&["dotfiles", "incremental"]))
+        .arg(invert_arg.clone().conflicts_with_all(&["dotfiles", "incremental"]))
+        .arg(target_dir_arg.clone())
+        .arg(
+            link_mode_arg
+                .clone()
+                .help("link the dotfiles this way instead of as configured")
+                .conflicts_with_all(&["incremental", "for_user", "dry_run"]),
+        )
+        .arg(
+            Arg::with_name("older_than")
+                .help("only deploy dotfiles last modified in the store before <age> ago")
+                .long("older-than")
+                .takes_value(true)
+                .value_name("age")
+                .conflicts_with_all(&mtime_conflicts),
+        )
+        .arg(
+            Arg::with_name("newer_than")
+                .help("only deploy dotfiles last modified in the store within <age>")
+                .long("newer-than")
+                .takes_value(true)
+                .value_name("age")
+                .conflicts_with_all(&mtime_conflicts),
+        )
+        .arg(
+            Arg::with_name("expand_dest")
+                .help("replace ${NAME} in symlink paths with environment variable NAME")
+                .long("expand-dest")
+                .conflicts_with_all(&["incremental", "for_user", "dry_run"]),
+        )
+        .arg(
+            Arg::with_name("create")
+                .help("create the directory given by --target-dir if it doesn't exist")
+                .long("create")
+                .requires("target_dir"),
+        )
+        .arg(
+            Arg::with_name("dry_run")
+                .help("show the symlinks that would be created without creating them")
+                .long("dry-run")
+                .conflicts_with_all(&["incremental", "jobs", "skip_conflicts", "create"]),
+        )
+        .arg(
+            Arg::with_name("tree")
+                .help("with --dry-run, show the symlinks as a tree of their directories")
+                .long("tree")
+                .requires("dry_run"),
+        )
+        .arg(
+            Arg::with_name("interactive")
+                .help("ask whether to skip, back up or fail on each conflict")
+                .long("interactive")
+                .short('i')
+                .conflicts_with_all(&[
+                    "dry_run",
+                    "incremental",
+                    "jobs",
+                    "skip_conflicts",
+                    "store_dir",
+                    "path",
+                    "dir",
+                    "group",
+                    "host",
+                    "invert",
+                ]),
+        )
+        .arg(
+            Arg::with_name("for_user")
+                .help(
+                    "as root, deploy the dotfiles inside the home directory into the \
+                     home directory of this user instead, who then owns the symlinks",
+                )
+                .long("for-user")
+                .takes_value(true)
+                .value_name("user")
+                .multiple_occurrences(true)
+                .conflicts_with_all(&[
+                    "dotfiles",
+                    "store_dir",
+                    "incremental",
+                    "path",
+                    "dir",
+                    "group",
+                    "host",
+                    "invert",
+                    "target_dir",
+                    "dry_run",
+                    "interactive",
+                    "jobs",
+                    "skip_conflicts",
+                ]),
         );
 
     let restore_subcommand = App::new("restore")
@@ -62,128 +414,2679 @@ fn main() -> Result<()> {
         .display_order(4)
         .arg(
             Arg::with_name("dotfiles_dir")
-                .help("path of the dotfiles directory")
-                .required(true)
+                .help(
+                    "path of the dotfiles directory, or the first pattern with \
+                     --dotfiles-dir",
+                )
+                .required_unless_present_any([
+                    "symlink_dir",
+                    "path",
+                    "dir",
+                    "group",
+                    "host",
+                    "invert",
+                ])
                 .multiple(false),
         )
         .arg(
             Arg::with_name("dotfiles")
                 .help("the dotfiles to restore to original locations")
                 .multiple(true)
-                .required(true),
+                .required_unless_present_any([
+                    "symlink_dir",
+                    "store_dir",
+                    "path",
+                    "dir",
+                    "group",
+                    "host",
+                    "invert",
+                ]),
+        )
+        .arg(
+            Arg::with_name("store_dir")
+                .help("treat dotfiles as glob patterns within this dotfiles directory")
+                .long("dotfiles-dir")
+                .takes_value(true)
+                .value_name("dotfiles_dir")
+                .requires("dotfiles_dir")
+                .conflicts_with_all(&[
+                    "symlink_dir",
+                    "path",
+                    "dir",
+                    "group",
+                    "host",
+                    "invert",
+                ]),
+        )
+        .arg(
+            Arg::with_name("force")
+                .help("replace files at the original locations even if they are newer")
+                .long("force"),
+        )
+        .arg(
+            Arg::with_name("clean_store")
+                .help(
+                    "remove directories of the dotfiles directory left empty by moving \
+                     the dotfiles out of it",
+                )
+                .long("clean-store"),
+        )
+        .arg(
+            Arg::with_name("to")
+                .help(
+                    "move the dotfiles into <dir> below their relative paths instead of \
+                     back to their symlinks",
+                )
+                .long("to")
+                .takes_value(true)
+                .value_name("dir")
+                .conflicts_with("force"),
+        )
+        .arg(
+            Arg::with_name("symlink_dir")
+                .help("restore every dotfile deployed to this directory")
+                .long("symlink-dir")
+                .takes_value(true)
+                .conflicts_with_all(&["dotfiles_dir", "dotfiles", "force"]),
+        )
+        .arg(
+            path_arg
+                .clone()
+                .conflicts_with_all(&["dotfiles_dir", "symlink_dir"]),
+        )
+        .arg(
+            dir_arg
+                .clone()
+                .conflicts_with_all(&["dotfiles_dir", "symlink_dir"]),
+        )
+        .arg(
+            group_arg
+                .clone()
+                .conflicts_with_all(&["dotfiles_dir", "symlink_dir"]),
+        )
+        .arg(
+            host_arg
+                .clone()
+                .conflicts_with_all(&["dotfiles_dir", "symlink_dir"]),
+        )
+        .arg(
+            invert_arg
+                .clone()
+                .conflicts_with_all(&["dotfiles_dir", "symlink_dir"]),
         );
 
-    let matches = App::new(crate_name!())
-        .setting(AppSettings::ArgRequiredElseHelp)
-        .about(crate_description!())
-        .author(crate_authors!())
-        .after_help("https://github.com/waynr/ghmd")
+    let forget_subcommand = App::new("forget")
+        .about("stop tracking dotfiles without touching any files")
+        .display_order(5)
         .arg(
-            Arg::with_name("verbose")
-                .short('v')
+            Arg::with_name("dotfiles_dir")
                 .help("path of the dotfiles directory")
-                .action(clap::ArgAction::Count),
+                .required_unless_present_any(["path", "dir", "group", "host", "invert"])
+                .multiple(false),
+        )
+        .arg(
+            Arg::with_name("dotfiles")
+                .help("the dotfiles to stop tracking")
+                .multiple(true)
+                .required_unless_present_any([
+                    "all", "path", "dir", "group", "host", "invert",
+                ]),
+        )
+        .arg(
+            Arg::with_name("all")
+                .help("stop tracking every dotfile in the dotfiles directory")
+                .long("all")
+                .conflicts_with("dotfiles"),
         )
-        .subcommands(vec![stow_subcommand, deploy_subcommand, restore_subcommand])
-        .get_matches();
+        .arg(
+            Arg::with_name("remove_store")
+                .help("remove the dotfiles directory from the config as well")
+                .long("remove-store")
+                .requires("all"),
+        )
+        .arg(
+            path_arg
+                .clone()
+                .conflicts_with_all(&["dotfiles_dir", "all"]),
+        )
+        .arg(dir_arg.clone().conflicts_with_all(&["dotfiles_dir", "all"]))
+        .arg(
+            group_arg
+                .clone()
+                .conflicts_with_all(&["dotfiles_dir", "all"]),
+        )
+        .arg(
+            host_arg
+                .clone()
+                .conflicts_with_all(&["dotfiles_dir", "all"]),
+        )
+        .arg(
+            invert_arg
+                .clone()
+                .conflicts_with_all(&["dotfiles_dir", "all"]),
+        );
 
-    let verbosity = matches.get_one::<u8>("verbose").copied();
+    let split_subcommand = App::new("split")
+        .about("move the entries of a dotfiles directory into a new config file")
+        .display_order(5)
+        .arg(
+            Arg::with_name("dotfiles_dir")
+                .help("path of the dotfiles directory")
+                .required(true)
+                .multiple(false),
+        )
+        .arg(
+            Arg::with_name("new_config_path")
+                .help("path of the config file to create")
+                .required(true)
+                .multiple(false),
+        );
 
-    let mut logger_builder = &mut pretty_env_logger::formatted_builder();
+    let patterns_arg = Arg::with_name("patterns")
+        .help("glob patterns matched against tracked dotfile paths; defaults to all dotfiles")
+        .multiple(true);
 
-    let level = match verbosity {
-        Some(0) => log::LevelFilter::Info,
-        Some(1) => log::LevelFilter::Debug,
-        Some(_) => log::LevelFilter::Trace,
-        None => log::LevelFilter::Info,
-    };
+    let sort_arg = Arg::with_name("sort")
+        .help("order in which tracked dotfiles are printed")
+        .long("sort")
+        .takes_value(true)
+        .possible_values(["path", "state", "store"])
+        .default_value("path");
 
-    logger_builder = logger_builder.filter_level(level);
-    if level == log::LevelFilter::Info {
-        logger_builder = logger_builder.default_format();
-        logger_builder = logger_builder.format_module_path(false);
-        logger_builder = logger_builder.format_level(false);
-        logger_builder = logger_builder.format_timestamp(None);
-    }
+    let format_arg = Arg::with_name("format")
+        .help("output format")
+        .long("format")
+        .takes_value(true)
+        .possible_values(["text", "json"])
+        .default_value("text");
 
-    logger_builder.try_init()?;
-    log::debug!("verbosity set to {0}", level);
+    let template_format_arg = Arg::with_name("format")
+        .help("output format: text, json or a template such as '{path}\\t{state}'")
+        .long("format")
+        .takes_value(true)
+        .value_name("format")
+        .default_value("text");
 
-    let mut config = Config::load()?;
+    let list_subcommand = App::new("list")
+        .about("list tracked dotfiles")
+        .display_order(6)
+        .arg(patterns_arg.clone())
+        .arg(path_arg.clone())
+        .arg(dir_arg.clone())
+        .arg(group_arg.clone())
+        .arg(host_arg.clone())
+        .arg(invert_arg.clone())
+        .arg(sort_arg.clone())
+        .arg(
+            Arg::with_name("by_target")
+                .help("group dotfiles by the directory they are deployed to")
+                .long("by-target")
+                .conflicts_with("sort"),
+        )
+        .arg(template_format_arg.clone())
+        .arg(target_dir_arg.clone());
 
-    match matches.subcommand() {
-        Some(("stow", stow_matches)) => stow(&mut config, stow_matches)?,
-        Some(("deploy", deploy_matches)) => deploy(&config, deploy_matches)?,
-        Some(("restore", restore_matches)) => restore(&mut config, restore_matches)?,
-        Some((s, _)) => return Err(anyhow!("invalid subcommand: {0}", s)),
-        None => return Err(anyhow!("missing subcommand")),
-    }
-    Ok(())
-}
+    let status_subcommand = App::new("status")
+        .about("show the deployment state of tracked dotfiles")
+        .display_order(7)
+        .arg(patterns_arg.clone())
+        .arg(path_arg.clone())
+        .arg(dir_arg.clone())
+        .arg(group_arg.clone())
+        .arg(host_arg.clone())
+        .arg(invert_arg.clone())
+        .arg(sort_arg)
+        .arg(template_format_arg.clone())
+        .arg(target_dir_arg.clone())
+        .arg(
+            Arg::with_name("pending")
+                .help("only list the tracked dotfiles that have nothing at their symlink path")
+                .long("pending")
+                .conflicts_with_all(&[
+                    "patterns",
+                    "path",
+                    "dir",
+                    "group",
+                    "host",
+                    "invert",
+                    "sort",
+                    "format",
+                    "target_dir",
+                ]),
+        );
 
-fn stow(config: &mut Config, matches: &ArgMatches) -> Result<()> {
-    let dotfiles_dir: DotfilesDir = matches
-        .get_one::<String>("dotfiles_dir")
-        .and_then(|s| Some(PathBuf::from(s)))
-        .ok_or(anyhow!("must include dotfiles_dir argument"))?
-        .try_into()?;
+    let verify_subcommand = App::new("verify")
+        .about("report tracked dotfiles that are not deployed and fail if there are any")
+        .display_order(8)
+        .arg(patterns_arg)
+        .arg(path_arg)
+        .arg(dir_arg)
+        .arg(group_arg)
+        .arg(host_arg)
+        .arg(invert_arg)
+        .arg(target_dir_arg);
 
-    let symlink_dir: SymlinkDir = matches
-        .get_one::<String>("symlink_dir")
-        .and_then(|s| Some(PathBuf::from(s)))
-        .ok_or(anyhow!("must include symlink_dir argument"))?
-        .try_into()?;
+    let find_subcommand = App::new("find")
+        .about(
+            "find tracked dotfiles by case-insensitive substring or regular expression",
+        )
+        .display_order(9)
+        .arg(
+            Arg::with_name("query")
+                .help("text to search for in tracked dotfile paths")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("regex")
+                .help("interpret the query as a regular expression")
+                .long("regex"),
+        );
 
-    log::debug!("dotfiles_dir: {:?}", dotfiles_dir);
-    log::debug!("symlink_dir: {:?}", symlink_dir);
+    let explain_subcommand = App::new("explain")
+        .about("trace how a path is resolved to a tracked dotfile")
+        .display_order(9)
+        .arg(
+            Arg::with_name("path")
+                .help("dotfile path, relative or inside a symlink or dotfiles directory")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("store")
+                .help("only print the matching directory inside a dotfiles directory")
+                .long("store"),
+        );
 
-    let mut dotfile_paths: Vec<DotfilePath> = Vec::new();
-    for glob_path in matches.values_of("files").unwrap() {
-        for path in &glob(glob_path)?
-            .filter_map(Result::ok)
-            .collect::<Vec<PathBuf>>()
-        {
-            dotfile_paths.push((&symlink_dir, &dotfiles_dir, path).try_into()?);
-        }
-    }
+    let plan_subcommand = App::new("plan")
+        .about("show whether files would be stowed, deployed or restored")
+        .display_order(9)
+        .arg(
+            Arg::with_name("files")
+                .help("paths in dotfiles or symlink directories")
+                .required(true)
+                .multiple(true),
+        );
 
-    config.stow_paths(symlink_dir, dotfiles_dir, dotfile_paths)?;
-    Ok(())
-}
+    let export_subcommand = App::new("export")
+        .about("export tracked dotfiles into a format understood by other tools")
+        .display_order(10)
+        .arg(
+            Arg::with_name("ansible")
+                .help("generate an Ansible role deploying the dotfiles in this directory")
+                .long("ansible")
+                .takes_value(true)
+                .value_name("role_dir"),
+        )
+        .arg(
+            Arg::with_name("for_host")
+                .help("print the config with only the entries that apply to this host")
+                .long("for-host")
+                .takes_value(true)
+                .value_name("hostname"),
+        )
+        .arg(
+            Arg::with_name("manifest")
+                .help("print the content hashes of tracked dotfiles for verify-remote")
+                .long("manifest"),
+        )
+        .arg(
+            Arg::with_name("makefile")
+                .help("generate a Makefile running ghmd to deploy the dotfiles")
+                .long("makefile")
+                .takes_value(true)
+                .value_name("path"),
+        )
+        .group(
+            ArgGroup::new("export_format")
+                .required(true)
+                .args(&["ansible", "for_host", "manifest", "makefile"]),
+        );
 
-fn deploy(config: &Config, values: &ArgMatches) -> Result<()> {
-    if values.is_present("all") {
-        config.deploy_all()?;
-        return Ok(());
-    };
+    let import_subcommand = App::new("import")
+        .about("track dotfiles managed by another tool")
+        .display_order(10)
+        .arg(
+            Arg::with_name("chezmoi")
+                .help("track the files of this chezmoi source directory, leaving it untouched")
+                .long("chezmoi")
+                .takes_value(true)
+                .value_name("src_dir"),
+        )
+        .arg(
+            Arg::with_name("target")
+                .help("directory the imported dotfiles are deployed to, $HOME by default")
+                .long("target")
+                .takes_value(true)
+                .value_name("symlink_dir"),
+        )
+        .group(
+            ArgGroup::new("import_format")
+                .required(true)
+                .args(&["chezmoi"]),
+        );
 
-    let paths: Vec<PathBuf> = values
-        .values_of("dotfiles")
-        .unwrap()
-        .map(PathBuf::from)
-        .collect();
+    let config_subcommand = App::new("config")
+        .about("inspect ghmd's own configuration")
+        .display_order(11)
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            App::new("paths").about("print the directories where ghmd keeps its files"),
+        );
 
-    config.deploy_paths(paths)?;
-    Ok(())
-}
+    let doctor_subcommand = App::new("doctor")
+        .about("diagnose problems with ghmd's configuration and persisted files")
+        .display_order(12)
+        .arg(
+            Arg::with_name("fix")
+                .help(
+                    "offer to turn dotfiles directories that aren't under version \
+                     control into git repositories",
+                )
+                .long("fix"),
+        );
 
-fn restore(config: &mut Config, matches: &ArgMatches) -> Result<()> {
-    let dotfiles_dir: DotfilesDir = matches
-        .get_one::<String>("dotfiles_dir")
-        .and_then(|s| Some(PathBuf::from(s)))
-        .ok_or(anyhow!("must include dotfiles_dir argument"))?
-        .try_into()?;
+    let prune_config_subcommand = App::new("prune-config")
+        .about("stop tracking dotfiles that no longer exist in their dotfiles directory")
+        .display_order(13)
+        .arg(
+            Arg::with_name("yes")
+                .help("remove the missing dotfiles from the config without asking")
+                .long("yes")
+                .short('y'),
+        )
+        .arg(
+            Arg::with_name("remove_links")
+                .help("also delete dangling symlinks left behind by the missing dotfiles")
+                .long("remove-links"),
+        )
+        .arg(format_arg);
 
-    let dotfiles: Vec<PathBuf> = matches
-        .values_of("dotfiles")
-        .unwrap()
-        .map(PathBuf::from)
-        .collect();
+    let unlink_subcommand = App::new("unlink")
+        .about("remove deployed symlinks without restoring dotfiles")
+        .display_order(4)
+        .arg(
+            Arg::with_name("all")
+                .help("remove the symlinks of all tracked dotfiles")
+                .long("all"),
+        )
+        .group(
+            ArgGroup::new("unlink_selection")
+                .required(true)
+                .args(&["all"]),
+        );
 
-    for dotfile in dotfiles.into_iter() {
-        let dotfile: DotfilePath = (dotfiles_dir.clone(), dotfile).try_into()?;
-        config.restore_dotfile(dotfile)?;
+    let repair_subcommand = App::new("repair")
+        .about("fix up the filesystem around deployed symlinks")
+        .display_order(14)
+        .arg(
+            Arg::with_name("dirs")
+                .help("create missing parent directories of all symlinks")
+                .long("dirs"),
+        )
+        .arg(
+            Arg::with_name("old")
+                .help(
+                    "previous path of a dotfiles directory moved to --new by other \
+                     means, whose symlinks are pointed at the new location",
+                )
+                .long("old")
+                .takes_value(true)
+                .value_name("old")
+                .requires("new"),
+        )
+        .arg(
+            Arg::with_name("new")
+                .help("path the dotfiles directory given by --old was moved to")
+                .long("new")
+                .takes_value(true)
+                .value_name("new")
+                .requires("old"),
+        )
+        .group(
+            ArgGroup::new("repair_mode")
+                .required(true)
+                .args(&["dirs", "old"]),
+        );
+
+    let convert_links_subcommand = App::new("convert-links")
+        .about("recreate deployed symlinks using absolute or relative targets")
+        .display_order(14)
+        .arg(
+            Arg::with_name("link_style")
+                .help("form of the target paths written into the symlinks")
+                .required(true)
+                .possible_values(["absolute", "relative"]),
+        );
+
+    let rename_dir_subcommand = App::new("rename-dir")
+        .about("change a configured directory in the config without touching any files")
+        .display_order(14)
+        .arg(
+            Arg::with_name("kind")
+                .help("which directory of the dotfiles entry to change")
+                .long("kind")
+                .takes_value(true)
+                .required(true)
+                .possible_values(["dotfiles", "symlink"]),
+        )
+        .arg(
+            Arg::with_name("old")
+                .help("directory as currently configured")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("new")
+                .help("existing directory to configure instead")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("all_matching")
+                .help("change every dotfiles entry configured with the old directory")
+                .long("all-matching"),
+        );
+
+    let install_service_subcommand = App::new("install-service")
+        .about("install a systemd user timer that periodically deploys all dotfiles")
+        .display_order(15)
+        .arg(
+            Arg::with_name("interval")
+                .help("how often to deploy, as a systemd time span")
+                .long("interval")
+                .takes_value(true)
+                .default_value("1h"),
+        )
+        .arg(
+            Arg::with_name("now")
+                .help("also enable and start the timer with systemctl")
+                .long("now"),
+        );
+
+    let uninstall_service_subcommand = App::new("uninstall-service")
+        .about("disable and remove the systemd user timer installed by install-service")
+        .display_order(16);
+
+    // run by `deploy --for-user` as the other user, see `Config::deploy_for_user`
+    let deploy_for_parent_subcommand = App::new(USER_DEPLOY_SUBCOMMAND).hide(true);
+
+    let shell_hook_subcommand = App::new("shell-hook")
+        .about("print shell code warning about undeployed dotfiles before every prompt")
+        .display_order(17)
+        .arg(
+            Arg::with_name("shell")
+                .help("shell to generate the code for")
+                .required(true)
+                .possible_values(["bash", "zsh", "fish"]),
+        );
+
+    let skip_pattern_arg = Arg::with_name("pattern")
+        .help("glob pattern matched against the relative paths of tracked dotfiles")
+        .required(true);
+    let skip_subcommand = App::new("skip")
+        .about("manage dotfiles that deploy --all leaves alone on this machine")
+        .display_order(18)
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            App::new("add")
+                .about("skip dotfiles matching a pattern on this machine")
+                .arg(skip_pattern_arg.clone()),
+        )
+        .subcommand(
+            App::new("remove")
+                .about("stop skipping a pattern on this machine")
+                .arg(skip_pattern_arg),
+        )
+        .subcommand(App::new("list").about("print the patterns skipped on this machine"));
+
+    let entry_dir_arg = Arg::with_name("dotfiles_dir")
+        .help("dotfiles directory of the entry")
+        .required(true);
+    let entry_subcommand = App::new("entry")
+        .about("park dotfiles entries without removing them from the config")
+        .display_order(24)
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            App::new("enable")
+                .about("include an entry in operations on all dotfiles again")
+                .arg(entry_dir_arg.clone()),
+        )
+        .subcommand(
+            App::new("disable")
+                .about("leave an entry out unless its dotfiles directory is named")
+                .arg(entry_dir_arg),
+        );
+
+    let watch_subcommand = App::new("watch")
+        .about("deploy tracked dotfiles whenever their dotfiles directory changes")
+        .display_order(19);
+
+    #[cfg(feature = "bundle")]
+    let bundle_subcommand = App::new("bundle")
+        .about("archive every dotfiles directory with a manifest for offline machines")
+        .display_order(22)
+        .arg(
+            Arg::with_name("output")
+                .help("path of the zstd-compressed tar archive to write")
+                .long("output")
+                .short('o')
+                .takes_value(true)
+                .value_name("path")
+                .required(true),
+        );
+
+    #[cfg(feature = "bundle")]
+    let unbundle_subcommand = App::new("unbundle")
+        .about("extract a bundle and track the dotfiles directories it contains")
+        .display_order(23)
+        .arg(
+            Arg::with_name("bundle")
+                .help("bundle written by ghmd bundle")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("dest")
+                .help("directory to extract the dotfiles directories to")
+                .long("dest")
+                .takes_value(true)
+                .value_name("dir")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("deploy")
+                .help("deploy all dotfiles after extracting the bundle")
+                .long("deploy"),
+        );
+
+    let diff_subcommand = App::new("diff")
+        .about("compare tracked dotfiles with the symlinks that exist on disk")
+        .display_order(21)
+        .arg(
+            Arg::with_name("include_dotdirs")
+                .help("also look for symlinks to entries inside .git directories")
+                .long("include-dotdirs"),
+        );
+
+    let snapshots_subcommand = App::new("snapshots")
+        .about("list the snapshots taken of the config whenever it changes, oldest first")
+        .display_order(25)
+        .arg(
+            Arg::with_name("prune")
+                .help("delete all but the newest snapshots instead of listing them")
+                .long("prune"),
+        )
+        .arg(
+            Arg::with_name("keep")
+                .help(
+                    "number of snapshots --prune keeps instead of the configured number",
+                )
+                .long("keep")
+                .takes_value(true)
+                .value_name("n")
+                .value_parser(clap::value_parser!(usize))
+                .requires("prune"),
+        );
+
+    let verify_remote_subcommand = App::new("verify-remote")
+        .about("compare tracked dotfiles with a git revision or a published manifest")
+        .display_order(20)
+        .arg(
+            Arg::with_name("rev")
+                .help("git revision of the dotfiles directories to compare with")
+                .long("rev")
+                .takes_value(true)
+                .value_name("rev"),
+        )
+        .arg(
+            Arg::with_name("manifest_url")
+                .help("URL of a manifest written by export --manifest to compare with")
+                .long("manifest-url")
+                .takes_value(true)
+                .value_name("url"),
+        )
+        .arg(
+            Arg::with_name("manifest_file")
+                .help("local file holding a manifest written by export --manifest")
+                .long("manifest-file")
+                .takes_value(true)
+                .value_name("path"),
+        )
+        .group(ArgGroup::new("reference").required(true).args(&[
+            "rev",
+            "manifest_url",
+            "manifest_file",
+        ]));
+
+    let app = App::new(crate_name!())
+        .version(version.as_str())
+        .about(crate_description!())
+        .author(crate_authors!())
+        .after_help("https://github.com/waynr/ghmd")
+        .setting(AppSettings::ArgRequiredElseHelp)
+        .arg(
+            Arg::with_name("verbose")
+                .short('v')
+                .help("path of the dotfiles directory")
+                .action(clap::ArgAction::Count),
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .short('q')
+                .long("quiet")
+                .help("only log warnings and errors")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("durable")
+                .help(
+                    "sync directories to disk after writing the config and after each \
+                     symlink or file a deploy, stow or restore creates; slower with many \
+                     dotfiles",
+                )
+                .long("durable")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("merge_config")
+                .help(
+                    "merge changes made to the config file by something else while ghmd \
+                     was running instead of refusing to save the config",
+                )
+                .long("merge-config")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("no_validate")
+                .help(
+                    "skip checking that the configured directories exist and the config \
+                     file's entries are consistent when loading it",
+                )
+                .long("no-validate")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("snapshot_keep")
+                .help(
+                    "number of config snapshots to keep, overriding GHMD_SNAPSHOT_KEEP and the \
+                     default of 30",
+                )
+                .long("snapshot-keep")
+                .takes_value(true)
+                .value_name("n")
+                .value_parser(clap::value_parser!(usize))
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("json_errors")
+                .help("print errors to stderr as JSON objects")
+                .long("json-errors")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("json_events")
+                .help(
+                    "print a JSON object to stdout for each dotfile stowed, deployed or \
+                     restored",
+                )
+                .long("json-events")
+                .global(true),
+        )
+        .subcommands(vec![
+            stow_subcommand,
+            deploy_subcommand,
+            restore_subcommand,
+            unlink_subcommand,
+            forget_subcommand,
+            split_subcommand,
+            list_subcommand,
+            status_subcommand,
+            verify_subcommand,
+            find_subcommand,
+            explain_subcommand,
+            plan_subcommand,
+            export_subcommand,
+            import_subcommand,
+            config_subcommand,
+            doctor_subcommand,
+            prune_config_subcommand,
+            repair_subcommand,
+            convert_links_subcommand,
+            rename_dir_subcommand,
+            install_service_subcommand,
+            uninstall_service_subcommand,
+            shell_hook_subcommand,
+            skip_subcommand,
+            entry_subcommand,
+            watch_subcommand,
+            verify_remote_subcommand,
+            diff_subcommand,
+            snapshots_subcommand,
+            deploy_for_parent_subcommand,
+        ]);
+    #[cfg(feature = "bundle")]
+    let app = app
+        .subcommand(bundle_subcommand)
+        .subcommand(unbundle_subcommand);
+    let mut help = app.clone();
+    let matches = match app.try_get_matches() {
+        Ok(matches) => matches,
+        Err(e)
+            if e.kind() == clap::ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand =>
+        {
+            e.print()?;
+            show_pending()?;
+            // the usage error code clap exits with after printing this help
+            return Ok(ExitCode::from(2));
+        },
+        Err(e) => e.exit(),
+    };
+
+    let verbosity = matches.get_one::<u8>("verbose").copied();
+
+    let mut logger_builder = &mut pretty_env_logger::formatted_builder();
+
+    let level = match verbosity {
+        _ if matches.is_present("quiet") => log::LevelFilter::Warn,
+        Some(0) => log::LevelFilter::Info,
+        Some(1) => log::LevelFilter::Debug,
+        Some(_) => log::LevelFilter::Trace,
+        None => log::LevelFilter::Info,
+    };
+
+    logger_builder = logger_builder.filter_level(level);
+    if level <= log::LevelFilter::Info {
+        logger_builder = logger_builder.default_format();
+        logger_builder = logger_builder.format_module_path(false);
+        logger_builder = logger_builder.format_level(false);
+        logger_builder = logger_builder.format_timestamp(None);
+    }
+
+    logger_builder.try_init()?;
+    log::debug!("verbosity set to {0}", level);
+
+    if matches.subcommand().is_none() {
+        help.print_help()?;
+        show_pending()?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let Some((USER_DEPLOY_SUBCOMMAND, _)) = matches.subcommand() {
+        // the config comes from the parent process and is never written
+        Config::deploy_for_parent(&mut io::stdin(), &mut io::stdout())?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    match run(&matches) {
+        Err(e) if matches.is_present("json_errors") => {
+            let json = match e.downcast_ref::<Error>() {
+                Some(e) => e.to_json(),
+                None => serde_json::json!({
+                    "code": "other",
+                    "message": e.to_string(),
+                    "path": null,
+                    "hint": null,
+                    "conflict": null,
+                }),
+            };
+            eprintln!("{0}", json);
+            Ok(ExitCode::FAILURE)
+        },
+        Err(e) => {
+            let conflict = e.downcast_ref::<Error>().and_then(Error::conflict);
+            if let Some(conflict) = conflict {
+                for line in conflict.describe() {
+                    log::error!("{0}: {1}", conflict.path.display(), line);
+                }
+            }
+            Err(e)
+        },
+        result => result,
+    }
+}
+
+/// Subcommands that fix or inspect a broken config, so they must work without validating it.
+const REPAIR_SUBCOMMANDS: [&str; 6] = [
+    "doctor",
+    "repair",
+    "rename-dir",
+    "prune-config",
+    "entry",
+    "config",
+];
+
+/// Subcommands that only inspect the config and the dotfiles, so they never write the config.
+const READ_ONLY_SUBCOMMANDS: [&str; 13] = [
+    "list",
+    "status",
+    "verify",
+    "verify-remote",
+    "find",
+    "explain",
+    "plan",
+    "export",
+    "diff",
+    "doctor",
+    "bundle",
+    "shell-hook",
+    "snapshots",
+];
+
+/// Subcommands that never look at the symlink directories, so they don't run the
+/// `symlink_dir_command` of any entry.
+const CONFIG_ONLY_SUBCOMMANDS: [&str; 7] = [
+    "config",
+    "entry",
+    "skip",
+    "snapshots",
+    "shell-hook",
+    "install-service",
+    "uninstall-service",
+];
+
+/// Run the subcommand of `matches`, returning the code `ghmd` exits with unless it fails.
+fn run(matches: &ArgMatches) -> Result<ExitCode> {
+    let name = matches.subcommand_name();
+    let repairs = name.is_some_and(|name| REPAIR_SUBCOMMANDS.contains(&name));
+    let mut config = Config::load()?;
+    if !name.is_some_and(|name| CONFIG_ONLY_SUBCOMMANDS.contains(&name)) {
+        config.resolve_symlink_dirs()?;
+    }
+    if !matches.is_present("no_validate") && !repairs {
+        config.check_consistency()?;
+    }
+    if name.is_some_and(|name| READ_ONLY_SUBCOMMANDS.contains(&name)) {
+        config.set_read_only();
+    }
+    config.set_merge_external_edits(matches.is_present("merge_config"));
+    config.set_durable(matches.is_present("durable"));
+    if let Some(keep) = matches.get_one::<usize>("snapshot_keep") {
+        config.set_snapshot_keep(*keep);
+    }
+
+    let mut code = ExitCode::SUCCESS;
+    match matches.subcommand() {
+        Some(("stow", stow_matches)) => stow(&mut config, stow_matches)?,
+        Some(("deploy", deploy_matches)) => deploy(&mut config, deploy_matches)?,
+        Some(("restore", restore_matches)) => restore(&mut config, restore_matches)?,
+        Some(("unlink", unlink_matches)) => unlink(&config, unlink_matches)?,
+        Some(("forget", forget_matches)) => forget(&mut config, forget_matches)?,
+        Some(("split", split_matches)) => split(&mut config, split_matches)?,
+        Some(("list", list_matches)) => list(&config, list_matches)?,
+        Some(("status", status_matches)) => status(&config, status_matches)?,
+        Some(("verify", verify_matches)) => verify(&config, verify_matches)?,
+        Some(("find", find_matches)) => find(&config, find_matches)?,
+        Some(("explain", explain_matches)) => explain(&config, explain_matches)?,
+        Some(("plan", plan_matches)) => plan(&config, plan_matches)?,
+        Some(("export", export_matches)) => export(&config, export_matches)?,
+        Some(("import", import_matches)) => import(&mut config, import_matches)?,
+        Some(("config", config_matches)) => config_command(config_matches)?,
+        Some(("doctor", doctor_matches)) => doctor(&config, doctor_matches)?,
+        Some(("prune-config", prune_matches)) => {
+            prune_config(&mut config, prune_matches)?
+        },
+        Some(("repair", repair_matches)) => repair(&mut config, repair_matches)?,
+        Some(("convert-links", convert_matches)) => {
+            convert_links(&mut config, convert_matches)?
+        },
+        Some(("rename-dir", rename_matches)) => rename_dir(&mut config, rename_matches)?,
+        Some(("install-service", service_matches)) => install_service(service_matches)?,
+        Some(("uninstall-service", _)) => uninstall_service()?,
+        Some(("shell-hook", hook_matches)) => shell_hook(hook_matches)?,
+        Some(("skip", skip_matches)) => skip(skip_matches)?,
+        Some(("entry", entry_matches)) => entry(&mut config, entry_matches)?,
+        Some(("watch", _)) => watch(&config)?,
+        Some(("diff", diff_matches)) => diff(&config, diff_matches)?,
+        Some(("snapshots", snapshots_matches)) => snapshots(&config, snapshots_matches)?,
+        #[cfg(feature = "bundle")]
+        Some(("bundle", bundle_matches)) => bundle(&config, bundle_matches)?,
+        #[cfg(feature = "bundle")]
+        Some(("unbundle", unbundle_matches)) => unbundle(&mut config, unbundle_matches)?,
+        Some(("verify-remote", remote_matches)) => {
+            code = verify_remote(&config, remote_matches)?
+        },
+        Some((s, _)) => return Err(anyhow!("invalid subcommand: {0}", s)),
+        None => return Err(anyhow!("missing subcommand")),
+    }
+    config.save()?;
+    Ok(code)
+}
+
+/// ErrorSummary collects the failures of a best-effort batch command so that they are reported
+/// together in a single block once the command is done rather than among its progress messages.
+#[derive(Default)]
+struct ErrorSummary {
+    errors: Vec<(Option<PathBuf>, Error)>,
+}
+
+impl ErrorSummary {
+    fn push(&mut self, path: PathBuf, error: Error) {
+        self.errors.push((Some(path), error));
+    }
+
+    /// Print the collected errors and fail if there are any, naming the failed `action`.
+    fn finish(self, action: &str) -> Result<()> {
+        if self.errors.is_empty() {
+            return Ok(());
+        }
+        eprintln!("Completed with {0} error(s):", self.errors.len());
+        for (path, e) in &self.errors {
+            match path {
+                Some(path) => eprintln!("  {0}: {1}", path.display(), e),
+                None => eprintln!("  {0}", e),
+            }
+        }
+        Err(anyhow!(
+            "{0} path(s) failed to {1}",
+            self.errors.len(),
+            action
+        ))
+    }
+}
+
+fn stow(config: &mut Config, matches: &ArgMatches) -> Result<()> {
+    if matches.is_present("auto") {
+        return stow_auto(config, matches);
+    }
+
+    let dotfiles_dir: DotfilesDir = matches
+        .get_one::<String>("dotfiles_dir")
+        .map(PathBuf::from)
+        .ok_or(anyhow!("must include dotfiles_dir argument"))?
+        .try_into()?;
+
+    let symlink_dir: SymlinkDir = matches
+        .get_one::<String>("symlink_dir")
+        .map(PathBuf::from)
+        .ok_or(anyhow!("must include symlink_dir argument"))?
+        .try_into()?;
+
+    log::debug!("dotfiles_dir: {:?}", dotfiles_dir);
+    log::debug!("symlink_dir: {:?}", symlink_dir);
+    config.warn_unversioned([dotfiles_dir.as_path()])?;
+
+    let steal = matches.is_present("steal");
+    let xdg = matches.is_present("xdg");
+    let policy = match matches.is_present("continue_on_error") {
+        true => ErrorPolicy::Continue,
+        false => ErrorPolicy::FailFast,
+    };
+    let link_type = matches
+        .get_one::<String>("link_type")
+        .map(|s| s.parse::<LinkType>())
+        .transpose()?;
+    let junction = link_type == Some(LinkType::Junction);
+    if junction && !cfg!(target_os = "windows") {
+        return Err(Error::JunctionsUnsupported.into());
+    }
+
+    if let Some(dest) = matches.get_one::<String>("as") {
+        let mut files = matches.values_of("files").into_iter().flatten();
+        let source = match (files.next(), files.next()) {
+            (Some(source), None) => PathBuf::from(source),
+            _ => return Err(anyhow!("--as requires exactly one file")),
+        };
+        if junction && !source.is_dir() {
+            return Err(Error::JunctionTargetNotADirectory(source).into());
+        }
+        let dest = Path::new(dest);
+        let output = Output::new(matches);
+        let options = StowOptions::new().observer(&output);
+        let path = stow_with_link_options(
+            config,
+            matches,
+            &symlink_dir,
+            &dotfiles_dir,
+            |config| {
+                Ok(config.stow_as(
+                    &symlink_dir,
+                    &dotfiles_dir,
+                    &source,
+                    dest,
+                    &options,
+                )?)
+            },
+        )?;
+        log::info!("stowed {0} as {1}", source.display(), path.display());
+        return Ok(commit_stowed(matches, &dotfiles_dir, &[path])?);
+    }
+
+    let mut dotfile_paths: Vec<DotfilePath> = Vec::new();
+    let mut failed = ErrorSummary::default();
+    let glob_options = MatchOptions {
+        case_sensitive: !matches.is_present("glob_case_insensitive"),
+        ..MatchOptions::new()
+    };
+    let files = matches
+        .values_of("files")
+        .ok_or(anyhow!("must include files argument"))?;
+    let traversal = traversal(matches);
+    for glob_path in files {
+        let paths = glob_with(glob_path, glob_options)?.filter_map(Result::ok);
+        for path in paths.filter(|path| traversal.expands(glob_path, path)) {
+            let candidate = if junction && !path.is_dir() {
+                Err(Error::JunctionTargetNotADirectory(path.clone()))
+            } else {
+                stow_candidate(config, &symlink_dir, &dotfiles_dir, &path, steal, xdg)
+            };
+            match candidate {
+                Ok(Some(dotfile_path)) => dotfile_paths.push(dotfile_path),
+                Ok(None) => (),
+                Err(e) if policy == ErrorPolicy::Continue => failed.push(path, e),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    // ask before the link settings below change the config
+    let dry_run = matches.is_present("dry_run");
+    if dry_run && !confirm_stow(config, &symlink_dir, &dotfiles_dir, &dotfile_paths)? {
+        return failed.finish("stow");
+    }
+
+    let (link_dir, store_dir) = (symlink_dir.clone(), dotfiles_dir.clone());
+    stow_with_link_options(config, matches, &link_dir, &store_dir, |config| {
+        if matches.is_present("copy") {
+            let paths = dotfile_paths.clone();
+            let count =
+                config.copy_paths(symlink_dir, dotfiles_dir.clone(), dotfile_paths)?;
+            log::info!("copied {0} path(s)", count);
+            return Ok(commit_stowed(matches, &dotfiles_dir, &paths)?);
+        }
+
+        let output = Output::new(matches);
+        let options = StowOptions::new().policy(policy).observer(&output);
+        if policy == ErrorPolicy::FailFast {
+            let paths = dotfile_paths.clone();
+            let dir = dotfiles_dir.clone();
+            let count = config.add_dotfiles_with_stow_with(
+                symlink_dir,
+                dir,
+                dotfile_paths,
+                &options,
+            )?;
+            log::info!("stowed {0} path(s)", count);
+            return Ok(commit_stowed(matches, &dotfiles_dir, &paths)?);
+        }
+
+        let dir = dotfiles_dir.clone();
+        let outcome =
+            config.stow_paths_with(symlink_dir, dir, dotfile_paths, &options)?;
+        log::info!("stowed {0} path(s)", outcome.stowed.len());
+        // stowed files are committed even if others failed to stow
+        let committed = commit_stowed(matches, &dotfiles_dir, &outcome.stowed);
+        for (path, e) in outcome.failed {
+            failed.push(path, e);
+        }
+        failed.finish("stow")?;
+        Ok(committed?)
+    })
+}
+
+/// Apply the link settings given in `matches` to the entry for the given directories, creating
+/// it if needed, and run `stow`. An entry created here is removed again unless `stow` tracked a
+/// dotfile in it, so a failed stow leaves no empty entry behind.
+fn stow_with_link_options<T>(
+    config: &mut Config,
+    matches: &ArgMatches,
+    symlink_dir: &SymlinkDir,
+    dotfiles_dir: &DotfilesDir,
+    stow: impl FnOnce(&mut Config) -> Result<T>,
+) -> Result<T> {
+    let existed = config.has_dotfiles(symlink_dir, dotfiles_dir);
+    let result = set_link_options(config, matches, symlink_dir, dotfiles_dir)
+        .and_then(|()| stow(config));
+    if !existed {
+        let _ = config.remove_dotfiles_if_unused(symlink_dir, dotfiles_dir);
+    }
+    result
+}
+
+/// Commit the stowed `paths` to the git repository of `dotfiles_dir` if `--git-commit` was
+/// given. The stowed files are left in place if that fails.
+fn commit_stowed(
+    matches: &ArgMatches,
+    dotfiles_dir: &Path,
+    paths: &[DotfilePath],
+) -> ghmd::Result<()> {
+    if !matches.is_present("git_commit") {
+        return Ok(());
+    }
+    let message = match matches.get_one::<String>("git_commit") {
+        Some(message) => message.clone(),
+        None => stow_commit_message(paths),
+    };
+    if commit_paths(dotfiles_dir, paths, &message, &Git)? {
+        log::info!(
+            "committed {0} path(s) in {1}",
+            paths.len(),
+            dotfiles_dir.display()
+        );
+    }
+    Ok(())
+}
+
+/// Apply the link settings given to `stow` to the dotfiles entry for the given directories,
+/// creating it if needed.
+fn set_link_options(
+    config: &mut Config,
+    matches: &ArgMatches,
+    symlink_dir: &SymlinkDir,
+    dotfiles_dir: &DotfilesDir,
+) -> Result<()> {
+    let link_style = matches
+        .get_one::<String>("link_style")
+        .map(|s| s.parse::<LinkStyle>())
+        .transpose()?;
+    let link_type = matches
+        .get_one::<String>("link_type")
+        .map(|s| s.parse::<LinkType>())
+        .transpose()?;
+    let link_mode = link_mode(matches)?;
+    let canonical_targets = matches.is_present("canonical_targets");
+
+    let overridden = link_style.is_some() || link_type.is_some() || link_mode.is_some();
+    if overridden || canonical_targets {
+        let dotfiles = config.get_or_create_dotfiles(symlink_dir, dotfiles_dir)?;
+        if let Some(link_style) = link_style {
+            dotfiles.set_link_style(link_style);
+        }
+        if let Some(link_type) = link_type {
+            dotfiles.set_link_type(link_type)?;
+        }
+        if let Some(link_mode) = link_mode {
+            dotfiles.set_link_mode(link_mode);
+        }
+        if canonical_targets {
+            dotfiles.set_canonical_targets(true);
+        }
+    }
+    Ok(())
+}
+
+/// The link mode given with `--link-mode`, if any.
+fn link_mode(matches: &ArgMatches) -> ghmd::Result<Option<LinkMode>> {
+    matches
+        .get_one::<String>("link_mode")
+        .map(|s| s.parse::<LinkMode>())
+        .transpose()
+}
+
+/// Print what stowing `paths` would do and ask whether to go ahead.
+fn confirm_stow(
+    config: &Config,
+    symlink_dir: &SymlinkDir,
+    dotfiles_dir: &DotfilesDir,
+    paths: &[DotfilePath],
+) -> Result<bool> {
+    let plan = config.dry_run_stow(symlink_dir, dotfiles_dir, paths)?;
+    let symlink_path = |path: &DotfilePath| {
+        config
+            .xdg_dirs()
+            .and_then(|dirs| dirs.expand(path))
+            .unwrap_or_else(|| symlink_dir.join(&**path))
+    };
+    for (source, destination) in &plan.moves {
+        println!(
+            "{0:<8} {1} -> {2}",
+            "move",
+            source.display(),
+            destination.display()
+        );
+    }
+    for (symlink, target) in &plan.symlinks_to_create {
+        println!(
+            "{0:<8} {1} -> {2}",
+            "link",
+            symlink.display(),
+            target.display()
+        );
+    }
+    for path in &plan.already_done {
+        println!("{0:<8} {1}", "track", symlink_path(path).display());
+    }
+    for (path, e) in &plan.errors {
+        println!("{0:<8} {1}: {2}", "fail", symlink_path(path).display(), e);
+    }
+    if plan.moves.is_empty() && plan.already_done.is_empty() {
+        return Ok(false);
+    }
+
+    eprint!(
+        "stow {0} path(s)? [y/N] ",
+        plan.moves.len() + plan.already_done.len()
+    );
+    let mut answer = String::new();
+    let _ = io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Stow the files given with `--auto` into the configured dotfiles directories whose symlink
+/// directories contain them.
+fn stow_auto(config: &mut Config, matches: &ArgMatches) -> Result<()> {
+    let policy = match matches.is_present("continue_on_error") {
+        true => ErrorPolicy::Continue,
+        false => ErrorPolicy::FailFast,
+    };
+    let glob_options = MatchOptions {
+        case_sensitive: !matches.is_present("glob_case_insensitive"),
+        ..MatchOptions::new()
+    };
+    let mut stowed: BTreeMap<DotfilesDir, Vec<DotfilePath>> = BTreeMap::new();
+    let mut failed = ErrorSummary::default();
+    let patterns = matches.values_of("auto").into_iter().flatten();
+    let traversal = traversal(matches);
+    for pattern in patterns {
+        let paths = glob_with(pattern, glob_options)?.filter_map(Result::ok);
+        for path in paths.filter(|path| traversal.expands(pattern, path)) {
+            match config.stow_auto_path(&path) {
+                Ok((dir, relative)) => stowed.entry(dir).or_default().push(relative),
+                Err(e) if policy == ErrorPolicy::Continue => failed.push(path, e),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    log::info!(
+        "stowed {0} path(s)",
+        stowed.values().map(Vec::len).sum::<usize>()
+    );
+    config.warn_unversioned(stowed.keys().map(|dir| dir.as_path()))?;
+    let committed = stowed
+        .iter()
+        .try_for_each(|(dir, paths)| commit_stowed(matches, dir, paths));
+    failed.finish("stow")?;
+    Ok(committed?)
+}
+
+/// Which hidden entries wildcards in the file arguments of stow `matches` match.
+fn traversal(matches: &ArgMatches) -> Traversal {
+    Traversal::new()
+        .include_dotfiles(matches.is_present("include_dotfiles"))
+        .include_dotdirs(matches.is_present("include_dotdirs"))
+}
+
+/// Validate `path` for stowing into `dotfiles_dir`, returning the dotfile path to stow. With
+/// `steal`, a path tracked in another dotfiles directory is taken over right away and `None` is
+/// returned. With `xdg`, a path inside an XDG base directory is stored under its token, see
+/// [`Dirs::tokenize`].
+fn stow_candidate(
+    config: &mut Config,
+    symlink_dir: &SymlinkDir,
+    dotfiles_dir: &DotfilesDir,
+    path: &Path,
+    steal: bool,
+    xdg: bool,
+) -> ghmd::Result<Option<DotfilePath>> {
+    let path = &config.unalias(symlink_dir, path);
+    if xdg {
+        let absolute = ghmd::paths::normalize(&symlink_dir.join(path));
+        let tokenized = config.xdg_dirs().and_then(|dirs| dirs.tokenize(&absolute));
+        if let Some(tokenized) = tokenized {
+            let dotfile_path: DotfilePath = tokenized.try_into()?;
+            config.check_owner(symlink_dir, dotfiles_dir, &dotfile_path)?;
+            return Ok(Some(dotfile_path));
+        }
+    }
+    // a file tracked elsewhere may be deployed as a symlink into the other store, so check
+    // ownership before validating the path against this store
+    let relative = match path.is_absolute() {
+        true => path.strip_prefix(&**symlink_dir).ok(),
+        false => Some(path.as_path()),
+    };
+    if let Some(relative) = relative {
+        let dotfile_path: DotfilePath = relative.to_path_buf().try_into()?;
+        if steal
+            && config
+                .owner_of(symlink_dir, dotfiles_dir, &dotfile_path)
+                .is_some()
+        {
+            config.steal_path(symlink_dir, dotfiles_dir, &dotfile_path)?;
+            log::info!("took over {0}", dotfile_path.display());
+            return Ok(None);
+        }
+        config.check_owner(symlink_dir, dotfiles_dir, &dotfile_path)?;
+    }
+    Ok(Some((symlink_dir, dotfiles_dir, path).try_into()?))
+}
+
+/// If `--target-dir` was given, return a non-persistent copy of `config` that uses it in place
+/// of every configured symlink directory.
+fn retarget(
+    config: &Config,
+    matches: &ArgMatches,
+    create: bool,
+) -> Result<Option<Config>> {
+    let dir = match matches.value_of("target_dir") {
+        Some(dir) => PathBuf::from(dir),
+        None => return Ok(None),
+    };
+    if create && !dir.exists() {
+        log::info!("creating target directory {0}", dir.display());
+        fs::create_dir_all(&dir)?;
+    }
+    let dir: SymlinkDir = dir.try_into()?;
+    Ok(Some(config.with_target_dir(&dir)))
+}
+
+fn deploy(config: &mut Config, values: &ArgMatches) -> Result<()> {
+    if let Some(names) = values.values_of("for_user") {
+        if !running_as_root() {
+            return Err(Error::NotRoot.into());
+        }
+        // don't deploy for anyone if one of the users doesn't exist
+        let users = names
+            .map(User::lookup)
+            .collect::<ghmd::Result<Vec<User>>>()?;
+        for user in &users {
+            let summary = config.deploy_for_user(user)?;
+            log::info!(
+                "deployed {0} path(s) for {1}, {2} unchanged",
+                summary.created,
+                user.name,
+                summary.unchanged
+            );
+        }
+        return Ok(());
+    }
+
+    // the operations log is that of the invoking user, whatever the target
+    let operations_log = config.xdg_dirs().map(Dirs::operations_log);
+    let mut retargeted = retarget(config, values, values.is_present("create"))?;
+    let config = match retargeted.as_mut() {
+        Some(retargeted) => retargeted,
+        None => config,
+    };
+
+    if values.is_present("dry_run") {
+        return deploy_dry_run(config, values);
+    }
+
+    // every change is recorded in the operations log, even if the deploy fails partway
+    let output = Output::new(values).interactive(values.is_present("interactive"));
+    let log = OperationsLog::forwarding_to(&output);
+    let result = deploy_logged(config, values, &log);
+    if let (Some(user), Some(path)) = (User::current(), operations_log) {
+        log.write(&path, &user.name)?;
+    }
+    result
+}
+
+fn deploy_logged(
+    config: &mut Config,
+    values: &ArgMatches,
+    log: &OperationsLog,
+) -> Result<()> {
+    let interactive = values.is_present("interactive");
+    let modified = mtime_filter(values)?;
+    let mut base = DeployOptions::new()
+        .expand_dest(values.is_present("expand_dest"))
+        .modified(modified);
+    if let Some(mode) = link_mode(values)? {
+        base = base.link_mode(mode);
+    }
+    if values.is_present("expand_dest") {
+        // later commands must find the symlinks where this deploy puts them
+        let _ = config.record_expand_dest();
+    }
+    if values.is_present("all") || values.is_present("incremental") {
+        note_disabled(config);
+    }
+    if values.is_present("all") && interactive {
+        let options = base
+            .backup(true)
+            .skip_broken(true)
+            .skip_listed(true)
+            .observer(log);
+        let summary = config.deploy_all_with(&options)?;
+        log::info!("{0} path(s) unchanged", summary.unchanged);
+        return Ok(());
+    }
+
+    if values.is_present("all") {
+        let jobs = values.get_one::<usize>("jobs").copied().unwrap_or(1);
+        let options = base
+            .skip_conflicts(values.is_present("skip_conflicts"))
+            .observer(log);
+        let results = config.deploy_all_results_with(jobs, &options)?;
+        let failed = results.iter().filter(|(_, result)| result.is_err()).count();
+        if failed > 0 || !values.is_present("quiet") {
+            print!("{0}", Config::format_deploy_report(&results));
+        }
+        let mut errors = results.into_iter().filter_map(|(_, result)| result.err());
+        return match (errors.next(), failed) {
+            (None, _) => Ok(()),
+            // a single failure is reported along with its hint and conflict details
+            (Some(e), 1) => Err(e.into()),
+            (Some(_), failed) => Err(anyhow!("{0} path(s) failed to deploy", failed)),
+        };
+    };
+
+    if values.is_present("incremental") {
+        let options = base
+            .backup(true)
+            .skip_broken(true)
+            .skip_listed(true)
+            .observer(log);
+        let count = config.deploy_incremental_with(&options)?;
+        log::info!("deployed {0} changed path(s)", count);
+        return Ok(());
+    };
+
+    if let Some(name) = values.value_of("symlink_name") {
+        let paths: Vec<&str> =
+            values.values_of("dotfiles").into_iter().flatten().collect();
+        let path = match paths[..] {
+            [path] => path,
+            _ => return Err(anyhow!("--symlink-name takes a single dotfile")),
+        };
+        let options = base.observer(log);
+        let _ = config.deploy_as(Path::new(path), name, &options)?;
+        return Ok(());
+    }
+
+    let mut selector = match values.value_of("store_dir") {
+        Some(dir) => store_selector(
+            PathBuf::from(dir).try_into()?,
+            values.values_of("dotfiles").into_iter().flatten(),
+        )?,
+        None => selector(values)?,
+    };
+    selector.modified = modified;
+    if !selector.is_empty() {
+        let options = base.backup(true).skip_broken(true).observer(log);
+        let summary = config.deploy_selected_with(&selector, &options)?;
+        log::info!("{0} path(s) unchanged", summary.unchanged);
+        return Ok(());
+    }
+    if !modified.is_empty() {
+        return Err(anyhow!(
+            "--older-than and --newer-than need --all, --path, --dir or --dotfiles-dir"
+        ));
+    }
+
+    let paths: Vec<PathBuf> = values
+        .values_of("dotfiles")
+        .unwrap()
+        .map(PathBuf::from)
+        .collect();
+
+    let options = base.observer(log);
+    let summary = config.deploy_paths_with(paths, &options)?;
+    log::info!("{0} path(s) unchanged", summary.unchanged);
+    Ok(())
+}
+
+/// Output presents each dotfile a command stows, deploys or restores: as a log message, or
+/// with `--json-events` as a JSON object per line on stdout. When interactive it asks on the
+/// terminal what to do about each conflict found while deploying.
+#[derive(Debug, Clone, Copy)]
+struct Output {
+    json: bool,
+    interactive: bool,
+}
+
+impl Output {
+    fn new(matches: &ArgMatches) -> Self {
+        Self {
+            json: matches.is_present("json_events"),
+            interactive: false,
+        }
+    }
+
+    fn interactive(self, interactive: bool) -> Self {
+        Self {
+            interactive,
+            ..self
+        }
+    }
+}
+
+impl Observer for Output {
+    fn on_action_done(&self, action: &Action<'_>, outcome: &ActionOutcome<'_>) {
+        if self.json {
+            println!("{0}", action_json(action, outcome));
+            return;
+        }
+        let path = action.path().display();
+        match outcome {
+            // deploys log the symlink they created themselves
+            ActionOutcome::Done if matches!(action, Action::Deploy(_)) => {
+                log::debug!("deployed {0}", path)
+            },
+            ActionOutcome::Done => {
+                let verb = match action {
+                    Action::Restore(_) => "restored",
+                    _ => "stowed",
+                };
+                log::info!("{0} {1}", verb, path)
+            },
+            ActionOutcome::Skipped => log::info!("skipped {0}", path),
+            ActionOutcome::Unchanged => log::debug!("{0} is already in place", path),
+            // failures are reported by the command
+            ActionOutcome::Failed(e) => log::debug!("failed to {0}: {1}", action, e),
+        }
+    }
+
+    fn on_conflict(&self, conflict: &Conflict) -> Resolution {
+        if !self.interactive {
+            return Resolution::Fail;
+        }
+        eprintln!("{0} is in the way:", conflict.path.display());
+        for line in conflict.describe() {
+            eprintln!("  {0}", line);
+        }
+        eprint!("[s]kip, [b]ack up and replace or [f]ail? [s/b/F] ");
+        let mut answer = String::new();
+        if io::stdin().read_line(&mut answer).is_err() {
+            return Resolution::Fail;
+        }
+        match answer.trim() {
+            "s" | "S" | "skip" => Resolution::Skip,
+            "b" | "B" | "backup" => Resolution::Backup,
+            _ => Resolution::Fail,
+        }
+    }
+}
+
+/// The JSON object `--json-events` prints for `action` ending with `outcome`.
+fn action_json(action: &Action<'_>, outcome: &ActionOutcome<'_>) -> serde_json::Value {
+    let kind = match action {
+        Action::Stow(_) => "stow",
+        Action::Deploy(_) => "deploy",
+        Action::Restore(_) => "restore",
+    };
+    let (outcome, error) = match outcome {
+        ActionOutcome::Done => ("done", None),
+        ActionOutcome::Unchanged => ("unchanged", None),
+        ActionOutcome::Skipped => ("skipped", None),
+        ActionOutcome::Failed(e) => ("failed", Some(e.to_json())),
+    };
+    serde_json::json!({
+        "action": kind,
+        "path": action.path(),
+        "outcome": outcome,
+        "error": error,
+    })
+}
+
+fn deploy_dry_run(config: &Config, values: &ArgMatches) -> Result<()> {
+    let tree = if values.is_present("all") {
+        config.deploy_tree(config.iter_included_entries()?, true)
+    } else {
+        let selector = match values.value_of("store_dir") {
+            Some(dir) => store_selector(
+                PathBuf::from(dir).try_into()?,
+                values.values_of("dotfiles").into_iter().flatten(),
+            )?,
+            None => selector(values)?,
+        };
+        if selector.is_empty() {
+            return Err(anyhow!(
+                "--dry-run requires --all, --dotfiles-dir or a selection"
+            ));
+        }
+        config.deploy_tree(selector.evaluate(config)?, false)
+    };
+
+    if values.is_present("tree") {
+        tree.render().iter().for_each(|line| println!("{0}", line));
+        return Ok(());
+    }
+    for (symlink, node) in tree.symlinks() {
+        match &node.mark {
+            TreeMark::Failed(e) => {
+                println!("{0:<8} {1}: {2}", "fails", symlink.display(), e)
+            },
+            mark => println!("{0:<8} {1}", mark, symlink.display()),
+        }
+    }
+    Ok(())
+}
+
+fn restore(config: &mut Config, matches: &ArgMatches) -> Result<()> {
+    // every restore is recorded in the operations log, and so is every move into the directory
+    // of --to along with where the dotfile was stored, so that it can be undone
+    let output = Output::new(matches);
+    let log = OperationsLog::forwarding_to(&output);
+    let moves = match matches.value_of("to") {
+        Some(to) => restore_moves(config, &std::path::absolute(to)?),
+        None => Vec::new(),
+    };
+    let operations_log = config.xdg_dirs().map(Dirs::operations_log);
+    let result = restore_logged(config, matches, &log);
+    for (store, destination) in moves {
+        if destination.symlink_metadata().is_ok() && store.symlink_metadata().is_err() {
+            log.record(format!(
+                "move {0} to {1}",
+                store.display(),
+                destination.display()
+            ));
+        }
+    }
+    if let (Some(user), Some(path)) = (User::current(), operations_log) {
+        log.write(&path, &user.name)?;
+    }
+    result
+}
+
+/// The store path and the destination inside `to` of every tracked dotfile `restore --to` could
+/// move, i.e. whose destination is still free.
+fn restore_moves(config: &Config, to: &Path) -> Vec<(PathBuf, PathBuf)> {
+    config
+        .iter_entries()
+        .map(|resolved| (resolved.store, to.join(&**resolved.relative)))
+        .filter(|(_, destination)| destination.symlink_metadata().is_err())
+        .collect()
+}
+
+fn restore_logged(
+    config: &mut Config,
+    matches: &ArgMatches,
+    log: &OperationsLog,
+) -> Result<()> {
+    let to = matches.value_of("to").map(Path::new);
+    let mut options = RestoreOptions::new()
+        .force(matches.is_present("force"))
+        .clean_store(matches.is_present("clean_store"))
+        .observer(log);
+    if let Some(to) = to {
+        options = options.to(to);
+    }
+    if let Some(dir) = matches.value_of("symlink_dir") {
+        let dir: SymlinkDir = PathBuf::from(dir).try_into()?;
+        let count = config.restore_by_symlink_dir_with(&dir, &options)?;
+        log::info!("restored {0} path(s) to {1}", count, dir.display());
+        return Ok(());
+    }
+
+    // with --dotfiles-dir every positional argument is a pattern
+    let selector = match matches.value_of("store_dir") {
+        Some(dir) => {
+            let patterns = ["dotfiles_dir", "dotfiles"]
+                .into_iter()
+                .flat_map(|name| matches.values_of(name).into_iter().flatten());
+            store_selector(PathBuf::from(dir).try_into()?, patterns)?
+        },
+        None => selector(matches)?,
+    };
+    if !selector.is_empty() {
+        let count = config.restore_selected_with(&selector, &options)?;
+        log::info!("restored {0} path(s)", count);
+        return Ok(());
+    }
+
+    let dotfiles_dir: DotfilesDir = matches
+        .get_one::<String>("dotfiles_dir")
+        .map(PathBuf::from)
+        .ok_or(anyhow!("must include dotfiles_dir argument"))?
+        .try_into()?;
+
+    let dotfiles: Vec<PathBuf> = matches
+        .values_of("dotfiles")
+        .unwrap()
+        .map(PathBuf::from)
+        .collect();
+
+    // absolute paths may belong to any dotfiles directory
+    let mut resolved = Vec::new();
+    for dotfile in dotfiles.into_iter() {
+        let absolute = dotfile.is_absolute();
+        let dotfile: DotfilePath = match absolute {
+            true => config.resolve_dotfile_path(&dotfile)?,
+            false => (dotfiles_dir.clone(), dotfile).try_into()?,
+        };
+        resolved.push((dotfile, absolute));
+    }
+    if let Some(to) = to {
+        Config::check_restore_to(to, resolved.iter().map(|(dotfile, _)| dotfile))?;
+    }
+    for (dotfile, absolute) in resolved {
+        match absolute {
+            true => config.restore_with(dotfile, &options)?,
+            false => config.restore_from_with(&dotfiles_dir, dotfile, &options)?,
+        }
+    }
+
+    Ok(())
+}
+
+fn unlink(config: &Config, matches: &ArgMatches) -> Result<()> {
+    if matches.is_present("all") {
+        let count = config.unlink_all()?;
+        log::info!("removed {0} symlink(s)", count);
+    }
+    Ok(())
+}
+
+fn forget(config: &mut Config, matches: &ArgMatches) -> Result<()> {
+    let selector = selector(matches)?;
+    if !selector.is_empty() {
+        let count = config.forget_selected(&selector)?;
+        println!("forgot {0} path(s)", count);
+        return Ok(());
+    }
+
+    let dotfiles_dir: DotfilesDir = matches
+        .get_one::<String>("dotfiles_dir")
+        .map(PathBuf::from)
+        .ok_or(anyhow!("must include dotfiles_dir argument"))?
+        .try_into()?;
+
+    if matches.is_present("all") {
+        let count =
+            config.forget_all(&dotfiles_dir, matches.is_present("remove_store"))?;
+        println!("forgot {0} path(s) in {1}", count, dotfiles_dir.display());
+        return Ok(());
+    }
+
+    for dotfile in matches.values_of("dotfiles").unwrap().map(PathBuf::from) {
+        let dotfile: DotfilePath = (dotfiles_dir.clone(), dotfile).try_into()?;
+        config.forget(&dotfiles_dir, &dotfile)?;
+        log::info!("forgot {0}", dotfile.display());
+    }
+    Ok(())
+}
+
+fn split(config: &mut Config, matches: &ArgMatches) -> Result<()> {
+    let dotfiles_dir: DotfilesDir = matches
+        .get_one::<String>("dotfiles_dir")
+        .map(PathBuf::from)
+        .ok_or(anyhow!("must include dotfiles_dir argument"))?
+        .try_into()?;
+    let path = matches
+        .get_one::<String>("new_config_path")
+        .map(PathBuf::from)
+        .ok_or(anyhow!("must include new_config_path argument"))?;
+
+    let count = config.split(&dotfiles_dir, &path)?;
+    log::info!(
+        "moved {0} entry(ies) for {1} to {2}",
+        count,
+        dotfiles_dir.display(),
+        path.display()
+    );
+    Ok(())
+}
+
+fn patterns(matches: &ArgMatches) -> Result<Vec<Pattern>> {
+    let mut patterns = Vec::new();
+    if let Some(values) = matches.values_of("patterns") {
+        for value in values {
+            patterns.push(Pattern::new(value)?);
+        }
+    }
+    Ok(patterns)
+}
+
+/// Build the selector given by the `--path`, `--dir`, `--group`, `--host` and `--invert`
+/// arguments. Commands taking
+/// positional glob patterns add them to the selector's paths.
+fn selector(matches: &ArgMatches) -> Result<Selector> {
+    let mut selector = Selector {
+        invert: matches.is_present("invert"),
+        groups: matches
+            .values_of("group")
+            .into_iter()
+            .flatten()
+            .map(String::from)
+            .collect(),
+        hosts: matches
+            .values_of("host")
+            .into_iter()
+            .flatten()
+            .map(String::from)
+            .collect(),
+        ..Selector::default()
+    };
+    if let Some(values) = matches.values_of("path") {
+        for value in values {
+            selector.paths.push(Pattern::new(value)?);
+        }
+    }
+    if let Some(values) = matches.values_of("dir") {
+        for value in values {
+            selector.dirs.push(PathBuf::from(value).try_into()?);
+        }
+    }
+    Ok(selector)
+}
+
+/// The range of modification times given with `--older-than` and `--newer-than`.
+fn mtime_filter(matches: &ArgMatches) -> Result<MtimeFilter> {
+    let now = SystemTime::now();
+    let before = |name: &str| -> Result<Option<SystemTime>> {
+        match matches.value_of(name) {
+            Some(age) => Ok(Some(now.checked_sub(parse_age(age)?).unwrap_or(UNIX_EPOCH))),
+            None => Ok(None),
+        }
+    };
+    Ok(MtimeFilter {
+        older_than: before("older_than")?,
+        newer_than: before("newer_than")?,
+    })
+}
+
+/// Build a selector for the dotfiles in `dir` whose paths match the glob patterns `patterns`.
+/// `*` and `?` only match within a single path component.
+fn store_selector<'a>(
+    dir: DotfilesDir,
+    patterns: impl IntoIterator<Item = &'a str>,
+) -> Result<Selector> {
+    let mut selector = Selector {
+        dirs: vec![dir],
+        literal_separator: true,
+        ..Selector::default()
+    };
+    for pattern in patterns {
+        selector.paths.push(Pattern::new(pattern)?);
+    }
+    Ok(selector)
+}
+
+fn list_selector(matches: &ArgMatches) -> Result<Selector> {
+    let mut selector = selector(matches)?;
+    selector.paths.extend(patterns(matches)?);
+    Ok(selector)
+}
+
+struct Entry<'a> {
+    dotfiles_dir: &'a DotfilesDir,
+    path: &'a DotfilePath,
+    store: PathBuf,
+    target: PathBuf,
+    state: Option<SymlinkState>,
+    line: Option<String>,
+}
+
+fn list(config: &Config, matches: &ArgMatches) -> Result<()> {
+    if matches.is_present("by_target") {
+        return list_by_target(config, matches);
+    }
+    render_entries(config, matches, false)
+}
+
+/// Print the tracked dotfiles selected by `matches` grouped by the directory they are deployed
+/// to.
+fn list_by_target(config: &Config, matches: &ArgMatches) -> Result<()> {
+    if format_template(matches)?.is_some() {
+        return Err(anyhow!(
+            "--by-target can't be combined with a --format template"
+        ));
+    }
+    let retargeted = retarget(config, matches, false)?;
+    let config = retargeted.as_ref().unwrap_or(config);
+    let selector = list_selector(matches)?;
+    let _ = selector.select(config)?;
+
+    let mut groups = serde_json::Map::new();
+    for (symlink_dir, entries) in config.group_by_symlink_dir() {
+        let entries: Vec<(&Dotfiles, &DotfilePath)> = entries
+            .into_iter()
+            .filter(|(dotfiles, path)| selector.matches(dotfiles, path))
+            .collect();
+        if entries.is_empty() {
+            continue;
+        }
+
+        if matches.value_of("format") == Some("json") {
+            let values: Vec<serde_json::Value> = entries
+                .iter()
+                .map(|(dotfiles, path)| {
+                    serde_json::json!({
+                        "path": &***path,
+                        "dotfile": dotfiles.store_path(path),
+                        "symlink": dotfiles.symlink_path(path),
+                    })
+                })
+                .collect();
+            let key = symlink_dir.to_string_lossy().into_owned();
+            let _ = groups.insert(key, values.into());
+            continue;
+        }
+
+        println!("{0}:", symlink_dir.display());
+        for (dotfiles, path) in entries {
+            println!(
+                "  {0} -> {1}",
+                path.display(),
+                dotfiles.store_path(path).display()
+            );
+        }
+    }
+
+    if matches.value_of("format") == Some("json") {
+        println!("{0}", serde_json::to_string_pretty(&groups)?);
+    }
+    Ok(())
+}
+
+fn status(config: &Config, matches: &ArgMatches) -> Result<()> {
+    if matches.is_present("pending") {
+        print_pending(&config.pending_operations()?);
+        return Ok(());
+    }
+    if !matches.is_present("dir") {
+        note_disabled(config);
+    }
+    render_entries(config, matches, true)
+}
+
+/// Print the dotfiles that remain to be deployed after the help text, if there is a config. The
+/// config is only read, never written.
+fn show_pending() -> Result<()> {
+    if !Config::file_path()?.exists() {
+        return Ok(());
+    }
+    let mut config = Config::load()?;
+    config.set_read_only();
+    config.resolve_symlink_dirs()?;
+    let pending = config.pending_operations()?;
+    if !pending.is_empty() {
+        println!();
+        println!("not deployed yet, `ghmd deploy --all` deploys them:");
+        print_pending(&pending);
+    }
+    Ok(())
+}
+
+fn print_pending(pending: &[(&Dotfiles, &DotfilePath)]) {
+    for (dotfiles, path) in pending {
+        println!(
+            "{0:<9} {1}",
+            "deploy",
+            dotfiles.symlink_path(path).display()
+        );
+    }
+}
+
+/// Print the tracked dotfiles selected by `matches` in the requested order and format. The state
+/// of the dotfiles is only determined when it is printed or needed for sorting.
+fn render_entries(config: &Config, matches: &ArgMatches, with_state: bool) -> Result<()> {
+    let retargeted = retarget(config, matches, false)?;
+    let config = retargeted.as_ref().unwrap_or(config);
+
+    let sort = matches.value_of("sort").unwrap_or("path");
+    let template = format_template(matches)?;
+    let needs_state = match &template {
+        Some(template) => template.uses("state") || sort == "state",
+        None => with_state || sort == "state",
+    };
+
+    let mut entries = Vec::new();
+    for resolved in list_selector(matches)?.select(config)? {
+        let dotfiles = &config.dotfiles()[resolved.entry_index];
+        let state = match needs_state {
+            true => Some(config.verify_symlink(dotfiles, resolved.relative)?),
+            false => None,
+        };
+        let line = template
+            .as_ref()
+            .map(|template| template.render(config, &resolved, state));
+        entries.push(Entry {
+            dotfiles_dir: dotfiles.dotfile_directory(),
+            path: resolved.relative,
+            store: resolved.store,
+            target: resolved.target,
+            state,
+            line,
+        });
+    }
+
+    match sort {
+        "state" => entries.sort_by(|a, b| a.state.cmp(&b.state).then(a.path.cmp(b.path))),
+        "store" => entries
+            .sort_by(|a, b| a.dotfiles_dir.cmp(b.dotfiles_dir).then(a.path.cmp(b.path))),
+        _ => entries
+            .sort_by(|a, b| a.path.cmp(b.path).then(a.dotfiles_dir.cmp(b.dotfiles_dir))),
+    }
+
+    if matches.value_of("format") == Some("json") {
+        let values: Vec<serde_json::Value> = entries
+            .iter()
+            .map(|entry| {
+                let mut value = serde_json::json!({
+                    "path": &**entry.path,
+                    "dotfile": &entry.store,
+                    "symlink": &entry.target,
+                });
+                if with_state {
+                    value["state"] = serde_json::json!(entry.state);
+                    if let Some(conflict) = entry_conflict(entry) {
+                        value["conflict"] = serde_json::json!(conflict);
+                    }
+                }
+                value
+            })
+            .collect();
+        println!("{0}", serde_json::to_string_pretty(&values)?);
+        return Ok(());
+    }
+
+    for entry in entries {
+        if let Some(line) = &entry.line {
+            println!("{0}", line);
+            continue;
+        }
+        match entry.state {
+            Some(state) if with_state => {
+                println!("{0:<15} {1}", state, entry.target.display());
+                for line in entry_conflict(&entry).iter().flat_map(Conflict::describe) {
+                    println!("{0:<15}   {1}", "", line);
+                }
+            },
+            _ => println!("{0} -> {1}", entry.target.display(), entry.store.display()),
+        }
+    }
+    Ok(())
+}
+
+/// Parse the value of `--format` as a template if it is neither `text` nor `json`.
+fn format_template(matches: &ArgMatches) -> ghmd::Result<Option<Template>> {
+    match matches.value_of("format") {
+        None | Some("text") | Some("json") => Ok(None),
+        Some(format) => Ok(Some(Template::parse(format)?)),
+    }
+}
+
+/// Details about whatever occupies the symlink path of an entry whose symlink is wrong.
+fn entry_conflict(entry: &Entry) -> Option<Conflict> {
+    match entry.state {
+        Some(SymlinkState::WrongTarget | SymlinkState::NotASymlink) => {
+            Conflict::inspect(&entry.target)
+        },
+        _ => None,
+    }
+}
+
+fn verify(config: &Config, matches: &ArgMatches) -> Result<()> {
+    let retargeted = retarget(config, matches, false)?;
+    let config = retargeted.as_ref().unwrap_or(config);
+
+    let mut failures = 0;
+    for resolved in list_selector(matches)?.select(config)? {
+        let dotfiles = &config.dotfiles()[resolved.entry_index];
+        let state = config.verify_symlink(dotfiles, resolved.relative)?;
+        if state.is_ok() {
+            continue;
+        }
+        failures += 1;
+        // name the symlinks that are wrong, which may be other names of the dotfile
+        for (symlink, state) in dotfiles.verify_symlinks(resolved.relative)? {
+            if !state.is_ok() {
+                println!("{0:<15} {1}", state, symlink.display());
+            }
+        }
+    }
+    if failures > 0 {
+        return Err(anyhow!("{0} dotfile(s) not deployed correctly", failures));
+    }
+    Ok(())
+}
+
+fn verify_remote(config: &Config, matches: &ArgMatches) -> Result<ExitCode> {
+    let reference = match (
+        matches.value_of("rev"),
+        matches.value_of("manifest_url"),
+        matches.value_of("manifest_file"),
+    ) {
+        (Some(rev), _, _) => Reference::Rev(rev.to_string()),
+        (None, Some(url), _) => Reference::Url(url.to_string()),
+        (None, None, Some(path)) => Reference::File(PathBuf::from(path)),
+        (None, None, None) => {
+            return Err(anyhow!(
+                "must include --rev, --manifest-url or --manifest-file"
+            ))
+        },
+    };
+
+    // failing to obtain the reference exits with 2 so that it can't be mistaken for drift
+    let manifest = match reference.fetch(config, &Git) {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            match matches.is_present("json_errors") {
+                true => eprintln!("{0}", e.to_json()),
+                false => {
+                    log::error!("could not obtain reference: {0}", e);
+                    if let Some(hint) = e.hint() {
+                        log::error!("hint: {0}", hint);
+                    }
+                },
+            }
+            return Ok(ExitCode::from(2));
+        },
+    };
+
+    let diff = config.diff_manifest(&manifest)?;
+    for (label, paths) in [
+        ("added", &diff.added),
+        ("removed", &diff.removed),
+        ("drifted", &diff.drifted),
+    ] {
+        for path in paths {
+            println!("{0:<8} {1}", label, path.display());
+        }
+    }
+    if !diff.is_clean() {
+        return Err(anyhow!(
+            "{0} added, {1} removed and {2} drifted file(s) compared to the reference",
+            diff.added.len(),
+            diff.removed.len(),
+            diff.drifted.len()
+        ));
+    }
+    log::info!("tracked dotfiles match the reference");
+    Ok(ExitCode::SUCCESS)
+}
+
+fn diff(config: &Config, matches: &ArgMatches) -> Result<()> {
+    let include_dotdirs = matches.is_present("include_dotdirs");
+    let traversal = Traversal::new().include_dotdirs(include_dotdirs);
+    let diff = config.diff_with_filesystem(traversal)?;
+    for (dotfiles, path) in &diff.needs_deploy {
+        println!(
+            "{0:<9} {1}",
+            "deploy",
+            dotfiles.symlink_path(path).display()
+        );
+    }
+    for symlink in &diff.needs_registration {
+        println!("{0:<9} {1}", "register", symlink.display());
+    }
+    for (dotfiles, path, state) in &diff.conflicts {
+        let symlink = dotfiles.symlink_path(path);
+        println!("{0:<9} {1} ({2})", "conflict", symlink.display(), state);
+    }
+    for (dotfiles, path) in &diff.consistent {
+        log::debug!("consistent {0}", dotfiles.symlink_path(path).display());
+    }
+    log::info!("{0} path(s) consistent", diff.consistent.len());
+    Ok(())
+}
+
+#[cfg(feature = "bundle")]
+fn bundle(config: &Config, matches: &ArgMatches) -> Result<()> {
+    let output = matches
+        .value_of("output")
+        .ok_or(anyhow!("must include --output argument"))?;
+    let manifest = config.bundle(Path::new(output))?;
+    let files: usize = manifest.entries.iter().map(|entry| entry.files.len()).sum();
+    log::info!(
+        "bundled {0} file(s) of {1} dotfiles directory(s) into {2}",
+        files,
+        manifest.entries.len(),
+        output
+    );
+    Ok(())
+}
+
+#[cfg(feature = "bundle")]
+fn unbundle(config: &mut Config, matches: &ArgMatches) -> Result<()> {
+    let bundle = matches
+        .value_of("bundle")
+        .ok_or(anyhow!("must include bundle argument"))?;
+    let dest = matches
+        .value_of("dest")
+        .ok_or(anyhow!("must include --dest argument"))?;
+    for dir in config.unbundle(Path::new(bundle), Path::new(dest))? {
+        log::info!("extracted and tracking {0}", dir.display());
+    }
+    if matches.is_present("deploy") {
+        let summary = config.deploy_all()?;
+        log::info!("{0} path(s) unchanged", summary.unchanged);
+    }
+    Ok(())
+}
+
+fn find(config: &Config, matches: &ArgMatches) -> Result<()> {
+    let query = matches
+        .get_one::<String>("query")
+        .ok_or(anyhow!("must include query argument"))?;
+
+    let results = if matches.is_present("regex") {
+        config.search_regex(query)?
+    } else {
+        config.search(query)
+    };
+
+    for (dotfiles, path) in results {
+        println!(
+            "{0} -> {1}",
+            dotfiles.symlink_path(path).display(),
+            dotfiles.store_path(path).display()
+        );
+    }
+    Ok(())
+}
+
+fn explain(config: &Config, matches: &ArgMatches) -> Result<()> {
+    let path = matches
+        .get_one::<String>("path")
+        .map(PathBuf::from)
+        .ok_or(anyhow!("must include path argument"))?;
+    if matches.is_present("store") {
+        let dir = config.store_dir_for(&path).ok_or(anyhow!(
+            "{0} is not inside a configured directory",
+            path.display()
+        ))?;
+        println!("{0}", dir.display());
+        return Ok(());
+    }
+    for step in config.explain(&path) {
+        println!("{0}", step);
+    }
+    Ok(())
+}
+
+fn plan(config: &Config, matches: &ArgMatches) -> Result<()> {
+    let files: Vec<PathBuf> = matches
+        .values_of("files")
+        .unwrap()
+        .map(PathBuf::from)
+        .collect();
+    for (file, operation) in files.iter().zip(config.list_operations(&files)?) {
+        println!("{0}: {1}", file.display(), operation);
+    }
+    Ok(())
+}
+
+fn export(config: &Config, matches: &ArgMatches) -> Result<()> {
+    if let Some(role_dir) = matches.value_of("ansible") {
+        config.export_ansible(&PathBuf::from(role_dir))?;
+        log::info!("exported ansible role to {0}", role_dir);
+    }
+    if let Some(hostname) = matches.value_of("for_host") {
+        let filtered = config.clone_for_host(hostname);
+        print!("{0}", toml::to_string_pretty(&filtered)?);
+    }
+    if let Some(path) = matches.value_of("makefile") {
+        config.create_makefile(Path::new(path))?;
+        log::info!("wrote makefile to {0}", path);
+    }
+    if matches.is_present("manifest") {
+        let manifest = ManifestFile {
+            files: config.manifest()?,
+        };
+        print!("{0}", toml::to_string_pretty(&manifest)?);
+    }
+    Ok(())
+}
+
+fn import(config: &mut Config, matches: &ArgMatches) -> Result<()> {
+    let target: SymlinkDir = match matches.value_of("target") {
+        Some(target) => PathBuf::from(target),
+        None => config
+            .xdg_dirs()
+            .ok_or(Error::MissingHomeDirectory)?
+            .home_dir()?
+            .to_path_buf(),
+    }
+    .try_into()?;
+
+    if let Some(src_dir) = matches.value_of("chezmoi") {
+        let count = config.import_from_chezmoi(&PathBuf::from(src_dir), &target)?;
+        log::info!("imported {0} path(s) from {1}", count, src_dir);
+    }
+    Ok(())
+}
+
+fn config_command(matches: &ArgMatches) -> Result<()> {
+    match matches.subcommand() {
+        Some(("paths", _)) => {
+            let dirs = Dirs::from_env()?;
+            println!("config file: {0}", dirs.config_file().display());
+            println!("config:      {0}", dirs.config_dir().display());
+            println!("data:        {0}", dirs.data_dir().display());
+            println!("state:       {0}", dirs.state_dir().display());
+            println!("cache:       {0}", dirs.cache_dir().display());
+            println!("runtime:     {0}", dirs.runtime_dir().display());
+        },
+        Some((s, _)) => return Err(anyhow!("invalid subcommand: {0}", s)),
+        None => return Err(anyhow!("missing subcommand")),
+    }
+    Ok(())
+}
+
+fn snapshots(config: &Config, matches: &ArgMatches) -> Result<()> {
+    if !matches.is_present("prune") {
+        for snapshot in config.snapshots()? {
+            println!("{0}", snapshot.display());
+        }
+        return Ok(());
+    }
+    let keep = match matches.get_one::<usize>("keep") {
+        Some(keep) => *keep,
+        None => config.snapshot_keep()?,
+    };
+    let deleted = config.rotate_snapshots(keep)?;
+    log::info!("deleted {0} snapshots, keeping at most {1}", deleted, keep);
+    Ok(())
+}
+
+fn skip(matches: &ArgMatches) -> Result<()> {
+    let mut state = MachineState::load()?;
+    match matches.subcommand() {
+        Some(("add", add_matches)) => {
+            let pattern = add_matches
+                .get_one::<String>("pattern")
+                .ok_or(anyhow!("must include pattern argument"))?;
+            if state.add_skip(pattern)? {
+                state.write()?;
+                log::info!("skipping {0} on this machine", pattern);
+            }
+        },
+        Some(("remove", remove_matches)) => {
+            let pattern = remove_matches
+                .get_one::<String>("pattern")
+                .ok_or(anyhow!("must include pattern argument"))?;
+            if !state.remove_skip(pattern) {
+                return Err(anyhow!("{0} is not skipped on this machine", pattern));
+            }
+            state.write()?;
+            log::info!("no longer skipping {0} on this machine", pattern);
+        },
+        Some(("list", _)) => {
+            for pattern in state.skip_list() {
+                println!("{0}", pattern);
+            }
+        },
+        Some((s, _)) => return Err(anyhow!("invalid subcommand: {0}", s)),
+        None => return Err(anyhow!("missing subcommand")),
+    }
+    Ok(())
+}
+
+fn watch(config: &Config) -> Result<()> {
+    let config_file = Config::file_path()?;
+    let mut reloaded: Option<Config> = None;
+    loop {
+        let current = reloaded.as_ref().unwrap_or(config);
+        let (sender, receiver) = mpsc::channel();
+        let config_sender = sender.clone();
+        let _watcher = current.watch(move |path| {
+            let _ = sender.send(path.to_path_buf());
+        })?;
+        let _config_watcher = Config::watch_config_file(move |path| {
+            let _ = config_sender.send(path.to_path_buf());
+        })?;
+        log::info!("watching dotfiles directories for changes, press Ctrl-C to stop");
+        loop {
+            let mut changes = match debounce(&receiver, WATCH_DEBOUNCE) {
+                Some(changes) => changes,
+                None => return Ok(()),
+            };
+            let config_changed = changes.remove(&config_file);
+            let deployed = current.deploy_watched(changes.iter().map(PathBuf::as_path));
+            if deployed > 0 {
+                log::info!("deployed {0} changed dotfile(s)", deployed);
+            }
+            if config_changed {
+                break;
+            }
+        }
+
+        // watch the dotfiles directories of the changed config from now on
+        let loaded = Config::load_and_validate().map(|mut config| {
+            config.set_read_only();
+            config
+        });
+        match loaded {
+            Ok(config) => {
+                log::info!("reloaded {0}", config_file.display());
+                reloaded = Some(config);
+            },
+            Err(e) => log::warn!(
+                "keeping the previous config, failed to reload {0}: {1}",
+                config_file.display(),
+                e
+            ),
+        }
+    }
+}
+
+fn repair(config: &mut Config, matches: &ArgMatches) -> Result<()> {
+    if matches.is_present("dirs") {
+        let created = config.touch_symlinks()?;
+        log::info!("created {0} missing directories", created);
+    }
+    if let (Some(old), Some(new)) = (matches.value_of("old"), matches.value_of("new")) {
+        let new: DotfilesDir = PathBuf::from(new).try_into()?;
+        let summary = config.repair_moved_store(Path::new(old), &new)?;
+        for path in &summary.skipped {
+            log::warn!(
+                "left {0} alone, it doesn't point into {1}",
+                path.display(),
+                old
+            );
+        }
+        log::info!(
+            "relinked {0} symlink(s), {1} already pointed at their dotfile",
+            summary.relinked,
+            summary.unchanged
+        );
+    }
+    Ok(())
+}
+
+fn entry(config: &mut Config, matches: &ArgMatches) -> Result<()> {
+    let (enabled, matches) = match matches.subcommand() {
+        Some(("enable", enable_matches)) => (true, enable_matches),
+        Some(("disable", disable_matches)) => (false, disable_matches),
+        Some((s, _)) => return Err(anyhow!("invalid subcommand: {0}", s)),
+        None => return Err(anyhow!("missing subcommand")),
+    };
+    let dotfiles_dir: DotfilesDir = matches
+        .get_one::<String>("dotfiles_dir")
+        .map(PathBuf::from)
+        .ok_or(anyhow!("must include dotfiles_dir argument"))?
+        .try_into()?;
+    let state = match enabled {
+        true => "enabled",
+        false => "disabled",
+    };
+    match config.set_enabled(&dotfiles_dir, enabled)? {
+        0 => log::info!("{0} is already {1}", dotfiles_dir.display(), state),
+        _ => log::info!("{0} is now {1}", dotfiles_dir.display(), state),
+    }
+    Ok(())
+}
+
+/// Note how many dotfiles entries an operation on all dotfiles leaves out because they are
+/// disabled.
+fn note_disabled(config: &Config) {
+    let count = config.disabled_count();
+    if count > 0 {
+        log::info!("skipping {0} disabled dotfiles entry(s)", count);
+    }
+}
+
+fn rename_dir(config: &mut Config, matches: &ArgMatches) -> Result<()> {
+    let kind: DirKind = matches
+        .get_one::<String>("kind")
+        .ok_or(anyhow!("must include kind argument"))?
+        .parse()?;
+    let (old, new) = match (matches.value_of("old"), matches.value_of("new")) {
+        (Some(old), Some(new)) => (Path::new(old), Path::new(new)),
+        _ => return Err(anyhow!("must include old and new arguments")),
+    };
+    let report = config.rename_dir(kind, old, new, matches.is_present("all_matching"))?;
+    log::info!("updated {0} dotfiles entry(s)", report.renamed);
+    if report.unresolved.is_empty() {
+        return Ok(());
+    }
+    let count = report.unresolved.len();
+    println!(
+        "{0} dotfile(s) don't resolve with the new directory:",
+        count
+    );
+    for (path, state) in &report.unresolved {
+        println!("{0:<15} {1}", state, path.display());
+    }
+    Ok(())
+}
+
+fn convert_links(config: &mut Config, matches: &ArgMatches) -> Result<()> {
+    let style: LinkStyle = matches
+        .get_one::<String>("link_style")
+        .ok_or(anyhow!("must include link_style argument"))?
+        .parse()?;
+    let summary = config.convert_links(style)?;
+    for path in &summary.skipped {
+        log::warn!(
+            "skipped {0}, it doesn't point at its dotfile",
+            path.display()
+        );
+    }
+    log::info!(
+        "converted {0} symlink(s), {1} already used the requested style",
+        summary.converted,
+        summary.unchanged
+    );
+    Ok(())
+}
+
+fn service(interval: &str) -> Result<Service> {
+    Ok(Service::new(
+        std::env::current_exe()?,
+        interval,
+        &Dirs::from_env()?,
+    )?)
+}
+
+fn install_service(matches: &ArgMatches) -> Result<()> {
+    let service = service(matches.value_of("interval").unwrap_or("1h"))?;
+    service.install(&Systemctl, matches.is_present("now"))?;
+    if !matches.is_present("now") {
+        log::info!(
+            "run `systemctl --user enable --now ghmd-deploy.timer` to start deploying"
+        );
+    }
+    Ok(())
+}
+
+fn uninstall_service() -> Result<()> {
+    // the interval only matters for the contents of the timer unit
+    Ok(service("1h")?.uninstall(&Systemctl)?)
+}
+
+fn shell_hook(matches: &ArgMatches) -> Result<()> {
+    let shell: Shell = matches
+        .get_one::<String>("shell")
+        .ok_or(anyhow!("must include shell argument"))?
+        .parse()?;
+    let hook = shell.hook(&std::env::current_exe()?, &Config::file_path()?);
+    print!("{0}", hook);
+    Ok(())
+}
+
+fn doctor(config: &Config, matches: &ArgMatches) -> Result<()> {
+    println!(
+        "ghmd version: {0} (config schema {1})",
+        VERSION, CONFIG_SCHEMA_VERSION
+    );
+
+    let config_path = Config::file_path()?;
+    match config.loaded_version() {
+        Some(version) => println!(
+            "config file:  {0} (written by ghmd {1})",
+            config_path.display(),
+            version
+        ),
+        None if config_path.exists() => println!(
+            "config file:  {0} (written by an unknown version of ghmd)",
+            config_path.display()
+        ),
+        None => println!("config file:  {0} (not yet written)", config_path.display()),
+    }
+
+    let counts = config.count_by_state()?;
+    let failures: usize = counts
+        .iter()
+        .filter(|(state, _)| !state.is_ok())
+        .map(|(_, count)| count)
+        .sum();
+    println!(
+        "dotfiles:     {0} tracked, {1} not deployed correctly",
+        counts.values().sum::<usize>(),
+        failures
+    );
+
+    let missing = counts
+        .get(&SymlinkState::DotfileMissing)
+        .copied()
+        .unwrap_or(0);
+    if missing > 0 {
+        println!(
+            "              {0} missing from their dotfiles directory, see `ghmd prune-config`",
+            missing
+        );
+    }
+
+    for (a, b) in config.duplicate_entries() {
+        let dotfiles = &config.dotfiles()[b];
+        println!(
+            "duplicate:    {0} -> {1} is configured by entries {2} and {3}, merge them",
+            dotfiles.dotfile_directory().display(),
+            dotfiles.symlink_dir().display(),
+            a,
+            b
+        );
+    }
+
+    for dotfiles in config.dotfiles() {
+        for (path, dir) in dotfiles.nested_paths() {
+            println!(
+                "nested:       {0} is inside the tracked directory {1}, forget one of them",
+                dotfiles.dotfile_directory().join(&**path).display(),
+                dir.display()
+            );
+        }
+    }
+
+    let stores = config
+        .dotfiles()
+        .iter()
+        .map(|dotfiles| dotfiles.dotfile_directory());
+    for dir in config.unversioned_dirs(stores.map(|dir| dir.as_path())) {
+        println!(
+            "unversioned:  {0} is not under version control",
+            dir.display()
+        );
+        if matches.is_present("fix") {
+            eprint!("run `git init` in {0}? [y/N] ", dir.display());
+            let mut answer = String::new();
+            let _ = io::stdin().read_line(&mut answer)?;
+            if matches!(answer.trim(), "y" | "Y" | "yes") {
+                init_repo(&dir, &Git)?;
+                log::info!("initialized a git repository in {0}", dir.display());
+            }
+        }
+    }
+    Ok(())
+}
+
+fn prune_config(config: &mut Config, matches: &ArgMatches) -> Result<()> {
+    let missing = config.missing_dotfiles()?;
+    if matches.value_of("format") == Some("json") {
+        println!("{0}", serde_json::to_string_pretty(&missing)?);
+    } else {
+        for status in &missing {
+            println!("{0:<15} {1}", status.state, status.store_path().display());
+        }
+    }
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    if !matches.is_present("yes") {
+        eprint!(
+            "stop tracking {0} missing dotfile(s)? [y/N] ",
+            missing.len()
+        );
+        let mut answer = String::new();
+        let _ = io::stdin().read_line(&mut answer)?;
+        if !matches!(answer.trim(), "y" | "Y" | "yes") {
+            return Ok(());
+        }
     }
 
+    let count = config.prune_paths(&missing, matches.is_present("remove_links"))?;
+    log::info!("removed {0} path(s) from the config", count);
     Ok(())
 }
@@ -0,0 +1,273 @@
+//! Three-way merge of the dotfiles entries of a loaded [`Config`] with changes made to its config
+//! file by something else in the meantime, e.g. by hand while `ghmd watch` is running.
+//!
+//! [`Config`]: crate::Config
+use std::collections::BTreeSet;
+use std::sync::{Mutex, PoisonError};
+
+use crate::config::Dotfiles;
+
+/// FileState is what a config loaded from the config file knows about that file.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub(crate) struct FileState {
+    /// Hash of the contents of the config file as last read or written, `None` if it didn't
+    /// exist.
+    pub(crate) hash: Option<String>,
+
+    /// Dotfiles entries of the config file when it was loaded.
+    pub(crate) base: Vec<Dotfiles>,
+
+    /// Dotfiles entries as last written.
+    pub(crate) written: Vec<Dotfiles>,
+
+    /// Dotfiles entries with every change made to the config file by others merged into
+    /// `base`.
+    pub(crate) external: Vec<Dotfiles>,
+
+    /// Whether writing the config file was refused because of changes made to it by others.
+    pub(crate) refused: bool,
+}
+
+impl FileState {
+    /// State of a config file with the given contents, or of a missing one, whose dotfiles
+    /// entries are `dotfiles`.
+    pub(crate) fn new(hash: Option<String>, dotfiles: &[Dotfiles]) -> Self {
+        Self {
+            hash,
+            base: dotfiles.to_vec(),
+            written: dotfiles.to_vec(),
+            external: dotfiles.to_vec(),
+            refused: false,
+        }
+    }
+}
+
+/// Tracked is the [`FileState`] of a config, if it was loaded from the config file, behind a
+/// lock so that it can be updated when a shared config is written.
+#[derive(Debug, Default)]
+pub(crate) struct Tracked(Mutex<Option<FileState>>);
+
+impl Tracked {
+    pub(crate) fn new(state: FileState) -> Self {
+        Self(Mutex::new(Some(state)))
+    }
+
+    pub(crate) fn get(&self) -> Option<FileState> {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    pub(crate) fn set(&self, state: FileState) {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) = Some(state);
+    }
+}
+
+impl Clone for Tracked {
+    fn clone(&self) -> Self {
+        Self(Mutex::new(self.get()))
+    }
+}
+
+impl PartialEq for Tracked {
+    // bookkeeping doesn't make configs differ
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+/// Merge the changes `mine` and `theirs` made to the dotfiles entries `base`. Entries are
+/// matched by their dotfiles and symlink directories. Dotfiles added on either side are kept and
+/// dotfiles removed on either side are dropped. Returns a description of the conflict if both
+/// sides changed the settings of an entry differently, or one side removed an entry the other
+/// changed.
+pub(crate) fn merge_entries(
+    base: &[Dotfiles],
+    mine: &[Dotfiles],
+    theirs: &[Dotfiles],
+) -> Result<Vec<Dotfiles>, String> {
+    let mut merged = Vec::with_capacity(mine.len());
+    for entry in mine {
+        let base = find(base, entry);
+        match (base, find(theirs, entry)) {
+            (_, Some(other)) => merged.push(merge_entry(base, entry, other)?),
+            (None, None) => merged.push(entry.clone()),
+            (Some(base), None) if same_entry(base, entry) => {},
+            (Some(_), None) => {
+                return Err(conflict(
+                    entry,
+                    "was removed from the config file but changed here",
+                ))
+            },
+        }
+    }
+    for entry in theirs.iter().filter(|entry| find(mine, entry).is_none()) {
+        match find(base, entry) {
+            None => merged.push(entry.clone()),
+            Some(base) if same_entry(base, entry) => {},
+            Some(_) => {
+                return Err(conflict(
+                    entry,
+                    "was changed in the config file but removed here",
+                ))
+            },
+        }
+    }
+    Ok(merged)
+}
+
+fn merge_entry(
+    base: Option<&Dotfiles>,
+    mine: &Dotfiles,
+    theirs: &Dotfiles,
+) -> Result<Dotfiles, String> {
+    let (settings, other) = (mine.settings(), theirs.settings());
+    let base_settings = base.map(Dotfiles::settings);
+    let mut merged = if settings == other || Some(&other) == base_settings.as_ref() {
+        mine.clone()
+    } else if Some(&settings) == base_settings.as_ref() {
+        theirs.clone()
+    } else {
+        return Err(conflict(
+            mine,
+            "has different settings here and in the config file",
+        ));
+    };
+    merged.paths = merge_set(base.map(|base| &base.paths), &mine.paths, &theirs.paths);
+    merged.copied_paths = merge_set(
+        base.map(|base| &base.copied_paths),
+        &mine.copied_paths,
+        &theirs.copied_paths,
+    );
+    Ok(merged)
+}
+
+/// Items of `mine` and `theirs` that either both contain or one of them added to `base`.
+fn merge_set<T: Ord + Clone>(
+    base: Option<&BTreeSet<T>>,
+    mine: &BTreeSet<T>,
+    theirs: &BTreeSet<T>,
+) -> BTreeSet<T> {
+    mine.union(theirs)
+        .filter(|item| {
+            let kept = mine.contains(item) && theirs.contains(item);
+            kept || !base.is_some_and(|base| base.contains(item))
+        })
+        .cloned()
+        .collect()
+}
+
+fn find<'a>(entries: &'a [Dotfiles], entry: &Dotfiles) -> Option<&'a Dotfiles> {
+    entries.iter().find(|other| {
+        other.dotfile_directory == entry.dotfile_directory
+            && other.symlink_directory == entry.symlink_directory
+    })
+}
+
+fn same_entry(a: &Dotfiles, b: &Dotfiles) -> bool {
+    a.settings() == b.settings() && a.paths == b.paths && a.copied_paths == b.copied_paths
+}
+
+fn conflict(entry: &Dotfiles, reason: &str) -> String {
+    format!(
+        "the entry for {0} {1}",
+        entry.dotfile_directory.display(),
+        reason
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use crate::config::LinkStyle;
+
+    /// An entry storing `paths` in `<dir>/<store>` and deploying them to `<dir>/home`.
+    fn entry(dir: &Path, store: &str, paths: &[&str]) -> Dotfiles {
+        let (home, store) = (dir.join("home"), dir.join(store));
+        fs::create_dir_all(&home).unwrap();
+        fs::create_dir_all(&store).unwrap();
+        let mut dotfiles =
+            Dotfiles::new(&home.try_into().unwrap(), &store.try_into().unwrap()).unwrap();
+        for path in paths {
+            let _ = dotfiles
+                .paths
+                .insert(PathBuf::from(path).try_into().unwrap());
+        }
+        dotfiles
+    }
+
+    fn paths(dotfiles: &Dotfiles) -> Vec<String> {
+        dotfiles
+            .paths
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn paths_added_and_removed_on_either_side_are_merged() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let base = [entry(dir, "dots", &["a", "b", "c"])];
+        let mine = [entry(dir, "dots", &["a", "b", "mine"])];
+        let mut theirs = entry(dir, "dots", &["b", "c", "theirs"]);
+        theirs.set_link_style(LinkStyle::Relative);
+        let theirs = [theirs, entry(dir, "new", &["x"])];
+
+        let merged = merge_entries(&base, &mine, &theirs).unwrap();
+        assert_eq!(merged.len(), 2);
+        assert_eq!(paths(&merged[0]), ["b", "mine", "theirs"]);
+        // only their side changed the settings
+        assert_eq!(merged[0].link_style(), LinkStyle::Relative);
+        assert_eq!(paths(&merged[1]), ["x"]);
+    }
+
+    #[test]
+    fn entries_removed_unchanged_on_one_side_are_dropped() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let base = [entry(dir, "dots", &["a"]), entry(dir, "gone", &["g"])];
+        let mine = [entry(dir, "dots", &["a", "b"]), entry(dir, "gone", &["g"])];
+        let theirs = [entry(dir, "dots", &["a"])];
+
+        let merged = merge_entries(&base, &mine, &theirs).unwrap();
+        assert_eq!(merged.len(), 1);
+        assert_eq!(paths(&merged[0]), ["a", "b"]);
+    }
+
+    #[test]
+    fn conflicting_changes_are_refused() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let base = [entry(dir, "dots", &["a"])];
+
+        // both sides changed the settings of the entry differently
+        let mut mine = entry(dir, "dots", &["a"]);
+        mine.set_link_style(LinkStyle::Relative);
+        let mut theirs = entry(dir, "dots", &["a"]);
+        theirs.canonical_targets = true;
+        let conflict = merge_entries(&base, &[mine], &[theirs]).unwrap_err();
+        assert!(conflict.contains("has different settings"), "{0}", conflict);
+
+        // one side removed the entry the other changed
+        let mine = [entry(dir, "dots", &["a", "b"])];
+        let conflict = merge_entries(&base, &mine, &[]).unwrap_err();
+        assert!(
+            conflict.contains("was removed from the config file"),
+            "{0}",
+            conflict
+        );
+        let theirs = [entry(dir, "dots", &["a", "b"])];
+        let conflict = merge_entries(&base, &[], &theirs).unwrap_err();
+        assert!(
+            conflict.contains("was changed in the config file"),
+            "{0}",
+            conflict
+        );
+    }
+}
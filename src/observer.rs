@@ -0,0 +1,123 @@
+//! Events reported while stowing, deploying and restoring dotfiles, for frontends that present
+//! progress and results themselves rather than through the log.
+use std::fmt;
+use std::path::Path;
+
+use crate::conflict::Conflict;
+use crate::errors::{Error, Result};
+
+/// Action is a single dotfile being stowed, deployed or restored, identified by its path
+/// relative to the dotfiles and symlink directories.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Action<'a> {
+    /// The dotfile is moved into a dotfiles directory and replaced by a symlink.
+    Stow(&'a Path),
+
+    /// The symlink of the dotfile is created.
+    Deploy(&'a Path),
+
+    /// The dotfile is moved back to where its symlink is.
+    Restore(&'a Path),
+}
+
+impl Action<'_> {
+    /// Path of the dotfile relative to the dotfiles and symlink directories.
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Stow(path) | Self::Deploy(path) | Self::Restore(path) => path,
+        }
+    }
+}
+
+impl fmt::Display for Action<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verb = match self {
+            Self::Stow(_) => "stow",
+            Self::Deploy(_) => "deploy",
+            Self::Restore(_) => "restore",
+        };
+        write!(f, "{0} {1}", verb, self.path().display())
+    }
+}
+
+/// ActionOutcome is how an [`Action`] ended.
+#[derive(Debug)]
+pub enum ActionOutcome<'a> {
+    /// The action changed the filesystem, or would have in a dry run.
+    Done,
+
+    /// Everything was already in place.
+    Unchanged,
+
+    /// The dotfile was left alone, e.g. because it is on the skip list of this machine or a
+    /// conflict was resolved with [`Resolution::Skip`].
+    Skipped,
+
+    /// The action failed.
+    Failed(&'a Error),
+}
+
+/// Resolution is what to do about a [`Conflict`] at the location a dotfile is deployed to.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Resolution {
+    /// Fail the deploy of the dotfile with the error describing the conflict.
+    #[default]
+    Fail,
+
+    /// Leave whatever is in the way alone and don't deploy the dotfile.
+    Skip,
+
+    /// Move whatever is in the way into the backup directory and deploy the dotfile.
+    Backup,
+}
+
+/// Observer receives the events of stow, deploy and restore operations that are given it through
+/// their options, see e.g. [`DeployOptions::observer`](crate::DeployOptions::observer). Every
+/// method does nothing by default, so implementations only override the events they present.
+///
+/// Deploying with several jobs reports events from several threads at once.
+pub trait Observer: fmt::Debug + Sync {
+    /// Called before `action` is attempted.
+    fn on_action_start(&self, action: &Action<'_>) {
+        let _ = action;
+    }
+
+    /// Called once `action` ended with `outcome`.
+    fn on_action_done(&self, action: &Action<'_>, outcome: &ActionOutcome<'_>) {
+        let _ = (action, outcome);
+    }
+
+    /// Called when deploying a dotfile finds `conflict` in the way that the options of the
+    /// deploy don't resolve, returning what to do about it.
+    fn on_conflict(&self, conflict: &Conflict) -> Resolution {
+        let _ = conflict;
+        Resolution::Fail
+    }
+
+    /// Called as files are moved while stowing, with the number of `bytes` of the `total` moved
+    /// so far. Progress is reported once each file has been moved.
+    fn on_progress(&self, bytes: u64, total: u64) {
+        let _ = (bytes, total);
+    }
+}
+
+/// Run `f` as `action`, reporting its start and how it ended according to `outcome` to
+/// `observer`, if there is one.
+pub(crate) fn observe<T>(
+    observer: Option<&dyn Observer>,
+    action: Action<'_>,
+    f: impl FnOnce() -> Result<T>,
+    outcome: impl FnOnce(&T) -> ActionOutcome<'static>,
+) -> Result<T> {
+    let observer = match observer {
+        Some(observer) => observer,
+        None => return f(),
+    };
+    observer.on_action_start(&action);
+    let result = f();
+    match &result {
+        Ok(value) => observer.on_action_done(&action, &outcome(value)),
+        Err(e) => observer.on_action_done(&action, &ActionOutcome::Failed(e)),
+    }
+    result
+}
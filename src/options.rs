@@ -0,0 +1,380 @@
+//! Behavioral toggles accepted by the stow, deploy and restore operations of [`Config`].
+//!
+//! Options compare equal if all their toggles are equal and they have the same observer, i.e.
+//! the same object rather than an equal one.
+//!
+//! [`Config`]: crate::Config
+use std::path::Path;
+use std::ptr;
+
+use crate::config::{ErrorPolicy, LinkMode, LinkStyle};
+use crate::observer::Observer;
+use crate::selector::MtimeFilter;
+
+/// StowOptions controls how [`Config::stow_paths_with`](crate::Config::stow_paths_with) stows
+/// dotfiles.
+#[derive(Debug, Clone, Copy, Default)]
+#[non_exhaustive]
+pub struct StowOptions<'a> {
+    /// How a path that fails to stow affects the remaining paths.
+    pub policy: ErrorPolicy,
+
+    /// Validate and log every path without moving files or modifying the config.
+    pub dry_run: bool,
+
+    /// Style of the symlinks created in place of the stowed files. The style of the dotfiles
+    /// entry is used if unset.
+    pub link_style: Option<LinkStyle>,
+
+    /// Sync the directory containing each created symlink and moved file to disk, so that the
+    /// changes survive a crash or power loss. This costs a disk flush per entry.
+    pub durable: bool,
+
+    /// Receiver of the events of the stow, see [`Observer`].
+    pub observer: Option<&'a dyn Observer>,
+}
+
+impl<'a> StowOptions<'a> {
+    /// Options that stop at the first failure and link using the style of the dotfiles entry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set how a path that fails to stow affects the remaining paths.
+    pub fn policy(mut self, policy: ErrorPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Set whether paths are only validated and logged.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Set the style of the created symlinks.
+    pub fn link_style(mut self, link_style: LinkStyle) -> Self {
+        self.link_style = Some(link_style);
+        self
+    }
+
+    /// Set whether created symlinks and moved files are synced to disk.
+    pub fn durable(mut self, durable: bool) -> Self {
+        self.durable = durable;
+        self
+    }
+
+    /// Set the receiver of the events of the stow.
+    pub fn observer(mut self, observer: &'a dyn Observer) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+}
+
+impl PartialEq for StowOptions<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        let Self {
+            policy,
+            dry_run,
+            link_style,
+            durable,
+            observer,
+        } = self;
+        *policy == other.policy
+            && *dry_run == other.dry_run
+            && *link_style == other.link_style
+            && *durable == other.durable
+            && same_observer(*observer, other.observer)
+    }
+}
+
+impl Eq for StowOptions<'_> {}
+
+/// DeployOptions controls how [`Config::deploy_paths_with`](crate::Config::deploy_paths_with)
+/// deploys dotfiles.
+#[derive(Debug, Clone, Copy, Default)]
+#[non_exhaustive]
+pub struct DeployOptions<'a> {
+    /// Log the symlinks that would be created without creating them.
+    pub dry_run: bool,
+
+    /// Replace symlinks that point somewhere other than their dotfile.
+    pub force: bool,
+
+    /// Move files and directories in the way of a symlink into the backup directory instead of
+    /// failing.
+    pub backup: bool,
+
+    /// Skip dotfiles whose symlink path is occupied by something that isn't replaced (see
+    /// [`Error::is_conflict`](crate::Error::is_conflict)) with a warning instead of failing.
+    pub skip_conflicts: bool,
+
+    /// Skip dotfiles stored as symlinks whose target doesn't exist with a warning instead of
+    /// failing with [`Error::StoreEntryBroken`](crate::Error::StoreEntryBroken).
+    pub skip_broken: bool,
+
+    /// Skip dotfiles on the skip list of this machine (see
+    /// [`MachineState`](crate::MachineState)) instead of deploying them.
+    pub skip_listed: bool,
+
+    /// Link dotfiles in this mode instead of the one configured for their dotfiles entry.
+    pub link_mode: Option<LinkMode>,
+
+    /// Replace each `${NAME}` in symlink paths with the value of the environment variable
+    /// `NAME`, see [`paths::expand_env`](crate::paths::expand_env). Use
+    /// [`Config::record_expand_dest`](crate::Config::record_expand_dest) for other commands to
+    /// find the symlinks there too.
+    pub expand_dest: bool,
+
+    /// Only deploy stored dotfiles modified within this range when deploying all dotfiles.
+    pub modified: MtimeFilter,
+
+    /// Sync the directory containing each created symlink and moved file to disk, so that the
+    /// changes survive a crash or power loss. This costs a disk flush per entry.
+    pub durable: bool,
+
+    /// Receiver of the events of the deploy, see [`Observer`].
+    pub observer: Option<&'a dyn Observer>,
+}
+
+impl<'a> DeployOptions<'a> {
+    /// Options that fail on anything in the way of a symlink.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set whether symlinks are only logged.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Set whether symlinks pointing elsewhere are replaced.
+    pub fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// Set whether anything else in the way of a symlink is backed up and replaced.
+    pub fn backup(mut self, backup: bool) -> Self {
+        self.backup = backup;
+        self
+    }
+
+    /// Set whether conflicting dotfiles are skipped.
+    pub fn skip_conflicts(mut self, skip_conflicts: bool) -> Self {
+        self.skip_conflicts = skip_conflicts;
+        self
+    }
+
+    /// Set whether dotfiles stored as broken symlinks are skipped.
+    pub fn skip_broken(mut self, skip_broken: bool) -> Self {
+        self.skip_broken = skip_broken;
+        self
+    }
+
+    /// Set whether dotfiles on the skip list of this machine are skipped.
+    pub fn skip_listed(mut self, skip_listed: bool) -> Self {
+        self.skip_listed = skip_listed;
+        self
+    }
+
+    /// Set the mode dotfiles are linked in instead of the configured one.
+    pub fn link_mode(mut self, link_mode: LinkMode) -> Self {
+        self.link_mode = Some(link_mode);
+        self
+    }
+
+    /// Set whether environment variables in symlink paths are expanded.
+    pub fn expand_dest(mut self, expand_dest: bool) -> Self {
+        self.expand_dest = expand_dest;
+        self
+    }
+
+    /// Set the range of modification times of the dotfiles deployed when deploying all
+    /// dotfiles.
+    pub fn modified(mut self, modified: MtimeFilter) -> Self {
+        self.modified = modified;
+        self
+    }
+
+    /// Set whether created symlinks and moved files are synced to disk.
+    pub fn durable(mut self, durable: bool) -> Self {
+        self.durable = durable;
+        self
+    }
+
+    /// Set the receiver of the events of the deploy.
+    pub fn observer(mut self, observer: &'a dyn Observer) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+}
+
+impl PartialEq for DeployOptions<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        let Self {
+            dry_run,
+            force,
+            backup,
+            skip_conflicts,
+            skip_broken,
+            skip_listed,
+            link_mode,
+            expand_dest,
+            modified,
+            durable,
+            observer,
+        } = self;
+        *dry_run == other.dry_run
+            && *force == other.force
+            && *backup == other.backup
+            && *skip_conflicts == other.skip_conflicts
+            && *skip_broken == other.skip_broken
+            && *skip_listed == other.skip_listed
+            && *link_mode == other.link_mode
+            && *expand_dest == other.expand_dest
+            && *modified == other.modified
+            && *durable == other.durable
+            && same_observer(*observer, other.observer)
+    }
+}
+
+impl Eq for DeployOptions<'_> {}
+
+/// RestoreOptions controls how [`Config::restore_with`](crate::Config::restore_with) restores
+/// dotfiles.
+#[derive(Debug, Clone, Copy, Default)]
+#[non_exhaustive]
+pub struct RestoreOptions<'a> {
+    /// Validate and log the restore without moving files or modifying the config.
+    pub dry_run: bool,
+
+    /// Replace a file at the original location even if it is newer than the stored dotfile.
+    pub force: bool,
+
+    /// Remove the directories of the store left empty by moving the dotfile out of it, up to
+    /// but excluding the dotfiles directory.
+    pub clean_store: bool,
+
+    /// Directory to move the dotfiles into, at their paths relative to the dotfiles directory,
+    /// instead of back to their symlinks.
+    pub to: Option<&'a Path>,
+
+    /// Sync the directory containing each created symlink and moved file to disk, so that the
+    /// changes survive a crash or power loss. This costs a disk flush per entry.
+    pub durable: bool,
+
+    /// Receiver of the events of the restore, see [`Observer`].
+    pub observer: Option<&'a dyn Observer>,
+
+    /// Directory files that replaced the symlink of a dotfile are moved into before the
+    /// dotfile is restored. Set by the restore methods of [`Config`](crate::Config).
+    pub(crate) backup_directory: Option<&'a Path>,
+}
+
+impl<'a> RestoreOptions<'a> {
+    /// Options that refuse to replace files newer than the stored dotfile.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set whether the restore is only validated and logged.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Set whether newer files at the original location are replaced.
+    pub fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// Set whether store directories left empty by the restore are removed.
+    pub fn clean_store(mut self, clean_store: bool) -> Self {
+        self.clean_store = clean_store;
+        self
+    }
+
+    /// Set the directory the dotfiles are moved into instead of back to their symlinks.
+    pub fn to(mut self, dir: &'a Path) -> Self {
+        self.to = Some(dir);
+        self
+    }
+
+    /// Set whether created symlinks and moved files are synced to disk.
+    pub fn durable(mut self, durable: bool) -> Self {
+        self.durable = durable;
+        self
+    }
+
+    /// Set the receiver of the events of the restore.
+    pub fn observer(mut self, observer: &'a dyn Observer) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    pub(crate) fn backup_directory(mut self, dir: &'a Path) -> Self {
+        self.backup_directory = Some(dir);
+        self
+    }
+}
+
+impl PartialEq for RestoreOptions<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        let Self {
+            dry_run,
+            force,
+            clean_store,
+            to,
+            durable,
+            observer,
+            backup_directory,
+        } = self;
+        *dry_run == other.dry_run
+            && *force == other.force
+            && *clean_store == other.clean_store
+            && *to == other.to
+            && *durable == other.durable
+            && same_observer(*observer, other.observer)
+            && *backup_directory == other.backup_directory
+    }
+}
+
+impl Eq for RestoreOptions<'_> {}
+
+/// Whether `a` and `b` are both unset or the same observer.
+fn same_observer(a: Option<&dyn Observer>, b: Option<&dyn Observer>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => ptr::addr_eq(a, b),
+        (a, b) => a.is_none() && b.is_none(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::users::OperationsLog;
+
+    #[test]
+    fn options_with_the_same_observer_are_equal() {
+        let (a, b) = (OperationsLog::new(), OperationsLog::new());
+        assert_eq!(DeployOptions::new(), DeployOptions::default());
+        assert_eq!(
+            DeployOptions::new().backup(true).observer(&a),
+            DeployOptions::new().backup(true).observer(&a)
+        );
+        assert_ne!(
+            DeployOptions::new().observer(&a),
+            DeployOptions::new().observer(&b)
+        );
+        assert_ne!(DeployOptions::new(), DeployOptions::new().observer(&a));
+        assert_ne!(StowOptions::new(), StowOptions::new().dry_run(true));
+        assert_eq!(
+            RestoreOptions::new().to(Path::new("/tmp")),
+            RestoreOptions::new().to(Path::new("/tmp"))
+        );
+    }
+}
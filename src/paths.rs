@@ -1,38 +1,378 @@
 //! Includes paths/fs-specific helper functions.
+use std::env;
+use std::ffi::{OsStr, OsString};
 use std::fs;
-use std::io;
-use std::path::PathBuf;
-use std::os::linux::fs::MetadataExt;
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+use sha2::{Digest, Sha256};
 
 use crate::errors::{Error, Result};
 
+/// Sync the directory containing "path" to disk, so that a file created, renamed or removed in it
+/// survives a crash or power loss. This costs a disk flush per call, which is why callers only do
+/// it when durability was requested. This is a no-op on platforms other than unix.
+pub fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    if let Some(parent) = path.parent() {
+        let parent = match parent.as_os_str().is_empty() {
+            true => Path::new("."),
+            false => parent,
+        };
+        log::trace!("syncing directory {0}", parent.display());
+        fs::File::open(parent)?.sync_all()?;
+    }
+    Ok(())
+}
+
 /// Read file at path src and write to created/truncated file at path dst.
 pub fn move_file(src: &PathBuf, dst: &PathBuf) -> Result<()> {
-    let src_meta = src.symlink_metadata()?;
+    // if src and dst are on the same filesystem, there is no need to copy bytes around at all,
+    // just rename the file. comparing devices can't tell whether that's the case on overlayfs or
+    // with bind mounts, so just attempt the rename and fall back to copying if it fails
+    match fs::rename(src, dst) {
+        Ok(()) => return Ok(()),
+        Err(e) if crosses_devices(&e) => {
+            log::debug!(
+                "cannot rename {0} across devices, copying it instead",
+                src.display()
+            );
+        },
+        Err(e) => return Err(e.into()),
+    }
 
-    let dst_dir = dst.parent().ok_or(Error::UnexpectedError("unable to retrieve parent directory"))?;
-    if dst_dir.exists() && src_meta.st_dev() == dst_dir.symlink_metadata()?.st_dev() {
-        // if src and dst_dir are on the same filesystem, there is no need to copy bytes around at
-        // all, just rename the file
-        fs::rename(src, dst)?;
+    let src_meta = src.symlink_metadata()?;
+    if src_meta.is_symlink() {
+        // copying would replace the symlink with the contents of its target, so recreate the
+        // symlink at dst instead
+        move_symlink(src, dst)?;
+    } else if src_meta.is_file() {
+        let mut opts = fs_extra::file::CopyOptions::new();
+        opts.overwrite = false;
+        opts.skip_exist = true;
+        let _ = fs_extra::file::move_file(src, dst, &opts)?;
     } else {
-        if src_meta.is_symlink() || src_meta.is_file() {
-            let mut opts = fs_extra::file::CopyOptions::new();
-            opts.overwrite = false;
-            opts.skip_exist = true;
-            let _ = fs_extra::file::move_file(src, dst, &opts)?;
-        } else { // it's a dir
-            let mut opts = fs_extra::dir::CopyOptions::new();
-            opts.overwrite = false;
-            opts.skip_exist = true;
-            opts.copy_inside = true;
-            let _ = fs_extra::dir::move_dir(src, dst, &opts)?;
-        }
+        // it's a dir
+        let mut opts = fs_extra::dir::CopyOptions::new();
+        opts.overwrite = false;
+        opts.skip_exist = true;
+        opts.copy_inside = true;
+        let _ = fs_extra::dir::move_dir(src, dst, &opts)?;
     }
 
     Ok(())
 }
 
+/// Move the symlink "src" to "dst" by creating a symlink to the same location at "dst" and
+/// removing "src". A relative target is adjusted to lead from "dst" to where it led from "src".
+fn move_symlink(src: &Path, dst: &Path) -> Result<()> {
+    let mut target = fs::read_link(src)?;
+    if target.is_relative() {
+        let resolved = resolve_link(src)?;
+        let dst_dir = normalize(dst.parent().unwrap_or(Path::new("/")));
+        target = relative_path(&dst_dir, &resolved);
+    }
+    create_symlink(&target, &dst.to_path_buf())?;
+    fs::remove_file(src)?;
+    Ok(())
+}
+
+/// Whether "e" indicates that a rename failed because source and destination are on different
+/// filesystems.
+fn crosses_devices(e: &io::Error) -> bool {
+    #[cfg(not(target_os = "windows"))]
+    const EXDEV: i32 = 18;
+    #[cfg(target_os = "windows")]
+    const EXDEV: i32 = 17; // ERROR_NOT_SAME_DEVICE
+
+    e.kind() == io::ErrorKind::CrossesDevices || e.raw_os_error() == Some(EXDEV)
+}
+
+/// Write "contents" to "path" by writing a temporary file next to it and renaming it into place,
+/// so that readers never observe a partially written file. If "durable" is set, the directory
+/// containing "path" is synced afterwards, see [`sync_parent_dir`].
+pub fn write_atomic(path: &Path, contents: &str, durable: bool) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let result = fs::File::create(&tmp)
+        .and_then(|mut file| {
+            file.write_all(contents.as_bytes())?;
+            file.sync_data()
+        })
+        .and_then(|()| fs::rename(&tmp, path));
+    if let Err(e) = result {
+        let _ = fs::remove_file(&tmp);
+        return Err(e);
+    }
+    match durable {
+        true => sync_parent_dir(path),
+        false => Ok(()),
+    }
+}
+
+/// Return the ancestor directories of "path" that don't exist, outermost first.
+pub fn missing_parent_dirs(path: &Path) -> Vec<PathBuf> {
+    let parent = match path.parent() {
+        Some(parent) => parent,
+        None => return Vec::new(),
+    };
+    let mut missing: Vec<PathBuf> = parent
+        .ancestors()
+        .take_while(|dir| !dir.as_os_str().is_empty() && !dir.exists())
+        .map(Path::to_path_buf)
+        .collect();
+    missing.reverse();
+    missing
+}
+
+/// Create the missing ancestor directories of "path", returning the directories that were
+/// created, outermost first.
+pub fn create_parent_dirs(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let missing = missing_parent_dirs(path);
+    if let Some(parent) = missing.last() {
+        log::debug!("creating directory {0}", parent.display());
+        fs::create_dir_all(parent)?;
+    }
+    Ok(missing)
+}
+
+/// Remove the ancestor directories of "path" that are empty, innermost first, stopping at the
+/// first one that isn't empty or at "stop_at", which is never removed. Returns the removed
+/// directories, innermost first.
+pub fn remove_empty_parents(path: &Path, stop_at: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut removed = Vec::new();
+    let parents = path.ancestors().skip(1);
+    for dir in parents.take_while(|dir| dir.starts_with(stop_at) && *dir != stop_at) {
+        if fs::read_dir(dir)?.next().is_some() {
+            break;
+        }
+        log::debug!("removing empty directory {0}", dir.display());
+        fs::remove_dir(dir)?;
+        removed.push(dir.to_path_buf());
+    }
+    Ok(removed)
+}
+
+/// Traversal decides which hidden entries are visited by walks of directories and by the
+/// expansion of wildcard patterns. By default walks skip `.git` and wildcards don't match hidden
+/// files or directories unless the pattern spells out their leading dot, like in a shell.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Traversal {
+    /// Whether wildcards match hidden files.
+    pub include_dotfiles: bool,
+
+    /// Whether wildcards match hidden directories and walks descend into `.git`.
+    pub include_dotdirs: bool,
+}
+
+impl Traversal {
+    /// Traversal that skips `.git` and hidden entries matched by wildcards.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set whether wildcards match hidden files.
+    pub fn include_dotfiles(mut self, include: bool) -> Self {
+        self.include_dotfiles = include;
+        self
+    }
+
+    /// Set whether wildcards match hidden directories and walks descend into `.git`.
+    pub fn include_dotdirs(mut self, include: bool) -> Self {
+        self.include_dotdirs = include;
+        self
+    }
+
+    /// Whether a walk of a directory visits its entry "path".
+    pub fn visits(&self, path: &Path) -> bool {
+        self.include_dotdirs || path.file_name() != Some(".git".as_ref())
+    }
+
+    /// Whether "path", one of the matches of the wildcard pattern "pattern", is kept. Hidden
+    /// components of "path" are kept if "pattern" has a component with a leading dot matching
+    /// them, or spelling out `.git`, otherwise only if hidden files or directories are included.
+    pub fn expands(&self, pattern: &str, path: &Path) -> bool {
+        let literal: Vec<&str> = Path::new(pattern)
+            .iter()
+            .filter_map(|component| component.to_str())
+            .filter(|component| component.starts_with('.'))
+            .collect();
+        // `.git` has to be spelled out, e.g. `.*` doesn't match it
+        let spelled = |name: &str| {
+            literal.iter().any(|component| match name {
+                ".git" => *component == name,
+                _ => glob::Pattern::new(component).is_ok_and(|p| p.matches(name)),
+            })
+        };
+        // `.*` matches `.` and `..` too
+        if !matches!(path.components().next_back(), Some(Component::Normal(_))) {
+            return false;
+        }
+        let components: Vec<&OsStr> = path
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(name),
+                _ => None,
+            })
+            .collect();
+        components.iter().enumerate().all(|(i, name)| {
+            let name = name.to_string_lossy();
+            if !name.starts_with('.') || spelled(&name) {
+                return true;
+            }
+            match i + 1 < components.len() || path.is_dir() {
+                true => self.include_dotdirs,
+                false => self.include_dotfiles,
+            }
+        })
+    }
+}
+
+/// Modification time of the file at `path`, or of the symlink itself if it is a symlink whose
+/// target doesn't exist.
+pub fn modified(path: &Path) -> io::Result<SystemTime> {
+    fs::metadata(path)
+        .or_else(|_| path.symlink_metadata())?
+        .modified()
+}
+
+/// Replace each `${NAME}` in `path` with the value of the environment variable `NAME`. Fails with
+/// [`Error::UnsetEnvVar`] if one of them isn't set. Paths that aren't valid unicode are returned
+/// unchanged.
+pub fn expand_env(path: &Path) -> Result<PathBuf> {
+    let mut rest = match path.to_str() {
+        Some(rest) => rest,
+        None => return Ok(path.to_path_buf()),
+    };
+    let mut expanded = OsString::new();
+    while let Some(start) = rest.find("${") {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        let name = &rest[start + 2..end];
+        let unset = || Error::UnsetEnvVar(name.to_string(), path.to_path_buf());
+        // env::var_os may panic on names that can't be environment variables
+        if name.is_empty() || name.contains(['=', '\0']) {
+            return Err(unset());
+        }
+        expanded.push(&rest[..start]);
+        expanded.push(env::var_os(name).ok_or_else(unset)?);
+        rest = &rest[end + 1..];
+    }
+    expanded.push(rest);
+    Ok(PathBuf::from(expanded))
+}
+
+/// Check whether the paths "a" and "b" are both directories or both non-directories. Symlinks are
+/// not followed.
+pub fn same_file_type(a: &Path, b: &Path) -> io::Result<bool> {
+    Ok(a.symlink_metadata()?.is_dir() == b.symlink_metadata()?.is_dir())
+}
+
+/// Number of bytes in the file at "path", or in every file below it if it is a directory.
+/// Symlinks are not followed and entries that can't be read count as empty.
+pub fn disk_size(path: &Path) -> u64 {
+    let metadata = match path.symlink_metadata() {
+        Ok(metadata) => metadata,
+        Err(_) => return 0,
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    fs::read_dir(path)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| disk_size(&entry.path()))
+        .sum()
+}
+
+/// Check whether the paths "a" and "b" are hardlinks of the same regular file, i.e. both are
+/// regular files with the same device and inode numbers. Symlinks are not followed. This is
+/// always false on platforms other than unix.
+pub fn same_inode(a: &Path, b: &Path) -> io::Result<bool> {
+    let (a, b) = (a.symlink_metadata()?, b.symlink_metadata()?);
+    if !a.is_file() || !b.is_file() {
+        return Ok(false);
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        Ok(a.dev() == b.dev() && a.ino() == b.ino())
+    }
+
+    #[cfg(not(unix))]
+    Ok(false)
+}
+
+/// Check whether "a" and "b" are on the same device, comparing their nearest existing ancestors
+/// so that either may not exist yet. Symlinks are followed. This is always true on platforms
+/// other than unix.
+pub fn same_device(a: &Path, b: &Path) -> io::Result<bool> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let device = |path: &Path| match path.ancestors().find(|p| p.exists()) {
+            Some(existing) => Ok(existing.metadata()?.dev()),
+            None => Err(io::Error::from(io::ErrorKind::NotFound)),
+        };
+        Ok(device(a)? == device(b)?)
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = (a, b);
+        Ok(true)
+    }
+}
+
+/// SHA-256 hash of the contents of the file at "path", read in chunks rather than all at once.
+/// Symlinks are followed.
+pub fn file_hash(path: &Path) -> Result<[u8; 32]> {
+    let mut hasher = Sha256::new();
+    let _ = io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().into())
+}
+
+/// Check whether the files at "a" and "b" have the same contents. Files of different sizes
+/// differ without being read, otherwise both are read side by side in chunks until they differ.
+/// Symlinks are followed.
+pub fn files_equal(a: &Path, b: &Path) -> Result<bool> {
+    if a.metadata()?.len() != b.metadata()?.len() {
+        return Ok(false);
+    }
+    let (mut a, mut b) = (fs::File::open(a)?, fs::File::open(b)?);
+    let (mut chunk_a, mut chunk_b) = ([0; 64 * 1024], [0; 64 * 1024]);
+    loop {
+        let (read_a, read_b) = (fill(&mut a, &mut chunk_a)?, fill(&mut b, &mut chunk_b)?);
+        if chunk_a[..read_a] != chunk_b[..read_b] {
+            return Ok(false);
+        }
+        if read_a == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+/// Read from "reader" until "buf" is full or the end is reached, returning the number of bytes
+/// read.
+fn fill(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {},
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
 /// Create a symlink at "dst" pointing to "src."
 ///
 /// For Unix platforms, [`std::os::unix::fs::symlink`] is used to create
@@ -47,9 +387,286 @@ pub fn create_symlink(src: &PathBuf, dst: &PathBuf) -> io::Result<()> {
     #[cfg(target_os = "windows")]
     use std::os::windows::fs::symlink_file as symlink;
 
-    log::debug!("deploying symlink {0} pointing to {1}", dst.display(), src.display());
+    log::debug!(
+        "deploying symlink {0} pointing to {1}",
+        dst.display(),
+        src.display()
+    );
     symlink(src, dst)?;
     log::debug!("symlink deployed");
     Ok(())
+}
+
+/// Create a hardlink at "dst" of the file at "src". Both must be on the same filesystem and
+/// "src" must not be a directory.
+pub fn hardlink_file(src: &PathBuf, dst: &PathBuf) -> io::Result<()> {
+    log::debug!(
+        "deploying hardlink {0} of {1}",
+        dst.display(),
+        src.display()
+    );
+    fs::hard_link(src, dst)
+}
+
+/// Atomically replace the symlink at "dst" with one pointing to "src" by creating the new symlink
+/// next to it and renaming it over the old one.
+pub fn replace_symlink(src: &Path, dst: &Path) -> io::Result<()> {
+    let mut tmp = dst.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    create_symlink(&src.to_path_buf(), &tmp)?;
+    fs::rename(&tmp, dst).inspect_err(|_| {
+        let _ = fs::remove_file(&tmp);
+    })
+}
+
+/// Create a directory junction at "dst" pointing to the directory "src."
+///
+/// Unlike symlinks, junctions can be created on Windows without elevated privileges or Developer
+/// Mode. They only exist on Windows, can only point at directories and always use absolute
+/// targets.
+pub fn create_junction(src: &Path, dst: &Path) -> Result<()> {
+    if !src.is_dir() {
+        return Err(Error::JunctionTargetNotADirectory(src.to_path_buf()));
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        log::debug!(
+            "deploying junction {0} pointing to {1}",
+            dst.display(),
+            src.display()
+        );
+        junction::create(src, dst)?;
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        let _ = dst;
+        Err(Error::JunctionsUnsupported)
+    }
+}
+
+/// Create a symlink at "dst" pointing to "src" using a target path relative to the parent
+/// directory of "dst" rather than the absolute "src" path.
+///
+/// Relative symlinks keep working when the directory tree containing both ends of the link is
+/// moved or mounted somewhere else as a whole.
+pub fn create_symlink_relative(src: &Path, dst: &Path) -> io::Result<()> {
+    let dst_dir = dst.parent().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("cannot determine parent directory of {0}", dst.display()),
+        )
+    })?;
+    create_symlink(&relative_path(dst_dir, src), &dst.to_path_buf())
+}
+
+/// Compute a path that leads from directory "from" to "to". Both paths are expected to be
+/// absolute; if they share no common prefix then "to" is returned unchanged.
+pub fn relative_path(from: &Path, to: &Path) -> PathBuf {
+    let from: Vec<Component> = from.components().collect();
+    let to: Vec<Component> = to.components().collect();
+
+    let common = from
+        .iter()
+        .zip(to.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    if common == 0 {
+        return to.iter().collect();
+    }
+
+    let mut result = PathBuf::new();
+    for _ in common..from.len() {
+        result.push(Component::ParentDir);
+    }
+    for component in &to[common..] {
+        result.push(component);
+    }
+    result
+}
+
+/// Read the target of the symlink at "path" and, if the target is relative, resolve it against the
+/// directory containing the symlink so that the result can be compared with absolute paths. On
+/// Windows the target of a junction is returned as well.
+pub fn resolve_link(path: &Path) -> io::Result<PathBuf> {
+    #[cfg(target_os = "windows")]
+    if junction::exists(path)? {
+        return junction::get_target(path);
+    }
+
+    let target = fs::read_link(path)?;
+    if target.is_absolute() {
+        return Ok(target);
+    }
+    Ok(path
+        .parent()
+        .map(|parent| normalize(&parent.join(&target)))
+        .unwrap_or(target))
+}
+
+/// Spell `path` relative to `home` using `~` if it is inside it.
+pub fn tilde_path(path: &Path, home: Option<&Path>) -> String {
+    match home.and_then(|home| path.strip_prefix(home).ok()) {
+        Some(relative) if relative.as_os_str().is_empty() => "~".to_string(),
+        Some(relative) => format!("~/{0}", relative.display()),
+        None => path.display().to_string(),
+    }
+}
+
+/// The canonical spelling of the directory "path", used to tell whether differently spelled
+/// directories are the same. Paths that can't be canonicalized, e.g. because they don't exist,
+/// are lexically normalized instead.
+pub fn resolve_dir(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| normalize(path))
+}
+
+/// Strip trailing separators from "path", e.g. turn `/root/` into `/root`. The root directory
+/// itself is left alone.
+pub fn trim_trailing_separators(path: &Path) -> PathBuf {
+    path.components().collect()
+}
+
+/// Lexically normalize a path by dropping `.` components and folding `..` components into their
+/// preceding component. The filesystem is not consulted, so symlinks are not resolved.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => match result.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    let _ = result.pop();
+                },
+                // `..` at the root is the root itself
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => (),
+                _ => result.push(component),
+            },
+            _ => result.push(component),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moved_relative_symlinks_keep_their_target() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (a, b) = (tmp.path().join("a"), tmp.path().join("b/c"));
+        fs::create_dir_all(&a).unwrap();
+        fs::create_dir_all(&b).unwrap();
+        fs::write(tmp.path().join("target"), "contents").unwrap();
+        let (src, dst) = (a.join("link"), b.join("link"));
+        std::os::unix::fs::symlink("../target", &src).unwrap();
 
+        move_symlink(&src, &dst).unwrap();
+        assert!(src.symlink_metadata().is_err());
+        assert_eq!(fs::read_link(&dst).unwrap(), Path::new("../../target"));
+        assert_eq!(fs::read_to_string(&dst).unwrap(), "contents");
+    }
+
+    #[test]
+    fn git_directories_are_skipped_unless_dotdirs_are_included() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::create_dir_all(tmp.path().join(".git/objects")).unwrap();
+        fs::create_dir_all(tmp.path().join("bin")).unwrap();
+        for path in [".git/config", ".git/objects/ab", "bin/tool", ".vimrc"] {
+            fs::write(tmp.path().join(path), path).unwrap();
+        }
+        let pattern = format!("{0}/**/*", tmp.path().display());
+        let expand = |traversal: Traversal| {
+            let mut expanded: Vec<PathBuf> = glob::glob(&pattern)
+                .unwrap()
+                .filter_map(|path| path.ok())
+                .filter(|path| traversal.expands(&pattern, path))
+                .map(|path| path.strip_prefix(tmp.path()).unwrap().to_path_buf())
+                .collect();
+            expanded.sort();
+            expanded
+        };
+
+        assert_eq!(
+            expand(Traversal::new()),
+            [PathBuf::from("bin"), PathBuf::from("bin/tool")]
+        );
+        let with_dotdirs = expand(Traversal::new().include_dotdirs(true));
+        assert!(with_dotdirs.contains(&PathBuf::from(".git/config")));
+        assert!(with_dotdirs.contains(&PathBuf::from(".git/objects/ab")));
+        assert!(!with_dotdirs.contains(&PathBuf::from(".vimrc")));
+        let with_both = expand(
+            Traversal::new()
+                .include_dotdirs(true)
+                .include_dotfiles(true),
+        );
+        assert!(with_both.contains(&PathBuf::from(".vimrc")));
+
+        let git = tmp.path().join(".git");
+        assert!(!Traversal::new().visits(&git));
+        assert!(Traversal::new().include_dotdirs(true).visits(&git));
+        assert!(Traversal::new().visits(&tmp.path().join("bin")));
+    }
+
+    #[test]
+    fn moved_absolute_symlinks_are_unchanged() {
+        let tmp = tempfile::tempdir().unwrap();
+        let target = tmp.path().join("target");
+        let (src, dst) = (tmp.path().join("link"), tmp.path().join("moved"));
+        std::os::unix::fs::symlink(&target, &src).unwrap();
+
+        move_symlink(&src, &dst).unwrap();
+        assert_eq!(fs::read_link(&dst).unwrap(), target);
+    }
+
+    #[test]
+    fn file_contents_are_compared_and_hashed() {
+        let tmp = tempfile::tempdir().unwrap();
+        let file = |name: &str, contents: &[u8]| {
+            let path = tmp.path().join(name);
+            fs::write(&path, contents).unwrap();
+            path
+        };
+        let abc = file("abc", b"abc");
+        assert_eq!(
+            file_hash(&abc).unwrap()[..4],
+            [0xba, 0x78, 0x16, 0xbf],
+            "SHA-256 of abc"
+        );
+
+        assert!(files_equal(&abc, &file("same", b"abc")).unwrap());
+        assert!(!files_equal(&abc, &file("longer", b"abcd")).unwrap());
+        assert!(!files_equal(&abc, &file("other", b"abd")).unwrap());
+        assert!(files_equal(&file("empty", b""), &file("also_empty", b"")).unwrap());
+
+        // differing only past the first chunk
+        let mut big = vec![b'x'; 64 * 1024 + 10];
+        let a = file("big_a", &big);
+        big[64 * 1024 + 5] = b'y';
+        let b = file("big_b", &big);
+        assert!(!files_equal(&a, &b).unwrap());
+        assert_ne!(file_hash(&a).unwrap(), file_hash(&b).unwrap());
+        assert!(files_equal(&a, &file("big_c", &vec![b'x'; 64 * 1024 + 10])).unwrap());
+        assert!(files_equal(&a, &tmp.path().join("missing")).is_err());
+    }
+
+    #[test]
+    fn only_empty_parents_below_the_stop_directory_are_removed() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().join("root");
+        fs::create_dir_all(root.join("a/b/c")).unwrap();
+        fs::write(root.join("a/file"), "").unwrap();
+
+        let removed = remove_empty_parents(&root.join("a/b/c/gone"), &root).unwrap();
+        assert_eq!(removed, [root.join("a/b/c"), root.join("a/b")]);
+        assert!(root.join("a/file").is_file());
+
+        fs::remove_file(root.join("a/file")).unwrap();
+        let removed = remove_empty_parents(&root.join("a/file"), &root).unwrap();
+        assert_eq!(removed, [root.join("a")]);
+        assert!(root.is_dir());
+    }
 }
@@ -0,0 +1,323 @@
+//! Previews of the operations ghmd would carry out for given paths.
+use std::env;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::config::{Config, DeployOutcome, DotfilePath, Dotfiles, DotfilesDir};
+use crate::config::{StowAction, SymlinkDir};
+use crate::errors::{Error, Result};
+use crate::options::DeployOptions;
+use crate::paths;
+use crate::status::SymlinkState;
+
+/// PlannedOperation is the operation that applies to a path given to [`Config::list_operations`].
+#[derive(Debug)]
+pub enum PlannedOperation {
+    /// The path is an untracked file in a symlink directory that can be stowed.
+    WouldStow,
+
+    /// The path is a tracked dotfile whose symlink would be created by deploying it.
+    WouldDeploy,
+
+    /// The path is a deployed symlink whose dotfile would be moved back by restoring it.
+    WouldRestore,
+
+    /// Nothing needs to or can be done for the path.
+    WouldSkip(SkipReason),
+
+    /// The applicable operation would fail.
+    WouldFail(Error),
+}
+
+/// SkipReason describes why no operation applies to a path.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SkipReason {
+    /// The path is a tracked dotfile whose symlink is already in place.
+    AlreadyDeployed,
+
+    /// The path is inside a dotfiles directory but not tracked.
+    NotTracked,
+
+    /// The path is neither inside a dotfiles directory nor inside a symlink directory.
+    OutsideConfiguredDirectories,
+}
+
+/// StowPlan lists what [`Config::dry_run_stow`] found stowing a set of paths would do.
+#[derive(Debug, Default)]
+pub struct StowPlan {
+    /// Files that would be moved from the symlink directory into the store, as pairs of source
+    /// and destination.
+    pub moves: Vec<(PathBuf, PathBuf)>,
+
+    /// Symlinks that would replace the moved files, as pairs of the symlink path and the stored
+    /// dotfile it would point at.
+    pub symlinks_to_create: Vec<(PathBuf, PathBuf)>,
+
+    /// Paths that already are symlinks to their stored dotfile and would only be tracked.
+    pub already_done: Vec<DotfilePath>,
+
+    /// Paths that would fail to stow.
+    pub errors: Vec<(DotfilePath, Error)>,
+}
+
+/// DeployReport lists what [`Config::deploy_dry_run`] found deploying a set of dotfiles would do.
+#[derive(Debug, Default)]
+pub struct DeployReport {
+    /// Symlinks that would be created, as pairs of the symlink path and the stored dotfile it
+    /// would point at.
+    pub would_create: Vec<(PathBuf, PathBuf)>,
+
+    /// Dotfiles whose symlinks are all in place already.
+    pub already_correct: Vec<DotfilePath>,
+
+    /// Symlink paths that deploying would fail at, with what is wrong there.
+    pub conflicts: Vec<(PathBuf, ConflictKind)>,
+
+    /// Directories that would be created to hold the symlinks, outermost first.
+    pub dirs_to_create: Vec<PathBuf>,
+}
+
+/// ConflictKind is what keeps a symlink listed in [`DeployReport::conflicts`] from being
+/// created.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ConflictKind {
+    /// Something other than a symlink is at the symlink path.
+    NotASymlink,
+
+    /// A symlink pointing at the given path rather than at the dotfile is at the symlink path.
+    PointsToDifferentTarget(PathBuf),
+
+    /// The stored dotfile doesn't exist or is a symlink whose target doesn't exist.
+    TargetMissing,
+}
+
+impl fmt::Display for PlannedOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WouldStow => f.pad("stow"),
+            Self::WouldDeploy => f.pad("deploy"),
+            Self::WouldRestore => f.pad("restore"),
+            Self::WouldSkip(reason) => f.pad(&format!("skip ({0})", reason)),
+            Self::WouldFail(e) => f.pad(&format!("fail ({0})", e)),
+        }
+    }
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::AlreadyDeployed => "already deployed",
+            Self::NotTracked => "not tracked",
+            Self::OutsideConfiguredDirectories => "outside configured directories",
+        };
+        f.pad(s)
+    }
+}
+
+impl Config {
+    /// Determine the operation that applies to each of `paths` without modifying anything,
+    /// returning one planned operation per path. Relative paths are interpreted relative to the
+    /// current directory.
+    ///
+    /// A path inside a dotfiles directory refers to a stored dotfile, which would be deployed if
+    /// it is tracked. A path inside a symlink directory would be restored if it is the deployed
+    /// symlink of a tracked dotfile, deployed if it is the missing symlink of one and stowed
+    /// otherwise. Dotfiles directories take precedence since they are often located inside a
+    /// symlink directory such as `$HOME`.
+    pub fn list_operations(&self, paths: &[PathBuf]) -> Result<Vec<PlannedOperation>> {
+        let cwd = env::current_dir()?;
+        paths
+            .iter()
+            .map(|path| self.plan_path(&paths::normalize(&cwd.join(path))))
+            .collect()
+    }
+
+    /// Determine what stowing `paths` from `symlink_dir` into `dotfile_dir` would do, making the
+    /// same checks as stowing them without moving any file or creating any symlink. Failing
+    /// checks are collected in [`StowPlan::errors`].
+    pub fn dry_run_stow(
+        &self,
+        symlink_dir: &SymlinkDir,
+        dotfile_dir: &DotfilesDir,
+        paths: &[DotfilePath],
+    ) -> Result<StowPlan> {
+        let scratch;
+        let dotfiles = match self.dotfiles_index(symlink_dir, dotfile_dir) {
+            Some(index) => &self.dotfiles[index],
+            None => {
+                scratch = self.new_dotfiles(symlink_dir, dotfile_dir)?;
+                &scratch
+            },
+        };
+
+        let mut plan = StowPlan::default();
+        for path in paths {
+            let action = self
+                .check_owner(symlink_dir, dotfile_dir, path)
+                .and_then(|_| dotfiles.check_stow(path));
+            match action {
+                Ok(StowAction::Adopt) => plan.already_done.push(path.clone()),
+                Ok(StowAction::Move) => {
+                    let symlink_path = dotfiles.symlink_path(path);
+                    let store_path = dotfiles.store_path(path);
+                    plan.moves.push((symlink_path.clone(), store_path));
+                    plan.symlinks_to_create
+                        .push((symlink_path, dotfiles.target_for(path)));
+                },
+                Err(e) => plan.errors.push((path.clone(), e)),
+            }
+        }
+        Ok(plan)
+    }
+
+    /// Determine what deploying the dotfiles at `paths`, resolved like with
+    /// [`Config::deploy_paths`], or all dotfiles like [`Config::deploy_all`] would do, making the
+    /// same checks as deploying them without creating any symlink or directory. Unlike
+    /// [`Config::deploy_all`] nothing in the way is backed up, so whatever is in the way of a
+    /// symlink is reported in [`DeployReport::conflicts`]. Dotfiles on the skip list of this
+    /// machine are left out unless given in `paths`.
+    pub fn deploy_dry_run(&self, paths: Option<Vec<PathBuf>>) -> Result<DeployReport> {
+        let selected = match paths {
+            Some(paths) => self.resolve_tracked(&paths)?,
+            None => self
+                .iter_included_entries()?
+                .filter(|resolved| !self.is_skipped(resolved.relative))
+                .map(|resolved| {
+                    let dotfiles = &self.dotfiles[resolved.entry_index];
+                    (dotfiles, resolved.relative.clone())
+                })
+                .collect(),
+        };
+
+        let options = DeployOptions::new().dry_run(true);
+        let mut report = DeployReport::default();
+        for (dotfiles, path) in selected {
+            let mut correct = true;
+            let symlinks = std::iter::once(dotfiles.symlink_path(&path))
+                .chain(dotfiles.alias_paths(&path));
+            for symlink in symlinks {
+                let kind = match dotfiles.deploy_to(&path, &symlink, &options) {
+                    Ok(DeployOutcome::Unchanged | DeployOutcome::Skipped) => continue,
+                    Ok(DeployOutcome::Created { created_dirs }) => {
+                        report
+                            .would_create
+                            .push((symlink, dotfiles.store_path(&path)));
+                        for dir in created_dirs {
+                            if !report.dirs_to_create.contains(&dir) {
+                                report.dirs_to_create.push(dir);
+                            }
+                        }
+                        correct = false;
+                        continue;
+                    },
+                    Err(Error::SymlinkPathIsNotASymlink(_)) => ConflictKind::NotASymlink,
+                    Err(Error::StorePathTypeMismatch(_)) => ConflictKind::NotASymlink,
+                    Err(Error::SymlinkPathAlreadyExists(_)) => {
+                        let target = paths::resolve_link(&symlink)?;
+                        ConflictKind::PointsToDifferentTarget(target)
+                    },
+                    Err(Error::DotfileNotFound(_) | Error::StoreEntryBroken(_)) => {
+                        ConflictKind::TargetMissing
+                    },
+                    Err(e) => return Err(e),
+                };
+                report.conflicts.push((symlink, kind));
+                correct = false;
+            }
+            if correct {
+                report.already_correct.push(path);
+            }
+        }
+        Ok(report)
+    }
+
+    /// Resolve each of `paths` to the first dotfiles entry tracking it, see
+    /// [`Config::resolve_candidates`].
+    fn resolve_tracked(
+        &self,
+        paths: &[PathBuf],
+    ) -> Result<Vec<(&Dotfiles, DotfilePath)>> {
+        paths
+            .iter()
+            .map(|path| {
+                self.resolve_candidates(path)
+                    .into_iter()
+                    .find(|(dotfiles, relative)| dotfiles.paths.contains(relative))
+                    .ok_or_else(|| Error::NoMatchingDotfileConfigured(path.clone()))
+            })
+            .collect()
+    }
+
+    fn plan_path(&self, path: &Path) -> Result<PlannedOperation> {
+        let mut in_store = false;
+        for dotfiles in &self.dotfiles {
+            if let Some(relative) = dotfiles.strip_dotfile_dir(path) {
+                if dotfiles.paths.contains(&relative) {
+                    return plan_deploy(self, dotfiles, &relative);
+                }
+                in_store = true;
+            }
+        }
+        if in_store {
+            return Ok(PlannedOperation::WouldSkip(SkipReason::NotTracked));
+        }
+
+        let mut in_symlink_dir = false;
+        for dotfiles in &self.dotfiles {
+            if let Some(relative) = dotfiles.strip_symlink_dir(path) {
+                if dotfiles.paths.contains(&relative) {
+                    return match dotfiles.verify_symlink(&relative)? {
+                        SymlinkState::Deployed | SymlinkState::CopyTracked => {
+                            Ok(PlannedOperation::WouldRestore)
+                        },
+                        _ => plan_deploy(self, dotfiles, &relative),
+                    };
+                }
+                in_symlink_dir = true;
+            }
+        }
+        if !in_symlink_dir {
+            return Ok(PlannedOperation::WouldSkip(
+                SkipReason::OutsideConfiguredDirectories,
+            ));
+        }
+
+        Ok(match path.symlink_metadata() {
+            Err(_) => {
+                PlannedOperation::WouldFail(Error::PathDoesNotExist(path.to_path_buf()))
+            },
+            Ok(metadata) if metadata.is_symlink() => PlannedOperation::WouldFail(
+                Error::SymlinkPathAlreadyExists(path.to_path_buf()),
+            ),
+            Ok(_) => PlannedOperation::WouldStow,
+        })
+    }
+}
+
+/// Determine what deploying the tracked dotfile `relative` of `dotfiles` would do.
+fn plan_deploy(
+    config: &Config,
+    dotfiles: &Dotfiles,
+    relative: &DotfilePath,
+) -> Result<PlannedOperation> {
+    let store_path = dotfiles.store_path(relative);
+    let symlink_path = dotfiles.symlink_path(relative);
+    Ok(match config.verify_symlink(dotfiles, relative)? {
+        // explicitly given paths are deployed even if they are skipped on this machine
+        SymlinkState::NotDeployed | SymlinkState::Skipped => {
+            PlannedOperation::WouldDeploy
+        },
+        SymlinkState::Deployed | SymlinkState::CopyTracked => {
+            PlannedOperation::WouldSkip(SkipReason::AlreadyDeployed)
+        },
+        SymlinkState::DotfileMissing => {
+            PlannedOperation::WouldFail(Error::DotfileNotFound(store_path))
+        },
+        SymlinkState::NotASymlink => {
+            PlannedOperation::WouldFail(Error::SymlinkPathIsNotASymlink(symlink_path))
+        },
+        SymlinkState::WrongTarget => PlannedOperation::WouldFail(
+            Error::SymlinkPathDoesNotMatchDotfilePath(symlink_path, store_path),
+        ),
+    })
+}
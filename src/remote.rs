@@ -0,0 +1,293 @@
+//! Comparison of the tracked dotfiles with a reference state recorded elsewhere, such as a commit
+//! of a git-backed dotfiles directory or a manifest published for a fleet of machines.
+//!
+//! Obtaining the reference is left to a [`GitRunner`] or, for manifests that aren't local files,
+//! to `curl`, and failures to do so are reported as [`Error::GitFailed`] and
+//! [`Error::ManifestFetchFailed`] so that they can be told apart from actual differences.
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::config::{Config, Dotfiles};
+use crate::errors::{Error, Result};
+use crate::paths::{self, Traversal};
+
+/// Manifest maps the paths of the files of tracked dotfiles, relative to their dotfiles
+/// directory, to the hex-encoded SHA-256 hashes of their contents. Symlinks inside tracked
+/// directories are hashed by their target path, the way git stores them.
+pub type Manifest = BTreeMap<PathBuf, String>;
+
+/// ManifestFile is the TOML representation of a [`Manifest`], a `[files]` table mapping paths to
+/// hashes.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq, Clone)]
+pub struct ManifestFile {
+    /// Hashes of the files by path.
+    pub files: Manifest,
+}
+
+/// GitRunner runs `git` commands.
+pub trait GitRunner {
+    /// Run `git` with the given arguments in `dir`, returning its standard output.
+    fn git(&self, dir: &Path, args: &[&str]) -> Result<Vec<u8>>;
+}
+
+/// Git is the [`GitRunner`] that spawns the `git` binary.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Git;
+
+impl GitRunner for Git {
+    fn git(&self, dir: &Path, args: &[&str]) -> Result<Vec<u8>> {
+        log::debug!("running git {0} in {1}", args.join(" "), dir.display());
+        let failed = |reason: String| Error::GitFailed(args.join(" "), reason);
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .output()
+            .map_err(|e| failed(e.to_string()))?;
+        if output.status.success() {
+            return Ok(output.stdout);
+        }
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        let reason = if stderr.is_empty() {
+            output.status.to_string()
+        } else {
+            stderr
+        };
+        Err(failed(reason))
+    }
+}
+
+/// Reference is the state tracked dotfiles are compared with.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Reference {
+    /// The tracked dotfiles as of a git revision of each dotfiles directory, e.g. `origin/main`.
+    Rev(String),
+
+    /// A [`ManifestFile`] downloaded from a URL. `file://` URLs are read like
+    /// [`Reference::File`].
+    Url(String),
+
+    /// A [`ManifestFile`] read from a local file, e.g. one downloaded by other means.
+    File(PathBuf),
+}
+
+impl Reference {
+    /// Obtain the manifest of the reference for the dotfiles tracked by `config`. For a git
+    /// revision only the tracked paths are looked up in each dotfiles directory.
+    pub fn fetch(&self, config: &Config, git: &dyn GitRunner) -> Result<Manifest> {
+        match self {
+            Self::Rev(rev) => {
+                let mut manifest = Manifest::new();
+                for dotfiles in &config.dotfiles {
+                    manifest.extend(rev_manifest(dotfiles, rev, git)?);
+                }
+                Ok(manifest)
+            },
+            Self::Url(url) => match url.strip_prefix("file://") {
+                Some(path) => read_manifest(Path::new(path), url),
+                None => fetch_manifest(url),
+            },
+            Self::File(path) => read_manifest(path, &path.display().to_string()),
+        }
+    }
+}
+
+/// RemoteDiff lists how the tracked dotfiles differ from a reference [`Manifest`].
+#[derive(Serialize, Debug, Default, PartialEq, Eq, Clone)]
+pub struct RemoteDiff {
+    /// Files of tracked dotfiles that the reference doesn't contain.
+    pub added: Vec<PathBuf>,
+
+    /// Files the reference contains that aren't part of any tracked dotfile here.
+    pub removed: Vec<PathBuf>,
+
+    /// Files whose contents differ from the reference.
+    pub drifted: Vec<PathBuf>,
+}
+
+impl RemoteDiff {
+    /// Compare the manifest of the local dotfiles with the `reference` manifest.
+    pub fn between(local: &Manifest, reference: &Manifest) -> Self {
+        let mut diff = Self::default();
+        for (path, hash) in local {
+            match reference.get(path) {
+                None => diff.added.push(path.clone()),
+                Some(expected) if expected != hash => diff.drifted.push(path.clone()),
+                Some(_) => {},
+            }
+        }
+        diff.removed = reference
+            .keys()
+            .filter(|path| !local.contains_key(*path))
+            .cloned()
+            .collect();
+        diff
+    }
+
+    /// Whether the local dotfiles match the reference.
+    pub fn is_clean(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.drifted.is_empty()
+    }
+}
+
+impl Config {
+    /// Hash the files of every tracked dotfile as they currently are in the dotfiles directories.
+    /// Dotfiles missing from their dotfiles directory are left out.
+    pub fn manifest(&self) -> Result<Manifest> {
+        let mut manifest = Manifest::new();
+        for dotfiles in &self.dotfiles {
+            for resolved in dotfiles.iter_paths() {
+                if resolved.store.symlink_metadata().is_ok() {
+                    hash_tree(&resolved.store, resolved.relative, &mut manifest)?;
+                }
+            }
+        }
+        Ok(manifest)
+    }
+
+    /// Compare the tracked dotfiles with the `reference` manifest.
+    pub fn diff_manifest(&self, reference: &Manifest) -> Result<RemoteDiff> {
+        Ok(RemoteDiff::between(&self.manifest()?, reference))
+    }
+}
+
+/// Hash the file at `path`, or every file below it if it is a directory, recording the hashes
+/// under `relative` in `manifest`. `.git` directories are skipped like in every walk with the
+/// default [`Traversal`] and files are read in chunks.
+pub(crate) fn hash_tree(
+    path: &Path,
+    relative: &Path,
+    manifest: &mut Manifest,
+) -> Result<()> {
+    let metadata = path.symlink_metadata()?;
+    if metadata.is_dir() {
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            if !Traversal::new().visits(&entry.path()) {
+                continue;
+            }
+            hash_tree(&entry.path(), &relative.join(entry.file_name()), manifest)?;
+        }
+        return Ok(());
+    }
+    let hash = match metadata.is_symlink() {
+        true => hash(&fs::read_link(path)?.into_os_string().into_encoded_bytes()),
+        false => hex(&paths::file_hash(path)?),
+    };
+    let _ = manifest.insert(relative.to_path_buf(), hash);
+    Ok(())
+}
+
+/// Hex-encoded SHA-256 hash of `contents`.
+pub(crate) fn hash(contents: &[u8]) -> String {
+    hex(&Sha256::digest(contents).into())
+}
+
+fn hex(hash: &[u8; 32]) -> String {
+    hash.iter().map(|byte| format!("{0:02x}", byte)).collect()
+}
+
+/// Hash the files of the tracked dotfiles of `dotfiles` as of `rev`.
+fn rev_manifest(dotfiles: &Dotfiles, rev: &str, git: &dyn GitRunner) -> Result<Manifest> {
+    let mut manifest = Manifest::new();
+    let paths: Vec<String> = dotfiles
+        .paths
+        .iter()
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect();
+    if paths.is_empty() {
+        return Ok(manifest);
+    }
+
+    let dir = &*dotfiles.dotfile_directory;
+    let mut args = vec!["ls-tree", "-r", "-z", rev, "--"];
+    args.extend(paths.iter().map(String::as_str));
+    let listing = git.git(dir, &args)?;
+    for entry in listing.split(|b| *b == 0).filter(|entry| !entry.is_empty()) {
+        // each entry is "<mode> <type> <object>\t<path>"
+        let entry = String::from_utf8_lossy(entry);
+        let (info, path) = entry.split_once('\t').ok_or_else(|| {
+            Error::GitFailed(args.join(" "), "unexpected output".to_string())
+        })?;
+        let mut info = info.split(' ');
+        let (kind, object) = (info.nth(1), info.next());
+        if let (Some("blob"), Some(object)) = (kind, object) {
+            let contents = git.git(dir, &["cat-file", "blob", object])?;
+            let _ = manifest.insert(PathBuf::from(path), hash(&contents));
+        }
+    }
+    Ok(manifest)
+}
+
+/// Download and parse the [`ManifestFile`] at `url` using `curl`.
+fn fetch_manifest(url: &str) -> Result<Manifest> {
+    let failed = |reason: String| Error::ManifestFetchFailed(url.to_string(), reason);
+    log::debug!("fetching manifest from {0}", url);
+    let output = Command::new("curl")
+        .args(["--fail", "--silent", "--show-error", "--location", url])
+        .output()
+        .map_err(|e| failed(format!("could not run curl: {0}", e)))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        let reason = if stderr.is_empty() {
+            output.status.to_string()
+        } else {
+            stderr
+        };
+        return Err(failed(reason));
+    }
+    let contents = String::from_utf8(output.stdout).map_err(|e| failed(e.to_string()))?;
+    parse_manifest(&contents, url)
+}
+
+/// Read and parse the [`ManifestFile`] at `path`, naming it `source` in errors.
+fn read_manifest(path: &Path, source: &str) -> Result<Manifest> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| Error::ManifestFetchFailed(source.to_string(), e.to_string()))?;
+    parse_manifest(&contents, source)
+}
+
+fn parse_manifest(contents: &str, source: &str) -> Result<Manifest> {
+    let file: ManifestFile = toml::from_str(contents).map_err(|e| {
+        Error::ManifestFetchFailed(
+            source.to_string(),
+            format!("invalid manifest: {0}", e),
+        )
+    })?;
+    Ok(file.files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_manifests_are_read_without_curl() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("manifest.toml");
+        fs::write(&path, "[files]\n'.vimrc' = 'abc'\n").unwrap();
+        let config = Config::in_memory(tmp.path().join("backup"));
+        let expected = Manifest::from([(PathBuf::from(".vimrc"), "abc".to_string())]);
+
+        let file = Reference::File(path.clone());
+        assert_eq!(file.fetch(&config, &Git).unwrap(), expected);
+        let url = Reference::Url(format!("file://{0}", path.display()));
+        assert_eq!(url.fetch(&config, &Git).unwrap(), expected);
+
+        fs::write(&path, "not a manifest").unwrap();
+        assert!(matches!(
+            file.fetch(&config, &Git),
+            Err(Error::ManifestFetchFailed(_, _))
+        ));
+        let missing = Reference::File(tmp.path().join("missing.toml"));
+        assert!(matches!(
+            missing.fetch(&config, &Git),
+            Err(Error::ManifestFetchFailed(_, _))
+        ));
+    }
+}
@@ -0,0 +1,270 @@
+//! Selection of tracked dotfiles shared by the commands operating on several dotfiles at once.
+use std::time::{Duration, SystemTime};
+
+use glob::{MatchOptions, Pattern};
+
+use crate::config::{Config, DotfilePath, Dotfiles, DotfilesDir, ResolvedPath};
+use crate::errors::{Error, Result};
+
+/// Selector describes a subset of the tracked dotfiles of a config. A dotfile is selected if its
+/// relative path matches any of `paths`, it is stored in any of `dirs`, its entry belongs to any
+/// of `groups` and applies to any of `hosts`, where an empty list doesn't restrict the selection.
+/// With `invert` every dotfile not matched this way is selected instead. Dotfiles of disabled
+/// entries are only selected if their directory is in `dirs`.
+#[derive(Debug, Clone, Default)]
+pub struct Selector {
+    /// Glob patterns matched against the relative paths of tracked dotfiles.
+    pub paths: Vec<Pattern>,
+
+    /// Dotfiles directories the selected dotfiles are stored in.
+    pub dirs: Vec<DotfilesDir>,
+
+    /// Profiles the entries of the selected dotfiles belong to, see [`Dotfiles::in_profile`].
+    pub groups: Vec<String>,
+
+    /// Hosts the entries of the selected dotfiles apply to, see [`Dotfiles::applies_to_host`].
+    pub hosts: Vec<String>,
+
+    /// Select the dotfiles not matched by the other criteria.
+    pub invert: bool,
+
+    /// Only let `**` match across directories in `paths`, while `*` and `?` match within a
+    /// single path component.
+    pub literal_separator: bool,
+
+    /// Range of modification times of the stored dotfiles. Unlike the other criteria it only
+    /// narrows down a selection, so a selector with nothing but `modified` is still empty.
+    pub modified: MtimeFilter,
+}
+
+/// MtimeFilter is a range of modification times of stored dotfiles, see
+/// [`Config::filter_by_mtime`]. An empty range matches every time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MtimeFilter {
+    /// Only match dotfiles modified before this time.
+    pub older_than: Option<SystemTime>,
+
+    /// Only match dotfiles modified after this time.
+    pub newer_than: Option<SystemTime>,
+}
+
+impl MtimeFilter {
+    /// Whether the filter matches every modification time.
+    pub fn is_empty(&self) -> bool {
+        self.older_than.is_none() && self.newer_than.is_none()
+    }
+
+    /// Whether `modified` is within the range.
+    pub fn matches(&self, modified: SystemTime) -> bool {
+        self.older_than.is_none_or(|time| modified < time)
+            && self.newer_than.is_none_or(|time| modified > time)
+    }
+}
+
+/// Parse an age such as `90s`, `30m`, `12h`, `7d` or `2w`.
+pub fn parse_age(age: &str) -> Result<Duration> {
+    let invalid = || Error::InvalidAge(age.to_string());
+    let split = age
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(invalid)?;
+    let (count, unit) = age.split_at(split);
+    let count: u64 = count.parse().map_err(|_| invalid())?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    count
+        .checked_mul(seconds)
+        .map(Duration::from_secs)
+        .ok_or_else(invalid)
+}
+
+impl Selector {
+    /// Whether the selector selects every tracked dotfile because it has no criteria.
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+            && self.dirs.is_empty()
+            && self.groups.is_empty()
+            && self.hosts.is_empty()
+            && !self.invert
+    }
+
+    /// Whether the dotfile at `path` tracked by `dotfiles` is selected.
+    pub fn matches(&self, dotfiles: &Dotfiles, path: &DotfilePath) -> bool {
+        self.considers(dotfiles) && self.matches_criteria(dotfiles, path) != self.invert
+    }
+
+    /// Whether the dotfiles of `dotfiles` can be selected at all, i.e. it is enabled or named
+    /// explicitly.
+    fn considers(&self, dotfiles: &Dotfiles) -> bool {
+        dotfiles.enabled() || self.names(dotfiles)
+    }
+
+    fn names(&self, dotfiles: &Dotfiles) -> bool {
+        self.dirs.iter().any(|dir| dotfiles.is_stored_in(dir))
+    }
+
+    fn matches_pattern(&self, pattern: &Pattern, path: &DotfilePath) -> bool {
+        let options = MatchOptions {
+            require_literal_separator: self.literal_separator,
+            ..MatchOptions::new()
+        };
+        pattern.matches_path_with(path, options)
+    }
+
+    fn matches_criteria(&self, dotfiles: &Dotfiles, path: &DotfilePath) -> bool {
+        let path_matches = self.paths.is_empty()
+            || self.paths.iter().any(|p| self.matches_pattern(p, path));
+        path_matches && self.matches_entry(dotfiles)
+    }
+
+    /// Whether dotfiles entries are picked explicitly, by `dirs`, `groups` or `hosts`.
+    pub fn picks_entries(&self) -> bool {
+        !self.dirs.is_empty() || !self.groups.is_empty() || !self.hosts.is_empty()
+    }
+
+    /// Whether `dotfiles` is in any of `dirs`, `groups` and `hosts`, or the lists are empty.
+    fn matches_entry(&self, dotfiles: &Dotfiles) -> bool {
+        (self.dirs.is_empty() || self.names(dotfiles))
+            && (self.groups.is_empty()
+                || self.groups.iter().any(|g| dotfiles.in_profile(g)))
+            && (self.hosts.is_empty()
+                || self.hosts.iter().any(|h| dotfiles.applies_to_host(h)))
+    }
+
+    /// Resolve the selected dotfiles of `config`.
+    ///
+    /// Every criterion has to contribute: a directory that isn't configured, a group no entry
+    /// names in its `profiles`, or a pattern that doesn't match any tracked dotfile of the
+    /// selected entries is an error, as is a non-empty selector that selects nothing, e.g.
+    /// because of `invert`. An empty selector selects every tracked dotfile of the enabled
+    /// entries. Either way only dotfiles within the `modified` range are selected. Use
+    /// [`Selector::select`] for a selection that may be empty.
+    pub fn evaluate<'a>(&self, config: &'a Config) -> Result<Vec<ResolvedPath<'a>>> {
+        self.check_dirs(config)?;
+        for group in &self.groups {
+            let named = config.dotfiles.iter().any(|d| d.profiles().contains(group));
+            if !named {
+                return Err(Error::GroupNotConfigured(group.clone()));
+            }
+        }
+
+        let in_entries = |resolved: &ResolvedPath| {
+            let dotfiles = &config.dotfiles[resolved.entry_index];
+            self.considers(dotfiles) && self.matches_entry(dotfiles)
+        };
+        for pattern in &self.paths {
+            let found = config
+                .iter_entries()
+                .filter(in_entries)
+                .any(|resolved| self.matches_pattern(pattern, resolved.relative));
+            if !found {
+                return Err(Error::PatternMatchesNothing(pattern.as_str().to_string()));
+            }
+        }
+
+        let selected = self.select(config)?;
+        if selected.is_empty() && !self.is_empty() {
+            return Err(Error::NothingSelected);
+        }
+        Ok(selected)
+    }
+
+    /// Resolve the selected dotfiles of `config` like [`Selector::evaluate`], except that
+    /// criteria matching nothing simply leave the selection empty, as listing dotfiles does.
+    /// Only a directory that isn't configured is an error.
+    pub fn select<'a>(&self, config: &'a Config) -> Result<Vec<ResolvedPath<'a>>> {
+        self.check_dirs(config)?;
+        let mut selected: Vec<ResolvedPath> = config
+            .iter_entries()
+            .filter(|resolved| {
+                self.matches(&config.dotfiles[resolved.entry_index], resolved.relative)
+            })
+            .collect();
+        if !self.modified.is_empty() {
+            let modified = self.modified;
+            selected = config.filter_by_mtime(selected, |time| modified.matches(time))?;
+        }
+        Ok(selected)
+    }
+
+    fn check_dirs(&self, config: &Config) -> Result<()> {
+        for dir in &self.dirs {
+            if config.get_dotfiles(dir).is_none() {
+                return Err(Error::DotfilesDirNotConfigured(dir.to_path_buf()));
+            }
+            let mut named = config.dotfiles.iter().filter(|d| d.is_stored_in(dir));
+            if named.any(|dotfiles| !dotfiles.enabled()) {
+                log::info!(
+                    "{0} is disabled, using it since it was named",
+                    dir.display()
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ages_have_a_count_and_a_unit() {
+        let ages = [
+            ("90s", 90),
+            ("30m", 30 * 60),
+            ("12h", 12 * 3600),
+            ("7d", 7 * 86400),
+        ];
+        for (age, seconds) in ages {
+            assert_eq!(
+                parse_age(age).unwrap(),
+                Duration::from_secs(seconds),
+                "{0}",
+                age
+            );
+        }
+        assert_eq!(parse_age("2w").unwrap(), Duration::from_secs(14 * 86400));
+        assert_eq!(parse_age("0d").unwrap(), Duration::ZERO);
+        for age in [
+            "",
+            "7",
+            "d",
+            "7y",
+            "1.5h",
+            "-1d",
+            "7 d",
+            "99999999999999999999w",
+        ] {
+            assert!(
+                matches!(parse_age(age), Err(Error::InvalidAge(a)) if a == age),
+                "{0}",
+                age
+            );
+        }
+    }
+
+    #[test]
+    fn mtime_filters_exclude_their_bounds() {
+        let now = SystemTime::now();
+        let hour = Duration::from_secs(3600);
+        assert!(MtimeFilter::default().is_empty());
+        assert!(MtimeFilter::default().matches(now));
+
+        let filter = MtimeFilter {
+            older_than: Some(now),
+            newer_than: Some(now - 2 * hour),
+        };
+        assert!(!filter.is_empty());
+        assert!(filter.matches(now - hour));
+        assert!(!filter.matches(now));
+        assert!(!filter.matches(now - 2 * hour));
+        assert!(!filter.matches(now + hour));
+        assert!(!filter.matches(now - 3 * hour));
+    }
+}
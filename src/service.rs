@@ -0,0 +1,328 @@
+//! Generation and installation of the systemd user units that periodically deploy all dotfiles.
+//!
+//! Installing the units only writes them to the systemd user unit directory; talking to systemd is
+//! left to a [`ServiceRunner`] so that the units can be generated and written without a running
+//! systemd instance.
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::errors::{Error, Result};
+use crate::paths::write_atomic;
+use crate::xdg::{Dirs, XdgDir};
+
+const SERVICE_NAME: &str = "ghmd-deploy.service";
+const TIMER_NAME: &str = "ghmd-deploy.timer";
+
+/// ServiceRunner runs `systemctl --user` commands.
+pub trait ServiceRunner {
+    /// Run `systemctl --user` with the given arguments.
+    fn systemctl(&self, args: &[&str]) -> Result<()>;
+}
+
+/// Systemctl is the [`ServiceRunner`] that spawns the `systemctl` binary.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Systemctl;
+
+impl ServiceRunner for Systemctl {
+    fn systemctl(&self, args: &[&str]) -> Result<()> {
+        log::debug!("running systemctl --user {0}", args.join(" "));
+        let output = Command::new("systemctl")
+            .arg("--user")
+            .args(args)
+            .output()?;
+        if output.status.success() {
+            return Ok(());
+        }
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        let reason = if stderr.is_empty() {
+            output.status.to_string()
+        } else {
+            stderr
+        };
+        Err(Error::SystemctlFailed(args.join(" "), reason))
+    }
+}
+
+/// Service describes a systemd user service running `ghmd deploy --all` and the timer that
+/// triggers it on a fixed interval.
+#[derive(Debug, Clone)]
+pub struct Service {
+    exe: PathBuf,
+    interval: String,
+    unit_dir: PathBuf,
+}
+
+impl Service {
+    /// Describe units running the ghmd binary at `exe` every `interval`, given as a systemd time
+    /// span such as `30min` or `1h`. The units are placed in the systemd user unit directory,
+    /// `$XDG_CONFIG_HOME/systemd/user` or `~/.config/systemd/user` if `XDG_CONFIG_HOME` isn't set.
+    pub fn new(exe: PathBuf, interval: &str, dirs: &Dirs) -> Result<Self> {
+        let interval = interval.trim();
+        if interval.is_empty() || interval.contains(['\n', '\r']) {
+            return Err(Error::BadInput(
+                "service interval must be a systemd time span",
+            ));
+        }
+        let unit_dir = dirs.xdg_home(XdgDir::Config).join("systemd").join("user");
+        Ok(Self {
+            exe,
+            interval: interval.to_string(),
+            unit_dir,
+        })
+    }
+
+    /// Directory the units are written to.
+    pub fn unit_dir(&self) -> &Path {
+        &self.unit_dir
+    }
+
+    /// Contents of the service unit.
+    pub fn service_unit(&self) -> String {
+        format!(
+            "[Unit]\n\
+            Description=Deploy dotfiles managed by ghmd\n\
+            \n\
+            [Service]\n\
+            Type=oneshot\n\
+            ExecStart={0} deploy --all --skip-conflicts --quiet\n",
+            quote_exec_arg(&self.exe.to_string_lossy()),
+        )
+    }
+
+    /// Contents of the timer unit.
+    pub fn timer_unit(&self) -> String {
+        format!(
+            "[Unit]\n\
+            Description=Periodically deploy dotfiles managed by ghmd\n\
+            \n\
+            [Timer]\n\
+            OnBootSec=1min\n\
+            OnUnitActiveSec={0}\n\
+            Unit={1}\n\
+            \n\
+            [Install]\n\
+            WantedBy=timers.target\n",
+            self.interval, SERVICE_NAME,
+        )
+    }
+
+    /// Write both units. With `now` systemd is also reloaded and the timer enabled and started.
+    pub fn install(&self, runner: &dyn ServiceRunner, now: bool) -> Result<()> {
+        fs::create_dir_all(&self.unit_dir)?;
+        write_atomic(
+            &self.unit_dir.join(SERVICE_NAME),
+            &self.service_unit(),
+            false,
+        )?;
+        write_atomic(&self.unit_dir.join(TIMER_NAME), &self.timer_unit(), false)?;
+        log::info!(
+            "wrote {0} and {1} to {2}",
+            SERVICE_NAME,
+            TIMER_NAME,
+            self.unit_dir.display()
+        );
+
+        if now {
+            runner.systemctl(&["daemon-reload"])?;
+            runner.systemctl(&["enable", "--now", TIMER_NAME])?;
+            log::info!("enabled and started {0}", TIMER_NAME);
+        }
+        Ok(())
+    }
+
+    /// Stop and disable the timer and remove both units. Failing to talk to systemd isn't fatal
+    /// since the units are gone either way.
+    pub fn uninstall(&self, runner: &dyn ServiceRunner) -> Result<()> {
+        if let Err(e) = runner.systemctl(&["disable", "--now", TIMER_NAME]) {
+            log::warn!("could not disable {0}: {1}", TIMER_NAME, e);
+        }
+        for name in [TIMER_NAME, SERVICE_NAME] {
+            let path = self.unit_dir.join(name);
+            if path.exists() {
+                fs::remove_file(&path)?;
+                log::info!("removed {0}", path.display());
+            }
+        }
+        if let Err(e) = runner.systemctl(&["daemon-reload"]) {
+            log::warn!("could not reload systemd: {0}", e);
+        }
+        Ok(())
+    }
+}
+
+/// Quote an argument of a systemd `ExecStart=` line, escaping the characters systemd would
+/// otherwise interpret.
+fn quote_exec_arg(arg: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in arg.chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            },
+            '%' => quoted.push_str("%%"),
+            '$' => quoted.push_str("$$"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::RefCell;
+    use std::ffi::OsString;
+
+    use tempfile::TempDir;
+
+    /// Records the systemctl commands it is asked to run, failing all of them if `fail` is set.
+    #[derive(Default)]
+    struct Recorder {
+        calls: RefCell<Vec<String>>,
+        fail: bool,
+    }
+
+    impl ServiceRunner for Recorder {
+        fn systemctl(&self, args: &[&str]) -> Result<()> {
+            self.calls.borrow_mut().push(args.join(" "));
+            if self.fail {
+                return Err(Error::SystemctlFailed(
+                    args.join(" "),
+                    "no session".to_string(),
+                ));
+            }
+            Ok(())
+        }
+    }
+
+    fn service_with(vars: &[(&str, &str)]) -> Service {
+        let dirs = Dirs::from_env_with(|key| {
+            vars.iter()
+                .find(|(name, _)| *name == key)
+                .map(|(_, value)| OsString::from(value))
+        })
+        .unwrap();
+        Service::new(PathBuf::from("/usr/bin/ghmd"), "1h", &dirs).unwrap()
+    }
+
+    #[test]
+    fn units_go_to_the_systemd_user_directory() {
+        let service = service_with(&[("HOME", "/home/me")]);
+        assert_eq!(
+            service.unit_dir(),
+            Path::new("/home/me/.config/systemd/user")
+        );
+        let service = service_with(&[("HOME", "/home/me"), ("XDG_CONFIG_HOME", "/cfg")]);
+        assert_eq!(service.unit_dir(), Path::new("/cfg/systemd/user"));
+    }
+
+    #[test]
+    fn intervals_must_be_time_spans() {
+        let dirs = Dirs::from_env_with(|key| {
+            (key == "HOME").then(|| OsString::from("/home/me"))
+        })
+        .unwrap();
+        let exe = PathBuf::from("/usr/bin/ghmd");
+        assert!(Service::new(exe.clone(), " ", &dirs).is_err());
+        assert!(Service::new(exe.clone(), "1h\nExecStart=x", &dirs).is_err());
+        let service = Service::new(exe, " 30min ", &dirs).unwrap();
+        assert!(service.timer_unit().contains("30min"));
+    }
+
+    #[test]
+    fn units_run_the_binary_on_the_interval() {
+        let service = service_with(&[("HOME", "/home/me")]);
+        assert_eq!(
+            service.service_unit(),
+            "[Unit]\n\
+            Description=Deploy dotfiles managed by ghmd\n\
+            \n\
+            [Service]\n\
+            Type=oneshot\n\
+            ExecStart=\"/usr/bin/ghmd\" deploy --all --skip-conflicts --quiet\n"
+        );
+        assert_eq!(
+            service.timer_unit(),
+            "[Unit]\n\
+            Description=Periodically deploy dotfiles managed by ghmd\n\
+            \n\
+            [Timer]\n\
+            OnBootSec=1min\n\
+            OnUnitActiveSec=1h\n\
+            Unit=ghmd-deploy.service\n\
+            \n\
+            [Install]\n\
+            WantedBy=timers.target\n"
+        );
+    }
+
+    #[test]
+    fn exec_arguments_are_quoted() {
+        assert_eq!(quote_exec_arg("/usr/bin/ghmd"), "\"/usr/bin/ghmd\"");
+        assert_eq!(
+            quote_exec_arg(r#"/opt/my "tools"/100%/$bin\ghmd"#),
+            r#""/opt/my \"tools\"/100%%/$$bin\\ghmd""#
+        );
+    }
+
+    #[test]
+    fn install_writes_units_and_talks_to_systemd_only_with_now() {
+        let tmp = TempDir::new().unwrap();
+        let home = tmp.path().to_str().unwrap();
+        let service = service_with(&[("HOME", home)]);
+
+        let runner = Recorder::default();
+        service.install(&runner, false).unwrap();
+        let unit_dir = tmp.path().join(".config/systemd/user");
+        assert_eq!(
+            fs::read_to_string(unit_dir.join(SERVICE_NAME)).unwrap(),
+            service.service_unit()
+        );
+        assert_eq!(
+            fs::read_to_string(unit_dir.join(TIMER_NAME)).unwrap(),
+            service.timer_unit()
+        );
+        assert!(runner.calls.borrow().is_empty());
+
+        service.install(&runner, true).unwrap();
+        assert_eq!(
+            *runner.calls.borrow(),
+            ["daemon-reload", "enable --now ghmd-deploy.timer"]
+        );
+
+        let failing = Recorder {
+            fail: true,
+            ..Recorder::default()
+        };
+        assert!(matches!(
+            service.install(&failing, true),
+            Err(Error::SystemctlFailed(..))
+        ));
+    }
+
+    #[test]
+    fn uninstall_removes_units_even_without_systemd() {
+        let tmp = TempDir::new().unwrap();
+        let home = tmp.path().to_str().unwrap();
+        let service = service_with(&[("HOME", home)]);
+        service.install(&Recorder::default(), false).unwrap();
+
+        let runner = Recorder {
+            fail: true,
+            ..Recorder::default()
+        };
+        service.uninstall(&runner).unwrap();
+        assert_eq!(
+            *runner.calls.borrow(),
+            ["disable --now ghmd-deploy.timer", "daemon-reload"]
+        );
+        assert!(!service.unit_dir().join(SERVICE_NAME).exists());
+        assert!(!service.unit_dir().join(TIMER_NAME).exists());
+        // nothing left to remove
+        service.uninstall(&Recorder::default()).unwrap();
+    }
+}
@@ -0,0 +1,146 @@
+//! Snippets integrating ghmd into interactive shells.
+//!
+//! The snippets are generated from templates embedded in the binary. They check on every prompt
+//! whether the tracked dotfiles are deployed, printing a warning only if they are not, and define
+//! a `ghmd-cd-store` function that changes to the directory of the dotfiles directory
+//! corresponding to the current directory.
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::errors::{Error, Result};
+
+const BASH_TEMPLATE: &str = include_str!("shell/bash.sh");
+const ZSH_TEMPLATE: &str = include_str!("shell/zsh.sh");
+const FISH_TEMPLATE: &str = include_str!("shell/fish.fish");
+
+/// Shell is an interactive shell that ghmd can generate an integration snippet for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    /// GNU bash.
+    Bash,
+
+    /// The Z shell.
+    Zsh,
+
+    /// The friendly interactive shell.
+    Fish,
+}
+
+impl FromStr for Shell {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "bash" => Ok(Shell::Bash),
+            "zsh" => Ok(Shell::Zsh),
+            "fish" => Ok(Shell::Fish),
+            _ => Err(Error::BadInput("shell must be one of bash, zsh or fish")),
+        }
+    }
+}
+
+impl Shell {
+    /// Generate the snippet for this shell running the ghmd binary at `exe`. The prompt check is
+    /// skipped while the config file at `config_file` doesn't exist.
+    pub fn hook(&self, exe: &Path, config_file: &Path) -> String {
+        let template = match self {
+            Shell::Bash => BASH_TEMPLATE,
+            Shell::Zsh => ZSH_TEMPLATE,
+            Shell::Fish => FISH_TEMPLATE,
+        };
+        template
+            .replace("@GHMD@", &self.quote(exe))
+            .replace("@CONFIG@", &self.quote(config_file))
+    }
+
+    /// Quote `path` as a single word for this shell.
+    fn quote(&self, path: &Path) -> String {
+        let path = path.to_string_lossy();
+        match self {
+            Shell::Bash | Shell::Zsh => format!("'{0}'", path.replace('\'', r"'\''")),
+            Shell::Fish => {
+                format!("'{0}'", path.replace('\\', r"\\").replace('\'', r"\'"))
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Generate the snippet for `shell` with a config file path that needs quoting.
+    fn hook(shell: Shell) -> String {
+        shell.hook(
+            Path::new("/usr/bin/ghmd"),
+            Path::new("/home/o'neil/.config/ghmd/config.toml"),
+        )
+    }
+
+    #[test]
+    fn bash_hook() {
+        let expected = r##"# ghmd integration for bash, load it with: eval "$(ghmd shell-hook bash)"
+
+_ghmd_check() {
+    [ -f '/home/o'\''neil/.config/ghmd/config.toml' ] || return 0
+    '/usr/bin/ghmd' --quiet --no-validate verify >/dev/null 2>&1 ||
+        echo "ghmd: dotfiles are out of sync, run 'ghmd status' for details" >&2
+}
+
+ghmd-cd-store() {
+    local dir
+    dir="$('/usr/bin/ghmd' --quiet explain --store "$PWD")" && cd "$dir"
+}
+
+case ";${PROMPT_COMMAND:-};" in
+    *";_ghmd_check;"*) ;;
+    *) PROMPT_COMMAND="_ghmd_check${PROMPT_COMMAND:+;$PROMPT_COMMAND}" ;;
+esac
+"##;
+        assert_eq!(hook(Shell::Bash), expected);
+    }
+
+    #[test]
+    fn zsh_hook() {
+        let expected = r##"# ghmd integration for zsh, load it with: eval "$(ghmd shell-hook zsh)"
+
+_ghmd_check() {
+    [[ -f '/home/o'\''neil/.config/ghmd/config.toml' ]] || return 0
+    '/usr/bin/ghmd' --quiet --no-validate verify >/dev/null 2>&1 ||
+        print -u2 "ghmd: dotfiles are out of sync, run 'ghmd status' for details"
+}
+
+ghmd-cd-store() {
+    local dir
+    dir="$('/usr/bin/ghmd' --quiet explain --store "$PWD")" && cd "$dir"
+}
+
+autoload -Uz add-zsh-hook
+add-zsh-hook precmd _ghmd_check
+"##;
+        assert_eq!(hook(Shell::Zsh), expected);
+    }
+
+    #[test]
+    fn fish_hook() {
+        let expected = r##"# ghmd integration for fish, load it with: ghmd shell-hook fish | source
+
+function _ghmd_check --on-event fish_prompt
+    test -f '/home/o\'neil/.config/ghmd/config.toml'; or return 0
+    '/usr/bin/ghmd' --quiet --no-validate verify >/dev/null 2>&1
+    or echo "ghmd: dotfiles are out of sync, run 'ghmd status' for details" >&2
+end
+
+function ghmd-cd-store
+    set -l dir ('/usr/bin/ghmd' --quiet explain --store $PWD); and cd $dir
+end
+"##;
+        assert_eq!(hook(Shell::Fish), expected);
+    }
+
+    #[test]
+    fn shells_are_parsed_by_name() {
+        assert_eq!("zsh".parse::<Shell>().unwrap(), Shell::Zsh);
+        assert!(matches!("tcsh".parse::<Shell>(), Err(Error::BadInput(_))));
+    }
+}
@@ -0,0 +1,17 @@
+# ghmd integration for bash, load it with: eval "$(ghmd shell-hook bash)"
+
+_ghmd_check() {
+    [ -f @CONFIG@ ] || return 0
+    @GHMD@ --quiet --no-validate verify >/dev/null 2>&1 ||
+        echo "ghmd: dotfiles are out of sync, run 'ghmd status' for details" >&2
+}
+
+ghmd-cd-store() {
+    local dir
+    dir="$(@GHMD@ --quiet explain --store "$PWD")" && cd "$dir"
+}
+
+case ";${PROMPT_COMMAND:-};" in
+    *";_ghmd_check;"*) ;;
+    *) PROMPT_COMMAND="_ghmd_check${PROMPT_COMMAND:+;$PROMPT_COMMAND}" ;;
+esac
@@ -0,0 +1,11 @@
+# ghmd integration for fish, load it with: ghmd shell-hook fish | source
+
+function _ghmd_check --on-event fish_prompt
+    test -f @CONFIG@; or return 0
+    @GHMD@ --quiet --no-validate verify >/dev/null 2>&1
+    or echo "ghmd: dotfiles are out of sync, run 'ghmd status' for details" >&2
+end
+
+function ghmd-cd-store
+    set -l dir (@GHMD@ --quiet explain --store $PWD); and cd $dir
+end
@@ -0,0 +1,15 @@
+# ghmd integration for zsh, load it with: eval "$(ghmd shell-hook zsh)"
+
+_ghmd_check() {
+    [[ -f @CONFIG@ ]] || return 0
+    @GHMD@ --quiet --no-validate verify >/dev/null 2>&1 ||
+        print -u2 "ghmd: dotfiles are out of sync, run 'ghmd status' for details"
+}
+
+ghmd-cd-store() {
+    local dir
+    dir="$(@GHMD@ --quiet explain --store "$PWD")" && cd "$dir"
+}
+
+autoload -Uz add-zsh-hook
+add-zsh-hook precmd _ghmd_check
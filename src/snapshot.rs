@@ -0,0 +1,223 @@
+//! Snapshots of the config, so that a config changed by mistake can be restored from the way it
+//! was before.
+//!
+//! Each time the config file is written with changes, a copy of the new config is put into the
+//! snapshots directory, see [`Dirs::snapshots_dir`]. Only the newest snapshots are kept, 30 by
+//! default, which `GHMD_SNAPSHOT_KEEP` or [`Config::set_snapshot_keep`] override.
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+
+use crate::config::Config;
+use crate::errors::{Error, Result};
+use crate::paths;
+use crate::xdg::Dirs;
+
+/// Number of snapshots kept unless configured otherwise.
+pub const DEFAULT_SNAPSHOT_KEEP: usize = 30;
+
+/// Environment variable overriding [`DEFAULT_SNAPSHOT_KEEP`].
+pub const SNAPSHOT_KEEP_VAR: &str = "GHMD_SNAPSHOT_KEEP";
+
+impl Config {
+    /// Put a copy of this config into the snapshots directory and delete all but the newest
+    /// snapshots, see [`Config::rotate_snapshots`]. Returns the path of the snapshot.
+    pub fn snapshot(&self) -> Result<PathBuf> {
+        let dir = self.snapshots_dir()?;
+        Dirs::create(&dir)?;
+        let path = self.snapshot_into(&dir)?;
+        let _ = self.rotate_snapshots(self.snapshot_keep()?)?;
+        Ok(path)
+    }
+
+    /// Delete all but the newest `keep` snapshots by modification time, returning how many
+    /// were deleted.
+    pub fn rotate_snapshots(&self, keep: usize) -> Result<usize> {
+        rotate_snapshots_in(&self.snapshots_dir()?, keep)
+    }
+
+    /// Paths of the snapshots, oldest first.
+    pub fn snapshots(&self) -> Result<Vec<PathBuf>> {
+        snapshots_in(&self.snapshots_dir()?)
+    }
+
+    /// The snapshots directory of this config's XDG directories, see [`Config::set_xdg_dirs`].
+    fn snapshots_dir(&self) -> Result<PathBuf> {
+        self.xdg_dirs()
+            .map(Dirs::snapshots_dir)
+            .ok_or(Error::CannotDetermineConfigDir)
+    }
+
+    /// Number of snapshots kept by [`Config::snapshot`]: the one set with
+    /// [`Config::set_snapshot_keep`], else the value of `GHMD_SNAPSHOT_KEEP`, else
+    /// [`DEFAULT_SNAPSHOT_KEEP`]. Fails with [`Error::BadInput`] if `GHMD_SNAPSHOT_KEEP` isn't
+    /// a number.
+    pub fn snapshot_keep(&self) -> Result<usize> {
+        match self.snapshot_keep {
+            Some(keep) => Ok(keep),
+            None => parse_snapshot_keep(env::var(SNAPSHOT_KEEP_VAR).ok().as_deref()),
+        }
+    }
+
+    /// Set the number of snapshots kept by [`Config::snapshot`], overriding
+    /// `GHMD_SNAPSHOT_KEEP`.
+    pub fn set_snapshot_keep(&mut self, keep: usize) {
+        self.snapshot_keep = Some(keep);
+    }
+
+    /// Write a copy of this config into `dir`, named after the current time.
+    pub(crate) fn snapshot_into(&self, dir: &Path) -> Result<PathBuf> {
+        let toml = toml::to_string_pretty(self).unwrap();
+        let name = Local::now()
+            .format("%Y-%m-%dT%H-%M-%S%.6f.toml")
+            .to_string();
+        let path = dir.join(name);
+        paths::write_atomic(&path, &toml, false)?;
+        log::debug!("wrote config snapshot {0}", path.display());
+        Ok(path)
+    }
+}
+
+/// Parse the value of `GHMD_SNAPSHOT_KEEP`, if it is set.
+fn parse_snapshot_keep(value: Option<&str>) -> Result<usize> {
+    match value {
+        None => Ok(DEFAULT_SNAPSHOT_KEEP),
+        Some(value) => value.trim().parse().map_err(|_| {
+            Error::BadInput("GHMD_SNAPSHOT_KEEP must be a non-negative number")
+        }),
+    }
+}
+
+/// Paths of the snapshots in `dir`, oldest first. A missing directory has no snapshots.
+fn snapshots_in(dir: &Path) -> Result<Vec<PathBuf>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut snapshots = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let md = entry.metadata()?;
+        if md.is_file() {
+            snapshots.push((md.modified()?, entry.path()));
+        }
+    }
+    // names sort by creation time too, which orders snapshots with equal mtimes
+    snapshots.sort();
+    Ok(snapshots.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Delete all but the newest `keep` snapshots in `dir`, returning how many were deleted.
+fn rotate_snapshots_in(dir: &Path, keep: usize) -> Result<usize> {
+    let snapshots = snapshots_in(dir)?;
+    let excess = snapshots.len().saturating_sub(keep);
+    for path in &snapshots[..excess] {
+        log::debug!("deleting config snapshot {0}", path.display());
+        fs::remove_file(path)?;
+    }
+    Ok(excess)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::{File, FileTimes};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn snapshot_at(dir: &Path, name: &str, secs: u64) -> PathBuf {
+        let path = dir.join(name);
+        let file = File::create(&path).unwrap();
+        let time = UNIX_EPOCH + Duration::from_secs(secs);
+        file.set_times(FileTimes::new().set_modified(time)).unwrap();
+        path
+    }
+
+    #[test]
+    fn rotation_keeps_the_newest_snapshots() {
+        let tmp = tempfile::tempdir().unwrap();
+        // names in the opposite order of their modification times
+        let newest = snapshot_at(tmp.path(), "a.toml", 30);
+        let middle = snapshot_at(tmp.path(), "b.toml", 20);
+        let _oldest = snapshot_at(tmp.path(), "c.toml", 10);
+
+        assert_eq!(rotate_snapshots_in(tmp.path(), 2).unwrap(), 1);
+        assert_eq!(
+            snapshots_in(tmp.path()).unwrap(),
+            vec![middle, newest.clone()]
+        );
+        assert_eq!(rotate_snapshots_in(tmp.path(), 5).unwrap(), 0);
+        assert_eq!(rotate_snapshots_in(tmp.path(), 1).unwrap(), 1);
+        assert_eq!(snapshots_in(tmp.path()).unwrap(), vec![newest]);
+    }
+
+    #[test]
+    fn missing_snapshots_directory_has_no_snapshots() {
+        let tmp = tempfile::tempdir().unwrap();
+        let missing = tmp.path().join("snapshots");
+        assert!(snapshots_in(&missing).unwrap().is_empty());
+        assert_eq!(rotate_snapshots_in(&missing, 0).unwrap(), 0);
+    }
+
+    #[test]
+    fn snapshot_contains_the_config() {
+        let tmp = tempfile::tempdir().unwrap();
+        let config = Config::in_memory(tmp.path().join("backup"));
+        let path = config.snapshot_into(tmp.path()).unwrap();
+        let mut parsed: Config =
+            toml::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        parsed.set_read_only();
+        assert!(parsed.dotfiles.is_empty());
+    }
+
+    #[test]
+    fn snapshots_go_into_the_configs_own_data_dir() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut config = Config::in_memory(tmp.path().join("backup"));
+        assert!(matches!(
+            config.snapshot(),
+            Err(Error::CannotDetermineConfigDir)
+        ));
+        assert!(matches!(
+            config.snapshots(),
+            Err(Error::CannotDetermineConfigDir)
+        ));
+
+        let home = tmp.path().join("home");
+        let dirs = Dirs::from_env_with(|key| match key {
+            "HOME" => Some(home.clone().into_os_string()),
+            _ => None,
+        })
+        .unwrap();
+        let dir = dirs.snapshots_dir();
+        assert!(dir.starts_with(&home));
+        config.set_xdg_dirs(dirs);
+        config.set_snapshot_keep(1);
+        let _ = config.snapshot().unwrap();
+        let newest = config.snapshot().unwrap();
+        assert_eq!(newest.parent(), Some(dir.as_path()));
+        assert_eq!(config.snapshots().unwrap(), vec![newest]);
+    }
+
+    #[test]
+    fn snapshot_keep_parsing() {
+        assert_eq!(parse_snapshot_keep(None).unwrap(), DEFAULT_SNAPSHOT_KEEP);
+        assert_eq!(parse_snapshot_keep(Some(" 5\n")).unwrap(), 5);
+        assert_eq!(parse_snapshot_keep(Some("0")).unwrap(), 0);
+        assert!(matches!(
+            parse_snapshot_keep(Some("-1")),
+            Err(Error::BadInput(_))
+        ));
+    }
+
+    #[test]
+    fn set_snapshot_keep_overrides_the_environment() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut config = Config::in_memory(tmp.path().join("backup"));
+        config.set_snapshot_keep(3);
+        assert_eq!(config.snapshot_keep().unwrap(), 3);
+    }
+}
@@ -0,0 +1,355 @@
+//! Read-only inspection of the deployment state of configured dotfiles.
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde_derive::Serialize;
+
+use crate::config::{Config, DotfilePath, Dotfiles, DotfilesDir, SymlinkDir};
+use crate::errors::Result;
+use crate::paths::{self, Traversal};
+
+/// FsDiff compares the tracked dotfiles of a [`Config`] with the symlinks that actually exist,
+/// see [`Config::diff_with_filesystem`].
+#[derive(Debug, Default, Clone)]
+pub struct FsDiff<'a> {
+    /// Tracked dotfiles without a symlink, which deploying would create.
+    pub needs_deploy: Vec<(&'a Dotfiles, &'a DotfilePath)>,
+
+    /// Symlinks pointing at untracked files or directories inside a dotfiles directory, which
+    /// could be tracked without moving anything by stowing them.
+    pub needs_registration: Vec<PathBuf>,
+
+    /// Tracked dotfiles whose state requires no action, see [`SymlinkState::is_ok`].
+    pub consistent: Vec<(&'a Dotfiles, &'a DotfilePath)>,
+
+    /// Tracked dotfiles whose symlink path is occupied by something else or whose dotfile is
+    /// missing, along with their state.
+    pub conflicts: Vec<(&'a Dotfiles, &'a DotfilePath, SymlinkState)>,
+}
+
+/// SymlinkState describes what currently exists at the symlink path of a single dotfile.
+#[derive(Serialize, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum SymlinkState {
+    /// The symlink exists and points at the expected dotfile, or a hardlink of the dotfile
+    /// exists in its place.
+    Deployed,
+
+    /// Nothing exists at the symlink path.
+    NotDeployed,
+
+    /// Something other than a symlink exists at the symlink path.
+    NotASymlink,
+
+    /// A symlink exists at the symlink path but points somewhere other than the dotfile.
+    WrongTarget,
+
+    /// The dotfile itself is missing from the dotfile directory.
+    DotfileMissing,
+
+    /// The dotfile was copied into the dotfile directory and whatever exists at the symlink path
+    /// is not managed by ghmd.
+    CopyTracked,
+
+    /// The dotfile is on the skip list of this machine and nothing exists at its symlink path,
+    /// as intended. Whatever is in the way of a skipped dotfile is still reported as such.
+    Skipped,
+}
+
+impl SymlinkState {
+    /// Whether this state requires no further action. A skipped dotfile requires none, since
+    /// it is only [`SymlinkState::Skipped`] while its symlink path is empty.
+    pub fn is_ok(&self) -> bool {
+        matches!(self, Self::Deployed | Self::CopyTracked | Self::Skipped)
+    }
+}
+
+impl fmt::Display for SymlinkState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Deployed => "deployed",
+            Self::NotDeployed => "not deployed",
+            Self::NotASymlink => "not a symlink",
+            Self::WrongTarget => "wrong target",
+            Self::DotfileMissing => "dotfile missing",
+            Self::CopyTracked => "copy-tracked, target unmanaged",
+            Self::Skipped => "skipped",
+        };
+        f.pad(s)
+    }
+}
+
+/// DotfileStatus is the deployment state of a single tracked dotfile together with the
+/// directories of the dotfiles entry it belongs to.
+#[derive(Serialize, Debug, PartialEq, Eq, Clone)]
+pub struct DotfileStatus {
+    /// Directory the dotfile is stored in.
+    pub dotfiles_dir: DotfilesDir,
+
+    /// Directory the dotfile is deployed to.
+    pub symlink_dir: SymlinkDir,
+
+    /// Path of the dotfile relative to both directories.
+    pub path: DotfilePath,
+
+    /// Deployment state of the dotfile.
+    pub state: SymlinkState,
+
+    /// Absolute path of the dotfile in the store, see [`Dotfiles::store_path`].
+    #[serde(skip)]
+    store: PathBuf,
+
+    /// Absolute path of the symlink for the dotfile, see [`Dotfiles::symlink_path`].
+    #[serde(skip)]
+    symlink: PathBuf,
+}
+
+impl DotfileStatus {
+    /// Absolute path of the dotfile in the store.
+    pub fn store_path(&self) -> PathBuf {
+        self.store.clone()
+    }
+
+    /// Absolute path of the symlink for the dotfile. A path starting with an XDG token is
+    /// resolved against the XDG base directories of the dotfiles entry, see [`XdgDir`].
+    ///
+    /// [`XdgDir`]: crate::XdgDir
+    pub fn symlink_path(&self) -> PathBuf {
+        self.symlink.clone()
+    }
+}
+
+impl Dotfiles {
+    /// Determine the deployment state of the given dotfile without modifying anything. A dotfile
+    /// deployed under other names as well is only deployed once all of its symlinks are; the
+    /// state is that of the first one that isn't, see [`Dotfiles::verify_symlinks`].
+    pub fn verify_symlink(&self, path: &DotfilePath) -> Result<SymlinkState> {
+        let states = self.verify_symlinks(path)?;
+        let own = states[0].1;
+        Ok(states
+            .into_iter()
+            .map(|(_, state)| state)
+            .find(|state| !state.is_ok())
+            .unwrap_or(own))
+    }
+
+    /// Determine the deployment state of each symlink of the given dotfile: its own symlink
+    /// followed by those for its other names, see [`Dotfiles::alias_paths`].
+    pub fn verify_symlinks(
+        &self,
+        path: &DotfilePath,
+    ) -> Result<Vec<(PathBuf, SymlinkState)>> {
+        let mut symlinks = vec![self.symlink_path(path)];
+        symlinks.extend(self.alias_paths(path));
+        symlinks
+            .into_iter()
+            .map(|symlink| {
+                let state = self.verify_symlink_at(path, &symlink)?;
+                Ok((symlink, state))
+            })
+            .collect()
+    }
+
+    fn verify_symlink_at(
+        &self,
+        path: &DotfilePath,
+        symlink_path: &Path,
+    ) -> Result<SymlinkState> {
+        let dotfile_path = self.store_path(path);
+
+        if dotfile_path.symlink_metadata().is_err() {
+            return Ok(SymlinkState::DotfileMissing);
+        }
+
+        if self.is_copied(path) {
+            return Ok(SymlinkState::CopyTracked);
+        }
+
+        let metadata = match symlink_path.symlink_metadata() {
+            Ok(md) => md,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok(SymlinkState::NotDeployed)
+            },
+            Err(e) => return Err(e.into()),
+        };
+
+        if !metadata.is_symlink() {
+            // a hardlink of the dotfile is as good as the symlink, deploy leaves it alone
+            return match paths::same_inode(symlink_path, &dotfile_path)? {
+                true => Ok(SymlinkState::Deployed),
+                false => Ok(SymlinkState::NotASymlink),
+            };
+        }
+
+        if self.links_to(symlink_path, path)? {
+            return Ok(SymlinkState::Deployed);
+        }
+        Ok(SymlinkState::WrongTarget)
+    }
+
+    /// Determine the deployment state of every dotfile in this set of dotfiles.
+    pub fn verify_all(&self) -> Result<Vec<(&DotfilePath, SymlinkState)>> {
+        self.apply_ignore()?
+            .map(|path| Ok((path, self.verify_symlink(path)?)))
+            .collect()
+    }
+
+    /// Collect the status of every dotfile in this set of dotfiles.
+    pub fn status_all(&self) -> Result<Vec<DotfileStatus>> {
+        self.apply_ignore()?
+            .map(|path| {
+                Ok(DotfileStatus {
+                    dotfiles_dir: self.dotfile_directory.clone(),
+                    symlink_dir: self.symlink_dir().clone(),
+                    path: path.clone(),
+                    state: self.verify_symlink(path)?,
+                    store: self.store_path(path),
+                    symlink: self.symlink_path(path),
+                })
+            })
+            .collect()
+    }
+
+    /// Count the dotfiles of this set of dotfiles in each [`SymlinkState`], checking each of
+    /// them once. States no dotfile is in are left out.
+    pub fn count_by_state(&self) -> Result<HashMap<SymlinkState, usize>> {
+        let mut counts = HashMap::new();
+        for path in self.apply_ignore()? {
+            *counts.entry(self.verify_symlink(path)?).or_insert(0) += 1;
+        }
+        Ok(counts)
+    }
+}
+
+impl Config {
+    /// Collect the status of every tracked dotfile of every enabled dotfiles entry, see
+    /// [`Config::verify_symlink`].
+    pub fn status_all(&self) -> Result<Vec<DotfileStatus>> {
+        let mut statuses = Vec::new();
+        for dotfiles in self.dotfiles.iter().filter(|dotfiles| dotfiles.enabled) {
+            statuses.extend(dotfiles.status_all()?);
+        }
+        for status in &mut statuses {
+            status.state = self.skip_state(&status.path, status.state);
+        }
+        Ok(statuses)
+    }
+
+    /// Count the tracked dotfiles of every dotfiles entry in each [`SymlinkState`] in a single
+    /// pass, with the states of [`Config::status_all`]. States no dotfile is in are left out.
+    pub fn count_by_state(&self) -> Result<HashMap<SymlinkState, usize>> {
+        let mut counts = HashMap::new();
+        for dotfiles in self.dotfiles.iter().filter(|dotfiles| dotfiles.enabled) {
+            for path in dotfiles.apply_ignore()? {
+                *counts
+                    .entry(self.verify_symlink(dotfiles, path)?)
+                    .or_insert(0) += 1;
+            }
+        }
+        Ok(counts)
+    }
+
+    /// Collect every tracked dotfile, along with its dotfiles entry, that has nothing at its
+    /// symlink path yet, e.g. all of them right after cloning a dotfiles repository on a new
+    /// machine. Deploying these dotfiles is what remains to be done. Entries not deployed on
+    /// this machine, see [`Config::deploys_here`], and dotfiles matching the `ignore_patterns`
+    /// of their entry are never deployed, so they are left out.
+    pub fn pending_operations(&self) -> Result<Vec<(&Dotfiles, &DotfilePath)>> {
+        let mut pending = Vec::new();
+        for dotfiles in self.dotfiles.iter().filter(|d| self.deploys_here(d)) {
+            for path in dotfiles.apply_ignore()? {
+                match dotfiles.symlink_path(path).symlink_metadata() {
+                    Ok(_) => (),
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                        pending.push((dotfiles, path))
+                    },
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+        Ok(pending)
+    }
+
+    /// Determine the deployment state of the given dotfile of `dotfiles` like
+    /// [`Dotfiles::verify_symlink`], except that dotfiles on the skip list of this machine that
+    /// aren't deployed are reported as [`SymlinkState::Skipped`]. Other states, e.g. something
+    /// else in the way of a skipped dotfile, are reported as they are.
+    pub fn verify_symlink(
+        &self,
+        dotfiles: &Dotfiles,
+        path: &DotfilePath,
+    ) -> Result<SymlinkState> {
+        Ok(self.skip_state(path, dotfiles.verify_symlink(path)?))
+    }
+
+    fn skip_state(&self, path: &DotfilePath, state: SymlinkState) -> SymlinkState {
+        match state {
+            SymlinkState::NotDeployed if self.is_skipped(path) => SymlinkState::Skipped,
+            _ => state,
+        }
+    }
+
+    /// Compare the tracked dotfiles with the filesystem: which of them lack their symlink, which
+    /// are consistent and which are in conflict, and which symlinks in the symlink directories
+    /// point at untracked entries of a dotfiles directory. The latter are found by walking the
+    /// dotfiles directories as `traversal` allows rather than the symlink directories.
+    pub fn diff_with_filesystem(&self, traversal: Traversal) -> Result<FsDiff<'_>> {
+        let mut diff = FsDiff::default();
+        for dotfiles in &self.dotfiles {
+            for path in &dotfiles.paths {
+                match self.verify_symlink(dotfiles, path)? {
+                    SymlinkState::NotDeployed => diff.needs_deploy.push((dotfiles, path)),
+                    state if state.is_ok() => diff.consistent.push((dotfiles, path)),
+                    state => diff.conflicts.push((dotfiles, path, state)),
+                }
+            }
+            if dotfiles.dotfile_directory.is_dir() {
+                let dir = &dotfiles.dotfile_directory;
+                find_unregistered(dotfiles, dir, traversal, &mut diff)?;
+            }
+        }
+        Ok(diff)
+    }
+
+    /// Collect the status of every tracked dotfile that no longer exists in its dotfiles
+    /// directory.
+    pub fn missing_dotfiles(&self) -> Result<Vec<DotfileStatus>> {
+        let mut statuses = self.status_all()?;
+        statuses.retain(|status| status.state == SymlinkState::DotfileMissing);
+        Ok(statuses)
+    }
+}
+
+/// Add the symlinks that point at untracked entries below `dir` inside the dotfiles directory of
+/// `dotfiles` to `diff`. Tracked entries and entries `traversal` skips aren't descended into.
+fn find_unregistered(
+    dotfiles: &Dotfiles,
+    dir: &Path,
+    traversal: Traversal,
+    diff: &mut FsDiff<'_>,
+) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let store_path = entry?.path();
+        let path = match dotfiles.strip_dotfile_dir(&store_path) {
+            Some(path) => path,
+            None => continue,
+        };
+        if dotfiles.has_path(&path) || !traversal.visits(&path) {
+            continue;
+        }
+        let symlink_path = dotfiles.symlink_path(&path);
+        let is_link = symlink_path
+            .symlink_metadata()
+            .map(|md| md.is_symlink())
+            .unwrap_or(false);
+        if is_link && dotfiles.links_to(&symlink_path, &path)? {
+            diff.needs_registration.push(symlink_path);
+        } else if store_path.is_dir() && !store_path.is_symlink() {
+            find_unregistered(dotfiles, &store_path, traversal, diff)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,228 @@
+//! Minimal placeholder templates for printing one line per tracked dotfile.
+//!
+//! A template is literal text containing placeholders such as `{path}` that are replaced with
+//! fields of a tracked dotfile. `{{` and `}}` stand for literal braces and the escapes `\t`, `\n`
+//! and `\\` for a tab, a newline and a backslash.
+use std::fmt::Write;
+
+use crate::config::{Config, ResolvedPath};
+use crate::errors::{Error, Result};
+use crate::status::SymlinkState;
+
+/// Names of the placeholders a [`Template`] may contain.
+pub const TEMPLATE_FIELDS: &[&str] = &[
+    "path",
+    "store",
+    "target",
+    "state",
+    "entry",
+    "dotfiles_dir",
+    "symlink_dir",
+];
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Segment {
+    Literal(String),
+    Field(&'static str),
+}
+
+/// Template is a parsed format string such as `{store}\t{target}\t{state}`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    /// Parse `format`, failing on placeholders that aren't listed in [`TEMPLATE_FIELDS`] and on
+    /// unbalanced braces.
+    pub fn parse(format: &str) -> Result<Self> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = format.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('t') => literal.push('\t'),
+                    Some('n') => literal.push('\n'),
+                    Some('\\') => literal.push('\\'),
+                    Some(other) => {
+                        literal.push('\\');
+                        literal.push(other);
+                    },
+                    None => literal.push('\\'),
+                },
+                '{' if chars.peek() == Some(&'{') => {
+                    let _ = chars.next();
+                    literal.push('{');
+                },
+                '}' if chars.peek() == Some(&'}') => {
+                    let _ = chars.next();
+                    literal.push('}');
+                },
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => {
+                                return Err(Error::InvalidTemplate(format!(
+                                    "unclosed placeholder '{{{0}'",
+                                    name
+                                )))
+                            },
+                        }
+                    }
+                    let field = TEMPLATE_FIELDS
+                        .iter()
+                        .find(|field| **field == name)
+                        .ok_or(Error::UnknownTemplateField(name))?;
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Field(field));
+                },
+                '}' => {
+                    return Err(Error::InvalidTemplate(
+                        "unmatched '}', use '}}' for a literal brace".to_string(),
+                    ))
+                },
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Ok(Self { segments })
+    }
+
+    /// Whether the template contains the placeholder `field`.
+    pub fn uses(&self, field: &str) -> bool {
+        self.segments
+            .iter()
+            .any(|segment| matches!(segment, Segment::Field(f) if *f == field))
+    }
+
+    /// Fill in the template for the tracked dotfile `resolved` of `config`. `{state}` is replaced
+    /// with `state`, or left empty if it is `None`.
+    pub fn render(
+        &self,
+        config: &Config,
+        resolved: &ResolvedPath,
+        state: Option<SymlinkState>,
+    ) -> String {
+        let dotfiles = &config.dotfiles[resolved.entry_index];
+        let mut line = String::new();
+        for segment in &self.segments {
+            let _ = match segment {
+                Segment::Literal(text) => write!(line, "{0}", text),
+                Segment::Field("path") => {
+                    write!(line, "{0}", resolved.relative.display())
+                },
+                Segment::Field("store") => write!(line, "{0}", resolved.store.display()),
+                Segment::Field("target") => {
+                    write!(line, "{0}", resolved.target.display())
+                },
+                Segment::Field("state") => match state {
+                    Some(state) => write!(line, "{0}", state),
+                    None => Ok(()),
+                },
+                Segment::Field("entry") => write!(line, "{0}", resolved.entry_index),
+                Segment::Field("dotfiles_dir") => {
+                    write!(line, "{0}", dotfiles.dotfile_directory.display())
+                },
+                Segment::Field("symlink_dir") => {
+                    write!(line, "{0}", dotfiles.symlink_dir().display())
+                },
+                Segment::Field(_) => Ok(()),
+            };
+        }
+        line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+    use std::path::PathBuf;
+
+    use crate::config::{DotfilesDir, SymlinkDir};
+
+    fn literal(text: &str) -> Segment {
+        Segment::Literal(text.to_string())
+    }
+
+    #[test]
+    fn escapes_and_doubled_braces_are_literal_text() {
+        let template = Template::parse(r"{path}\t{{x}}\n\\{state}\q").unwrap();
+        assert_eq!(
+            template.segments,
+            [
+                Segment::Field("path"),
+                literal("\t{x}\n\\"),
+                Segment::Field("state"),
+                literal(r"\q"),
+            ]
+        );
+        assert!(template.uses("state") && !template.uses("store"));
+        assert_eq!(
+            Template::parse(r"end\").unwrap().segments,
+            [literal(r"end\")]
+        );
+    }
+
+    #[test]
+    fn unknown_fields_list_the_valid_ones() {
+        let unknown = Template::parse("{path} {size}").unwrap_err();
+        assert!(matches!(&unknown, Error::UnknownTemplateField(name) if name == "size"));
+        assert_eq!(
+            unknown.to_string(),
+            "unknown field '{size}' in format template, valid fields are: path, store, target, \
+             state, entry, dotfiles_dir, symlink_dir"
+        );
+        assert!(matches!(
+            Template::parse("{path"),
+            Err(Error::InvalidTemplate(_))
+        ));
+        assert!(matches!(
+            Template::parse("path}"),
+            Err(Error::InvalidTemplate(_))
+        ));
+    }
+
+    #[test]
+    fn fields_are_filled_in_from_resolved_paths() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (home, store) = (tmp.path().join("home"), tmp.path().join("dots"));
+        fs::create_dir_all(store.join("a")).unwrap();
+        fs::create_dir_all(&home).unwrap();
+        fs::write(store.join("a/b"), "").unwrap();
+        let symlink_dir: SymlinkDir = home.clone().try_into().unwrap();
+        let dotfile_dir: DotfilesDir = store.clone().try_into().unwrap();
+        let mut config = Config::in_memory(tmp.path().join("backup"));
+        config.add_dotfiles(&symlink_dir, &dotfile_dir).unwrap();
+        let _ = config
+            .add_path(
+                &symlink_dir,
+                &dotfile_dir,
+                PathBuf::from("a/b").try_into().unwrap(),
+            )
+            .unwrap();
+
+        let resolved = config.iter_entries().next().unwrap();
+        let template =
+            Template::parse("{entry} {path}\t{store}\t{target}\t{state}").unwrap();
+        let line = format!(
+            "0 a/b\t{0}\t{1}\t",
+            store.join("a/b").display(),
+            home.join("a/b").display()
+        );
+        assert_eq!(template.render(&config, &resolved, None), line);
+        assert_eq!(
+            template.render(&config, &resolved, Some(SymlinkState::NotDeployed)),
+            line + "not deployed"
+        );
+    }
+}
@@ -0,0 +1,263 @@
+//! Writing TOML documents over existing ones without losing comments or layout.
+//!
+//! The config is serialized from scratch on every write. Merging the result into the existing
+//! file keeps whatever users added by hand, e.g. comments explaining an entry, as long as the
+//! values next to it still exist. Keys ghmd doesn't know, e.g. ones added by a newer version,
+//! are kept as well.
+use toml_edit::{Array, ArrayOfTables, DocumentMut, Item, Table, Value};
+
+/// Apply the contents of the TOML document `updated` to the TOML document `existing` and return
+/// the result. Unchanged values keep their formatting and surrounding comments, new values are
+/// added and values missing from `updated` are removed. Tables in arrays of tables are matched up
+/// by their `dotfile_directory` and `symlink_directory` keys, or by position if they have none.
+///
+/// `understood` is `existing` as ghmd parsed and serialized it, if it could. Keys of `existing`
+/// missing from it are unknown to ghmd rather than removed by it, so they are kept. Without it
+/// every key missing from `updated` is removed. Returns None if a document can't be parsed.
+pub(crate) fn merge_documents(
+    existing: &str,
+    understood: Option<&str>,
+    updated: &str,
+) -> Option<String> {
+    let mut existing: DocumentMut = existing.parse().ok()?;
+    let understood: Option<DocumentMut> = match understood {
+        Some(understood) => Some(understood.parse().ok()?),
+        None => None,
+    };
+    let updated: DocumentMut = updated.parse().ok()?;
+    merge_table(
+        existing.as_table_mut(),
+        understood.as_ref().map(DocumentMut::as_table),
+        updated.as_table().clone(),
+    );
+    Some(existing.to_string())
+}
+
+fn merge_table(old: &mut Table, understood: Option<&Table>, new: Table) {
+    old.retain(|key, _| {
+        new.contains_key(key) || understood.is_some_and(|u| !u.contains_key(key))
+    });
+    for (key, item) in new {
+        match old.get_mut(&key) {
+            Some(existing) => {
+                merge_item(existing, understood.and_then(|u| u.get(&key)), item)
+            },
+            None => {
+                let _ = old.insert(&key, item);
+            },
+        }
+    }
+}
+
+fn merge_item(old: &mut Item, understood: Option<&Item>, new: Item) {
+    match (old, new) {
+        (Item::Table(old), Item::Table(new)) => {
+            merge_table(old, understood.and_then(Item::as_table), new)
+        },
+        (Item::ArrayOfTables(old), Item::ArrayOfTables(new)) => {
+            merge_array_of_tables(old, understood.and_then(Item::as_array_of_tables), new)
+        },
+        (Item::Value(old), Item::Value(new)) => merge_value(old, new),
+        (old, new) => *old = new,
+    }
+}
+
+fn merge_value(old: &mut Value, new: Value) {
+    if same_value(old, &new) {
+        return;
+    }
+    match (old, new) {
+        (Value::Array(old), Value::Array(new)) if !old.is_empty() => {
+            merge_array(old, new)
+        },
+        (old, mut new) => {
+            *new.decor_mut() = old.decor().clone();
+            *old = new;
+        },
+    }
+}
+
+/// Replace the items of `old` with those of `new`, keeping the formatting of items present in
+/// both. Added items are indented like the last item of `old`.
+///
+/// The whitespace and comments following an item on its line are stored as part of the next
+/// item, so they are moved along with the item they follow.
+fn merge_array(old: &mut Array, new: Array) {
+    let prefixes: Vec<String> = old
+        .iter()
+        .map(|v| {
+            v.decor()
+                .prefix()
+                .and_then(|p| p.as_str())
+                .unwrap_or("")
+                .to_string()
+        })
+        .collect();
+    // the part of a prefix up to the first line break follows the previous item
+    let split = |prefix: &str| -> (String, String) {
+        let (head, leading) = prefix.split_at(prefix.find('\n').unwrap_or(prefix.len()));
+        (head.to_string(), leading.to_string())
+    };
+    let multiline = prefixes.iter().any(|p| p.contains('\n'));
+    let default_tail = if multiline { "" } else { " " };
+    let new_leading = match prefixes.last() {
+        Some(p) => p[p.rfind('\n').unwrap_or(p.len())..].to_string(),
+        None => String::new(),
+    };
+    let (last_tail, trailing) = split(old.trailing().as_str().unwrap_or(""));
+
+    let mut previous: Vec<(Value, String, String)> = old
+        .iter()
+        .enumerate()
+        .map(|(i, v)| {
+            let tail = match prefixes.get(i + 1) {
+                Some(next) => split(next).0,
+                None => last_tail.clone(),
+            };
+            (v.clone(), split(&prefixes[i]).1, tail)
+        })
+        .collect();
+
+    let mut tail = prefixes.first().map(|p| split(p).0).unwrap_or_default();
+    let mut merged = Array::new();
+    for value in new {
+        let (mut value, leading, next_tail) =
+            match previous.iter().position(|(v, _, _)| same_value(v, &value)) {
+                Some(index) => previous.remove(index),
+                None => {
+                    let mut value = value;
+                    value.decor_mut().set_suffix("");
+                    // the last item of a single line array is followed by nothing
+                    let leading = match multiline || merged.is_empty() || !tail.is_empty()
+                    {
+                        true => new_leading.clone(),
+                        false => " ".to_string(),
+                    };
+                    (value, leading, default_tail.to_string())
+                },
+            };
+        value
+            .decor_mut()
+            .set_prefix(format!("{0}{1}", tail, leading));
+        merged.push_formatted(value);
+        tail = next_tail;
+    }
+
+    // keep comments following the last item, but not separators
+    if !tail.contains('#') {
+        tail.clear();
+    }
+    merged.set_trailing(format!("{0}{1}", tail, trailing));
+    merged.set_trailing_comma(old.trailing_comma());
+    *merged.decor_mut() = old.decor().clone();
+    *old = merged;
+}
+
+/// Merge the tables of `new` into the matching tables of `old`. The tables of `understood`
+/// correspond to those of `old` by position, since it is the same document serialized again.
+fn merge_array_of_tables(
+    old: &mut ArrayOfTables,
+    understood: Option<&ArrayOfTables>,
+    new: ArrayOfTables,
+) {
+    let mut previous: Vec<Option<Table>> = old.iter().cloned().map(Some).collect();
+    let mut position = old.iter().filter_map(Table::position).min();
+    let mut merged = ArrayOfTables::new();
+    for (index, table) in new.into_iter().enumerate() {
+        let matching = match identity(&table) {
+            Some(id) => previous
+                .iter()
+                .position(|t| t.as_ref().and_then(identity) == Some(id)),
+            None => previous
+                .get(index)
+                .and_then(|t| t.as_ref())
+                .filter(|t| identity(t).is_none())
+                .map(|_| index),
+        };
+        let mut table = match matching.and_then(|i| previous[i].take()) {
+            Some(mut existing) => {
+                let understood = match (matching, understood) {
+                    (Some(i), Some(understood)) => understood.get(i),
+                    _ => None,
+                };
+                merge_table(&mut existing, understood, table);
+                existing
+            },
+            None => table,
+        };
+        if let Some(position) = position.as_mut() {
+            table.set_position(*position);
+            *position += 1;
+        }
+        merged.push(table);
+    }
+    *old = merged;
+}
+
+/// The directories identifying a dotfiles entry, if `table` is one.
+fn identity(table: &Table) -> Option<(&str, &str)> {
+    let dotfile_directory = table.get("dotfile_directory")?.as_str()?;
+    let symlink_directory = table.get("symlink_directory")?.as_str()?;
+    Some((dotfile_directory, symlink_directory))
+}
+
+/// Whether two values are equal, ignoring their formatting.
+fn same_value(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::String(a), Value::String(b)) => a.value() == b.value(),
+        (Value::Integer(a), Value::Integer(b)) => a.value() == b.value(),
+        (Value::Float(a), Value::Float(b)) => a.value().to_bits() == b.value().to_bits(),
+        (Value::Boolean(a), Value::Boolean(b)) => a.value() == b.value(),
+        (Value::Datetime(a), Value::Datetime(b)) => a.value() == b.value(),
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| same_value(a, b))
+        },
+        (Value::InlineTable(a), Value::InlineTable(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(key, v)| b.get(key).is_some_and(|w| same_value(v, w)))
+        },
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXISTING: &str = "\
+# managed by ghmd
+[[dotfiles]]
+# my vim setup
+dotfile_directory = '/dots'
+symlink_directory = '/home'
+paths = ['.vimrc']
+from_the_future = true
+profiles = ['work']
+";
+
+    #[test]
+    fn comments_survive_added_values() {
+        let updated = "[[dotfiles]]\ndotfile_directory = \"/dots\"\nsymlink_directory = \
+                       \"/home\"\npaths = [\".vimrc\", \".bashrc\"]\nprofiles = [\"work\"]\n";
+        let merged = merge_documents(EXISTING, None, updated).unwrap();
+        assert!(merged.starts_with("# managed by ghmd\n[[dotfiles]]\n# my vim setup\n"));
+        assert!(merged.contains("paths = ['.vimrc', \".bashrc\"]"));
+    }
+
+    #[test]
+    fn unknown_keys_are_kept_and_removed_keys_are_not() {
+        // the existing file as ghmd understood it, and with its profiles cleared
+        let understood =
+            "[[dotfiles]]\ndotfile_directory = \"/dots\"\nsymlink_directory = \
+                          \"/home\"\npaths = [\".vimrc\"]\nprofiles = [\"work\"]\n";
+        let updated = "[[dotfiles]]\ndotfile_directory = \"/dots\"\nsymlink_directory = \
+                       \"/home\"\npaths = [\".vimrc\"]\n";
+        let merged = merge_documents(EXISTING, Some(understood), updated).unwrap();
+        assert!(merged.contains("from_the_future = true"));
+        assert!(!merged.contains("profiles"));
+
+        let merged = merge_documents(EXISTING, None, updated).unwrap();
+        assert!(!merged.contains("from_the_future"));
+    }
+}
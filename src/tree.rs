@@ -0,0 +1,188 @@
+//! Tree previews of the symlinks and directories a deploy would create.
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::config::{Config, DeployOutcome, ResolvedPath};
+use crate::errors::Error;
+use crate::options::DeployOptions;
+
+/// TreeMark tells whether a node of a [`DeployTree`] already exists or would be created.
+#[derive(Debug)]
+pub enum TreeMark {
+    /// The symlink or directory would be created.
+    Planned,
+
+    /// The symlink or directory is already in place.
+    Existing,
+
+    /// The dotfile would be left alone, e.g. because it is on the skip list of this machine.
+    Skipped,
+
+    /// Deploying the dotfile would fail.
+    Failed(Error),
+}
+
+impl fmt::Display for TreeMark {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Planned => f.pad("new"),
+            Self::Existing => f.pad("exists"),
+            Self::Skipped => f.pad("skipped"),
+            Self::Failed(e) => f.pad(&format!("fails: {0}", e)),
+        }
+    }
+}
+
+/// TreeNode is a directory or symlink in a [`DeployTree`].
+#[derive(Debug)]
+pub struct TreeNode {
+    /// Whether the node exists or would be created.
+    pub mark: TreeMark,
+
+    /// The stored dotfile the node is the symlink of, or `None` for directories.
+    pub dotfile: Option<PathBuf>,
+
+    /// Entries of a directory by name.
+    pub children: BTreeMap<OsString, TreeNode>,
+}
+
+impl TreeNode {
+    fn directory(path: &Path) -> Self {
+        let mark = match path.exists() {
+            true => TreeMark::Existing,
+            false => TreeMark::Planned,
+        };
+        Self {
+            mark,
+            dotfile: None,
+            children: BTreeMap::new(),
+        }
+    }
+
+    fn render(&self, name: &str, prefix: &str, last: bool, lines: &mut Vec<String>) {
+        let branch = if last { "└── " } else { "├── " };
+        lines.push(format!("{0}{1}{2}", prefix, branch, self.label(name)));
+        let prefix = format!("{0}{1}", prefix, if last { "    " } else { "│   " });
+        self.render_children(&prefix, lines);
+    }
+
+    fn render_children(&self, prefix: &str, lines: &mut Vec<String>) {
+        let count = self.children.len();
+        for (index, (name, child)) in self.children.iter().enumerate() {
+            child.render(&name.to_string_lossy(), prefix, index + 1 == count, lines);
+        }
+    }
+
+    fn label(&self, name: &str) -> String {
+        match &self.dotfile {
+            Some(dotfile) => {
+                format!("{0} -> {1} [{2}]", name, dotfile.display(), self.mark)
+            },
+            None => format!("{0}/ [{1}]", name, self.mark),
+        }
+    }
+}
+
+/// DeployTree is the tree of symlinks deploying a set of dotfiles would create, rooted at the
+/// symlink directories, along with the directories that would be created to hold them.
+#[derive(Debug, Default)]
+pub struct DeployTree {
+    /// Trees below each symlink directory.
+    pub roots: BTreeMap<PathBuf, TreeNode>,
+}
+
+impl DeployTree {
+    /// Render the tree as lines of text, one per node, with each symlink directory at the top
+    /// of its own tree.
+    pub fn render(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        for (root, node) in &self.roots {
+            lines.push(format!("{0} [{1}]", root.display(), node.mark));
+            node.render_children("", &mut lines);
+        }
+        lines
+    }
+
+    /// The symlinks in the tree and their marks as pairs of absolute symlink path and node, in
+    /// the order they are rendered.
+    pub fn symlinks(&self) -> Vec<(PathBuf, &TreeNode)> {
+        let mut symlinks = Vec::new();
+        for (root, node) in &self.roots {
+            collect_symlinks(root, node, &mut symlinks);
+        }
+        symlinks
+    }
+
+    fn insert(&mut self, root: &Path, relative: &Path, node: TreeNode) {
+        let mut parent = self
+            .roots
+            .entry(root.to_path_buf())
+            .or_insert_with(|| TreeNode::directory(root));
+        let mut path = root.to_path_buf();
+        let mut components = relative.iter().peekable();
+        while let Some(name) = components.next() {
+            path.push(name);
+            if components.peek().is_none() {
+                let _ = parent.children.insert(name.to_os_string(), node);
+                return;
+            }
+            parent = parent
+                .children
+                .entry(name.to_os_string())
+                .or_insert_with(|| TreeNode::directory(&path));
+        }
+    }
+}
+
+fn collect_symlinks<'a>(
+    path: &Path,
+    node: &'a TreeNode,
+    symlinks: &mut Vec<(PathBuf, &'a TreeNode)>,
+) {
+    for (name, child) in &node.children {
+        let path = path.join(name);
+        if child.dotfile.is_some() {
+            symlinks.push((path.clone(), child));
+        }
+        collect_symlinks(&path, child, symlinks);
+    }
+}
+
+impl Config {
+    /// Preview deploying the dotfiles `resolved` as a [`DeployTree`] without creating anything.
+    /// Every dotfile goes through the same checks as with [`Config::deploy_all`], including
+    /// backing up files in the way; with `skip_listed` dotfiles on the skip list of this machine
+    /// are marked as skipped. A dotfile that would fail to deploy is marked with its error rather
+    /// than failing the preview.
+    pub fn deploy_tree<'a>(
+        &self,
+        resolved: impl IntoIterator<Item = ResolvedPath<'a>>,
+        skip_listed: bool,
+    ) -> DeployTree {
+        let options = DeployOptions::new()
+            .dry_run(true)
+            .backup(true)
+            .skip_broken(true)
+            .skip_listed(skip_listed);
+        let mut tree = DeployTree::default();
+        for resolved in resolved {
+            let dotfiles = &self.dotfiles[resolved.entry_index];
+            let root = dotfiles.symlink_dir();
+            let mark = match self.deploy_dotfile(dotfiles, resolved.relative, &options) {
+                Ok(DeployOutcome::Created { .. }) => TreeMark::Planned,
+                Ok(DeployOutcome::Unchanged) => TreeMark::Existing,
+                Ok(DeployOutcome::Skipped) => TreeMark::Skipped,
+                Err(e) => TreeMark::Failed(e),
+            };
+            let node = TreeNode {
+                mark,
+                dotfile: Some(resolved.store),
+                children: BTreeMap::new(),
+            };
+            tree.insert(root, resolved.relative, node);
+        }
+        tree
+    }
+}
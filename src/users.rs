@@ -0,0 +1,414 @@
+//! Deploying dotfiles into the home directories of other users of the machine, e.g. by the
+//! admin of a shared workstation.
+//!
+//! Root never touches the home directory of another user itself, since that user controls every
+//! directory in it and could e.g. replace `~/.config` by a symlink to `/etc` at any time.
+//! Instead ghmd runs itself as that user to deploy there, see [`Config::deploy_for_user`].
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+
+use chrono::Local;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::config::{Config, DeploySummary};
+use crate::conflict::Conflict;
+use crate::errors::{Error, Result};
+use crate::observer::{Action, ActionOutcome, Observer, Resolution};
+use crate::options::DeployOptions;
+use crate::xdg::Dirs;
+
+/// The password database file users are looked up in.
+const PASSWD: &str = "/etc/passwd";
+
+/// Name of the hidden subcommand of the `ghmd` binary that runs the part of
+/// [`Config::deploy_for_user`] done as the other user, see [`Config::deploy_for_parent`].
+pub const USER_DEPLOY_SUBCOMMAND: &str = "deploy-for-parent";
+
+/// User is an account of this machine as listed in `/etc/passwd`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct User {
+    /// Login name of the user.
+    pub name: String,
+
+    /// Numeric user ID.
+    pub uid: u32,
+
+    /// Numeric ID of the primary group of the user.
+    pub gid: u32,
+
+    /// Home directory of the user.
+    pub home: PathBuf,
+}
+
+impl User {
+    /// Look up the user named `name` in `/etc/passwd`. Fails with [`Error::UnknownUser`] if
+    /// there is no such user.
+    pub fn lookup(name: &str) -> Result<Self> {
+        parse_passwd(&fs::read_to_string(PASSWD)?)
+            .into_iter()
+            .find(|user| user.name == name)
+            .ok_or_else(|| Error::UnknownUser(name.to_string()))
+    }
+
+    /// Look up the user with the id `uid` in `/etc/passwd`, if there is one.
+    pub fn by_uid(uid: u32) -> Option<Self> {
+        parse_passwd(&fs::read_to_string(PASSWD).ok()?)
+            .into_iter()
+            .find(|user| user.uid == uid)
+    }
+
+    /// The user ghmd runs as, if it can be determined.
+    pub fn current() -> Option<Self> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            // `/proc/self` belongs to the effective user of the process
+            Self::by_uid(fs::metadata("/proc/self").ok()?.uid())
+        }
+        #[cfg(not(unix))]
+        {
+            None
+        }
+    }
+}
+
+/// Parse the lines of a password database file, leaving out malformed ones.
+fn parse_passwd(contents: &str) -> Vec<User> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(':').collect();
+            match fields[..] {
+                [name, _, uid, gid, _, home, ..] if !name.starts_with('#') => {
+                    Some(User {
+                        name: name.to_string(),
+                        uid: uid.parse().ok()?,
+                        gid: gid.parse().ok()?,
+                        home: PathBuf::from(home),
+                    })
+                },
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Whether ghmd runs as root. This is never the case on platforms other than unix, and if it
+/// can't be determined.
+pub fn running_as_root() -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        // `/proc/self` belongs to the effective user of the process
+        fs::metadata("/proc/self")
+            .map(|metadata| metadata.uid() == 0)
+            .unwrap_or(false)
+    }
+    #[cfg(not(unix))]
+    {
+        false
+    }
+}
+
+/// UserDeployReport is what the process deploying as another user reports back to root.
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct UserDeployReport {
+    created: usize,
+    unchanged: usize,
+    skipped: usize,
+    created_dirs: Vec<PathBuf>,
+    operations: Vec<String>,
+}
+
+impl Config {
+    /// Deploy all dotfiles whose symlink directory is inside the home directory of the invoking
+    /// user into the home directory of `user` instead, see [`Config::with_home_dir`]. Dotfiles
+    /// on the skip list of `user` and dotfiles whose symlink path is occupied are skipped, so
+    /// nothing of `user` is replaced. Every deployed symlink is recorded in the operations log,
+    /// see [`Dirs::operations_log`].
+    ///
+    /// The deploy itself is done by the current executable running as `user`, given the
+    /// [`USER_DEPLOY_SUBCOMMAND`], so symlinks and directories are created with the permissions
+    /// of `user` and belong to them. Fails with [`Error::NotRoot`] unless ghmd runs as root.
+    pub fn deploy_for_user(&self, user: &User) -> Result<DeploySummary> {
+        if !running_as_root() {
+            return Err(Error::NotRoot);
+        }
+        let dirs = self.xdg_dirs().ok_or(Error::MissingHomeDirectory)?;
+        let config = self.with_home_dir(dirs.home_dir()?, &user.home)?;
+        let input = toml::to_string(&config).unwrap();
+        let report = run_as(user, &input)?;
+
+        let log = OperationsLog::new();
+        for line in report.operations {
+            log.record(line);
+        }
+        log.write(&dirs.operations_log(), &user.name)?;
+        Ok(DeploySummary {
+            created: report.created,
+            unchanged: report.unchanged,
+            skipped: report.skipped,
+            created_dirs: report.created_dirs,
+        })
+    }
+
+    /// Deploy the dotfiles of the config read from `input` into the home directory of the user
+    /// ghmd runs as, and write a report of what was done to `output`. This is the part of
+    /// [`Config::deploy_for_user`] done as the other user; the skip list is read from their own
+    /// state file.
+    #[doc(hidden)]
+    pub fn deploy_for_parent(input: &mut dyn Read, output: &mut dyn Write) -> Result<()> {
+        let mut contents = String::new();
+        let _ = input.read_to_string(&mut contents)?;
+        let mut config = Config::from_parent(&contents)?;
+        let log = OperationsLog::new();
+        let options = DeployOptions::new()
+            .skip_conflicts(true)
+            .skip_broken(true)
+            .skip_listed(true)
+            .observer(&log);
+        let summary = config.deploy_all_with(&options)?;
+        let report = UserDeployReport {
+            created: summary.created,
+            unchanged: summary.unchanged,
+            skipped: summary.skipped,
+            created_dirs: summary.created_dirs,
+            operations: log.take(),
+        };
+        serde_json::to_writer(&mut *output, &report)
+            .map_err(|e| Error::UserDeployFailed(String::new(), e.to_string()))?;
+        output.flush()?;
+        Ok(())
+    }
+}
+
+/// Run the current executable as `user` to deploy the config `input` into their home directory.
+#[cfg(unix)]
+fn run_as(user: &User, input: &str) -> Result<UserDeployReport> {
+    use std::os::unix::process::CommandExt;
+    use std::process::{Command, Stdio};
+
+    let mut command = Command::new(env::current_exe()?);
+    let _ = command
+        .arg(USER_DEPLOY_SUBCOMMAND)
+        .env_clear()
+        .env("HOME", &user.home)
+        .env("USER", &user.name)
+        .env("LOGNAME", &user.name)
+        .current_dir("/")
+        .uid(user.uid)
+        .gid(user.gid)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    for key in ["PATH", "RUST_LOG"] {
+        if let Some(value) = env::var_os(key) {
+            let _ = command.env(key, value);
+        }
+    }
+    let failed = |message: String| Error::UserDeployFailed(user.name.clone(), message);
+
+    let mut child = command.spawn()?;
+    // the child reads all of its input before writing anything
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(failed(stderr.trim().to_string()));
+    }
+    serde_json::from_slice(&output.stdout).map_err(|e| failed(e.to_string()))
+}
+
+#[cfg(not(unix))]
+fn run_as(_user: &User, _input: &str) -> Result<UserDeployReport> {
+    Err(Error::NotRoot)
+}
+
+/// OperationsLog is an [`Observer`] collecting the changes a deploy or restore makes for the
+/// operations log, see [`Dirs::operations_log`]. Every event is passed on to another observer,
+/// if any.
+#[derive(Debug, Default)]
+pub struct OperationsLog<'a> {
+    inner: Option<&'a dyn Observer>,
+    lines: Mutex<Vec<String>>,
+}
+
+impl<'a> OperationsLog<'a> {
+    /// An operations log that passes events on to no other observer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An operations log that passes every event on to `inner`.
+    pub fn forwarding_to(inner: &'a dyn Observer) -> Self {
+        Self {
+            inner: Some(inner),
+            lines: Mutex::default(),
+        }
+    }
+
+    /// Add `line` to the log.
+    pub fn record(&self, line: String) {
+        self.lines
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(line);
+    }
+
+    fn take(&self) -> Vec<String> {
+        std::mem::take(&mut *self.lines.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Append the recorded lines to the operations log at `path`, each with the time and the
+    /// user they were made for.
+    pub fn write(&self, path: &Path, user: &str) -> Result<()> {
+        let lines = self.take();
+        if lines.is_empty() {
+            return Ok(());
+        }
+        if let Some(parent) = path.parent() {
+            Dirs::create(parent)?;
+        }
+        let mut log = OpenOptions::new().create(true).append(true).open(path)?;
+        let now = Local::now().to_rfc3339();
+        for line in lines.iter() {
+            writeln!(log, "{0} user={1} {2}", now, user, line)?;
+        }
+        log.sync_data()?;
+        Ok(())
+    }
+}
+
+impl Observer for OperationsLog<'_> {
+    fn on_action_start(&self, action: &Action<'_>) {
+        if let Some(inner) = self.inner {
+            inner.on_action_start(action);
+        }
+    }
+
+    fn on_action_done(&self, action: &Action<'_>, outcome: &ActionOutcome<'_>) {
+        match outcome {
+            ActionOutcome::Done => self.record(action.to_string()),
+            ActionOutcome::Failed(e) => {
+                self.record(format!("{0} failed: {1}", action, e))
+            },
+            ActionOutcome::Unchanged | ActionOutcome::Skipped => (),
+        }
+        if let Some(inner) = self.inner {
+            inner.on_action_done(action, outcome);
+        }
+    }
+
+    fn on_conflict(&self, conflict: &Conflict) -> Resolution {
+        match self.inner {
+            Some(inner) => inner.on_conflict(conflict),
+            None => Resolution::Fail,
+        }
+    }
+
+    fn on_progress(&self, bytes: u64, total: u64) {
+        if let Some(inner) = self.inner {
+            inner.on_progress(bytes, total);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passwd_lines_are_parsed() {
+        let passwd = "\
+# comment
+root:x:0:0:root:/root:/bin/bash
+alice:x:1000:1000:Alice,,,:/home/alice:/bin/zsh
+broken:x:notanumber:1001::/home/broken:/bin/sh
+short:x:1002
+";
+        assert_eq!(
+            parse_passwd(passwd),
+            vec![
+                User {
+                    name: "root".to_string(),
+                    uid: 0,
+                    gid: 0,
+                    home: PathBuf::from("/root"),
+                },
+                User {
+                    name: "alice".to_string(),
+                    uid: 1000,
+                    gid: 1000,
+                    home: PathBuf::from("/home/alice"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn operations_log_appends_done_and_failed_actions() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("state/operations.log");
+        let log = OperationsLog::new();
+        let error = Error::NotRoot;
+        log.on_action_done(&Action::Deploy(Path::new(".vimrc")), &ActionOutcome::Done);
+        log.on_action_done(
+            &Action::Deploy(Path::new(".bashrc")),
+            &ActionOutcome::Unchanged,
+        );
+        log.on_action_done(
+            &Action::Deploy(Path::new(".zshrc")),
+            &ActionOutcome::Failed(&error),
+        );
+        log.write(&path, "alice").unwrap();
+        log.record("restore .vimrc".to_string());
+        log.write(&path, "alice").unwrap();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].ends_with(" user=alice deploy .vimrc"));
+        assert!(lines[1].contains(" user=alice deploy .zshrc failed: "));
+        assert!(lines[2].ends_with(" user=alice restore .vimrc"));
+    }
+
+    #[test]
+    fn deploy_for_parent_deploys_into_own_home() {
+        let tmp = tempfile::tempdir().unwrap();
+        let store = tmp.path().join("dots");
+        let home = tmp.path().join("home");
+        fs::create_dir_all(&store).unwrap();
+        fs::create_dir_all(&home).unwrap();
+        fs::write(store.join(".vimrc"), "set nocompatible\n").unwrap();
+        fs::write(store.join(".bashrc"), "# bash\n").unwrap();
+        fs::write(home.join(".bashrc"), "# the user's own\n").unwrap();
+
+        let input = format!(
+            "[[dotfiles]]\n\
+             dotfile_directory = {0:?}\n\
+             symlink_directory = {1:?}\n\
+             paths = [\".vimrc\", \".bashrc\"]\n",
+            store.display().to_string(),
+            home.display().to_string()
+        );
+        let mut output = Vec::new();
+        Config::deploy_for_parent(&mut input.as_bytes(), &mut output).unwrap();
+        let report: UserDeployReport = serde_json::from_slice(&output).unwrap();
+
+        assert_eq!(report.created, 1);
+        assert!(report.operations.contains(&"deploy .vimrc".to_string()));
+        assert_eq!(
+            fs::read_link(home.join(".vimrc")).unwrap(),
+            store.join(".vimrc")
+        );
+        assert_eq!(
+            fs::read_to_string(home.join(".bashrc")).unwrap(),
+            "# the user's own\n"
+        );
+    }
+}
@@ -0,0 +1,244 @@
+//! Recording changes to dotfiles directories that are kept in version control.
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::{Config, DotfilePath};
+use crate::errors::{Error, Result};
+use crate::machine::MachineState;
+use crate::remote::GitRunner;
+
+/// Files or directories whose presence marks a directory as the root of a repository.
+pub const VCS_MARKERS: [&str; 2] = [".git", ".hg"];
+
+/// Contents of the `.gitignore` created by [`init_repo`], leaving out the files ghmd keeps per
+/// machine in case they end up inside a dotfiles directory.
+const STARTER_GITIGNORE: &str = "\
+# files ghmd keeps per machine, see `ghmd config paths`
+state.toml
+operations.log
+*.tmp
+*.bak
+";
+
+/// Whether `dir` or one of its parents contains one of [`VCS_MARKERS`] or `markers`, i.e. is
+/// under version control. Unlike [`is_git_repo`] this only looks at the filesystem.
+pub fn is_version_controlled(dir: &Path, markers: &[String]) -> bool {
+    let markers = VCS_MARKERS
+        .into_iter()
+        .chain(markers.iter().map(String::as_str));
+    dir.ancestors().any(|dir| {
+        markers
+            .clone()
+            .any(|marker| dir.join(marker).symlink_metadata().is_ok())
+    })
+}
+
+/// Turn `dir` into a git repository with a starter `.gitignore`, unless it already has one.
+/// Failures are reported as [`Error::GitFailed`].
+pub fn init_repo(dir: &Path, git: &dyn GitRunner) -> Result<()> {
+    let _ = git.git(dir, &["init", "--quiet"])?;
+    let gitignore = dir.join(".gitignore");
+    if gitignore.symlink_metadata().is_err() {
+        fs::write(&gitignore, STARTER_GITIGNORE)?;
+    }
+    Ok(())
+}
+
+impl Config {
+    /// Dotfiles directories among `dirs` that aren't under version control, see
+    /// [`is_version_controlled`] and [`Config::vcs_markers`]. Empty if [`Config::vcs_warning`]
+    /// is off.
+    pub fn unversioned_dirs<'a>(
+        &self,
+        dirs: impl IntoIterator<Item = &'a Path>,
+    ) -> Vec<PathBuf> {
+        if !self.vcs_warning() {
+            return Vec::new();
+        }
+        let mut unversioned: Vec<PathBuf> = dirs
+            .into_iter()
+            .filter(|dir| !is_version_controlled(dir, self.vcs_markers()))
+            .map(Path::to_path_buf)
+            .collect();
+        unversioned.sort();
+        unversioned.dedup();
+        unversioned
+    }
+
+    /// Warn about each dotfiles directory among `dirs` that isn't under version control, see
+    /// [`Config::unversioned_dirs`]. Every directory is only warned about once per machine; the
+    /// directories warned about are recorded in the [`MachineState`].
+    pub fn warn_unversioned<'a>(
+        &self,
+        dirs: impl IntoIterator<Item = &'a Path>,
+    ) -> Result<()> {
+        let unversioned = self.unversioned_dirs(dirs);
+        if unversioned.is_empty() {
+            return Ok(());
+        }
+        let mut state = MachineState::load()?;
+        let mut warned = false;
+        for dir in unversioned
+            .iter()
+            .filter(|dir| state.record_vcs_warning(dir))
+        {
+            log::warn!(
+                "dotfiles directory {0} is not under version control, `ghmd doctor \
+                 --fix` can turn it into a git repository",
+                dir.display()
+            );
+            warned = true;
+        }
+        if warned {
+            state.write()?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether `dir` is inside the work tree of a git repository. Failing to run `git` counts as
+/// not being in one.
+pub fn is_git_repo(dir: &Path, git: &dyn GitRunner) -> bool {
+    git.git(dir, &["rev-parse", "--is-inside-work-tree"])
+        .map(|output| output.trim_ascii() == b"true")
+        .unwrap_or(false)
+}
+
+/// Add the dotfiles `paths` of the dotfiles directory `dir` to its git repository and commit
+/// them, and only them, with `message`. Returns whether a commit was made: nothing is done if
+/// `dir` isn't a git repository or the paths have no changes to commit. Failures are reported
+/// as [`Error::GitCommitFailed`].
+pub fn commit_paths(
+    dir: &Path,
+    paths: &[DotfilePath],
+    message: &str,
+    git: &dyn GitRunner,
+) -> Result<bool> {
+    if paths.is_empty() {
+        return Ok(false);
+    }
+    if !is_git_repo(dir, git) {
+        log::info!("{0} is not a git repository, not committing", dir.display());
+        return Ok(false);
+    }
+
+    let failed = |e: Error| Error::GitCommitFailed(dir.to_path_buf(), e.to_string());
+    let paths: Vec<String> = paths
+        .iter()
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect();
+    let _ = git
+        .git(dir, &with_paths(&["add", "--"], &paths))
+        .map_err(failed)?;
+    let changes = git
+        .git(dir, &with_paths(&["status", "--porcelain", "--"], &paths))
+        .map_err(failed)?;
+    if changes.is_empty() {
+        log::info!("nothing to commit in {0}", dir.display());
+        return Ok(false);
+    }
+    let commit = ["commit", "--quiet", "--message", message, "--"];
+    let _ = git.git(dir, &with_paths(&commit, &paths)).map_err(failed)?;
+    Ok(true)
+}
+
+fn with_paths<'a>(args: &[&'a str], paths: &'a [String]) -> Vec<&'a str> {
+    let mut args = args.to_vec();
+    args.extend(paths.iter().map(String::as_str));
+    args
+}
+
+/// The default commit message for stowing `paths`.
+pub fn stow_commit_message(paths: &[DotfilePath]) -> String {
+    match paths {
+        [path] => format!("Stow {0}", path.display()),
+        paths if paths.len() <= 3 => {
+            let names: Vec<String> = paths
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect();
+            format!("Stow {0}", names.join(", "))
+        },
+        paths => format!("Stow {0} files", paths.len()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::RefCell;
+
+    /// Records the git commands it is asked to run and creates the `.git` directory on `init`.
+    #[derive(Default)]
+    struct Recorder {
+        calls: RefCell<Vec<String>>,
+    }
+
+    impl GitRunner for Recorder {
+        fn git(&self, dir: &Path, args: &[&str]) -> Result<Vec<u8>> {
+            self.calls.borrow_mut().push(args.join(" "));
+            if args.first() == Some(&"init") {
+                fs::create_dir(dir.join(".git"))?;
+            }
+            Ok(Vec::new())
+        }
+    }
+
+    #[test]
+    fn directories_inside_repositories_are_version_controlled() {
+        let tmp = tempfile::tempdir().unwrap();
+        let nested = tmp.path().join("repo/dots/nested");
+        fs::create_dir_all(&nested).unwrap();
+        assert!(!is_version_controlled(&nested, &[]));
+
+        let markers = ["_darcs".to_string()];
+        fs::create_dir(tmp.path().join("repo/dots/_darcs")).unwrap();
+        assert!(!is_version_controlled(&nested, &[]));
+        assert!(is_version_controlled(&nested, &markers));
+
+        for marker in VCS_MARKERS {
+            let path = tmp.path().join("repo").join(marker);
+            fs::create_dir(&path).unwrap();
+            assert!(is_version_controlled(&nested, &[]), "{0}", marker);
+            fs::remove_dir(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn init_repo_keeps_an_existing_gitignore() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (fresh, ignoring) = (tmp.path().join("fresh"), tmp.path().join("ignoring"));
+        fs::create_dir(&fresh).unwrap();
+        fs::create_dir(&ignoring).unwrap();
+        fs::write(ignoring.join(".gitignore"), "secrets\n").unwrap();
+
+        let git = Recorder::default();
+        init_repo(&fresh, &git).unwrap();
+        init_repo(&ignoring, &git).unwrap();
+        assert_eq!(*git.calls.borrow(), ["init --quiet", "init --quiet"]);
+        assert!(is_version_controlled(&fresh, &[]));
+        let gitignore = fs::read_to_string(fresh.join(".gitignore")).unwrap();
+        assert!(gitignore.contains("state.toml"), "{0}", gitignore);
+        assert_eq!(
+            fs::read_to_string(ignoring.join(".gitignore")).unwrap(),
+            "secrets\n"
+        );
+    }
+
+    #[test]
+    fn warnings_are_recorded_once_per_directory() {
+        let tmp = tempfile::tempdir().unwrap();
+        let state_file = tmp.path().join("state.toml");
+        let (a, b) = (Path::new("/dots/a"), Path::new("/dots/b"));
+
+        let mut state = MachineState::load_from(&state_file).unwrap();
+        assert!(state.record_vcs_warning(a));
+        assert!(!state.record_vcs_warning(a));
+        state.write_to(&state_file).unwrap();
+
+        let mut state = MachineState::load_from(&state_file).unwrap();
+        assert!(!state.record_vcs_warning(a));
+        assert!(state.record_vcs_warning(b));
+    }
+}
@@ -0,0 +1,111 @@
+//! Versioning of files persisted by ghmd.
+use std::path::Path;
+
+use crate::errors::{Error, Result};
+
+/// Version of the running ghmd binary.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Version of the config file schema supported by the running ghmd binary.
+pub const CONFIG_SCHEMA_VERSION: u32 = 1;
+
+/// Check whether `file`, written by ghmd version `written`, can safely be used by the running
+/// version of ghmd. Files written by a newer major version are rejected, files written by a newer
+/// minor version only produce a warning. Before 1.0 every minor version may be incompatible, so
+/// there files written by a newer minor version are rejected and a newer patch version warns.
+pub fn check_compatible(file: &Path, written: &str) -> Result<()> {
+    check_against(file, written, VERSION)
+}
+
+fn check_against(file: &Path, written: &str, running: &str) -> Result<()> {
+    let written_parts = version_parts(written);
+    let running_parts = version_parts(running);
+    // number of leading components that must not be newer, the major version or before 1.0 the
+    // minor version as well
+    let breaking = match (written_parts[0], running_parts[0]) {
+        (0, 0) => 2,
+        _ => 1,
+    };
+
+    if written_parts[..breaking] > running_parts[..breaking] {
+        return Err(Error::WrittenByNewerVersion(
+            file.to_path_buf(),
+            written.to_string(),
+            running.to_string(),
+        ));
+    }
+    if written_parts[..=breaking] > running_parts[..=breaking] {
+        log::warn!(
+            "{0} written by ghmd {1}, you are running {2}; some settings may be ignored",
+            file.display(),
+            written,
+            running
+        );
+    }
+    Ok(())
+}
+
+/// Check whether the config file `file`, written with config schema version `written`, can be
+/// used by the running version of ghmd, which only understands schemas up to
+/// [`CONFIG_SCHEMA_VERSION`].
+pub fn check_schema(file: &Path, written: u32) -> Result<()> {
+    if written > CONFIG_SCHEMA_VERSION {
+        return Err(Error::UnsupportedSchemaVersion(
+            file.to_path_buf(),
+            written,
+            CONFIG_SCHEMA_VERSION,
+        ));
+    }
+    Ok(())
+}
+
+/// The major, minor and patch numbers of `version`, with missing or unparsable numbers as 0.
+fn version_parts(version: &str) -> [u64; 3] {
+    let mut parts = version
+        .split(['.', '-', '+'])
+        .map(|part| part.parse::<u64>().unwrap_or(0));
+    [(); 3].map(|()| parts.next().unwrap_or(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compatible(written: &str, running: &str) -> bool {
+        check_against(Path::new("config.toml"), written, running).is_ok()
+    }
+
+    #[test]
+    fn newer_major_versions_are_rejected() {
+        assert!(!compatible("2.0.0", "1.9.9"));
+        assert!(compatible("1.9.0", "1.2.0"));
+        assert!(compatible("1.2.9", "1.2.0"));
+        assert!(compatible("1.0.0", "2.0.0"));
+    }
+
+    #[test]
+    fn newer_minor_versions_are_rejected_before_1_0() {
+        assert!(!compatible("0.3.0", "0.2.5"));
+        assert!(!compatible("1.0.0", "0.9.0"));
+        assert!(compatible("0.2.9", "0.2.0"));
+        assert!(compatible("0.1.0", "0.2.0"));
+        assert!(compatible("0.9.0", "1.0.0"));
+    }
+
+    #[test]
+    fn newer_schema_versions_are_rejected() {
+        let file = Path::new("config.toml");
+        assert!(check_schema(file, CONFIG_SCHEMA_VERSION).is_ok());
+        assert!(matches!(
+            check_schema(file, CONFIG_SCHEMA_VERSION + 1),
+            Err(Error::UnsupportedSchemaVersion(_, _, _))
+        ));
+    }
+
+    #[test]
+    fn version_parts_fill_in_zeros() {
+        assert_eq!(version_parts("1.2.3"), [1, 2, 3]);
+        assert_eq!(version_parts("0.4.0-rc.1"), [0, 4, 0]);
+        assert_eq!(version_parts("2"), [2, 0, 0]);
+    }
+}
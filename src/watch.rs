@@ -0,0 +1,241 @@
+//! Reacting to changes inside dotfiles directories, e.g. after pulling new commits into a store.
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Receiver;
+use std::time::Duration;
+
+use notify::event::{ModifyKind, RenameMode};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::config::{Config, DeployOutcome, DotfilePath};
+use crate::errors::{Error, Result};
+use crate::options::DeployOptions;
+use crate::xdg::Dirs;
+
+/// How long [`debounce`] waits for another change before handing over a batch of changes.
+pub const WATCH_DEBOUNCE: Duration = Duration::from_millis(300);
+
+impl Config {
+    /// Watch every dotfiles directory recursively and call `on_change` with each path that is
+    /// created or modified inside one of them. Paths are reported inside the configured
+    /// `dotfile_directory` even if the platform reports them by their canonical paths. Removed
+    /// paths aren't reported, since there is nothing left to deploy. Changes are reported until
+    /// the returned watcher is dropped. Failures of the watcher itself are logged.
+    pub fn watch(
+        &self,
+        on_change: impl Fn(&Path) + Send + 'static,
+    ) -> Result<RecommendedWatcher> {
+        let dirs: Vec<(PathBuf, PathBuf)> = self
+            .dotfiles
+            .iter()
+            .map(|dotfiles| {
+                let dir = dotfiles.dotfile_directory.to_path_buf();
+                let canonical = fs::canonicalize(&dir).unwrap_or_else(|_| dir.clone());
+                (canonical, dir)
+            })
+            .collect();
+        let watched = dirs.iter().map(|(_, dir)| dir.clone()).collect();
+        watch_paths(watched, RecursiveMode::Recursive, move |path| {
+            on_change(&configured_path(&dirs, path))
+        })
+    }
+
+    /// Watch the config file and call `on_change` with its path whenever it is replaced or
+    /// modified, e.g. by another `ghmd` process or an editor, until the returned watcher is
+    /// dropped.
+    pub fn watch_config_file(
+        on_change: impl Fn(&Path) + Send + 'static,
+    ) -> Result<RecommendedWatcher> {
+        let file = Self::file_path()?;
+        let dir = file.parent().ok_or(Error::CannotDetermineConfigDir)?;
+        Dirs::create(dir)?;
+        let watched = vec![dir.to_path_buf()];
+        watch_paths(watched, RecursiveMode::NonRecursive, move |path| {
+            if path == file {
+                on_change(path)
+            }
+        })
+    }
+
+    /// Deploy the tracked dotfile that the path `path` inside a dotfiles directory belongs to,
+    /// i.e. the dotfile itself or the tracked directory containing it, the same way
+    /// [`Config::deploy_all`] would. Returns `None` if nothing is at `path` anymore or it
+    /// doesn't belong to a tracked dotfile.
+    pub fn deploy_single_by_path(&self, path: &Path) -> Result<Option<DeployOutcome>> {
+        match self.tracked_dotfile_at(path)? {
+            Some((index, relative)) => {
+                Ok(Some(self.deploy_watched_dotfile(index, relative)?))
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Deploy every tracked dotfile one of `paths` belongs to once, see
+    /// [`Config::deploy_single_by_path`]. A failure is logged and doesn't stop the other
+    /// dotfiles from being deployed. Returns the number of dotfiles deployed.
+    pub fn deploy_watched<'a>(&self, paths: impl IntoIterator<Item = &'a Path>) -> usize {
+        let mut tracked = BTreeSet::new();
+        for path in paths {
+            match self.tracked_dotfile_at(path) {
+                Ok(Some(dotfile)) => {
+                    let _ = tracked.insert(dotfile);
+                },
+                Ok(None) => {
+                    log::debug!("ignoring change of untracked {0}", path.display())
+                },
+                Err(e) => log::warn!("failed to deploy {0}: {1}", path.display(), e),
+            }
+        }
+        let mut deployed = 0;
+        for (index, relative) in tracked {
+            match self.deploy_watched_dotfile(index, relative) {
+                Ok(_) => deployed += 1,
+                Err(e) => log::warn!("failed to deploy {0}: {1}", relative.display(), e),
+            }
+        }
+        deployed
+    }
+
+    /// The index of the dotfiles entry and the tracked dotfile the existing `path` inside a
+    /// dotfiles directory belongs to. Entries not deployed on this machine, see
+    /// [`Config::deploys_here`], and dotfiles matching the `ignore_patterns` of their entry are
+    /// never deployed, so they are left out.
+    fn tracked_dotfile_at(&self, path: &Path) -> Result<Option<(usize, &DotfilePath)>> {
+        if path.symlink_metadata().is_err() {
+            return Ok(None);
+        }
+        for (index, dotfiles) in self.dotfiles.iter().enumerate() {
+            let relative = match dotfiles.strip_dotfile_dir(path) {
+                Some(relative) if self.deploys_here(dotfiles) => relative,
+                _ => continue,
+            };
+            let mut included = dotfiles.apply_ignore()?;
+            if let Some(tracked) =
+                included.find(|tracked| relative.starts_with(&***tracked))
+            {
+                return Ok(Some((index, tracked)));
+            }
+        }
+        Ok(None)
+    }
+
+    fn deploy_watched_dotfile(
+        &self,
+        index: usize,
+        relative: &DotfilePath,
+    ) -> Result<DeployOutcome> {
+        let options = DeployOptions::new()
+            .backup(true)
+            .skip_broken(true)
+            .skip_listed(true);
+        self.deploy_dotfile(&self.dotfiles[index], relative, &options)
+    }
+}
+
+/// Wait for a path to arrive on `receiver`, then collect every further path until none arrived
+/// for `quiet`, so that a burst of changes such as a `git pull` is handled at once. Returns
+/// `None` once every sender is gone.
+pub fn debounce(
+    receiver: &Receiver<PathBuf>,
+    quiet: Duration,
+) -> Option<BTreeSet<PathBuf>> {
+    let mut paths = BTreeSet::new();
+    let _ = paths.insert(receiver.recv().ok()?);
+    while let Ok(path) = receiver.recv_timeout(quiet) {
+        let _ = paths.insert(path);
+    }
+    Some(paths)
+}
+
+/// Watch each of `paths` and call `on_change` with every path changed according to
+/// [`is_change`].
+fn watch_paths(
+    paths: Vec<PathBuf>,
+    mode: RecursiveMode,
+    on_change: impl Fn(&Path) + Send + 'static,
+) -> Result<RecommendedWatcher> {
+    let handler = move |event: notify::Result<notify::Event>| match event {
+        Ok(event) if is_change(&event.kind) => {
+            event.paths.iter().for_each(|path| on_change(path))
+        },
+        Ok(_) => {},
+        Err(e) => log::warn!("failed to watch for changes: {0}", e),
+    };
+    let mut watcher = notify::recommended_watcher(handler)?;
+    for path in &paths {
+        log::debug!("watching {0}", path.display());
+        watcher.watch(path, mode)?;
+    }
+    Ok(watcher)
+}
+
+/// `path` inside the configured directory of `dirs`, pairs of a canonicalized and a configured
+/// directory, whose canonicalized directory contains it, or `path` itself if there is none.
+fn configured_path(dirs: &[(PathBuf, PathBuf)], path: &Path) -> PathBuf {
+    dirs.iter()
+        .find_map(|(canonical, dir)| {
+            let rest = path.strip_prefix(canonical).ok()?;
+            Some(dir.join(rest))
+        })
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+/// Whether `kind` is an event that leaves something new at a path rather than only reading or
+/// removing it.
+fn is_change(kind: &EventKind) -> bool {
+    match kind {
+        EventKind::Modify(ModifyKind::Name(RenameMode::From)) => false,
+        EventKind::Create(_) | EventKind::Modify(_) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::mpsc;
+
+    use notify::event::{CreateKind, RemoveKind};
+
+    #[test]
+    fn removals_are_not_changes() {
+        assert!(is_change(&EventKind::Create(CreateKind::File)));
+        assert!(is_change(&EventKind::Modify(ModifyKind::Name(
+            RenameMode::To
+        ))));
+        assert!(!is_change(&EventKind::Modify(ModifyKind::Name(
+            RenameMode::From
+        ))));
+        assert!(!is_change(&EventKind::Remove(RemoveKind::File)));
+    }
+
+    #[test]
+    fn canonical_paths_are_mapped_to_the_configured_directory() {
+        let dirs = vec![(PathBuf::from("/real/dots"), PathBuf::from("/home/me/dots"))];
+        assert_eq!(
+            configured_path(&dirs, Path::new("/real/dots/.vimrc")),
+            Path::new("/home/me/dots/.vimrc")
+        );
+        assert_eq!(
+            configured_path(&dirs, Path::new("/elsewhere/.vimrc")),
+            Path::new("/elsewhere/.vimrc")
+        );
+    }
+
+    #[test]
+    fn bursts_of_changes_are_batched() {
+        let (sender, receiver) = mpsc::channel();
+        for name in ["a", "b", "a"] {
+            sender.send(PathBuf::from(name)).unwrap();
+        }
+        let batch = debounce(&receiver, Duration::from_millis(10)).unwrap();
+        assert_eq!(
+            batch.into_iter().collect::<Vec<_>>(),
+            vec![PathBuf::from("a"), PathBuf::from("b")]
+        );
+        drop(sender);
+        assert!(debounce(&receiver, Duration::from_millis(10)).is_none());
+    }
+}
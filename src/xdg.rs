@@ -0,0 +1,340 @@
+//! Resolution of the directories where ghmd keeps its own files.
+//!
+//! Each directory is taken from the corresponding XDG base directory environment variable when it
+//! is set to an absolute path. Otherwise on Linux and other unix-like platforms the XDG defaults
+//! relative to `$HOME` are used, while on macOS and Windows the platform conventions provided by
+//! the `dirs` crate apply. Every directory is namespaced with a trailing `ghmd` component.
+//!
+//! `$HOME` is only used if it is an absolute path and trailing separators are stripped from it,
+//! so that `HOME=/root/` and `HOME=/root` resolve to the same directories. Except on Windows an
+//! unset `$HOME` is not guessed from other sources.
+//!
+//! The XDG base directories themselves, without the `ghmd` component, are where tracked dotfile
+//! paths starting with a token such as `${CONFIG}` are deployed to, see [`XdgDir`].
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::errors::{Error, Result};
+use crate::paths;
+
+/// XdgDir is an XDG base directory that a tracked dotfile path may start with as a token, e.g.
+/// `${CONFIG}/nvim/init.lua`. Such a dotfile is stored below a directory literally named after
+/// the token, while its symlink goes into the base directory as resolved on each machine, e.g.
+/// `~/.config/nvim/init.lua` by default or `~/cfg/nvim/init.lua` with `XDG_CONFIG_HOME=~/cfg`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum XdgDir {
+    /// `$XDG_CONFIG_HOME`, written as `${CONFIG}`.
+    Config,
+
+    /// `$XDG_DATA_HOME`, written as `${DATA}`.
+    Data,
+
+    /// `$XDG_STATE_HOME`, written as `${STATE}`.
+    State,
+
+    /// `$XDG_CACHE_HOME`, written as `${CACHE}`.
+    Cache,
+}
+
+impl XdgDir {
+    /// Every base directory that has a token.
+    pub const ALL: [XdgDir; 4] = [Self::Config, Self::Data, Self::State, Self::Cache];
+
+    /// The token standing for this directory at the start of a tracked dotfile path.
+    pub fn token(&self) -> &'static str {
+        match self {
+            Self::Config => "${CONFIG}",
+            Self::Data => "${DATA}",
+            Self::State => "${STATE}",
+            Self::Cache => "${CACHE}",
+        }
+    }
+
+    /// Split `path` into the base directory named by its leading token and the rest of it, if it
+    /// starts with a token.
+    pub fn split(path: &Path) -> Option<(XdgDir, &Path)> {
+        let mut components = path.components();
+        let first = match components.next()? {
+            Component::Normal(first) => first,
+            _ => return None,
+        };
+        let dir = Self::ALL.into_iter().find(|dir| first == dir.token())?;
+        Some((dir, components.as_path()))
+    }
+}
+
+/// Dirs holds the resolved locations of ghmd's configuration and machine-local data.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Dirs {
+    home: Option<PathBuf>,
+    config_home: PathBuf,
+    data_home: PathBuf,
+    state_home: PathBuf,
+    cache_home: PathBuf,
+    config: PathBuf,
+    data: PathBuf,
+    state: PathBuf,
+    cache: PathBuf,
+    runtime: PathBuf,
+}
+
+impl Dirs {
+    /// Resolve directories from the process environment.
+    pub fn from_env() -> Result<Self> {
+        Self::from_env_with(|key| env::var_os(key))
+    }
+
+    /// Resolve directories using `var` to look up environment variables. This allows the
+    /// resolution to be exercised against an arbitrary environment. Fails with
+    /// [`Error::MissingHomeDirectory`] if a directory defaults to a location inside the home
+    /// directory but `$HOME` isn't usable.
+    pub fn from_env_with<F>(var: F) -> Result<Self>
+    where
+        F: Fn(&str) -> Option<OsString>,
+    {
+        let home = match var("HOME").filter(|h| !h.is_empty()) {
+            Some(home) => Some(paths::trim_trailing_separators(Path::new(&home)))
+                .filter(|home| home.is_absolute()),
+            None if cfg!(windows) => dirs::home_dir(),
+            None => None,
+        };
+
+        let resolve = |xdg_var: &str, home_relative: &str, platform: Option<PathBuf>| {
+            if let Some(dir) = var(xdg_var).map(PathBuf::from) {
+                if dir.is_absolute() {
+                    return Some(dir);
+                }
+            }
+            if cfg!(any(target_os = "macos", target_os = "windows")) {
+                if let Some(dir) = platform {
+                    return Some(dir);
+                }
+            }
+            home.as_ref().map(|h| h.join(home_relative))
+        };
+
+        // a directory can only be left unresolved by a missing home directory
+        let config_home = resolve("XDG_CONFIG_HOME", ".config", dirs::config_dir())
+            .ok_or(Error::MissingHomeDirectory)?;
+        let data_home = resolve("XDG_DATA_HOME", ".local/share", dirs::data_dir())
+            .ok_or(Error::MissingHomeDirectory)?;
+        let state_home =
+            resolve("XDG_STATE_HOME", ".local/state", dirs::data_local_dir())
+                .ok_or(Error::MissingHomeDirectory)?;
+        let cache_home = resolve("XDG_CACHE_HOME", ".cache", dirs::cache_dir())
+            .ok_or(Error::MissingHomeDirectory)?;
+        let cache = cache_home.join("ghmd");
+        let runtime = var("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .map(|dir| dir.join("ghmd"))
+            .unwrap_or_else(|| cache.join("run"));
+
+        Ok(Self {
+            home,
+            config: config_home.join("ghmd"),
+            data: data_home.join("ghmd"),
+            state: state_home.join("ghmd"),
+            cache,
+            config_home,
+            data_home,
+            state_home,
+            cache_home,
+            runtime,
+        })
+    }
+
+    /// Location of the XDG base directory `dir` itself, e.g. `~/.config` for
+    /// [`XdgDir::Config`] by default.
+    pub fn xdg_home(&self, dir: XdgDir) -> &Path {
+        match dir {
+            XdgDir::Config => &self.config_home,
+            XdgDir::Data => &self.data_home,
+            XdgDir::State => &self.state_home,
+            XdgDir::Cache => &self.cache_home,
+        }
+    }
+
+    /// Resolve the leading token of a tracked dotfile `path` to its XDG base directory, or
+    /// return `None` if it doesn't start with one, see [`XdgDir`].
+    pub fn expand(&self, path: &Path) -> Option<PathBuf> {
+        let (dir, rest) = XdgDir::split(path)?;
+        Some(self.xdg_home(dir).join(rest))
+    }
+
+    /// Express an absolute `path` inside one of the XDG base directories as a tracked dotfile
+    /// path starting with the token of that directory, the reverse of [`Dirs::expand`].
+    pub fn tokenize(&self, path: &Path) -> Option<PathBuf> {
+        XdgDir::ALL.into_iter().find_map(|dir| {
+            let rest = path.strip_prefix(self.xdg_home(dir)).ok()?;
+            (rest != Path::new("")).then(|| Path::new(dir.token()).join(rest))
+        })
+    }
+
+    /// Home directory of the user, the default symlink directory.
+    pub fn home_dir(&self) -> Result<&Path> {
+        self.home.as_deref().ok_or(Error::MissingHomeDirectory)
+    }
+
+    /// Directory containing the ghmd config file.
+    pub fn config_dir(&self) -> &Path {
+        &self.config
+    }
+
+    /// Path of the ghmd config file.
+    pub fn config_file(&self) -> PathBuf {
+        self.config.join("config.toml")
+    }
+
+    /// Directory for persistent machine-local data such as backups.
+    pub fn data_dir(&self) -> &Path {
+        &self.data
+    }
+
+    /// Directory holding snapshots of the config, see
+    /// [`Config::snapshot`](crate::Config::snapshot).
+    pub fn snapshots_dir(&self) -> PathBuf {
+        self.data.join("snapshots")
+    }
+
+    /// Directory for machine-local state that should survive restarts but isn't worth backing up.
+    pub fn state_dir(&self) -> &Path {
+        &self.state
+    }
+
+    /// Path of the file holding machine-local state such as the skip list.
+    pub fn state_file(&self) -> PathBuf {
+        self.state.join("state.toml")
+    }
+
+    /// Path of the log of the changes deploys and restores made, see
+    /// [`OperationsLog`](crate::OperationsLog).
+    pub fn operations_log(&self) -> PathBuf {
+        self.state.join("operations.log")
+    }
+
+    /// Directory for data that can be regenerated at any time.
+    pub fn cache_dir(&self) -> &Path {
+        &self.cache
+    }
+
+    /// Directory for runtime files such as locks.
+    pub fn runtime_dir(&self) -> &Path {
+        &self.runtime
+    }
+
+    /// Create `dir` and any missing parents. On unix newly created directories are only
+    /// accessible by the current user.
+    pub fn create(dir: &Path) -> Result<()> {
+        let mut builder = fs::DirBuilder::new();
+        let _ = builder.recursive(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::DirBuilderExt;
+            let _ = builder.mode(0o700);
+        }
+        builder.create(dir)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    fn dirs(vars: &[(&str, &str)]) -> Result<Dirs> {
+        let vars: HashMap<&str, &str> = vars.iter().copied().collect();
+        Dirs::from_env_with(|key| vars.get(key).map(OsString::from))
+    }
+
+    #[test]
+    #[cfg(all(unix, not(target_os = "macos")))]
+    fn directories_default_to_the_home_directory() {
+        let dirs = dirs(&[("HOME", "/home/me")]).unwrap();
+        assert_eq!(dirs.home_dir().unwrap(), Path::new("/home/me"));
+        assert_eq!(
+            dirs.config_file(),
+            Path::new("/home/me/.config/ghmd/config.toml")
+        );
+        assert_eq!(dirs.data_dir(), Path::new("/home/me/.local/share/ghmd"));
+        assert_eq!(dirs.state_dir(), Path::new("/home/me/.local/state/ghmd"));
+        assert_eq!(dirs.cache_dir(), Path::new("/home/me/.cache/ghmd"));
+        assert_eq!(dirs.runtime_dir(), Path::new("/home/me/.cache/ghmd/run"));
+    }
+
+    #[test]
+    #[cfg(all(unix, not(target_os = "macos")))]
+    fn absolute_xdg_variables_take_precedence() {
+        let dirs = dirs(&[
+            ("HOME", "/home/me"),
+            ("XDG_CONFIG_HOME", "/cfg"),
+            ("XDG_DATA_HOME", "relative/data"),
+            ("XDG_STATE_HOME", "/state"),
+            ("XDG_CACHE_HOME", ""),
+            ("XDG_RUNTIME_DIR", "/run/user/1000"),
+        ])
+        .unwrap();
+        assert_eq!(dirs.config_dir(), Path::new("/cfg/ghmd"));
+        assert_eq!(dirs.data_dir(), Path::new("/home/me/.local/share/ghmd"));
+        assert_eq!(dirs.state_dir(), Path::new("/state/ghmd"));
+        assert_eq!(dirs.cache_dir(), Path::new("/home/me/.cache/ghmd"));
+        assert_eq!(dirs.runtime_dir(), Path::new("/run/user/1000/ghmd"));
+        assert_eq!(dirs.xdg_home(XdgDir::Config), Path::new("/cfg"));
+    }
+
+    #[test]
+    #[cfg(all(unix, not(target_os = "macos")))]
+    fn trailing_separators_are_stripped_from_home() {
+        let plain = dirs(&[("HOME", "/root")]).unwrap();
+        for home in ["/root/", "/root//"] {
+            let dirs = dirs(&[("HOME", home)]).unwrap();
+            assert_eq!(dirs, plain);
+            assert_eq!(dirs.home_dir().unwrap().as_os_str(), "/root");
+        }
+        assert_eq!(
+            dirs(&[("HOME", "/")]).unwrap().home_dir().unwrap(),
+            Path::new("/")
+        );
+    }
+
+    #[test]
+    #[cfg(all(unix, not(target_os = "macos")))]
+    fn unusable_home_fails_only_when_needed() {
+        for home in [&[][..], &[("HOME", "")], &[("HOME", "relative")]] {
+            let e = dirs(home).unwrap_err();
+            assert!(matches!(e, Error::MissingHomeDirectory));
+            assert!(e.hint().is_some());
+        }
+        let dirs = dirs(&[
+            ("XDG_CONFIG_HOME", "/cfg"),
+            ("XDG_DATA_HOME", "/data"),
+            ("XDG_STATE_HOME", "/state"),
+            ("XDG_CACHE_HOME", "/cache"),
+        ])
+        .unwrap();
+        assert_eq!(dirs.config_dir(), Path::new("/cfg/ghmd"));
+        assert!(matches!(dirs.home_dir(), Err(Error::MissingHomeDirectory)));
+    }
+
+    #[test]
+    #[cfg(all(unix, not(target_os = "macos")))]
+    fn tokens_expand_and_tokenize_symmetrically() {
+        let dirs = dirs(&[("HOME", "/home/me"), ("XDG_CONFIG_HOME", "/cfg")]).unwrap();
+        let tracked = Path::new("${CONFIG}/nvim/init.lua");
+        assert_eq!(
+            dirs.expand(tracked).unwrap(),
+            Path::new("/cfg/nvim/init.lua")
+        );
+        assert_eq!(
+            dirs.tokenize(Path::new("/cfg/nvim/init.lua")).unwrap(),
+            tracked
+        );
+        assert!(dirs.expand(Path::new(".vimrc")).is_none());
+        assert!(dirs.tokenize(Path::new("/cfg")).is_none());
+        assert!(dirs.tokenize(Path::new("/home/me/.vimrc")).is_none());
+    }
+}
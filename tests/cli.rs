@@ -0,0 +1,773 @@
+//! Tests running the `ghmd` binary against a config in a temporary home directory.
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// A temporary home directory with ghmd's XDG directories inside it.
+struct Home {
+    dir: tempfile::TempDir,
+}
+
+impl Home {
+    fn new() -> Self {
+        let home = Self {
+            dir: tempfile::tempdir().unwrap(),
+        };
+        fs::create_dir_all(home.config_file().parent().unwrap()).unwrap();
+        home
+    }
+
+    fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    fn config_file(&self) -> PathBuf {
+        self.path().join(".config/ghmd/config.toml")
+    }
+
+    fn write_config(&self, contents: &str) {
+        fs::write(self.config_file(), contents).unwrap();
+    }
+
+    /// `ghmd` with `args`, running in this home directory and no other environment.
+    fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_ghmd"));
+        let _ = command
+            .args(args)
+            .env_clear()
+            .env("HOME", self.path())
+            .env("XDG_CONFIG_HOME", self.path().join(".config"))
+            .env("XDG_DATA_HOME", self.path().join(".local/share"))
+            .env("XDG_STATE_HOME", self.path().join(".local/state"))
+            .env("XDG_CACHE_HOME", self.path().join(".cache"))
+            .current_dir(self.path());
+        command
+    }
+
+    fn ghmd(&self, args: &[&str]) -> Output {
+        self.command(args).output().unwrap()
+    }
+}
+
+#[test]
+fn repair_commands_run_on_invalid_config() {
+    let home = Home::new();
+    let missing = home.path().join("missing");
+    home.write_config(&format!(
+        "[[dotfiles]]\ndotfile_directory = {0:?}\nsymlink_directory = {1:?}\npaths = []\n",
+        missing.display().to_string(),
+        home.path().display().to_string(),
+    ));
+
+    let list = home.ghmd(&["--json-errors", "list"]);
+    assert!(!list.status.success());
+    assert!(String::from_utf8_lossy(&list.stderr).contains("config_invalid"));
+
+    let doctor = home.ghmd(&["doctor"]);
+    assert!(
+        doctor.status.success(),
+        "{0}",
+        String::from_utf8_lossy(&doctor.stderr)
+    );
+}
+
+#[test]
+fn corrupt_config_is_replaced_with_its_backup() {
+    let home = Home::new();
+    let dots = home.path().join("dots");
+    fs::create_dir_all(&dots).unwrap();
+    fs::write(dots.join(".vimrc"), "set nu").unwrap();
+    let valid = format!(
+        "[[dotfiles]]\ndotfile_directory = {0:?}\nsymlink_directory = {1:?}\npaths = ['.vimrc']\n",
+        dots.display().to_string(),
+        home.path().display().to_string(),
+    );
+    home.write_config("[[dotfiles]\ndotfile_directory = ");
+    fs::write(home.path().join(".config/ghmd/config.toml.bak"), &valid).unwrap();
+
+    let deploy = home.ghmd(&["deploy", "--all"]);
+    assert!(
+        deploy.status.success(),
+        "{0}",
+        String::from_utf8_lossy(&deploy.stderr)
+    );
+    assert!(home.path().join(".vimrc").is_symlink());
+
+    let repaired = fs::read_to_string(home.config_file()).unwrap();
+    assert!(repaired.contains("'.vimrc'"), "{repaired}");
+    assert!(!home.path().join(".config/ghmd/config.toml.tmp").exists());
+}
+
+#[test]
+fn read_only_commands_leave_the_config_alone() {
+    let home = Home::new();
+    let dots = home.path().join("dots");
+    fs::create_dir_all(&dots).unwrap();
+    fs::write(dots.join(".vimrc"), "set nu").unwrap();
+    // written by an older ghmd, so any write would at least record the running version
+    let contents = format!(
+        "# my dotfiles\n[[dotfiles]]\ndotfile_directory = {0:?}\nsymlink_directory = {1:?}\n\
+         paths = ['.vimrc']\n",
+        dots.display().to_string(),
+        home.path().display().to_string(),
+    );
+    home.write_config(&contents);
+
+    for args in [
+        &["list"][..],
+        &["status"],
+        &["--quiet", "--no-validate", "verify"],
+    ] {
+        let _ = home.ghmd(args);
+        assert_eq!(
+            fs::read_to_string(home.config_file()).unwrap(),
+            contents,
+            "{args:?}"
+        );
+    }
+    assert!(!home.path().join(".config/ghmd/config.toml.bak").exists());
+
+    let bare = home.ghmd(&[]);
+    assert!(!bare.status.success());
+    assert!(String::from_utf8_lossy(&bare.stderr).contains("USAGE"));
+    assert!(String::from_utf8_lossy(&bare.stdout).contains(".vimrc"));
+    assert_eq!(fs::read_to_string(home.config_file()).unwrap(), contents);
+    assert!(!home.path().join(".config/ghmd/config.toml.bak").exists());
+
+    let hook = home.ghmd(&["shell-hook", "bash"]);
+    assert!(
+        String::from_utf8_lossy(&hook.stdout).contains("--quiet --no-validate verify")
+    );
+    assert_eq!(fs::read_to_string(home.config_file()).unwrap(), contents);
+}
+
+#[test]
+fn config_changes_are_snapshotted_and_rotated() {
+    let home = Home::new();
+    let dots = home.path().join("dots");
+    fs::create_dir_all(&dots).unwrap();
+    fs::write(dots.join(".vimrc"), "set nu").unwrap();
+    home.write_config(&format!(
+        "[[dotfiles]]\ndotfile_directory = {0:?}\nsymlink_directory = {1:?}\npaths = ['.vimrc']\n",
+        dots.display().to_string(),
+        home.path().display().to_string(),
+    ));
+    let snapshots_dir = home.path().join(".local/share/ghmd/snapshots");
+    let snapshots = || fs::read_dir(&snapshots_dir).unwrap().count();
+
+    assert!(home.ghmd(&["deploy", "--all"]).status.success());
+    assert_eq!(snapshots(), 1);
+    let listed = home.ghmd(&["snapshots"]);
+    assert_eq!(String::from_utf8_lossy(&listed.stdout).lines().count(), 1);
+
+    for name in ["old-1.toml", "old-2.toml"] {
+        fs::write(snapshots_dir.join(name), "").unwrap();
+    }
+    let forget = home
+        .command(&["forget", "dots", ".vimrc"])
+        .env("GHMD_SNAPSHOT_KEEP", "2")
+        .output()
+        .unwrap();
+    assert!(
+        forget.status.success(),
+        "{0}",
+        String::from_utf8_lossy(&forget.stderr)
+    );
+    assert_eq!(snapshots(), 2);
+
+    assert!(home
+        .ghmd(&["snapshots", "--prune", "--keep", "0"])
+        .status
+        .success());
+    assert_eq!(snapshots(), 0);
+}
+
+#[test]
+fn expanded_symlink_paths_are_found_after_deploying() {
+    let home = Home::new();
+    let dots = home.path().join("dots");
+    fs::create_dir_all(dots.join(".config/app")).unwrap();
+    fs::write(dots.join(".config/app/${HOSTNAME}.conf"), "color = true").unwrap();
+    home.write_config(&format!(
+        "[[dotfiles]]\ndotfile_directory = {0:?}\nsymlink_directory = {1:?}\n\
+         paths = ['.config/app/${{HOSTNAME}}.conf']\n",
+        dots.display().to_string(),
+        home.path().display().to_string(),
+    ));
+    let ghmd = |args: &[&str]| {
+        let output = home.command(args).env("HOSTNAME", "box").output().unwrap();
+        assert!(
+            output.status.success(),
+            "{args:?}: {0}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        output
+    };
+
+    let _ = ghmd(&["deploy", "--all", "--expand-dest"]);
+    let symlink = home.path().join(".config/app/box.conf");
+    assert!(symlink.is_symlink());
+    assert!(!home.path().join(".config/app/${HOSTNAME}.conf").exists());
+    assert!(fs::read_to_string(home.config_file())
+        .unwrap()
+        .contains("expand_dest = true"));
+
+    // without --expand-dest every command looks for the symlink where the deploy put it
+    let _ = ghmd(&["verify"]);
+    let _ = ghmd(&["deploy", "--all"]);
+    let _ = ghmd(&["restore", "dots", ".config/app/${HOSTNAME}.conf"]);
+    assert_eq!(fs::read_to_string(&symlink).unwrap(), "color = true");
+    assert!(!symlink.is_symlink());
+}
+
+#[test]
+fn failed_stow_leaves_no_entry_behind() {
+    let home = Home::new();
+    let dots = home.path().join("dots");
+    fs::create_dir_all(&dots).unwrap();
+    let home_dir = home.path().display().to_string();
+    let dots_dir = dots.display().to_string();
+
+    let stow = home.ghmd(&[
+        "stow",
+        "--link-style",
+        "relative",
+        "--as",
+        ".vimrc",
+        &home_dir,
+        &dots_dir,
+        "missing",
+    ]);
+    assert!(!stow.status.success());
+    let config = fs::read_to_string(home.config_file()).unwrap_or_default();
+    assert!(!config.contains("[[dotfiles]]"), "{config}");
+
+    fs::write(home.path().join("vimrc"), "set nu").unwrap();
+    let stow = home.ghmd(&[
+        "stow",
+        "--link-style",
+        "relative",
+        "--as",
+        ".vimrc",
+        &home_dir,
+        &dots_dir,
+        "vimrc",
+    ]);
+    assert!(
+        stow.status.success(),
+        "{0}",
+        String::from_utf8_lossy(&stow.stderr)
+    );
+    let config = fs::read_to_string(home.config_file()).unwrap();
+    assert!(config.contains("link_style = 'relative'"), "{config}");
+    assert!(home.path().join(".vimrc").is_symlink());
+}
+
+#[test]
+fn repair_relinks_after_moving_the_dotfiles_directory() {
+    let home = Home::new();
+    let (dots, moved) = (home.path().join("dots"), home.path().join("dotfiles"));
+    fs::create_dir_all(&dots).unwrap();
+    fs::write(home.path().join(".vimrc"), "set nu").unwrap();
+    let home_dir = home.path().display().to_string();
+    let stow = home.ghmd(&[
+        "stow",
+        "--link-style",
+        "relative",
+        &home_dir,
+        &dots.display().to_string(),
+        ".vimrc",
+    ]);
+    assert!(
+        stow.status.success(),
+        "{0}",
+        String::from_utf8_lossy(&stow.stderr)
+    );
+
+    fs::rename(&dots, &moved).unwrap();
+    let repair = home.ghmd(&[
+        "repair",
+        "--old",
+        &dots.display().to_string(),
+        "--new",
+        &moved.display().to_string(),
+    ]);
+    assert!(
+        repair.status.success(),
+        "{0}",
+        String::from_utf8_lossy(&repair.stderr)
+    );
+    let symlink = home.path().join(".vimrc");
+    assert_eq!(
+        fs::read_link(&symlink).unwrap(),
+        Path::new("dotfiles/.vimrc")
+    );
+    assert_eq!(fs::read_to_string(&symlink).unwrap(), "set nu");
+    let config = fs::read_to_string(home.config_file()).unwrap();
+    assert!(config.contains(&moved.display().to_string()), "{config}");
+}
+
+#[test]
+fn config_schema_version_is_recorded_and_checked() {
+    let home = Home::new();
+    let dots = home.path().join("dots");
+    fs::create_dir_all(&dots).unwrap();
+    fs::write(home.path().join(".vimrc"), "set nu").unwrap();
+    let stow = home.ghmd(&[
+        "stow",
+        &home.path().display().to_string(),
+        &dots.display().to_string(),
+        ".vimrc",
+    ]);
+    assert!(
+        stow.status.success(),
+        "{0}",
+        String::from_utf8_lossy(&stow.stderr)
+    );
+    let config = fs::read_to_string(home.config_file()).unwrap();
+    assert!(config.contains("schema_version = 1"), "{config}");
+
+    let newer = config.replace("schema_version = 1", "schema_version = 99");
+    home.write_config(&newer);
+    let list = home.ghmd(&["--json-errors", "list"]);
+    assert!(!list.status.success());
+    assert!(String::from_utf8_lossy(&list.stderr).contains("config_schema_too_new"));
+    let deploy = home.ghmd(&["deploy", "--all"]);
+    assert!(!deploy.status.success());
+    assert_eq!(fs::read_to_string(home.config_file()).unwrap(), newer);
+}
+
+#[test]
+fn symlink_dir_command_only_runs_when_needed() {
+    let home = Home::new();
+    let dots = home.path().join("dots");
+    let target = home.path().join("target");
+    fs::create_dir_all(&dots).unwrap();
+    fs::create_dir_all(&target).unwrap();
+    let marker = home.path().join("ran");
+    let command = format!("touch {0} && echo {1}", marker.display(), target.display());
+    home.write_config(&format!(
+        "[[dotfiles]]\ndotfile_directory = {0:?}\nsymlink_directory = {1:?}\n\
+         symlink_dir_command = {2:?}\npaths = []\n",
+        dots.display().to_string(),
+        home.path().join("missing").display().to_string(),
+        command,
+    ));
+
+    let skip = home.ghmd(&["skip", "list"]);
+    assert!(
+        skip.status.success(),
+        "{0}",
+        String::from_utf8_lossy(&skip.stderr)
+    );
+    assert!(!marker.exists());
+
+    let list = home.ghmd(&["list"]);
+    assert!(
+        list.status.success(),
+        "{0}",
+        String::from_utf8_lossy(&list.stderr)
+    );
+    assert!(marker.exists());
+}
+
+#[test]
+fn listing_with_criteria_matching_nothing_succeeds() {
+    let home = Home::new();
+    let dots = home.path().join("dots");
+    fs::create_dir_all(&dots).unwrap();
+    fs::write(dots.join(".vimrc"), "set nu").unwrap();
+    home.write_config(&format!(
+        "[[dotfiles]]\ndotfile_directory = {0:?}\nsymlink_directory = {1:?}\n\
+         paths = ['.vimrc']\n",
+        dots.display().to_string(),
+        home.path().display().to_string(),
+    ));
+
+    for command in ["list", "status", "verify"] {
+        let output = home.ghmd(&[command, "--path", "nomatch"]);
+        assert!(
+            output.status.success(),
+            "{0}: {1}",
+            command,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert!(output.stdout.is_empty(), "{0}", command);
+    }
+    let deploy = home.ghmd(&["deploy", "--path", "nomatch"]);
+    assert!(!deploy.status.success());
+}
+
+#[test]
+fn stowing_into_the_containing_entry_needs_auto() {
+    let home = Home::new();
+    let dots = home.path().join("dots");
+    fs::create_dir_all(&dots).unwrap();
+    home.write_config(&format!(
+        "[[dotfiles]]\ndotfile_directory = {0:?}\nsymlink_directory = {1:?}\npaths = []\n",
+        dots.display().to_string(),
+        home.path().display().to_string(),
+    ));
+    let file = home.path().join(".newconfig");
+    fs::write(&file, "new").unwrap();
+    let file = file.to_str().unwrap();
+
+    let implicit = home.ghmd(&["stow", file]);
+    assert!(!implicit.status.success());
+    assert!(!Path::new(file).is_symlink());
+
+    let auto = home.ghmd(&["stow", "--auto", file]);
+    assert!(
+        auto.status.success(),
+        "{0}",
+        String::from_utf8_lossy(&auto.stderr)
+    );
+    assert!(Path::new(file).is_symlink());
+    assert!(dots.join(".newconfig").is_file());
+}
+
+#[test]
+fn verify_remote_exits_with_2_without_a_reference() {
+    let home = Home::new();
+    let dots = home.path().join("dots");
+    fs::create_dir_all(&dots).unwrap();
+    fs::write(dots.join(".vimrc"), "set nu").unwrap();
+    home.write_config(&format!(
+        "[[dotfiles]]\ndotfile_directory = {0:?}\nsymlink_directory = {1:?}\n\
+         paths = ['.vimrc']\n",
+        dots.display().to_string(),
+        home.path().display().to_string(),
+    ));
+
+    let missing = home.ghmd(&["verify-remote", "--manifest-file", "missing.toml"]);
+    assert_eq!(missing.status.code(), Some(2));
+
+    let export = home.ghmd(&["export", "--manifest"]);
+    assert!(export.status.success());
+    fs::write(home.path().join("manifest.toml"), &export.stdout).unwrap();
+    let clean = home.ghmd(&["verify-remote", "--manifest-file", "manifest.toml"]);
+    assert_eq!(clean.status.code(), Some(0));
+
+    fs::write(dots.join(".vimrc"), "set nonu").unwrap();
+    let drifted = home.ghmd(&["verify-remote", "--manifest-file", "manifest.toml"]);
+    assert_eq!(drifted.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&drifted.stdout).contains("drifted"));
+}
+
+#[test]
+fn json_events_describe_each_deployed_dotfile() {
+    let home = Home::new();
+    let dots = home.path().join("dots");
+    fs::create_dir_all(&dots).unwrap();
+    fs::write(dots.join(".vimrc"), "set nu").unwrap();
+    home.write_config(&format!(
+        "[[dotfiles]]\ndotfile_directory = {0:?}\nsymlink_directory = {1:?}\npaths = ['.vimrc']\n",
+        dots.display().to_string(),
+        home.path().display().to_string(),
+    ));
+
+    let deploy = home.ghmd(&["--json-events", "deploy", "--all"]);
+    assert!(
+        deploy.status.success(),
+        "{0}",
+        String::from_utf8_lossy(&deploy.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&deploy.stdout);
+    let event: serde_json::Value =
+        serde_json::from_str(stdout.lines().next().unwrap()).unwrap();
+    assert_eq!(event["action"], "deploy");
+    assert_eq!(event["path"], ".vimrc");
+    assert_eq!(event["outcome"], "done");
+    assert!(event["error"].is_null());
+}
+
+#[test]
+fn stowing_keeps_comments_and_unknown_keys() {
+    let home = Home::new();
+    let dots = home.path().join("dots");
+    fs::create_dir_all(&dots).unwrap();
+    fs::write(home.path().join(".vimrc"), "set nu").unwrap();
+    home.write_config(&format!(
+        "# my dotfiles\n[[dotfiles]]\ndotfile_directory = {0:?}\nsymlink_directory = {1:?}\n\
+         paths = []\nfrom_the_future = 'kept'\n",
+        dots.display().to_string(),
+        home.path().display().to_string(),
+    ));
+
+    let stow = home.ghmd(&["stow", ".", "dots", ".vimrc"]);
+    assert!(
+        stow.status.success(),
+        "{0}",
+        String::from_utf8_lossy(&stow.stderr)
+    );
+    let config = fs::read_to_string(home.config_file()).unwrap();
+    assert!(
+        config.contains("# my dotfiles\n[[dotfiles]]"),
+        "{0}",
+        config
+    );
+    assert!(config.contains("from_the_future = 'kept'"), "{0}", config);
+    assert!(config.contains(".vimrc"), "{0}", config);
+}
+
+#[test]
+fn restoring_elsewhere_is_recorded_in_the_operations_log() {
+    let home = Home::new();
+    let dots = home.path().join("dots");
+    fs::create_dir_all(&dots).unwrap();
+    fs::write(dots.join(".vimrc"), "set nu").unwrap();
+    home.write_config(&format!(
+        "[[dotfiles]]\ndotfile_directory = {0:?}\nsymlink_directory = {1:?}\npaths = ['.vimrc']\n",
+        dots.display().to_string(),
+        home.path().display().to_string(),
+    ));
+
+    let restore = home.ghmd(&["restore", "--to", "archive", "dots", ".vimrc"]);
+    assert!(
+        restore.status.success(),
+        "{0}",
+        String::from_utf8_lossy(&restore.stderr)
+    );
+    assert!(home.path().join("archive/.vimrc").is_file());
+    let log =
+        fs::read_to_string(home.path().join(".local/state/ghmd/operations.log")).unwrap();
+    let expected = format!(
+        "move {0} to {1}",
+        dots.join(".vimrc").display(),
+        home.path().join("archive/.vimrc").display()
+    );
+    assert!(log.contains("restore .vimrc"), "{0}", log);
+    assert!(log.contains(&expected), "{0}", log);
+}
+
+#[test]
+fn dotfiles_dir_patterns_select_deployed_and_restored_dotfiles() {
+    let home = Home::new();
+    let dots = home.path().join("dots");
+    fs::create_dir_all(dots.join("config/nested")).unwrap();
+    for path in ["config/a.toml", "config/nested/b.toml"] {
+        fs::write(dots.join(path), path).unwrap();
+    }
+    home.write_config(&format!(
+        "[[dotfiles]]\ndotfile_directory = {0:?}\nsymlink_directory = {1:?}\n\
+         paths = ['config/a.toml', 'config/nested/b.toml']\n",
+        dots.display().to_string(),
+        home.path().display().to_string(),
+    ));
+
+    let deploy = home.ghmd(&["deploy", "--dotfiles-dir", "dots", "config/**/*.toml"]);
+    assert!(
+        deploy.status.success(),
+        "{0}",
+        String::from_utf8_lossy(&deploy.stderr)
+    );
+    assert!(home.path().join("config/a.toml").is_symlink());
+    assert!(home.path().join("config/nested/b.toml").is_symlink());
+
+    let restore = home.ghmd(&["restore", "--dotfiles-dir", "dots", "config/*.toml"]);
+    assert!(
+        restore.status.success(),
+        "{0}",
+        String::from_utf8_lossy(&restore.stderr)
+    );
+    assert!(home.path().join("config/a.toml").is_file());
+    assert!(!home.path().join("config/a.toml").is_symlink());
+    assert!(home.path().join("config/nested/b.toml").is_symlink());
+
+    let nothing = home.ghmd(&["restore", "--dotfiles-dir", "dots", "*.toml"]);
+    assert!(!nothing.status.success());
+}
+
+#[test]
+fn json_errors_print_one_object_to_stderr() {
+    let home = Home::new();
+    let json_error = |args: &[&str]| {
+        let output = home.ghmd(args);
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        assert_eq!(stderr.lines().count(), 1, "{stderr}");
+        assert!(stderr.starts_with('{'), "{stderr}");
+        stderr
+    };
+
+    home.write_config("not = [toml");
+    let parse = json_error(&["--json-errors", "list"]);
+    assert!(parse.contains("\"code\":\"config_parse\""), "{parse}");
+
+    home.write_config("dotfiles = []\n");
+    let deploy = json_error(&["--json-errors", "deploy", "nothing"]);
+    assert!(
+        deploy.contains("\"code\":\"dotfile_not_configured\""),
+        "{deploy}"
+    );
+}
+
+#[test]
+fn deploy_reports_created_symlinks_and_counts_unchanged_ones() {
+    let home = Home::new();
+    let dots = home.path().join("dots");
+    fs::create_dir_all(&dots).unwrap();
+    fs::write(dots.join(".vimrc"), "set nu").unwrap();
+    fs::write(dots.join(".bashrc"), "set -o vi").unwrap();
+    home.write_config(&format!(
+        "[[dotfiles]]\ndotfile_directory = {0:?}\nsymlink_directory = {1:?}\n\
+        paths = ['.bashrc', '.vimrc']\n",
+        dots.display().to_string(),
+        home.path().display().to_string(),
+    ));
+
+    assert!(home.ghmd(&["deploy", ".bashrc"]).status.success());
+    let deploy = home.ghmd(&["deploy", "--all"]);
+    assert!(deploy.status.success());
+    let output = String::from_utf8_lossy(&deploy.stderr).to_string()
+        + &String::from_utf8_lossy(&deploy.stdout);
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "created ~/.vimrc -> ~/dots/.vimrc",
+            "✓ 1 deployed, ✗ 0 failed, → 1 already correct",
+        ]
+    );
+
+    // verbose output names the unchanged symlinks too
+    let deploy = home.ghmd(&["-v", "deploy", "--all"]);
+    assert!(deploy.status.success());
+    let stderr = String::from_utf8_lossy(&deploy.stderr);
+    assert!(stderr.contains(".bashrc is already in place"));
+    assert!(stderr.contains(".vimrc is already in place"));
+    assert!(!stderr.contains("created"));
+}
+
+#[test]
+fn stow_globs_match_any_case_with_glob_case_insensitive() {
+    let home = Home::new();
+    let dots = home.path().join("dots");
+    fs::create_dir_all(&dots).unwrap();
+    for name in ["a.toml", "B.TOML", "c.Toml", "d.txt"] {
+        fs::write(home.path().join(name), name).unwrap();
+    }
+    home.write_config(&format!(
+        "[[dotfiles]]\ndotfile_directory = {0:?}\nsymlink_directory = {1:?}\npaths = []\n",
+        dots.display().to_string(),
+        home.path().display().to_string(),
+    ));
+    let stored = || {
+        let mut names: Vec<String> = fs::read_dir(&dots)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        names
+    };
+
+    let stow = home.ghmd(&["stow", ".", "dots", "*.toml"]);
+    assert!(
+        stow.status.success(),
+        "{0}",
+        String::from_utf8_lossy(&stow.stderr)
+    );
+    assert_eq!(stored(), ["a.toml"]);
+
+    let stow = home.ghmd(&["stow", "--glob-case-insensitive", ".", "dots", "*.toml"]);
+    assert!(
+        stow.status.success(),
+        "{0}",
+        String::from_utf8_lossy(&stow.stderr)
+    );
+    assert_eq!(stored(), ["B.TOML", "a.toml", "c.Toml"]);
+    assert!(home.path().join("c.Toml").is_symlink());
+    assert!(!home.path().join("d.txt").is_symlink());
+}
+
+#[test]
+fn stowing_over_an_intact_link_adopts_it_after_losing_the_config() {
+    let home = Home::new();
+    fs::create_dir_all(home.path().join("dots")).unwrap();
+    fs::write(home.path().join(".vimrc"), "set nu").unwrap();
+    assert!(home.ghmd(&["stow", ".", "dots", ".vimrc"]).status.success());
+    let stored = home.path().join("dots/.vimrc");
+    assert!(stored.is_file());
+
+    fs::remove_file(home.config_file()).unwrap();
+    let stow = home.ghmd(&["stow", ".", "dots", ".vimrc"]);
+    assert!(
+        stow.status.success(),
+        "{0}",
+        String::from_utf8_lossy(&stow.stderr)
+    );
+    assert!(String::from_utf8_lossy(&stow.stderr).contains("adopted existing link"));
+    let config = fs::read_to_string(home.config_file()).unwrap();
+    assert!(config.contains("paths = ['.vimrc']"), "{0}", config);
+    assert_eq!(fs::read_to_string(&stored).unwrap(), "set nu");
+    assert_eq!(
+        fs::read_to_string(home.path().join(".vimrc")).unwrap(),
+        "set nu"
+    );
+}
+
+#[test]
+fn unversioned_dotfiles_directories_are_warned_about_once() {
+    let home = Home::new();
+    let dots = home.path().join("dots");
+    fs::create_dir_all(&dots).unwrap();
+    for name in [".vimrc", ".bashrc"] {
+        fs::write(home.path().join(name), name).unwrap();
+    }
+    let config = format!(
+        "[[dotfiles]]\ndotfile_directory = {0:?}\nsymlink_directory = {1:?}\npaths = []\n",
+        dots.display().to_string(),
+        home.path().display().to_string(),
+    );
+    home.write_config(&config);
+    let warned = |output: &Output| {
+        String::from_utf8_lossy(&output.stderr).contains("is not under version control")
+    };
+
+    let stow = home.ghmd(&["stow", ".", "dots", ".vimrc"]);
+    assert!(stow.status.success());
+    assert!(warned(&stow));
+    let stow = home.ghmd(&["stow", ".", "dots", ".bashrc"]);
+    assert!(stow.status.success());
+    assert!(!warned(&stow));
+
+    // doctor always lists them
+    let doctor = home.ghmd(&["doctor"]);
+    assert!(String::from_utf8_lossy(&doctor.stdout).contains("unversioned:"));
+
+    // a new machine warns again, unless the warning is turned off
+    fs::remove_dir_all(home.path().join(".local/state")).unwrap();
+    fs::write(home.path().join(".inputrc"), "").unwrap();
+    let stow = home.ghmd(&["stow", ".", "dots", ".inputrc"]);
+    assert!(warned(&stow));
+    fs::remove_dir_all(home.path().join(".local/state")).unwrap();
+    let config = fs::read_to_string(home.config_file()).unwrap();
+    home.write_config(&format!("vcs_warning = false\n{0}", config));
+    fs::write(home.path().join(".gitconfig"), "").unwrap();
+    let stow = home.ghmd(&["stow", ".", "dots", ".gitconfig"]);
+    assert!(stow.status.success());
+    assert!(!warned(&stow));
+    let doctor = home.ghmd(&["doctor"]);
+    assert!(!String::from_utf8_lossy(&doctor.stdout).contains("unversioned:"));
+}
+
+#[test]
+fn shell_hook_check_is_silent_when_in_sync() {
+    let home = Home::new();
+    let dots = home.path().join("dots");
+    fs::create_dir_all(&dots).unwrap();
+    fs::write(dots.join(".vimrc"), "set nu").unwrap();
+    home.write_config(&format!(
+        "[[dotfiles]]\ndotfile_directory = {0:?}\nsymlink_directory = {1:?}\npaths = ['.vimrc']\n",
+        dots.display().to_string(),
+        home.path().display().to_string(),
+    ));
+    // the check the generated snippets run on every prompt
+    let check = ["--quiet", "--no-validate", "verify"];
+    assert!(!home.ghmd(&check).status.success());
+
+    assert!(home.ghmd(&["deploy", "--all"]).status.success());
+    let verify = home.ghmd(&check);
+    assert!(verify.status.success());
+    assert!(verify.stdout.is_empty() && verify.stderr.is_empty());
+}